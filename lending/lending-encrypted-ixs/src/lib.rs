@@ -1,35 +1,88 @@
 use arcis::imports::*;
 use confidential_spl_token::{ConfidentialTokenAccount, ConfidentialTransfer};
 
+/// Number of transfers made by a `borrow` computation.
+const BORROW_TRANSFER_COUNT: usize = 3;
+
+/// Maximum number of periods interest is compounded over by a `repay_compound` computation.
+const MAX_COMPOUNDING_PERIODS: usize = 16;
+
+/// Maximum number of loans summed by a `total_debt` computation, one per borrower of the pool.
+const MAX_BORROWERS: usize = 8;
+
 #[encrypted]
 pub mod encrypted_computations {
     use super::*;
 
     #[instruction]
+    #[allow(clippy::too_many_arguments)]
     pub fn borrow(
         mxe: Mxe,
         asset_vault_ata: ConfidentialTokenAccount,
         asset_borrower_ata: ConfidentialTokenAccount,
         collateral_vault_ata: ConfidentialTokenAccount,
         collateral_borrower_ata: ConfidentialTokenAccount,
+        asset_fee_ata: ConfidentialTokenAccount,
         price: u64,
         loan_to_value_bps: u16,
-    ) -> (ConfidentialTransfer, ConfidentialTransfer, Enc<Mxe, u64>) {
+        origination_fee_bps: u16,
+        min_borrow: u64,
+        max_borrow: u64,
+        min_collateral_ratio_bps: u16,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+    ) -> (
+        [ConfidentialTransfer; BORROW_TRANSFER_COUNT],
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+    ) {
         let asset_amount = asset_vault_ata.encrypted_balance();
         let collateral_amount = collateral_vault_ata.encrypted_balance();
 
-        let loan_to_value_bps_ratio = BasePoints(price * loan_to_value_bps);
+        // Widened so a large price can't wrap around, the ratio saturates at u64::MAX base points.
+        // Prices up to u64::MAX / loan_to_value_bps are supported exactly.
+        let loan_to_value_bps_ratio =
+            BasePoints(min(price as u128 * loan_to_value_bps as u128, u64::MAX as u128) as u64);
         let max_loan_amount = collateral_amount.clone() * loan_to_value_bps_ratio.clone();
-        let loan_amount = min(max_loan_amount, asset_amount);
-        let loan_collateral_amount = loan_amount.clone() / loan_to_value_bps_ratio;
-        let collateral_excess_amount = collateral_amount - loan_collateral_amount;
+        let loan_amount = min(min(max_loan_amount, asset_amount), max_borrow);
+
+        // The borrow is rejected outright if the collateral backing it would be worth less than
+        // min_collateral_ratio_bps of the loan, e.g. after rounding on small deposits.
+        let backing_collateral = min(
+            loan_amount.clone() / loan_to_value_bps_ratio.clone(),
+            collateral_amount.clone(),
+        );
+        let borrow_rejected =
+            backing_collateral * price < loan_amount.clone() * BasePoints(min_collateral_ratio_bps);
 
-        // Transfer of loan_amount to the borrower.
+        // Nothing is lent, and no collateral is returned, below the pool's minimum borrow.
+        let loan_is_granted = if borrow_rejected {
+            false
+        } else {
+            loan_amount >= min_borrow
+        };
+        let loan_amount = if loan_is_granted { loan_amount } else { 0 };
+        // The collateral backing the loan is clamped to the deposit, so a degenerate price can't
+        // make the excess returned to the borrower underflow.
+        let loan_collateral_amount = min(
+            loan_amount.clone() / loan_to_value_bps_ratio,
+            collateral_amount.clone(),
+        );
+        let collateral_excess_amount = if loan_is_granted {
+            collateral_amount - loan_collateral_amount.clone()
+        } else {
+            0
+        };
+        let origination_fee = loan_amount.clone() * BasePoints(origination_fee_bps);
+
+        // Transfer of loan_amount minus the origination fee to the borrower.
         let asset_transfer = confidential_spl_token::transfer(
             &mxe,
             &asset_vault_ata,
             &asset_borrower_ata,
-            loan_amount.clone(),
+            loan_amount.clone() - origination_fee.clone(),
         );
 
         // Transfer of collateral_excess_amount to the borrower.
@@ -40,49 +93,500 @@ pub mod encrypted_computations {
             collateral_excess_amount,
         );
 
+        // Transfer of the origination fee to the lender.
+        let fee_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_vault_ata,
+            &asset_fee_ata,
+            origination_fee,
+        );
+
+        // The loan was inactive, so the pool's total debt grows by the whole loan_amount.
+        let total_debt = other_loans_debt(total_debt.to_arcis(), total_debt_known, 0);
+
+        // The principal to repay is the full loan_amount, including the fee, backed by the
+        // loan_collateral_amount left locked in the collateral vault. Whether the loan is granted
+        // at all is revealed, a rejected borrow or one below min_borrow leaves the loan inactive.
+        (
+            [asset_transfer, collateral_transfer, fee_transfer],
+            mxe.from_arcis(loan_amount.clone()),
+            mxe.from_arcis(loan_collateral_amount),
+            loan_is_granted.reveal(),
+            mxe.from_arcis(total_debt + loan_amount),
+        )
+    }
+
+    /// Utilization of the pool in basis points: the share of the `total_debt` in the pool's
+    /// liquidity, the debt plus what is left in `asset_vault_ata`. A `total_debt` that was never
+    /// computed counts as an unused pool.
+    fn pool_utilization_bps(
+        asset_vault_ata: &ConfidentialTokenAccount,
+        total_debt: u64,
+        total_debt_known: bool,
+    ) -> u64 {
+        let total_debt = if total_debt_known { total_debt } else { 0 };
+        let total_liquidity = total_debt.clone() + asset_vault_ata.encrypted_balance();
+        if total_liquidity.eq(0) {
+            0
+        } else {
+            min(total_debt * 10_000 / total_liquidity, 10_000)
+        }
+    }
+
+    /// The pool's `total_debt` without the `principal` of one of its loans. A `total_debt` that was
+    /// never computed counts as no debt.
+    fn other_loans_debt(total_debt: u64, total_debt_known: bool, principal: u64) -> u64 {
+        let total_debt = if total_debt_known { total_debt } else { 0 };
+        total_debt.clone() - min(principal, total_debt)
+    }
+
+    /// Interest rate at `utilization_bps`, growing linearly from `base_rate_bps` by up to
+    /// `slope1_bps` until the `kink_bps` utilization and by up to `slope2_bps` above it.
+    fn utilization_rate_bps(
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        utilization_bps: u64,
+    ) -> u64 {
+        let below_kink = min(utilization_bps.clone(), kink_bps as u64);
+        let above_kink = utilization_bps - below_kink.clone();
+        let rate_bps = base_rate_bps as u64
+            + below_kink * slope1_bps as u64 / max(kink_bps as u64, 1)
+            + above_kink * slope2_bps as u64 / max(10_000 - kink_bps as u64, 1);
+        min(rate_bps, u16::MAX as u64)
+    }
+
+    /// Remaining principal plus the simple interest accrued over `slots_elapsed`.
+    fn simple_total_due(
+        remaining_principal: u64,
+        slots_elapsed: u64,
+        interest_rate_bps: u64,
+    ) -> u64 {
+        let interest_accrued =
+            remaining_principal.clone() * (interest_rate_bps * slots_elapsed) / 10_000;
+        remaining_principal + interest_accrued
+    }
+
+    /// Remaining principal plus the interest accrued over `slots_elapsed`, compounded over
+    /// `periods` equal periods (at most `MAX_COMPOUNDING_PERIODS`).
+    fn compound_total_due(
+        remaining_principal: u64,
+        slots_elapsed: u64,
+        interest_rate_bps: u64,
+        periods: u64,
+    ) -> u64 {
+        let period_interest_rate_bps = interest_rate_bps * slots_elapsed / periods;
+
+        // The interest accrued over a period is owed interest on in the following periods.
+        let mut total_due = remaining_principal;
+        for period in 0..MAX_COMPOUNDING_PERIODS {
+            let interest_accrued = total_due.clone() * period_interest_rate_bps.clone() / 10_000;
+            total_due = if (period as u64) < periods {
+                total_due + interest_accrued
+            } else {
+                total_due
+            };
+        }
+
+        total_due
+    }
+
+    /// Settles a repayment of `asset_repay_ata` against the `total_due` of the loan, returning
+    /// the amounts repaid to the lender and to the reserve, the collateral released, the remaining
+    /// due, whether the slippage bound was exceeded, the split of the repayment between interest
+    /// and principal, the overpayment refunded to the borrower and the collateral released so far.
+    fn settle_repayment(
+        asset_repay_ata: &ConfidentialTokenAccount,
+        collateral_vault_ata: &ConfidentialTokenAccount,
+        remaining_principal: u64,
+        loan_collateral: u64,
+        total_due: u64,
+        min_collateral_out: u64,
+        reserve_factor_bps: u16,
+    ) -> (u64, u64, u64, u64, bool, u64, u64, u64, u64) {
+        // Confidential token account balances.
+        let repay_amount = asset_repay_ata.encrypted_balance();
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+
+        let actual_repay_amount = min(repay_amount.clone(), total_due.clone());
+        // A full repayment releases whatever is left in the vault, so that rounding down the
+        // partial releases doesn't strand collateral.
+        let collateral_repayment = if actual_repay_amount.clone() >= total_due.clone() {
+            locked_collateral.clone()
+        } else {
+            (actual_repay_amount.clone() / total_due.clone()) * locked_collateral.clone()
+        };
+
+        // Abort the repayment if it would release less collateral than the borrower expects.
+        let slippage_exceeded = collateral_repayment < min_collateral_out;
+        let actual_repay_amount = if slippage_exceeded {
+            0
+        } else {
+            actual_repay_amount
+        };
+        let collateral_repayment = if slippage_exceeded {
+            0
+        } else {
+            collateral_repayment
+        };
+
+        // Repayments pay off the interest first, the reserve takes reserve_factor_bps of it.
+        let interest_accrued = total_due.clone() - remaining_principal;
+        let interest_repaid = min(actual_repay_amount.clone(), interest_accrued);
+        let principal_repaid = actual_repay_amount.clone() - interest_repaid.clone();
+        let reserve_amount = interest_repaid.clone() * BasePoints(reserve_factor_bps);
+        let lender_amount = actual_repay_amount.clone() - reserve_amount.clone();
+
+        let remaining_due = total_due - actual_repay_amount.clone();
+
+        // Anything paid above total_due goes back to the borrower, an aborted repayment moves
+        // nothing.
+        let refund_amount = if slippage_exceeded {
+            0
+        } else {
+            repay_amount - actual_repay_amount
+        };
+
+        // Collateral released to the borrower so far, what the loan locked that the vault no
+        // longer holds.
+        let remaining_collateral = locked_collateral - collateral_repayment.clone();
+        let released_collateral =
+            loan_collateral.clone() - min(loan_collateral, remaining_collateral);
+
+        (
+            lender_amount,
+            reserve_amount,
+            collateral_repayment,
+            remaining_due,
+            slippage_exceeded,
+            interest_repaid,
+            principal_repaid,
+            refund_amount,
+            released_collateral,
+        )
+    }
+
+    #[instruction]
+    #[allow(clippy::too_many_arguments)]
+    pub fn repay(
+        mxe: Mxe,
+        asset_repay_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        asset_reserve_ata: ConfidentialTokenAccount,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        asset_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        loan_collateral: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+        reserve_factor_bps: u16,
+        min_collateral_out: u64,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+    ) {
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
+        let remaining_principal = remaining_principal.to_arcis();
+        // The pool's total debt without the loan, its remaining due is added back once repaid.
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let (
+            lender_amount,
+            reserve_amount,
+            collateral_repayment,
+            remaining_due,
+            slippage_exceeded,
+            interest_paid,
+            principal_paid,
+            refund_amount,
+            released_collateral,
+        ) = settle_repayment(
+            &asset_repay_ata,
+            &collateral_vault_ata,
+            remaining_principal.clone(),
+            loan_collateral.to_arcis(),
+            simple_total_due(remaining_principal, slots_elapsed, interest_rate_bps),
+            min_collateral_out,
+            reserve_factor_bps,
+        );
+        let loan_is_fully_repaid = remaining_due.eq(0);
+
+        // Transfer of the lender's part of the repayment to the lender.
+        let asset_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_lender_ata,
+            lender_amount,
+        );
+
+        // Transfer of the reserve's cut of the interest to the reserve vault.
+        let reserve_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_reserve_ata,
+            reserve_amount,
+        );
+
+        // Transfer of collateral_repayment to the borrower.
+        let collateral_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            collateral_repayment,
+        );
+
+        // Transfer of the overpayment back to the borrower.
+        let refund_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_borrower_ata,
+            refund_amount,
+        );
+
         (
             asset_transfer,
             collateral_transfer,
-            mxe.from_arcis(loan_amount),
+            reserve_transfer,
+            refund_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            loan_is_fully_repaid.reveal(),
+            slippage_exceeded.reveal(),
+            mxe.from_arcis(interest_paid),
+            mxe.from_arcis(principal_paid),
+            mxe.from_arcis(released_collateral),
+            mxe.from_arcis(other_loans_debt + remaining_due),
         )
     }
 
+    /// Same as `repay`, but the fully repaid flag stays encrypted as 1 (repaid) or 0.
     #[instruction]
     #[allow(clippy::too_many_arguments)]
-    pub fn repay(
+    pub fn repay_private(
         mxe: Mxe,
         asset_repay_ata: ConfidentialTokenAccount,
         asset_lender_ata: ConfidentialTokenAccount,
+        asset_reserve_ata: ConfidentialTokenAccount,
         collateral_vault_ata: ConfidentialTokenAccount,
         collateral_borrower_ata: ConfidentialTokenAccount,
+        asset_borrower_ata: ConfidentialTokenAccount,
         remaining_principal: Enc<Mxe, u64>,
+        loan_collateral: Enc<Mxe, u64>,
         slots_elapsed: u64,
-        interest_rate_bps: u16,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+        reserve_factor_bps: u16,
+        min_collateral_out: u64,
     ) -> (
         ConfidentialTransfer,
         ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
         Enc<Mxe, u64>,
         bool,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
     ) {
-        // Confidential token account balances.
-        let repay_amount = asset_repay_ata.encrypted_balance();
-        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
+        let remaining_principal = remaining_principal.to_arcis();
+        // The pool's total debt without the loan, its remaining due is added back once repaid.
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let (
+            lender_amount,
+            reserve_amount,
+            collateral_repayment,
+            remaining_due,
+            slippage_exceeded,
+            interest_paid,
+            principal_paid,
+            refund_amount,
+            released_collateral,
+        ) = settle_repayment(
+            &asset_repay_ata,
+            &collateral_vault_ata,
+            remaining_principal.clone(),
+            loan_collateral.to_arcis(),
+            simple_total_due(remaining_principal, slots_elapsed, interest_rate_bps),
+            min_collateral_out,
+            reserve_factor_bps,
+        );
+        let repayment_status = if remaining_due.eq(0) { 1 } else { 0 };
+
+        // Transfer of the lender's part of the repayment to the lender.
+        let asset_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_lender_ata,
+            lender_amount,
+        );
+
+        // Transfer of the reserve's cut of the interest to the reserve vault.
+        let reserve_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_reserve_ata,
+            reserve_amount,
+        );
+
+        // Transfer of collateral_repayment to the borrower.
+        let collateral_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            collateral_repayment,
+        );
+
+        // Transfer of the overpayment back to the borrower.
+        let refund_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_borrower_ata,
+            refund_amount,
+        );
 
+        (
+            asset_transfer,
+            collateral_transfer,
+            reserve_transfer,
+            refund_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            mxe.from_arcis(repayment_status),
+            slippage_exceeded.reveal(),
+            mxe.from_arcis(interest_paid),
+            mxe.from_arcis(principal_paid),
+            mxe.from_arcis(released_collateral),
+            mxe.from_arcis(other_loans_debt + remaining_due),
+        )
+    }
+
+    /// Same as `repay`, but the interest is compounded over `periods` periods.
+    #[instruction]
+    #[allow(clippy::too_many_arguments)]
+    pub fn repay_compound(
+        mxe: Mxe,
+        asset_repay_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        asset_reserve_ata: ConfidentialTokenAccount,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        asset_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        loan_collateral: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+        reserve_factor_bps: u16,
+        min_collateral_out: u64,
+        periods: u64,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+    ) {
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
         let remaining_principal = remaining_principal.to_arcis();
-        let interest_accrued =
-            remaining_principal.clone() * BasePoints(interest_rate_bps * slots_elapsed);
-        let total_due = remaining_principal + interest_accrued;
-        let actual_repay_amount = min(repay_amount, total_due.clone());
-        let remaining_due = total_due.clone() - actual_repay_amount.clone();
-        let collateral_repayment = (actual_repay_amount.clone() / total_due) * locked_collateral;
+        // The pool's total debt without the loan, its remaining due is added back once repaid.
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let (
+            lender_amount,
+            reserve_amount,
+            collateral_repayment,
+            remaining_due,
+            slippage_exceeded,
+            interest_paid,
+            principal_paid,
+            refund_amount,
+            released_collateral,
+        ) = settle_repayment(
+            &asset_repay_ata,
+            &collateral_vault_ata,
+            remaining_principal.clone(),
+            loan_collateral.to_arcis(),
+            compound_total_due(
+                remaining_principal,
+                slots_elapsed,
+                interest_rate_bps,
+                periods,
+            ),
+            min_collateral_out,
+            reserve_factor_bps,
+        );
         let loan_is_fully_repaid = remaining_due.eq(0);
 
-        // Transfer of actual_repay_amount to the lender.
+        // Transfer of the lender's part of the repayment to the lender.
         let asset_transfer = confidential_spl_token::transfer(
             &mxe,
             &asset_repay_ata,
             &asset_lender_ata,
-            actual_repay_amount,
+            lender_amount,
+        );
+
+        // Transfer of the reserve's cut of the interest to the reserve vault.
+        let reserve_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_reserve_ata,
+            reserve_amount,
         );
 
         // Transfer of collateral_repayment to the borrower.
@@ -93,11 +597,644 @@ pub mod encrypted_computations {
             collateral_repayment,
         );
 
+        // Transfer of the overpayment back to the borrower.
+        let refund_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_borrower_ata,
+            refund_amount,
+        );
+
         (
             asset_transfer,
             collateral_transfer,
-            mxe.from_arcis(remaining_due),
+            reserve_transfer,
+            refund_transfer,
+            mxe.from_arcis(remaining_due.clone()),
             loan_is_fully_repaid.reveal(),
+            slippage_exceeded.reveal(),
+            mxe.from_arcis(interest_paid),
+            mxe.from_arcis(principal_paid),
+            mxe.from_arcis(released_collateral),
+            mxe.from_arcis(other_loans_debt + remaining_due),
+        )
+    }
+
+    /// Same as `repay_private`, but the interest is compounded over `periods` periods.
+    #[instruction]
+    #[allow(clippy::too_many_arguments)]
+    pub fn repay_compound_private(
+        mxe: Mxe,
+        asset_repay_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        asset_reserve_ata: ConfidentialTokenAccount,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        asset_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        loan_collateral: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+        reserve_factor_bps: u16,
+        min_collateral_out: u64,
+        periods: u64,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+    ) {
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
+        let remaining_principal = remaining_principal.to_arcis();
+        // The pool's total debt without the loan, its remaining due is added back once repaid.
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let (
+            lender_amount,
+            reserve_amount,
+            collateral_repayment,
+            remaining_due,
+            slippage_exceeded,
+            interest_paid,
+            principal_paid,
+            refund_amount,
+            released_collateral,
+        ) = settle_repayment(
+            &asset_repay_ata,
+            &collateral_vault_ata,
+            remaining_principal.clone(),
+            loan_collateral.to_arcis(),
+            compound_total_due(
+                remaining_principal,
+                slots_elapsed,
+                interest_rate_bps,
+                periods,
+            ),
+            min_collateral_out,
+            reserve_factor_bps,
+        );
+        let repayment_status = if remaining_due.eq(0) { 1 } else { 0 };
+
+        // Transfer of the lender's part of the repayment to the lender.
+        let asset_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_lender_ata,
+            lender_amount,
+        );
+
+        // Transfer of the reserve's cut of the interest to the reserve vault.
+        let reserve_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_reserve_ata,
+            reserve_amount,
+        );
+
+        // Transfer of collateral_repayment to the borrower.
+        let collateral_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            collateral_repayment,
+        );
+
+        // Transfer of the overpayment back to the borrower.
+        let refund_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_repay_ata,
+            &asset_borrower_ata,
+            refund_amount,
+        );
+
+        (
+            asset_transfer,
+            collateral_transfer,
+            reserve_transfer,
+            refund_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            mxe.from_arcis(repayment_status),
+            slippage_exceeded.reveal(),
+            mxe.from_arcis(interest_paid),
+            mxe.from_arcis(principal_paid),
+            mxe.from_arcis(released_collateral),
+            mxe.from_arcis(other_loans_debt + remaining_due),
+        )
+    }
+
+    /// Withdraws `amount` of liquidity to the lender, nothing is withdrawn if the vault holds less.
+    #[instruction]
+    pub fn withdraw(
+        mxe: Mxe,
+        asset_vault_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        amount: u64,
+    ) -> ConfidentialTransfer {
+        let available_amount = asset_vault_ata.encrypted_balance();
+        let withdraw_amount = if available_amount < amount { 0 } else { amount };
+
+        confidential_spl_token::transfer(&mxe, &asset_vault_ata, &asset_lender_ata, withdraw_amount)
+    }
+
+    /// Withdraws `amount` of the reserve to the lender, clamped to what the reserve vault holds.
+    #[instruction]
+    pub fn withdraw_reserve(
+        mxe: Mxe,
+        reserve_vault_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        amount: u64,
+    ) -> ConfidentialTransfer {
+        let available_amount = reserve_vault_ata.encrypted_balance();
+        let withdraw_amount = if available_amount < amount {
+            available_amount
+        } else {
+            amount
+        };
+
+        confidential_spl_token::transfer(
+            &mxe,
+            &reserve_vault_ata,
+            &asset_lender_ata,
+            withdraw_amount,
+        )
+    }
+
+    /// Computes the loan's collateral to debt ratio in basis points, encrypted to the borrower so
+    /// that only they can read it.
+    #[instruction]
+    pub fn reveal_health(
+        borrower: Shared,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        price: u64,
+    ) -> Enc<Shared, u64> {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let remaining_principal = remaining_principal.to_arcis();
+
+        let collateral_value = locked_collateral * price;
+        let health_bps = if remaining_principal.eq(0) {
+            u64::MAX
+        } else {
+            collateral_value * 10_000 / remaining_principal
+        };
+
+        borrower.from_arcis(health_bps)
+    }
+
+    #[instruction]
+    pub fn health_factor(
+        mxe: Mxe,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        price: u64,
+        collateral_threshold_bps: u16,
+    ) -> (Enc<Mxe, u64>, bool) {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let remaining_principal = remaining_principal.to_arcis();
+
+        // health_factor = collateral_amount * price * collateral_threshold_bps / 10_000 / loan_amount
+        // The ratio saturates like the loan to value one in borrow.
+        let collateral_threshold_ratio = BasePoints(min(
+            price as u128 * collateral_threshold_bps as u128,
+            u64::MAX as u128,
+        ) as u64);
+        let collateral_value = locked_collateral * collateral_threshold_ratio;
+        let is_healthy = collateral_value.clone() >= remaining_principal.clone();
+        let health_factor_bps = if remaining_principal.eq(0) {
+            u64::MAX
+        } else {
+            collateral_value * 10_000 / remaining_principal
+        };
+
+        (mxe.from_arcis(health_factor_bps), is_healthy.reveal())
+    }
+
+    /// Adds the `amount` topped up to the loan's collateral, capped at the collateral actually held
+    /// by the collateral vault.
+    #[instruction]
+    pub fn top_up_collateral(
+        mxe: Mxe,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        encrypted_collateral: Enc<Mxe, u64>,
+        amount: u64,
+    ) -> Enc<Mxe, u64> {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let collateral = min(encrypted_collateral.to_arcis() + amount, locked_collateral);
+
+        mxe.from_arcis(collateral)
+    }
+
+    /// Deposits `amount` of the borrower's collateral into the collateral vault and adds it to the
+    /// loan's collateral, nothing is deposited if the borrower holds less.
+    #[instruction]
+    pub fn deposit_collateral(
+        mxe: Mxe,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        encrypted_collateral: Enc<Mxe, u64>,
+        amount: u64,
+    ) -> (ConfidentialTransfer, Enc<Mxe, u64>) {
+        let available_amount = collateral_borrower_ata.encrypted_balance();
+        let deposit_amount = if available_amount < amount { 0 } else { amount };
+
+        // Transfer of deposit_amount to the collateral vault.
+        let collateral_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_borrower_ata,
+            &collateral_vault_ata,
+            deposit_amount.clone(),
+        );
+
+        (
+            collateral_transfer,
+            mxe.from_arcis(encrypted_collateral.to_arcis() + deposit_amount),
+        )
+    }
+
+    /// Folds the simple interest accrued over `slots_elapsed` into the remaining principal.
+    #[instruction]
+    #[allow(clippy::too_many_arguments)]
+    pub fn accrue_interest(
+        mxe: Mxe,
+        remaining_principal: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+    ) -> (Enc<Mxe, u64>, Enc<Mxe, u64>) {
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
+        let remaining_principal = remaining_principal.to_arcis();
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let accrued_principal =
+            simple_total_due(remaining_principal, slots_elapsed, interest_rate_bps);
+
+        (
+            mxe.from_arcis(accrued_principal.clone()),
+            mxe.from_arcis(other_loans_debt + accrued_principal),
+        )
+    }
+
+    /// Same as `accrue_interest`, but the interest is compounded over `periods` periods.
+    #[instruction]
+    #[allow(clippy::too_many_arguments)]
+    pub fn accrue_interest_compound(
+        mxe: Mxe,
+        remaining_principal: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+        asset_vault_ata: ConfidentialTokenAccount,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+        periods: u64,
+    ) -> (Enc<Mxe, u64>, Enc<Mxe, u64>) {
+        let total_debt = total_debt.to_arcis();
+        let interest_rate_bps = utilization_rate_bps(
+            base_rate_bps,
+            slope1_bps,
+            slope2_bps,
+            kink_bps,
+            pool_utilization_bps(&asset_vault_ata, total_debt.clone(), total_debt_known),
+        );
+        let remaining_principal = remaining_principal.to_arcis();
+        let other_loans_debt =
+            other_loans_debt(total_debt, total_debt_known, remaining_principal.clone());
+        let accrued_principal = compound_total_due(
+            remaining_principal,
+            slots_elapsed,
+            interest_rate_bps,
+            periods,
+        );
+
+        (
+            mxe.from_arcis(accrued_principal.clone()),
+            mxe.from_arcis(other_loans_debt + accrued_principal),
+        )
+    }
+
+    #[instruction]
+    pub fn reveal_repayment_status(repayment_status: Enc<Mxe, u64>) -> bool {
+        repayment_status.to_arcis().eq(1).reveal()
+    }
+
+    /// Settles a matured loan regardless of its health: the lender seizes the collateral worth the
+    /// remaining principal, rounded up and capped at the locked collateral, and the residual goes
+    /// back to the borrower. Any principal the collateral doesn't cover is left due, and whether
+    /// the loan is settled in full is revealed.
+    #[instruction]
+    pub fn settle_matured_loan(
+        mxe: Mxe,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        collateral_lender_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        price: u64,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+    ) {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let remaining_principal = remaining_principal.to_arcis();
+        let other_loans_debt = other_loans_debt(
+            total_debt.to_arcis(),
+            total_debt_known,
+            remaining_principal.clone(),
+        );
+
+        // Rounded up, so a principal the price doesn't divide is settled in full while the locked
+        // collateral covers it, instead of leaving a remainder below the price due forever.
+        let debt_collateral_amount = (remaining_principal.clone() + (price - 1)) / price;
+        let seized_collateral = min(debt_collateral_amount, locked_collateral.clone());
+        let residual_collateral = locked_collateral - seized_collateral.clone();
+        let seized_value = seized_collateral.clone() * price;
+        let remaining_due = remaining_principal.clone() - min(seized_value, remaining_principal);
+        let is_settled = remaining_due.eq(0);
+
+        // Transfer of seized_collateral to the lender.
+        let seize_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_lender_ata,
+            seized_collateral,
+        );
+
+        // Transfer of residual_collateral to the borrower.
+        let residual_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            residual_collateral,
+        );
+
+        (
+            seize_transfer,
+            residual_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            is_settled.reveal(),
+            mxe.from_arcis(other_loans_debt + remaining_due),
+        )
+    }
+
+    #[instruction]
+    pub fn liquidate(
+        mxe: Mxe,
+        asset_liquidator_ata: ConfidentialTokenAccount,
+        asset_lender_ata: ConfidentialTokenAccount,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        collateral_liquidator_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        price: u64,
+        collateral_threshold_bps: u16,
+        liquidation_bonus_bps: u16,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+    ) {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let remaining_principal = remaining_principal.to_arcis();
+        let other_loans_debt = other_loans_debt(
+            total_debt.to_arcis(),
+            total_debt_known,
+            remaining_principal.clone(),
+        );
+
+        // health_factor < 1 <=> collateral_amount * price * collateral_threshold_bps / 10_000 < loan_amount
+        let collateral_threshold_ratio = BasePoints(min(
+            price as u128 * collateral_threshold_bps as u128,
+            u64::MAX as u128,
+        ) as u64);
+        let collateral_value = locked_collateral.clone() * collateral_threshold_ratio;
+        let is_unhealthy = collateral_value < remaining_principal.clone();
+
+        // The liquidator must hold the remaining_principal it repays.
+        let is_covered = asset_liquidator_ata.encrypted_balance() >= remaining_principal.clone();
+        let is_liquidatable = if is_unhealthy { is_covered } else { false };
+
+        // Seize the collateral worth the outstanding principal plus the liquidation bonus, capped at
+        // the locked collateral, the residual goes back to the borrower.
+        let debt_collateral_amount = remaining_principal.clone() / price;
+        let seized_collateral = if is_liquidatable {
+            min(
+                debt_collateral_amount * BasePoints(10_000 + liquidation_bonus_bps),
+                locked_collateral.clone(),
+            )
+        } else {
+            0
+        };
+        let residual_collateral = if is_liquidatable {
+            locked_collateral - seized_collateral.clone()
+        } else {
+            0
+        };
+        let repaid_principal = if is_liquidatable {
+            remaining_principal.clone()
+        } else {
+            0
+        };
+        let remaining_due = if is_liquidatable {
+            0
+        } else {
+            remaining_principal
+        };
+
+        // Transfer of repaid_principal from the liquidator to the lender.
+        let repay_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &asset_liquidator_ata,
+            &asset_lender_ata,
+            repaid_principal,
+        );
+
+        // Transfer of seized_collateral to the liquidator.
+        let seize_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_liquidator_ata,
+            seized_collateral,
+        );
+
+        // Transfer of residual_collateral to the borrower.
+        let residual_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            residual_collateral,
+        );
+
+        (
+            repay_transfer,
+            seize_transfer,
+            residual_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            is_liquidatable.reveal(),
+            mxe.from_arcis(other_loans_debt + remaining_due),
+        )
+    }
+
+    #[instruction]
+    pub fn liquidate_by_lender(
+        mxe: Mxe,
+        collateral_vault_ata: ConfidentialTokenAccount,
+        liquidation_destination_ata: ConfidentialTokenAccount,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        remaining_principal: Enc<Mxe, u64>,
+        price: u64,
+        collateral_threshold_bps: u16,
+        total_debt: Enc<Mxe, u64>,
+        total_debt_known: bool,
+    ) -> (
+        ConfidentialTransfer,
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+    ) {
+        let locked_collateral = collateral_vault_ata.encrypted_balance();
+        let remaining_principal = remaining_principal.to_arcis();
+        let other_loans_debt = other_loans_debt(
+            total_debt.to_arcis(),
+            total_debt_known,
+            remaining_principal.clone(),
+        );
+
+        // health_factor < 1 <=> collateral_amount * price * collateral_threshold_bps / 10_000 < loan_amount
+        let collateral_threshold_ratio = BasePoints(min(
+            price as u128 * collateral_threshold_bps as u128,
+            u64::MAX as u128,
+        ) as u64);
+        let collateral_value = locked_collateral.clone() * collateral_threshold_ratio;
+        let is_liquidatable = collateral_value < remaining_principal.clone();
+
+        // Seize the collateral worth the outstanding principal, the residual goes back to the borrower.
+        let debt_collateral_amount = min(
+            remaining_principal.clone() / price,
+            locked_collateral.clone(),
+        );
+        let seized_collateral = if is_liquidatable {
+            debt_collateral_amount
+        } else {
+            0
+        };
+        let residual_collateral = if is_liquidatable {
+            locked_collateral - seized_collateral.clone()
+        } else {
+            0
+        };
+        let remaining_due = if is_liquidatable {
+            0
+        } else {
+            remaining_principal
+        };
+
+        // Transfer of seized_collateral to the liquidation proceeds destination.
+        let seize_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &liquidation_destination_ata,
+            seized_collateral,
+        );
+
+        // Transfer of residual_collateral to the borrower.
+        let residual_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &collateral_vault_ata,
+            &collateral_borrower_ata,
+            residual_collateral,
+        );
+
+        (
+            seize_transfer,
+            residual_transfer,
+            mxe.from_arcis(remaining_due.clone()),
+            is_liquidatable.reveal(),
+            mxe.from_arcis(other_loans_debt + remaining_due),
         )
     }
+
+    /// Sums the outstanding principal of the active loans, once for the MXE and once encrypted to
+    /// the lender so that they can read it.
+    #[instruction]
+    pub fn total_debt(
+        mxe: Mxe,
+        lender: Shared,
+        principals: [Enc<Mxe, u64>; MAX_BORROWERS],
+        num_loans: u16,
+    ) -> (Enc<Mxe, u64>, Enc<Shared, u64>) {
+        // Sum the outstanding principal of every active loan, unused slots are ignored.
+        let mut total_debt = 0;
+        for (i, principal) in principals.iter().enumerate() {
+            total_debt += if (i as u16) < num_loans {
+                principal.to_arcis()
+            } else {
+                0
+            };
+        }
+
+        (mxe.from_arcis(total_debt), lender.from_arcis(total_debt))
+    }
+
+    /// Quotes the most the borrower could borrow against the collateral held in
+    /// `collateral_borrower_ata` at `price` and `loan_to_value_bps`, without moving any funds.
+    #[instruction]
+    pub fn preview_borrow(
+        mxe: Mxe,
+        collateral_borrower_ata: ConfidentialTokenAccount,
+        price: u64,
+        loan_to_value_bps: u16,
+    ) -> Enc<Mxe, u64> {
+        let collateral_amount = collateral_borrower_ata.encrypted_balance();
+
+        // Same ratio as `borrow`, saturating at u64::MAX base points.
+        let loan_to_value_bps_ratio =
+            BasePoints(min(price as u128 * loan_to_value_bps as u128, u64::MAX as u128) as u64);
+        let max_loan_amount = collateral_amount * loan_to_value_bps_ratio;
+
+        mxe.from_arcis(max_loan_amount)
+    }
 }
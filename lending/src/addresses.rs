@@ -0,0 +1,72 @@
+//! Addresses of the accounts used by the lending program, derived from the lender, borrower and
+//! pool mints so clients can compute every account an instruction needs.
+
+use confidential_spl_token::get_associated_confidential_token_account_address;
+use solana_program::pubkey::Pubkey;
+
+pub fn lending_pool_pda(lender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"lending_pool", lender.as_ref()], &crate::ID)
+}
+
+pub fn loan_pda(lender: &Pubkey, borrower: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"loan", lender.as_ref(), borrower.as_ref()], &crate::ID)
+}
+
+pub fn price_oracle_pda(lender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"price_oracle", lender.as_ref()], &crate::ID)
+}
+
+pub fn borrow_quote_pda(lender: &Pubkey, borrower: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"borrow_quote", lender.as_ref(), borrower.as_ref()],
+        &crate::ID,
+    )
+}
+
+pub fn reserve_pda(lender: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"reserve", lender.as_ref()], &crate::ID)
+}
+
+/// Token account of the lending pool holding the liquidity that can be borrowed.
+pub fn asset_vault_ata(lender: &Pubkey, asset_mint: &Pubkey) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        &lending_pool_pda(lender).0,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    )
+}
+
+/// Token account of the loan holding the borrower's collateral.
+pub fn collateral_vault_ata(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    collateral_mint: &Pubkey,
+) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        &loan_pda(lender, borrower).0,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    )
+}
+
+/// Token account of the loan the borrower funds before repaying.
+pub fn asset_repay_ata(lender: &Pubkey, borrower: &Pubkey, asset_mint: &Pubkey) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        &loan_pda(lender, borrower).0,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    )
+}
+
+/// Token account held by the program accumulating the reserve's cut of the interest.
+pub fn reserve_vault_ata(lender: &Pubkey, asset_mint: &Pubkey) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        &reserve_pda(lender).0,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    )
+}
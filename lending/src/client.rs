@@ -0,0 +1,256 @@
+//! Helpers for clients to read lending accounts and predict the outcome of lending instructions
+//! before submitting them.
+
+use solana_program::program_error::ProgramError;
+
+use crate::state::{LendingPool, Loan};
+
+/// Decodes the data of a loan account, rejecting accounts of any other type.
+pub fn load_loan(data: &[u8]) -> Result<Loan, ProgramError> {
+    Loan::unpack(data)
+}
+
+/// Decodes the data of a lending pool account, rejecting accounts of any other type.
+pub fn load_lending_pool(data: &[u8]) -> Result<LendingPool, ProgramError> {
+    LendingPool::unpack(data)
+}
+
+/// Amounts moved by a `borrow`, as computed by the borrow circuit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BorrowOutcome {
+    /// Largest loan the collateral allows for.
+    pub max_loan_amount: u64,
+    /// Asset amount lent, capped by the pool's liquidity and `max_borrow`, 0 unless granted.
+    pub loan_amount: u64,
+    /// Collateral kept in the collateral vault to back `loan_amount`.
+    pub loan_collateral_amount: u64,
+    /// Collateral transferred back to the borrower.
+    pub collateral_excess_amount: u64,
+    /// Part of `loan_amount` paid to the lender instead of the borrower.
+    pub origination_fee: u64,
+    /// Whether the loan is granted, otherwise no funds are moved and the loan stays inactive.
+    pub loan_is_granted: bool,
+}
+
+/// Simulates a borrow of `asset_liquidity` against `collateral` at the given `price` under the
+/// `lending_pool`'s parameters, mirroring the base point arithmetic of the borrow circuit.
+///
+/// Like the circuit, the ratio of price and `loan_to_value_bps` saturates at `u64::MAX` base
+/// points, so prices above `u64::MAX / loan_to_value_bps` lend as much as a price of
+/// `u64::MAX / loan_to_value_bps`. A loan below `min_borrow`, or backed by collateral worth less
+/// than `min_collateral_ratio_bps` of it, isn't granted.
+pub fn simulate_borrow(
+    collateral: u64,
+    asset_liquidity: u64,
+    price: u64,
+    lending_pool: &LendingPool,
+) -> BorrowOutcome {
+    let ratio_bps = (price as u128 * lending_pool.loan_to_value_bps as u128).min(u64::MAX as u128);
+    let max_loan_amount = (collateral as u128 * ratio_bps / 10_000).min(u64::MAX as u128) as u64;
+    let loan_amount = max_loan_amount
+        .min(asset_liquidity)
+        .min(lending_pool.max_borrow);
+    let backing_collateral = |loan_amount: u64| {
+        if ratio_bps == 0 {
+            0
+        } else {
+            ((loan_amount as u128 * 10_000 / ratio_bps) as u64).min(collateral)
+        }
+    };
+
+    let borrow_rejected = (backing_collateral(loan_amount) as u128 * price as u128)
+        < loan_amount as u128 * lending_pool.min_collateral_ratio_bps as u128 / 10_000;
+    let loan_is_granted = !borrow_rejected && loan_amount >= lending_pool.min_borrow;
+    if !loan_is_granted {
+        return BorrowOutcome {
+            max_loan_amount,
+            ..Default::default()
+        };
+    }
+
+    let loan_collateral_amount = backing_collateral(loan_amount);
+    BorrowOutcome {
+        max_loan_amount,
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount: collateral - loan_collateral_amount,
+        origination_fee: (loan_amount as u128 * lending_pool.origination_fee_bps as u128 / 10_000)
+            as u64,
+        loan_is_granted,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use confidential_spl_token::confidential_transfer_adapter::state::RescueCiphertext;
+    use solana_program::pubkey::Pubkey;
+
+    use super::*;
+
+    #[test]
+    fn test_load_loan() {
+        let (borrower, lending_pool) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let data = Loan::new(&borrower, &lending_pool, false, 0)
+            .pack()
+            .unwrap();
+
+        let loan = load_loan(&data).unwrap();
+        assert_eq!(borrower.to_bytes(), loan.borrower);
+        assert!(!loan.is_active());
+        assert!(loan.encrypted_principal() == RescueCiphertext::default());
+        assert!(loan.encrypted_collateral() == RescueCiphertext::default());
+
+        // A loan can't be loaded as a lending pool.
+        assert!(load_lending_pool(&data).is_err());
+    }
+
+    /// Pool lending at `loan_to_value_bps` without any other borrow limit.
+    fn lending_pool(loan_to_value_bps: u16) -> LendingPool {
+        LendingPool {
+            loan_to_value_bps,
+            max_borrow: u64::MAX,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_simulate_borrow_collateral_bound() {
+        // 50% loan to value: half the collateral value can be borrowed.
+        assert_eq!(
+            BorrowOutcome {
+                max_loan_amount: 50,
+                loan_amount: 50,
+                loan_collateral_amount: 100,
+                collateral_excess_amount: 0,
+                origination_fee: 0,
+                loan_is_granted: true,
+            },
+            simulate_borrow(100, 1000, 1, &lending_pool(5_000))
+        );
+    }
+
+    #[test]
+    fn test_simulate_borrow_liquidity_bound() {
+        // Only 20 assets are available, the collateral backing them is kept and the rest returned.
+        assert_eq!(
+            BorrowOutcome {
+                max_loan_amount: 100,
+                loan_amount: 20,
+                loan_collateral_amount: 10,
+                collateral_excess_amount: 40,
+                origination_fee: 0,
+                loan_is_granted: true,
+            },
+            simulate_borrow(50, 20, 2, &lending_pool(10_000))
+        );
+    }
+
+    #[test]
+    fn test_simulate_borrow_max_borrow() {
+        // The loan is capped by max_borrow like by the liquidity.
+        let lending_pool = LendingPool {
+            max_borrow: 30,
+            ..lending_pool(10_000)
+        };
+        let outcome = simulate_borrow(100, 1000, 1, &lending_pool);
+        assert_eq!(100, outcome.max_loan_amount);
+        assert_eq!(30, outcome.loan_amount);
+        assert_eq!(30, outcome.loan_collateral_amount);
+        assert_eq!(70, outcome.collateral_excess_amount);
+    }
+
+    #[test]
+    fn test_simulate_borrow_min_borrow() {
+        // Nothing is lent below min_borrow, the collateral stays in the vault.
+        let lending_pool = LendingPool {
+            min_borrow: 51,
+            ..lending_pool(5_000)
+        };
+        assert_eq!(
+            BorrowOutcome {
+                max_loan_amount: 50,
+                ..Default::default()
+            },
+            simulate_borrow(100, 1000, 1, &lending_pool)
+        );
+        assert!(simulate_borrow(102, 1000, 1, &lending_pool).loan_is_granted);
+    }
+
+    #[test]
+    fn test_simulate_borrow_origination_fee() {
+        // The fee is taken out of the loan amount, rounding down.
+        let lending_pool = LendingPool {
+            origination_fee_bps: 250,
+            ..lending_pool(10_000)
+        };
+        let outcome = simulate_borrow(100, 1000, 1, &lending_pool);
+        assert_eq!(100, outcome.loan_amount);
+        assert_eq!(2, outcome.origination_fee);
+    }
+
+    #[test]
+    fn test_simulate_borrow_min_collateral_ratio() {
+        // The rounded loan of 10 is backed by 30 collateral, worth exactly 300% of it.
+        let pool = |min_collateral_ratio_bps| LendingPool {
+            min_collateral_ratio_bps,
+            ..lending_pool(3_333)
+        };
+        assert!(simulate_borrow(33, 1000, 1, &pool(30_000)).loan_is_granted);
+        assert_eq!(
+            BorrowOutcome {
+                max_loan_amount: 10,
+                ..Default::default()
+            },
+            simulate_borrow(33, 1000, 1, &pool(31_000))
+        );
+    }
+
+    #[test]
+    fn test_simulate_borrow_rounding() {
+        // max_loan_amount rounds down, loan_collateral_amount is derived from the rounded loan.
+        let outcome = simulate_borrow(33, 1000, 1, &lending_pool(3_333));
+        assert_eq!(10, outcome.max_loan_amount);
+        assert_eq!(10, outcome.loan_amount);
+        assert_eq!(30, outcome.loan_collateral_amount);
+        assert_eq!(3, outcome.collateral_excess_amount);
+    }
+
+    #[test]
+    fn test_simulate_borrow_extreme_price() {
+        // The collateral kept never exceeds the deposit, whatever the price.
+        for price in [u64::MAX / 10_000, u64::MAX] {
+            let outcome = simulate_borrow(100, 1000, price, &lending_pool(10_000));
+            assert!(outcome.loan_collateral_amount <= 100);
+            assert_eq!(
+                100,
+                outcome.loan_collateral_amount + outcome.collateral_excess_amount
+            );
+        }
+    }
+
+    #[test]
+    fn test_simulate_borrow_saturated_ratio() {
+        // Past u64::MAX / loan_to_value_bps the ratio saturates instead of wrapping around.
+        let max_price = u64::MAX / 5_000;
+        assert_eq!(
+            simulate_borrow(1, 1000, max_price, &lending_pool(5_000)),
+            simulate_borrow(1, 1000, max_price + 1, &lending_pool(5_000))
+        );
+        assert_eq!(
+            1000,
+            simulate_borrow(1, 1000, u64::MAX, &lending_pool(5_000)).loan_amount
+        );
+    }
+
+    #[test]
+    fn test_simulate_borrow_zero_loan_to_value() {
+        assert_eq!(
+            BorrowOutcome {
+                collateral_excess_amount: 100,
+                loan_is_granted: true,
+                ..Default::default()
+            },
+            simulate_borrow(100, 1000, 1, &lending_pool(0))
+        );
+    }
+}
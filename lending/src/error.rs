@@ -0,0 +1,61 @@
+use solana_program::program_error::ProgramError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LendingError {
+    /// The borrower is not on the lending pool's allowlist.
+    NotAllowlisted,
+    /// The lending pool's allowlist has no free slots left.
+    AllowlistFull,
+    /// The token account does not belong to the expected mint.
+    MintMismatch,
+    /// The loan has no outstanding principal.
+    LoanInactive,
+    /// A collateral vault already exists at the loan's derived address.
+    CollateralVaultNotEmpty,
+    /// The loan's repayment status is already revealed by repayments.
+    RepaymentStatusPublic,
+    /// The loan account has not been created by `initialize_loan`.
+    LoanNotInitialized,
+    /// The lending pool reached its maximum number of active loans.
+    PoolAtCapacity,
+    /// The lending pool account is not the lender's lending pool.
+    PoolMismatch,
+    /// The loan account is not derived from the lender and the borrower.
+    LoanPdaMismatch,
+    /// The vault token account is not the associated token account of its pool or loan.
+    VaultAtaMismatch,
+    /// The lending pool has no free borrower slots left.
+    TooManyBorrowers,
+    /// The loan still has an outstanding principal.
+    LoanActive,
+    /// The lending pool has borrowers, its parameters can only be updated by force.
+    PoolHasBorrowers,
+    /// The account data doesn't start with the discriminator of the expected account type.
+    DiscriminatorMismatch,
+    /// The loan has not been unhealthy for the lending pool's liquidation grace period.
+    LiquidationGracePeriod,
+    /// The transfer id is not the loan's next transfer id.
+    TransferIdMismatch,
+    /// The loan has no maturity or hasn't reached its maturity slot yet.
+    LoanNotMatured,
+    /// The loan is already borrowed against, it has to be repaid before borrowing again.
+    LoanAlreadyActive,
+    /// The computation's transfers didn't succeed, no funds were moved.
+    TransferFailed,
+    /// The lending pool's reserve vault is not held by the program, so it can't be withdrawn from.
+    ReserveVaultMismatch,
+    /// The borrower's asset or collateral token account doesn't exist at its derived address.
+    BorrowerAtaMissing,
+    /// The multisig threshold is 0 or above its number of signers, or a signer is repeated.
+    InvalidMultisig,
+    /// Fewer than the lending pool's multisig threshold of its signers signed.
+    MultisigThresholdNotMet,
+    /// The loan has not been repaid since it was borrowed against, it has no repayment status yet.
+    RepaymentStatusUnknown,
+}
+
+impl From<LendingError> for ProgramError {
+    fn from(e: LendingError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
@@ -0,0 +1,49 @@
+//! Events logged with `sol_log_data` so indexers can follow the lifecycle of loans from the
+//! transaction logs. Events never carry encrypted amounts, only public keys and revealed flags.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{entrypoint::ProgramResult, log::sol_log_data};
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LendingEvent {
+    /// The borrower drew a loan from the lender's pool.
+    LoanOpened {
+        borrower: [u8; 32],
+        lender: [u8; 32],
+    },
+    /// The borrower repaid (part of) their loan, `fully_repaid` is `None` if the loan's repayment
+    /// status is kept private.
+    LoanRepaid {
+        borrower: [u8; 32],
+        lender: [u8; 32],
+        fully_repaid: Option<bool>,
+    },
+}
+
+impl LendingEvent {
+    /// Logs the Borsh serialized event.
+    pub fn emit(&self) -> ProgramResult {
+        sol_log_data(&[&self.try_to_vec()?]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loan_repaid_roundtrip() {
+        let event = LendingEvent::LoanRepaid {
+            borrower: [1; 32],
+            lender: [2; 32],
+            fully_repaid: Some(true),
+        };
+        let data = event.try_to_vec().unwrap();
+
+        // Variant index, both keys and the optional flag, nothing else.
+        assert_eq!(1 + 32 + 32 + 2, data.len());
+        assert_eq!(event, LendingEvent::try_from_slice(&data).unwrap());
+    }
+}
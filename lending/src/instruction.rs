@@ -1,14 +1,28 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use confidential_spl_token::{
-    confidential_spl_token_authority::derive_authority, get_arcium_processor_accounts,
+    confidential_spl_token_authority::derive_authority,
+    confidential_transfer_adapter::state::RescueCiphertext, get_arcium_processor_accounts,
     get_associated_confidential_token_account_address, get_associated_token_address_and_adapter,
     get_create_account_proof_context_state_address, get_key_registry_address,
     get_transfer_account_address, programs::system_program,
 };
+use solana_compute_budget_interface::ComputeBudgetInstruction;
 use solana_instruction::{AccountMeta, Instruction};
 use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
-use crate::processor::{lending_pool_pda, loan_pda};
+use crate::addresses::{
+    borrow_quote_pda, lending_pool_pda, loan_pda, price_oracle_pda, reserve_pda,
+};
+
+/// Compute units requested by `initialize_loan_with_budget`, creating the loan and its two vault
+/// token accounts doesn't fit in the default limit.
+pub const INITIALIZE_LOAN_COMPUTE_UNITS: u32 = 400_000;
+/// Compute units requested by `borrow_with_budget`, enough to queue the borrow computation with
+/// its three transfers.
+pub const BORROW_COMPUTE_UNITS: u32 = 200_000;
+/// Compute units requested by `repay_with_budget`, enough to queue the repay computation with its
+/// four transfers.
+pub const REPAY_COMPUTE_UNITS: u32 = 250_000;
 
 #[repr(u8)]
 #[derive(BorshDeserialize, BorshSerialize)]
@@ -17,21 +31,191 @@ pub enum LendingInstruction {
         interest_rate_bps: u16,
         loan_to_value_bps: u16,
         collateral_threshold_bps: u16,
+        max_active_loans: u16,
+        min_borrow: u64,
+        max_borrow: u64,
+        multisig_threshold: u8,
+        multisig_signers: Vec<[u8; 32]>,
     },
 
-    InitializeLoan,
+    InitializeLoan {
+        reveal_repayment_status: bool,
+        maturity_slot: u64,
+    },
 
     Borrow {
         computation_offset: u32,
         transfer_id: u32,
     },
-    BorrowCallback,
+    BorrowCallback {
+        queued_total_debt_version: u64,
+    },
 
     Repay {
         computation_offset: u32,
         transfer_id: u32,
+        min_collateral_out: u64,
+    },
+    RepayCallback {
+        queued_total_debt_version: u64,
+    },
+
+    AddAllowedBorrower {
+        borrower: [u8; 32],
+    },
+
+    SetLiquidationBonus {
+        liquidation_bonus_bps: u16,
+    },
+
+    Liquidate {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    LiquidateCallback {
+        queued_total_debt_version: u64,
+    },
+
+    RevealRepaymentStatus {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    RevealRepaymentStatusCallback,
+
+    SetOriginationFee {
+        origination_fee_bps: u16,
+    },
+
+    RevealHealth {
+        computation_offset: u32,
+        transfer_id: u32,
+        encryption_pubkey: [u8; 32],
+        nonce: u128,
+    },
+    RevealHealthCallback,
+
+    SetPrice {
+        price: u64,
+    },
+
+    CloseLoan,
+
+    WithdrawLiquidity {
+        computation_offset: u32,
+        transfer_id: u32,
+        amount: u64,
+    },
+    WithdrawLiquidityCallback,
+
+    SetCompoundInterest {
+        compound: bool,
+    },
+
+    UpdatePoolParams {
+        interest_rate_bps: u16,
+        loan_to_value_bps: u16,
+        force: bool,
+    },
+
+    ComputeHealthFactor {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    ComputeHealthFactorCallback {
+        queued_slot: u64,
+    },
+
+    SetLiquidationGraceSlots {
+        liquidation_grace_slots: u64,
+    },
+
+    SetReserve {
+        reserve_factor_bps: u16,
+    },
+
+    TopUpCollateral {
+        computation_offset: u32,
+        transfer_id: u32,
+        amount: u64,
+    },
+    TopUpCollateralCallback,
+
+    AccrueInterest {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    AccrueInterestCallback {
+        queued_principal: RescueCiphertext,
+        queued_slot: u64,
+        queued_total_debt_version: u64,
+    },
+
+    SettleMaturedLoan {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    SettleMaturedLoanCallback {
+        queued_total_debt_version: u64,
+    },
+
+    SetLenderApproval {
+        require_lender_approval: bool,
+    },
+
+    ComputeTotalDebt {
+        computation_offset: u32,
+        transfer_id: u32,
+        encryption_pubkey: [u8; 32],
+        nonce: u128,
+    },
+    ComputeTotalDebtCallback {
+        queued_total_debt_version: u64,
+    },
+
+    SetMinCollateralRatio {
+        min_collateral_ratio_bps: u16,
+    },
+
+    SetRateCurve {
+        base_rate_bps: u16,
+        slope1_bps: u16,
+        slope2_bps: u16,
+        kink_bps: u16,
+    },
+
+    ReinitializeLoan {
+        maturity_slot: u64,
+    },
+
+    InitializeReserveVault,
+
+    WithdrawReserve {
+        computation_offset: u32,
+        transfer_id: u32,
+        amount: u64,
+    },
+    WithdrawReserveCallback,
+
+    SetCollateralThreshold {
+        collateral_threshold_bps: u16,
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+
+    PreviewBorrow {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    PreviewBorrowCallback,
+
+    SetLiquidationProceedsDestination,
+
+    DepositCollateral {
+        computation_offset: u32,
+        transfer_id: u32,
+        amount: u64,
     },
-    RepayCallback,
+    DepositCollateralCallback,
 }
 
 pub fn initialize_lending_pool(
@@ -41,6 +225,39 @@ pub fn initialize_lending_pool(
     interest_rate_bps: u16,
     loan_to_value_bps: u16,
     collateral_threshold_bps: u16,
+    max_active_loans: u16,
+    min_borrow: u64,
+    max_borrow: u64,
+) -> Result<Instruction, ProgramError> {
+    initialize_lending_pool_with_multisig(
+        lender,
+        asset_mint,
+        collateral_mint,
+        interest_rate_bps,
+        loan_to_value_bps,
+        collateral_threshold_bps,
+        max_active_loans,
+        min_borrow,
+        max_borrow,
+        0,
+        &[],
+    )
+}
+
+/// `initialize_lending_pool` for a pool whose withdrawals and settings changes also need
+/// `multisig_threshold` of the `multisig_signers` to sign, see `with_multisig_signers`.
+pub fn initialize_lending_pool_with_multisig(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    collateral_threshold_bps: u16,
+    max_active_loans: u16,
+    min_borrow: u64,
+    max_borrow: u64,
+    multisig_threshold: u8,
+    multisig_signers: &[Pubkey],
 ) -> Result<Instruction, ProgramError> {
     let (lending_pool_pda, _) = lending_pool_pda(lender);
     let derived_lending_pool_authority = derive_authority(&lending_pool_pda).0;
@@ -86,6 +303,14 @@ pub fn initialize_lending_pool(
         interest_rate_bps,
         loan_to_value_bps,
         collateral_threshold_bps,
+        max_active_loans,
+        min_borrow,
+        max_borrow,
+        multisig_threshold,
+        multisig_signers: multisig_signers
+            .iter()
+            .map(|signer| signer.to_bytes())
+            .collect(),
     }
     .try_to_vec()?;
 
@@ -96,11 +321,30 @@ pub fn initialize_lending_pool(
     })
 }
 
+/// Appends `multisig_signers` as signers of `instruction`, as the lender's instructions withdrawing
+/// funds or changing the pool's settings require for pools initialized with a multisig.
+pub fn with_multisig_signers(
+    mut instruction: Instruction,
+    multisig_signers: &[Pubkey],
+) -> Instruction {
+    instruction.accounts.extend(
+        multisig_signers
+            .iter()
+            .map(|signer| AccountMeta::new_readonly(*signer, true)),
+    );
+
+    instruction
+}
+
+/// Initializes a loan for `borrower`, if `reveal_repayment_status` is false repayments keep whether
+/// the loan has been fully repaid encrypted until the lender reveals it.
 pub fn initialize_loan(
     lender: &Pubkey,
     borrower: &Pubkey,
     asset_mint: &Pubkey,
     collateral_mint: &Pubkey,
+    reveal_repayment_status: bool,
+    maturity_slot: u64,
 ) -> Result<Instruction, ProgramError> {
     let lending_pool_pda = lending_pool_pda(lender).0;
     let loan_pda = loan_pda(lender, borrower).0;
@@ -163,7 +407,11 @@ pub fn initialize_loan(
             false,
         ),
     ];
-    let data = LendingInstruction::InitializeLoan {}.try_to_vec()?;
+    let data = LendingInstruction::InitializeLoan {
+        reveal_repayment_status,
+        maturity_slot,
+    }
+    .try_to_vec()?;
 
     Ok(Instruction {
         program_id: crate::ID,
@@ -172,11 +420,59 @@ pub fn initialize_loan(
     })
 }
 
+/// `initialize_loan` co-signed by the lender, as required by pools with `require_lender_approval`.
+pub fn initialize_loan_with_lender_approval(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    reveal_repayment_status: bool,
+    maturity_slot: u64,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = initialize_loan(
+        lender,
+        borrower,
+        asset_mint,
+        collateral_mint,
+        reveal_repayment_status,
+        maturity_slot,
+    )?;
+    instruction.accounts[1].is_signer = true;
+
+    Ok(instruction)
+}
+
+/// `initialize_loan` preceded by the compute budget instructions it needs, at a price of
+/// `compute_unit_price` micro-lamports per compute unit.
+pub fn initialize_loan_with_budget(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    reveal_repayment_status: bool,
+    maturity_slot: u64,
+    compute_unit_price: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(with_compute_budget(
+        initialize_loan(
+            lender,
+            borrower,
+            asset_mint,
+            collateral_mint,
+            reveal_repayment_status,
+            maturity_slot,
+        )?,
+        INITIALIZE_LOAN_COMPUTE_UNITS,
+        compute_unit_price,
+    ))
+}
+
 pub fn borrow(
     lender: &Pubkey,
     borrower: &Pubkey,
     asset_mint: &Pubkey,
     collateral_mint: &Pubkey,
+    price_oracle: &Pubkey,
     computation_offset: u32,
     transfer_id: u32,
 ) -> Result<Instruction, ProgramError> {
@@ -214,8 +510,19 @@ pub fn borrow(
         false,
     );
 
-    let transfer_account =
-        get_transfer_account_address(&[asset_vault_ata, collateral_vault_ata], transfer_id);
+    // Lender asset ATA.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    // One source per transfer, in the order of the borrow computation's transfers.
+    let transfer_account = get_transfer_account_address(
+        &[asset_vault_ata, collateral_vault_ata, asset_vault_ata],
+        transfer_id,
+    );
     let [mxe_account, computation_account] =
         get_arcium_processor_accounts(&crate::ID, computation_offset);
 
@@ -228,6 +535,7 @@ pub fn borrow(
         AccountMeta::new(derived_loan_authority, false),
         AccountMeta::new_readonly(*asset_mint, false),
         AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(*price_oracle, false),
         // Source for asset transfer.
         AccountMeta::new(asset_vault_ata, false),
         AccountMeta::new(asset_vault_ata_adapter, false),
@@ -238,6 +546,8 @@ pub fn borrow(
         AccountMeta::new_readonly(asset_borrower_ata, false),
         // Destination for excess collateral transfer.
         AccountMeta::new_readonly(collateral_borrower_ata, false),
+        // Destination for origination fee transfer.
+        AccountMeta::new_readonly(asset_lender_ata, false),
         AccountMeta::new(transfer_account, false),
         AccountMeta::new(mxe_account, false),
         AccountMeta::new(computation_account, false),
@@ -269,14 +579,45 @@ pub fn borrow(
     })
 }
 
+/// `borrow` preceded by the compute budget instructions it needs, at a price of
+/// `compute_unit_price` micro-lamports per compute unit.
+#[allow(clippy::too_many_arguments)]
+pub fn borrow_with_budget(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    price_oracle: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    compute_unit_price: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(with_compute_budget(
+        borrow(
+            lender,
+            borrower,
+            asset_mint,
+            collateral_mint,
+            price_oracle,
+            computation_offset,
+            transfer_id,
+        )?,
+        BORROW_COMPUTE_UNITS,
+        compute_unit_price,
+    ))
+}
+
 pub(crate) fn borrow_callback(
     lender: &Pubkey,
     borrower: &Pubkey,
     transfer_account: &Pubkey,
+    queued_total_debt_version: u64,
 ) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
     let (loan_pda, _) = loan_pda(lender, borrower);
 
     let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
         AccountMeta::new_readonly(loan_pda, false),
         AccountMeta::new_readonly(*transfer_account, false),
         AccountMeta::new_readonly(
@@ -284,7 +625,10 @@ pub(crate) fn borrow_callback(
             false,
         ),
     ];
-    let data = LendingInstruction::BorrowCallback.try_to_vec()?;
+    let data = LendingInstruction::BorrowCallback {
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
 
     Ok(Instruction {
         program_id: crate::ID,
@@ -293,13 +637,19 @@ pub(crate) fn borrow_callback(
     })
 }
 
+/// Repays the loan from `asset_repay_ata`, the repayment is aborted if it would release less than
+/// `min_collateral_out` collateral back to the borrower. The pool's cut of the interest is paid to
+/// its `reserve_vault_ata`.
+#[allow(clippy::too_many_arguments)]
 pub fn repay(
     lender: &Pubkey,
     borrower: &Pubkey,
     asset_mint: &Pubkey,
     collateral_mint: &Pubkey,
+    reserve_vault_ata: &Pubkey,
     computation_offset: u32,
     transfer_id: u32,
+    min_collateral_out: u64,
 ) -> Result<Instruction, ProgramError> {
     let lending_pool_pda = lending_pool_pda(lender).0;
     let loan_pda = loan_pda(lender, borrower).0;
@@ -337,8 +687,31 @@ pub fn repay(
         false,
     );
 
-    let transfer_account =
-        get_transfer_account_address(&[asset_repay_ata, collateral_vault_ata], transfer_id);
+    // Borrower asset ATA.
+    let asset_borrower_ata = get_associated_confidential_token_account_address(
+        borrower,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    // Pool asset vault ATA.
+    let asset_vault_ata = get_associated_confidential_token_account_address(
+        &lending_pool_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    let transfer_account = get_transfer_account_address(
+        &[
+            asset_repay_ata,
+            collateral_vault_ata,
+            asset_repay_ata,
+            asset_repay_ata,
+        ],
+        transfer_id,
+    );
     let [mxe_account, computation_account] =
         get_arcium_processor_accounts(&crate::ID, computation_offset);
 
@@ -360,6 +733,12 @@ pub fn repay(
         AccountMeta::new_readonly(asset_lender_ata, false),
         // Destination for excess collateral transfer.
         AccountMeta::new_readonly(collateral_borrower_ata, false),
+        // Destination for reserve transfer.
+        AccountMeta::new_readonly(*reserve_vault_ata, false),
+        // Destination for refund transfer.
+        AccountMeta::new_readonly(asset_borrower_ata, false),
+        // Pool liquidity left, for the utilization.
+        AccountMeta::new_readonly(asset_vault_ata, false),
         AccountMeta::new(transfer_account, false),
         AccountMeta::new(mxe_account, false),
         AccountMeta::new(computation_account, false),
@@ -381,6 +760,7 @@ pub fn repay(
     let data = LendingInstruction::Repay {
         computation_offset,
         transfer_id,
+        min_collateral_out,
     }
     .try_to_vec()?;
 
@@ -391,14 +771,60 @@ pub fn repay(
     })
 }
 
+/// `repay` preceded by the compute budget instructions it needs, at a price of
+/// `compute_unit_price` micro-lamports per compute unit.
+#[allow(clippy::too_many_arguments)]
+pub fn repay_with_budget(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    reserve_vault_ata: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    min_collateral_out: u64,
+    compute_unit_price: u64,
+) -> Result<Vec<Instruction>, ProgramError> {
+    Ok(with_compute_budget(
+        repay(
+            lender,
+            borrower,
+            asset_mint,
+            collateral_mint,
+            reserve_vault_ata,
+            computation_offset,
+            transfer_id,
+            min_collateral_out,
+        )?,
+        REPAY_COMPUTE_UNITS,
+        compute_unit_price,
+    ))
+}
+
+/// Prepends the compute unit price and limit instructions to `instruction`.
+fn with_compute_budget(
+    instruction: Instruction,
+    compute_unit_limit: u32,
+    compute_unit_price: u64,
+) -> Vec<Instruction> {
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_price(compute_unit_price),
+        ComputeBudgetInstruction::set_compute_unit_limit(compute_unit_limit),
+        instruction,
+    ]
+}
+
 pub(crate) fn repay_callback(
     lender: &Pubkey,
     borrower: &Pubkey,
     transfer_account: &Pubkey,
+    queued_total_debt_version: u64,
 ) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
     let (loan_pda, _) = loan_pda(lender, borrower);
 
     let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
         AccountMeta::new_readonly(loan_pda, false),
         AccountMeta::new_readonly(*transfer_account, false),
         AccountMeta::new_readonly(
@@ -406,7 +832,1664 @@ pub(crate) fn repay_callback(
             false,
         ),
     ];
-    let data = LendingInstruction::RepayCallback.try_to_vec()?;
+    let data = LendingInstruction::RepayCallback {
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn add_allowed_borrower(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::AddAllowedBorrower {
+        borrower: borrower.to_bytes(),
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_liquidation_bonus(
+    lender: &Pubkey,
+    liquidation_bonus_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetLiquidationBonus {
+        liquidation_bonus_bps,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Computes the collateral to debt ratio of the borrower's loan, encrypted to the borrower's
+/// x25519 `encryption_pubkey` with `nonce`. The encrypted ratio and its nonce are stored in the
+/// loan, so only the borrower can read it.
+#[allow(clippy::too_many_arguments)]
+pub fn reveal_health(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    encryption_pubkey: &[u8; 32],
+    nonce: u128,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let price_oracle_pda = price_oracle_pda(lender).0;
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        AccountMeta::new_readonly(collateral_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::RevealHealth {
+        computation_offset,
+        transfer_id,
+        encryption_pubkey: *encryption_pubkey,
+        nonce,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Adds `amount` to the collateral of the borrower's active loan, the borrower must have transferred
+/// it into `collateral_vault_ata` beforehand.
+#[allow(clippy::too_many_arguments)]
+pub fn top_up_collateral(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(collateral_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::TopUpCollateral {
+        computation_offset,
+        transfer_id,
+        amount,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Folds the interest accrued on the borrower's active loan since its last update into its
+/// principal.
+pub fn accrue_interest(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let asset_vault_ata = get_associated_confidential_token_account_address(
+        &lending_pool_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(*borrower, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(collateral_vault_ata, false),
+        AccountMeta::new_readonly(asset_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::AccrueInterest {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn accrue_interest_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+    queued_principal: RescueCiphertext,
+    queued_slot: u64,
+    queued_total_debt_version: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::AccrueInterestCallback {
+        queued_principal,
+        queued_slot,
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn top_up_collateral_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::TopUpCollateralCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Computes the encrypted health factor of the borrower's loan and stores it in the loan, only
+/// whether the loan is healthy is revealed. Anyone can compute it.
+pub fn compute_health_factor(
+    caller: &Pubkey,
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let price_oracle_pda = price_oracle_pda(lender).0;
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*caller, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(*borrower, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        AccountMeta::new_readonly(collateral_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::ComputeHealthFactor {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn compute_health_factor_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+    queued_slot: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::ComputeHealthFactorCallback { queued_slot }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn reveal_health_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::RevealHealthCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Withdraws `amount` of unborrowed liquidity from the asset vault to the lender's asset token
+/// account, withdrawing more than the vault holds is a no-op.
+pub fn withdraw_liquidity(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let derived_lending_pool_authority = derive_authority(&lending_pool_pda).0;
+
+    // Vault ATA.
+    let (asset_vault_ata, asset_vault_ata_adapter) = get_associated_token_address_and_adapter(
+        &lending_pool_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // Lender asset ATA.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    let transfer_account = get_transfer_account_address(&[asset_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(derived_lending_pool_authority, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        // Source for asset transfer.
+        AccountMeta::new(asset_vault_ata, false),
+        AccountMeta::new(asset_vault_ata_adapter, false),
+        // Destination for asset transfer.
+        AccountMeta::new_readonly(asset_lender_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::WithdrawLiquidity {
+        computation_offset,
+        transfer_id,
+        amount,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn withdraw_liquidity_callback(
+    lender: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::WithdrawLiquidityCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Closes the loan account once the loan is settled, i.e. owes no principal, refunding its rent to
+/// the borrower and freeing their slot in the lending pool. Confidential token accounts can't be
+/// closed, so the loan's vault token accounts stay allocated and the borrower can't open another
+/// loan with this lender, `reinitialize_loan` reuses a settled loan instead.
+pub fn close_loan(lender: &Pubkey, borrower: &Pubkey) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+    ];
+    let data = LendingInstruction::CloseLoan.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Publishes the price of the collateral in units of the asset used by `borrow`, creating the
+/// lender's price oracle account on first use.
+pub fn set_price(lender: &Pubkey, price: u64) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (price_oracle_pda, _) = price_oracle_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(price_oracle_pda, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+    ];
+    let data = LendingInstruction::SetPrice { price }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Updates the lending pool's rates, pools with borrowers are only updated with `force`. The
+/// collateral threshold is changed with `set_collateral_threshold`, which revalidates open loans.
+pub fn update_pool_params(
+    lender: &Pubkey,
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    force: bool,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::UpdatePoolParams {
+        interest_rate_bps,
+        loan_to_value_bps,
+        force,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_compound_interest(lender: &Pubkey, compound: bool) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetCompoundInterest { compound }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Requires the lender to co-sign `initialize_loan` before new loans can be opened.
+pub fn set_lender_approval(
+    lender: &Pubkey,
+    require_lender_approval: bool,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetLenderApproval {
+        require_lender_approval,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_origination_fee(
+    lender: &Pubkey,
+    origination_fee_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetOriginationFee {
+        origination_fee_bps,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Routes `reserve_factor_bps` of the interest repaid to `reserve_vault_ata`, a token account of
+/// the asset mint.
+pub fn set_reserve(
+    lender: &Pubkey,
+    reserve_vault_ata: &Pubkey,
+    reserve_factor_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(*reserve_vault_ata, false),
+    ];
+    let data = LendingInstruction::SetReserve { reserve_factor_bps }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_liquidation_grace_slots(
+    lender: &Pubkey,
+    liquidation_grace_slots: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetLiquidationGraceSlots {
+        liquidation_grace_slots,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Liquidates an unhealthy loan. A third-party liquidator repays the remaining principal from its
+/// asset ATA and receives the seized collateral plus the pool's liquidation bonus on its collateral
+/// ATA `collateral_liquidator_ata`. The lender repays nothing, its seized collateral goes to the
+/// pool's `liquidation_proceeds_destination` passed as `collateral_liquidator_ata`.
+#[allow(clippy::too_many_arguments)]
+pub fn liquidate(
+    liquidator: &Pubkey,
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    collateral_liquidator_ata: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let price_oracle_pda = price_oracle_pda(lender).0;
+    let derived_loan_authority = derive_authority(&loan_pda).0;
+
+    // Liquidator asset ATA.
+    let (asset_liquidator_ata, asset_liquidator_ata_adapter) =
+        get_associated_token_address_and_adapter(
+            liquidator,
+            asset_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+
+    // Vault ATA.
+    let (collateral_vault_ata, collateral_vault_ata_adapter) =
+        get_associated_token_address_and_adapter(
+            &loan_pda,
+            collateral_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            true,
+        );
+
+    // Lender asset ATA.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    // Borrower collateral ATA.
+    let collateral_borrower_ata = get_associated_confidential_token_account_address(
+        borrower,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    // The lender doesn't repay its own loan.
+    let transfer_account = if liquidator == lender {
+        get_transfer_account_address(&[collateral_vault_ata, collateral_vault_ata], transfer_id)
+    } else {
+        get_transfer_account_address(
+            &[
+                asset_liquidator_ata,
+                collateral_vault_ata,
+                collateral_vault_ata,
+            ],
+            transfer_id,
+        )
+    };
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*liquidator, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(*borrower, false),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new(derived_loan_authority, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        // Source for asset transfer.
+        AccountMeta::new(asset_liquidator_ata, false),
+        AccountMeta::new(asset_liquidator_ata_adapter, false),
+        // Source for collateral transfers.
+        AccountMeta::new(collateral_vault_ata, false),
+        AccountMeta::new(collateral_vault_ata_adapter, false),
+        // Destination for asset transfer.
+        AccountMeta::new_readonly(asset_lender_ata, false),
+        // Destination for seized collateral transfer.
+        AccountMeta::new_readonly(*collateral_liquidator_ata, false),
+        // Destination for residual collateral transfer.
+        AccountMeta::new_readonly(collateral_borrower_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::Liquidate {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Settles the borrower's loan once it reached its maturity slot, the lender seizes the collateral
+/// covering the remaining principal on their collateral token account.
+pub fn settle_matured_loan(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let price_oracle_pda = price_oracle_pda(lender).0;
+    let derived_loan_authority = derive_authority(&loan_pda).0;
+
+    // Vault ATA.
+    let (collateral_vault_ata, collateral_vault_ata_adapter) =
+        get_associated_token_address_and_adapter(
+            &loan_pda,
+            collateral_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            true,
+        );
+
+    // Lender collateral ATA.
+    let collateral_lender_ata = get_associated_confidential_token_account_address(
+        lender,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    // Borrower collateral ATA.
+    let collateral_borrower_ata = get_associated_confidential_token_account_address(
+        borrower,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    let transfer_account =
+        get_transfer_account_address(&[collateral_vault_ata, collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(*borrower, false),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new(derived_loan_authority, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        // Source for collateral transfers.
+        AccountMeta::new(collateral_vault_ata, false),
+        AccountMeta::new(collateral_vault_ata_adapter, false),
+        // Destination for seized collateral transfer.
+        AccountMeta::new_readonly(collateral_lender_ata, false),
+        // Destination for residual collateral transfer.
+        AccountMeta::new_readonly(collateral_borrower_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::SettleMaturedLoan {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn settle_matured_loan_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+    queued_total_debt_version: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::SettleMaturedLoanCallback {
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn liquidate_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+    queued_total_debt_version: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::LiquidateCallback {
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Reveals whether a loan with a private repayment status has been fully repaid.
+pub fn reveal_repayment_status(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(*borrower, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::RevealRepaymentStatus {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn reveal_repayment_status_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::RevealRepaymentStatusCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Sums the outstanding principal of the pool's active loans into the lending pool's
+/// `encrypted_computed_total_debt`, `borrowers` must list the pool's borrowers in order. The total
+/// is also encrypted to the lender's x25519 `encryption_pubkey` with `nonce` and stored as
+/// `encrypted_total_debt_for_lender`.
+pub fn compute_total_debt(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+    borrowers: &[Pubkey],
+    computation_offset: u32,
+    transfer_id: u32,
+    encryption_pubkey: &[u8; 32],
+    nonce: u128,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let asset_vault_ata = get_associated_confidential_token_account_address(
+        &lending_pool_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[asset_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let mut accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(asset_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    accounts.extend(
+        borrowers
+            .iter()
+            .map(|borrower| AccountMeta::new_readonly(loan_pda(lender, borrower).0, false)),
+    );
+    let data = LendingInstruction::ComputeTotalDebt {
+        computation_offset,
+        transfer_id,
+        encryption_pubkey: *encryption_pubkey,
+        nonce,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn compute_total_debt_callback(
+    lender: &Pubkey,
+    transfer_account: &Pubkey,
+    queued_total_debt_version: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::ComputeTotalDebtCallback {
+        queued_total_debt_version,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Rejects borrows whose backing collateral would be worth less than `min_collateral_ratio_bps`
+/// of the loan, `0` disables the floor.
+pub fn set_min_collateral_ratio(
+    lender: &Pubkey,
+    min_collateral_ratio_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetMinCollateralRatio {
+        min_collateral_ratio_bps,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Makes the interest rate follow the pool's utilization: from `base_rate_bps` it grows by up to
+/// `slope1_bps` until the `kink_bps` utilization and by up to `slope2_bps` above it. A `kink_bps`
+/// of `0` disables the curve, the fixed `interest_rate_bps` applying again.
+pub fn set_rate_curve(
+    lender: &Pubkey,
+    base_rate_bps: u16,
+    slope1_bps: u16,
+    slope2_bps: u16,
+    kink_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+    ];
+    let data = LendingInstruction::SetRateCurve {
+        base_rate_bps,
+        slope1_bps,
+        slope2_bps,
+        kink_bps,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Resets a fully repaid or liquidated loan so the borrower can borrow against it again, without
+/// paying the loan account's rent anew. The new loan matures at `maturity_slot`, `0` for no
+/// maturity.
+pub fn reinitialize_loan(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    maturity_slot: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+    ];
+    let data = LendingInstruction::ReinitializeLoan { maturity_slot }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// `reinitialize_loan` co-signed by the lender, as required by pools with
+/// `require_lender_approval`.
+pub fn reinitialize_loan_with_lender_approval(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    maturity_slot: u64,
+) -> Result<Instruction, ProgramError> {
+    let mut instruction = reinitialize_loan(lender, borrower, maturity_slot)?;
+    instruction.accounts[1].is_signer = true;
+
+    Ok(instruction)
+}
+
+/// Creates the reserve vault held by the program and routes the reserve's cut of the interest to
+/// it, so that the lender can later withdraw it with `withdraw_reserve`.
+pub fn initialize_reserve_vault(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (reserve_pda, _) = reserve_pda(lender);
+    let derived_reserve_authority = derive_authority(&reserve_pda).0;
+
+    let (reserve_vault_ata, reserve_vault_ata_adapter) = get_associated_token_address_and_adapter(
+        &reserve_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let key_registry_info = get_key_registry_address(&crate::ID);
+    let proof_context_state_info = get_create_account_proof_context_state_address(&crate::ID);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new(reserve_pda, false),
+        AccountMeta::new(derived_reserve_authority, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new(reserve_vault_ata, false),
+        AccountMeta::new(reserve_vault_ata_adapter, false),
+        AccountMeta::new(proof_context_state_info, false),
+        AccountMeta::new(key_registry_info, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::spl_associated_token_account::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::InitializeReserveVault.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Withdraws `amount` of the reserve from the program's reserve vault to the lender's asset token
+/// account, withdrawing more than the vault holds withdraws all of it.
+pub fn withdraw_reserve(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (reserve_pda, _) = reserve_pda(lender);
+    let derived_reserve_authority = derive_authority(&reserve_pda).0;
+
+    // Reserve vault ATA.
+    let (reserve_vault_ata, reserve_vault_ata_adapter) = get_associated_token_address_and_adapter(
+        &reserve_pda,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // Lender asset ATA.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender,
+        asset_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    let transfer_account = get_transfer_account_address(&[reserve_vault_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(reserve_pda, false),
+        AccountMeta::new(derived_reserve_authority, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        // Source for reserve transfer.
+        AccountMeta::new(reserve_vault_ata, false),
+        AccountMeta::new(reserve_vault_ata_adapter, false),
+        // Destination for reserve transfer.
+        AccountMeta::new_readonly(asset_lender_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::WithdrawReserve {
+        computation_offset,
+        transfer_id,
+        amount,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn withdraw_reserve_callback(
+    lender: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::WithdrawReserveCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Sets the collateral threshold loans are liquidated at. The loans of all `borrowers` of the pool
+/// are passed along and the health factor of every active loan is recomputed under the new
+/// threshold, the `i`-th borrower's loan using computation offset `computation_offset + i`.
+#[allow(clippy::too_many_arguments)]
+pub fn set_collateral_threshold(
+    lender: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    borrowers: &[Pubkey],
+    collateral_threshold_bps: u16,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let price_oracle_pda = price_oracle_pda(lender).0;
+    let [mxe_account, _] = get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let mut accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    for (i, borrower) in borrowers.iter().enumerate() {
+        let loan_pda = loan_pda(lender, borrower).0;
+        let collateral_vault_ata = get_associated_confidential_token_account_address(
+            &loan_pda,
+            collateral_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            true,
+        );
+        let transfer_account = get_transfer_account_address(&[collateral_vault_ata], transfer_id);
+        let loan_computation_offset = computation_offset
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let [_, computation_account] =
+            get_arcium_processor_accounts(&crate::ID, loan_computation_offset);
+        accounts.extend([
+            AccountMeta::new(loan_pda, false),
+            AccountMeta::new_readonly(collateral_vault_ata, false),
+            AccountMeta::new(transfer_account, false),
+            AccountMeta::new(computation_account, false),
+        ]);
+    }
+    let data = LendingInstruction::SetCollateralThreshold {
+        collateral_threshold_bps,
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Quotes the most `borrower` could borrow against the collateral in its collateral token account
+/// at the current price, without moving any funds. The quote is stored encrypted in the
+/// borrower's `BorrowQuote` account.
+pub fn preview_borrow(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (price_oracle_pda, _) = price_oracle_pda(lender);
+    let (borrow_quote_pda, _) = borrow_quote_pda(lender, borrower);
+
+    // Borrower collateral ATA.
+    let collateral_borrower_ata = get_associated_confidential_token_account_address(
+        borrower,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+
+    let transfer_account = get_transfer_account_address(&[collateral_borrower_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new_readonly(price_oracle_pda, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        AccountMeta::new_readonly(collateral_borrower_ata, false),
+        AccountMeta::new(borrow_quote_pda, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::PreviewBorrow {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn preview_borrow_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (borrow_quote_pda, _) = borrow_quote_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(borrow_quote_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::PreviewBorrowCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub fn set_liquidation_proceeds_destination(
+    lender: &Pubkey,
+    liquidation_proceeds_destination: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+
+    let accounts = vec![
+        AccountMeta::new(*lender, true),
+        AccountMeta::new(lending_pool_pda, false),
+        AccountMeta::new_readonly(*liquidation_proceeds_destination, false),
+    ];
+    let data = LendingInstruction::SetLiquidationProceedsDestination.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Transfers `amount` of collateral from the borrower's collateral token account into
+/// `collateral_vault_ata` and adds it to the collateral of the borrower's loan, nothing is
+/// deposited if the borrower holds less.
+#[allow(clippy::too_many_arguments)]
+pub fn deposit_collateral(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    asset_mint: &Pubkey,
+    collateral_mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let lending_pool_pda = lending_pool_pda(lender).0;
+    let loan_pda = loan_pda(lender, borrower).0;
+
+    // Borrower collateral ATA.
+    let (collateral_borrower_ata, collateral_borrower_ata_adapter) =
+        get_associated_token_address_and_adapter(
+            borrower,
+            collateral_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+
+    // Vault ATA.
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        &loan_pda,
+        collateral_mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    let transfer_account = get_transfer_account_address(&[collateral_borrower_ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*borrower, true),
+        AccountMeta::new_readonly(*lender, false),
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*asset_mint, false),
+        AccountMeta::new_readonly(*collateral_mint, false),
+        // Source for collateral transfer.
+        AccountMeta::new(collateral_borrower_ata, false),
+        AccountMeta::new(collateral_borrower_ata_adapter, false),
+        // Destination for collateral transfer.
+        AccountMeta::new_readonly(collateral_vault_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::DepositCollateral {
+        computation_offset,
+        transfer_id,
+        amount,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn deposit_collateral_callback(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let (lending_pool_pda, _) = lending_pool_pda(lender);
+    let (loan_pda, _) = loan_pda(lender, borrower);
+
+    let accounts = vec![
+        AccountMeta::new_readonly(lending_pool_pda, false),
+        AccountMeta::new(loan_pda, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = LendingInstruction::DepositCollateralCallback.try_to_vec()?;
 
     Ok(Instruction {
         program_id: crate::ID,
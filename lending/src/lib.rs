@@ -1,5 +1,9 @@
 #![allow(unexpected_cfgs)]
 
+pub mod addresses;
+pub mod client;
+pub mod error;
+pub mod event;
 pub mod instruction;
 pub mod processor;
 pub mod state;
@@ -26,6 +30,11 @@ pub fn process_instruction(
                 interest_rate_bps,
                 loan_to_value_bps,
                 collateral_threshold_bps,
+                max_active_loans,
+                min_borrow,
+                max_borrow,
+                multisig_threshold,
+                multisig_signers,
             } => {
                 msg!("InitializeLendingPool");
                 process_initialize_lending_pool(
@@ -34,11 +43,19 @@ pub fn process_instruction(
                     interest_rate_bps,
                     loan_to_value_bps,
                     collateral_threshold_bps,
+                    max_active_loans,
+                    min_borrow,
+                    max_borrow,
+                    multisig_threshold,
+                    &multisig_signers,
                 )
             }
-            LendingInstruction::InitializeLoan => {
+            LendingInstruction::InitializeLoan {
+                reveal_repayment_status,
+                maturity_slot,
+            } => {
                 msg!("InitializeLoan");
-                process_initialize_loan(accounts)
+                process_initialize_loan(accounts, reveal_repayment_status, maturity_slot)
             }
             LendingInstruction::Borrow {
                 computation_offset,
@@ -47,20 +64,289 @@ pub fn process_instruction(
                 msg!("Borrow");
                 process_borrow(accounts, computation_offset, transfer_id)
             }
-            LendingInstruction::BorrowCallback => {
+            LendingInstruction::BorrowCallback {
+                queued_total_debt_version,
+            } => {
                 msg!("BorrowCallback");
-                process_borrow_callback(accounts, instruction_data)
+                process_borrow_callback(accounts, queued_total_debt_version)
             }
             LendingInstruction::Repay {
                 computation_offset,
                 transfer_id,
+                min_collateral_out,
             } => {
                 msg!("Repay");
-                process_repay(accounts, computation_offset, transfer_id)
+                process_repay(
+                    accounts,
+                    computation_offset,
+                    transfer_id,
+                    min_collateral_out,
+                )
             }
-            LendingInstruction::RepayCallback => {
+            LendingInstruction::RepayCallback {
+                queued_total_debt_version,
+            } => {
                 msg!("RepayCallback");
-                process_repay_callback(accounts, instruction_data)
+                process_repay_callback(accounts, queued_total_debt_version)
+            }
+            LendingInstruction::AddAllowedBorrower { borrower } => {
+                msg!("AddAllowedBorrower");
+                process_add_allowed_borrower(accounts, &borrower)
+            }
+            LendingInstruction::SetLiquidationBonus {
+                liquidation_bonus_bps,
+            } => {
+                msg!("SetLiquidationBonus");
+                process_set_liquidation_bonus(accounts, liquidation_bonus_bps)
+            }
+            LendingInstruction::Liquidate {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("Liquidate");
+                process_liquidate(accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::LiquidateCallback {
+                queued_total_debt_version,
+            } => {
+                msg!("LiquidateCallback");
+                process_liquidate_callback(accounts, queued_total_debt_version)
+            }
+            LendingInstruction::RevealRepaymentStatus {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("RevealRepaymentStatus");
+                process_reveal_repayment_status(accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::RevealRepaymentStatusCallback => {
+                msg!("RevealRepaymentStatusCallback");
+                process_reveal_repayment_status_callback(accounts, instruction_data)
+            }
+            LendingInstruction::SetOriginationFee {
+                origination_fee_bps,
+            } => {
+                msg!("SetOriginationFee");
+                process_set_origination_fee(accounts, origination_fee_bps)
+            }
+            LendingInstruction::RevealHealth {
+                computation_offset,
+                transfer_id,
+                encryption_pubkey,
+                nonce,
+            } => {
+                msg!("RevealHealth");
+                process_reveal_health(
+                    accounts,
+                    computation_offset,
+                    transfer_id,
+                    encryption_pubkey,
+                    nonce,
+                )
+            }
+            LendingInstruction::RevealHealthCallback => {
+                msg!("RevealHealthCallback");
+                process_reveal_health_callback(accounts, instruction_data)
+            }
+            LendingInstruction::SetPrice { price } => {
+                msg!("SetPrice");
+                process_set_price(program_id, accounts, price)
+            }
+            LendingInstruction::CloseLoan => {
+                msg!("CloseLoan");
+                process_close_loan(accounts)
+            }
+            LendingInstruction::WithdrawLiquidity {
+                computation_offset,
+                transfer_id,
+                amount,
+            } => {
+                msg!("WithdrawLiquidity");
+                process_withdraw_liquidity(accounts, computation_offset, transfer_id, amount)
+            }
+            LendingInstruction::WithdrawLiquidityCallback => {
+                msg!("WithdrawLiquidityCallback");
+                process_withdraw_liquidity_callback(accounts)
+            }
+            LendingInstruction::SetCompoundInterest { compound } => {
+                msg!("SetCompoundInterest");
+                process_set_compound_interest(accounts, compound)
+            }
+            LendingInstruction::UpdatePoolParams {
+                interest_rate_bps,
+                loan_to_value_bps,
+                force,
+            } => {
+                msg!("UpdatePoolParams");
+                process_update_pool_params(accounts, interest_rate_bps, loan_to_value_bps, force)
+            }
+            LendingInstruction::ComputeHealthFactor {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ComputeHealthFactor");
+                process_compute_health_factor(accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::ComputeHealthFactorCallback { queued_slot } => {
+                msg!("ComputeHealthFactorCallback");
+                process_compute_health_factor_callback(accounts, queued_slot)
+            }
+            LendingInstruction::SetLiquidationGraceSlots {
+                liquidation_grace_slots,
+            } => {
+                msg!("SetLiquidationGraceSlots");
+                process_set_liquidation_grace_slots(accounts, liquidation_grace_slots)
+            }
+            LendingInstruction::SetReserve { reserve_factor_bps } => {
+                msg!("SetReserve");
+                process_set_reserve(accounts, reserve_factor_bps)
+            }
+            LendingInstruction::TopUpCollateral {
+                computation_offset,
+                transfer_id,
+                amount,
+            } => {
+                msg!("TopUpCollateral");
+                process_top_up_collateral(accounts, computation_offset, transfer_id, amount)
+            }
+            LendingInstruction::TopUpCollateralCallback => {
+                msg!("TopUpCollateralCallback");
+                process_top_up_collateral_callback(accounts, instruction_data)
+            }
+            LendingInstruction::AccrueInterest {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("AccrueInterest");
+                process_accrue_interest(accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::AccrueInterestCallback {
+                queued_principal,
+                queued_slot,
+                queued_total_debt_version,
+            } => {
+                msg!("AccrueInterestCallback");
+                process_accrue_interest_callback(
+                    accounts,
+                    queued_principal,
+                    queued_slot,
+                    queued_total_debt_version,
+                )
+            }
+            LendingInstruction::SettleMaturedLoan {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("SettleMaturedLoan");
+                process_settle_matured_loan(accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::SettleMaturedLoanCallback {
+                queued_total_debt_version,
+            } => {
+                msg!("SettleMaturedLoanCallback");
+                process_settle_matured_loan_callback(accounts, queued_total_debt_version)
+            }
+            LendingInstruction::SetLenderApproval {
+                require_lender_approval,
+            } => {
+                msg!("SetLenderApproval");
+                process_set_lender_approval(accounts, require_lender_approval)
+            }
+            LendingInstruction::ComputeTotalDebt {
+                computation_offset,
+                transfer_id,
+                encryption_pubkey,
+                nonce,
+            } => {
+                msg!("ComputeTotalDebt");
+                process_compute_total_debt(
+                    accounts,
+                    computation_offset,
+                    transfer_id,
+                    encryption_pubkey,
+                    nonce,
+                )
+            }
+            LendingInstruction::ComputeTotalDebtCallback {
+                queued_total_debt_version,
+            } => {
+                msg!("ComputeTotalDebtCallback");
+                process_compute_total_debt_callback(accounts, queued_total_debt_version)
+            }
+            LendingInstruction::SetMinCollateralRatio {
+                min_collateral_ratio_bps,
+            } => {
+                msg!("SetMinCollateralRatio");
+                process_set_min_collateral_ratio(accounts, min_collateral_ratio_bps)
+            }
+            LendingInstruction::SetRateCurve {
+                base_rate_bps,
+                slope1_bps,
+                slope2_bps,
+                kink_bps,
+            } => {
+                msg!("SetRateCurve");
+                process_set_rate_curve(accounts, base_rate_bps, slope1_bps, slope2_bps, kink_bps)
+            }
+            LendingInstruction::ReinitializeLoan { maturity_slot } => {
+                msg!("ReinitializeLoan");
+                process_reinitialize_loan(accounts, maturity_slot)
+            }
+            LendingInstruction::InitializeReserveVault => {
+                msg!("InitializeReserveVault");
+                process_initialize_reserve_vault(accounts)
+            }
+            LendingInstruction::WithdrawReserve {
+                computation_offset,
+                transfer_id,
+                amount,
+            } => {
+                msg!("WithdrawReserve");
+                process_withdraw_reserve(accounts, computation_offset, transfer_id, amount)
+            }
+            LendingInstruction::WithdrawReserveCallback => {
+                msg!("WithdrawReserveCallback");
+                process_withdraw_reserve_callback(accounts)
+            }
+            LendingInstruction::SetCollateralThreshold {
+                collateral_threshold_bps,
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("SetCollateralThreshold");
+                process_set_collateral_threshold(
+                    accounts,
+                    collateral_threshold_bps,
+                    computation_offset,
+                    transfer_id,
+                )
+            }
+            LendingInstruction::PreviewBorrow {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("PreviewBorrow");
+                process_preview_borrow(program_id, accounts, computation_offset, transfer_id)
+            }
+            LendingInstruction::PreviewBorrowCallback => {
+                msg!("PreviewBorrowCallback");
+                process_preview_borrow_callback(accounts, instruction_data)
+            }
+            LendingInstruction::SetLiquidationProceedsDestination => {
+                msg!("SetLiquidationProceedsDestination");
+                process_set_liquidation_proceeds_destination(accounts)
+            }
+            LendingInstruction::DepositCollateral {
+                computation_offset,
+                transfer_id,
+                amount,
+            } => {
+                msg!("DepositCollateral");
+                process_deposit_collateral(accounts, computation_offset, transfer_id, amount)
+            }
+            LendingInstruction::DepositCollateralCallback => {
+                msg!("DepositCollateralCallback");
+                process_deposit_collateral_callback(accounts)
             }
         },
         Err(e) => panic!("Failed to deserialize instruction {}", e),
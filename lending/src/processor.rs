@@ -1,17 +1,28 @@
-use crate::state::{LendingPool, Loan};
+use crate::addresses::{
+    borrow_quote_pda, lending_pool_pda, loan_pda, price_oracle_pda, reserve_pda, reserve_vault_ata,
+};
+use crate::error::LendingError;
+use crate::event::LendingEvent;
+use crate::state::{BorrowQuote, LendingPool, Loan, PriceOracle, MAX_BORROWERS};
 use borsh::{BorshDeserialize, BorshSerialize};
 use confidential_spl_token::confidential_spl_token_authority::Authority;
 use confidential_spl_token::confidential_transfer_adapter::arcium_types::Argument;
-use confidential_spl_token::confidential_transfer_adapter::state::RescueCiphertext;
+use confidential_spl_token::confidential_transfer_adapter::state::{
+    RescueCiphertext, TransferStatus,
+};
 use confidential_spl_token::invoke::TransferWithComputationInstruction;
-use confidential_spl_token::{get_associated_token_address_and_adapter, transfer_result};
+use confidential_spl_token::{
+    get_associated_confidential_token_account_address, get_associated_token_address_and_adapter,
+    get_transfer_account_address, transfer_result,
+};
 use solana_program::rent::Rent;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
+    msg,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::Sysvar,
+    sysvar::{clock::Clock, Sysvar},
 };
 
 pub(crate) fn process_initialize_lending_pool(
@@ -20,6 +31,11 @@ pub(crate) fn process_initialize_lending_pool(
     interest_rate_bps: u16,
     loan_to_value_bps: u16,
     collateral_threshold_bps: u16,
+    max_active_loans: u16,
+    min_borrow: u64,
+    max_borrow: u64,
+    multisig_threshold: u8,
+    multisig_signers: &[[u8; 32]],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -43,6 +59,11 @@ pub(crate) fn process_initialize_lending_pool(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    // A max_borrow of 0, or below min_borrow, leaves no amount a borrow could be granted.
+    if max_borrow == 0 || min_borrow > max_borrow {
+        return Err(ProgramError::InvalidArgument);
+    }
+
     let (pda, bump) = check_lending_pool(
         lender_info.key,
         lending_pool_info,
@@ -52,15 +73,19 @@ pub(crate) fn process_initialize_lending_pool(
     )?;
 
     // Create lending_pool_info.
-    let lending_pool = LendingPool::new(
+    let mut lending_pool = LendingPool::new(
         lender_info.key,
         asset_mint_info.key,
         collateral_mint_info.key,
         interest_rate_bps,
         loan_to_value_bps,
         collateral_threshold_bps,
+        max_active_loans,
+        min_borrow,
+        max_borrow,
     );
-    let lending_pool_data = lending_pool.try_to_vec()?;
+    lending_pool.set_multisig(multisig_threshold, multisig_signers)?;
+    let lending_pool_data = lending_pool.pack()?;
     let lamports = Rent::get()?.minimum_balance(lending_pool_data.len());
 
     solana_cpi::invoke_signed(
@@ -113,7 +138,11 @@ pub(crate) fn process_initialize_lending_pool(
     Ok(())
 }
 
-pub(crate) fn process_initialize_loan(accounts: &[AccountInfo]) -> ProgramResult {
+pub(crate) fn process_initialize_loan(
+    accounts: &[AccountInfo],
+    reveal_repayment_status: bool,
+    maturity_slot: u64,
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let borrower_info = next_account_info(account_info_iter)?;
@@ -156,15 +185,30 @@ pub(crate) fn process_initialize_loan(accounts: &[AccountInfo]) -> ProgramResult
         lender_info.key,
         borrower_info.key,
         loan_info,
+        lending_pool_info,
         asset_mint_info,
         collateral_mint_info,
         collateral_vault_ata_info,
         Some(asset_repay_ata_info),
     )?;
 
+    let mut lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    check_loan_origination(&lending_pool, lender_info, borrower_info.key)?;
+
+    // The vault balance is encrypted and cannot be inspected here, so any vault left behind at the
+    // derived address is treated as dirty rather than inherited by the new loan.
+    if !collateral_vault_ata_info.data_is_empty() {
+        return Err(LendingError::CollateralVaultNotEmpty.into());
+    }
+
     // Create loan_info account.
-    let loan = Loan::new(borrower_info.key, lending_pool_info.key);
-    let loan_data = loan.try_to_vec()?;
+    let loan = Loan::new(
+        borrower_info.key,
+        lending_pool_info.key,
+        reveal_repayment_status,
+        maturity_slot,
+    );
+    let loan_data = loan.pack()?;
     let lamports = Rent::get()?.minimum_balance(loan_data.len());
 
     solana_cpi::invoke_signed(
@@ -192,11 +236,10 @@ pub(crate) fn process_initialize_loan(accounts: &[AccountInfo]) -> ProgramResult
     loan_info.try_borrow_mut_data()?.copy_from_slice(&loan_data);
 
     // Add borrower to lending pool.
-    let mut lending_pool = LendingPool::try_from_slice(&lending_pool_info.data.borrow())?;
     lending_pool.add_borrower(borrower_info.key)?;
     lending_pool_info
         .try_borrow_mut_data()?
-        .copy_from_slice(&lending_pool.try_to_vec()?);
+        .copy_from_slice(&lending_pool.pack()?);
 
     // We utilize a derived authority to have simpler callbacks.
     let authority = Authority::Derived {
@@ -255,6 +298,30 @@ pub(crate) fn process_initialize_loan(accounts: &[AccountInfo]) -> ProgramResult
 
 pub const BORROW_COMP_DEF_OFFSET: u32 = 0;
 pub const REPAY_COMP_DEF_OFFSET: u32 = 1;
+pub const LIQUIDATE_COMP_DEF_OFFSET: u32 = 2;
+pub const REPAY_PRIVATE_COMP_DEF_OFFSET: u32 = 3;
+pub const REVEAL_REPAYMENT_STATUS_COMP_DEF_OFFSET: u32 = 4;
+pub const REVEAL_HEALTH_COMP_DEF_OFFSET: u32 = 5;
+pub const WITHDRAW_COMP_DEF_OFFSET: u32 = 6;
+pub const REPAY_COMPOUND_COMP_DEF_OFFSET: u32 = 7;
+pub const REPAY_COMPOUND_PRIVATE_COMP_DEF_OFFSET: u32 = 8;
+pub const HEALTH_FACTOR_COMP_DEF_OFFSET: u32 = 9;
+pub const TOP_UP_COLLATERAL_COMP_DEF_OFFSET: u32 = 10;
+pub const ACCRUE_INTEREST_COMP_DEF_OFFSET: u32 = 11;
+pub const ACCRUE_INTEREST_COMPOUND_COMP_DEF_OFFSET: u32 = 12;
+pub const SETTLE_MATURED_LOAN_COMP_DEF_OFFSET: u32 = 13;
+pub const TOTAL_DEBT_COMP_DEF_OFFSET: u32 = 14;
+pub const WITHDRAW_RESERVE_COMP_DEF_OFFSET: u32 = 15;
+pub const PREVIEW_BORROW_COMP_DEF_OFFSET: u32 = 16;
+pub const LIQUIDATE_BY_LENDER_COMP_DEF_OFFSET: u32 = 17;
+pub const DEPOSIT_COLLATERAL_COMP_DEF_OFFSET: u32 = 18;
+
+/// Maximum number of periods interest is compounded over in a single repayment.
+pub const MAX_COMPOUNDING_PERIODS: u64 = 16;
+
+/// Number of transfers made by the borrow computation: the loan to the borrower, the excess
+/// collateral back to the borrower and the origination fee to the lender.
+pub const BORROW_TRANSFER_COUNT: usize = 3;
 
 pub(crate) fn process_borrow(
     accounts: &[AccountInfo],
@@ -271,6 +338,7 @@ pub(crate) fn process_borrow(
     let derived_loan_authority_info = next_account_info(account_info_iter)?;
     let asset_mint_info = next_account_info(account_info_iter)?;
     let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
 
     // Source for asset transfer.
     let asset_vault_ata_info = next_account_info(account_info_iter)?;
@@ -286,6 +354,9 @@ pub(crate) fn process_borrow(
     // Destination for excess collateral transfer.
     let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
 
+    // Destination for origination fee transfer.
+    let asset_lender_ata_info = next_account_info(account_info_iter)?;
+
     let transfer_account_info = next_account_info(account_info_iter)?;
     let mxe_info = next_account_info(account_info_iter)?;
     let computation_info = next_account_info(account_info_iter)?;
@@ -300,6 +371,32 @@ pub(crate) fn process_borrow(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    if loan_info.data_is_empty() || loan_info.owner != &crate::ID {
+        return Err(LendingError::LoanNotInitialized.into());
+    }
+
+    // A second borrow would overwrite the principal of the outstanding one.
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    let loan = Loan::unpack(&loan_info.data.borrow())?;
+    if loan.active {
+        return Err(LendingError::LoanAlreadyActive.into());
+    }
+    // Loans opened before the pool filled up can't become active past its capacity.
+    if !lending_pool.has_active_loan_capacity() {
+        return Err(LendingError::PoolAtCapacity.into());
+    }
+
+    // The origination fee can only be paid to the lender.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender_info.key,
+        asset_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if asset_lender_ata_info.key != &asset_lender_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
     let (_, lending_pool_bump) = check_lending_pool(
         lender_info.key,
         lending_pool_info,
@@ -312,12 +409,26 @@ pub(crate) fn process_borrow(
         lender_info.key,
         borrower_info.key,
         loan_info,
+        lending_pool_info,
         asset_mint_info,
         collateral_mint_info,
         collateral_vault_ata_info,
         None,
     )?;
 
+    // The loan and the excess collateral are paid to the borrower's own token accounts, which have
+    // to exist before borrowing.
+    check_borrower_ata(borrower_info.key, asset_mint_info, asset_borrower_ata_info)?;
+    check_borrower_ata(
+        borrower_info.key,
+        collateral_mint_info,
+        collateral_borrower_ata_info,
+    )?;
+
+    use_next_transfer_id(loan_info, transfer_id)?;
+
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+
     // Transfer loan_amount to borrower.
     let asset_transfer = TransferWithComputationInstruction {
         authority: &Authority::Derived {
@@ -348,24 +459,59 @@ pub(crate) fn process_borrow(
         multisig_signers_infos: &[],
     };
 
+    // Transfer origination_fee to the lender.
+    let fee_transfer = TransferWithComputationInstruction {
+        authority: &Authority::Derived {
+            authority_info: &lending_pool_info.clone(),
+            derived_authority_info: &derived_lending_pool_authority_info.clone(),
+            confidential_spl_token_authority_program:
+                &confidential_spl_token_authority_program_info.clone(),
+        },
+        mint_info: asset_mint_info,
+        source_token_account_info: asset_vault_ata_info,
+        source_token_account_adapter_info: asset_vault_ata_adapter_info,
+        destination_token_account_info: asset_lender_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // The transfers match the borrow computation's transfers one to one.
+    let transfers: [TransferWithComputationInstruction; BORROW_TRANSFER_COUNT] =
+        [asset_transfer, collateral_transfer, fee_transfer];
+
     // Arguments for the encrypted computation.
-    let lending_pool = LendingPool::try_from_slice(&lending_pool_info.data.borrow())?;
-    let price = 1;
     let arguments = [
         Argument::ConfidentialTokenAccount(asset_vault_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(asset_borrower_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
-        Argument::PlaintextU16(price),
+        Argument::ConfidentialTokenAccount(asset_lender_ata_info.key.to_bytes()),
+        Argument::PlaintextU64(price),
         Argument::PlaintextU16(lending_pool.loan_to_value_bps),
+        Argument::PlaintextU16(lending_pool.origination_fee_bps),
+        Argument::PlaintextU64(lending_pool.min_borrow),
+        Argument::PlaintextU64(lending_pool.max_borrow),
+        Argument::PlaintextU16(lending_pool.min_collateral_ratio_bps),
+        Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+        Argument::PlaintextBool(lending_pool.encrypted_total_debt != RescueCiphertext::default()),
     ];
 
-    // TODO: Freeze collateral vault.
+    // The derived loan authority owns collateral_vault_ata, so the borrower can't move collateral
+    // out of it while the loan is outstanding, only this program's transfers can.
+
+    check_transfer_account(
+        transfer_account_info,
+        &[
+            *asset_vault_ata_info.key,
+            *collateral_vault_ata_info.key,
+            *asset_vault_ata_info.key,
+        ],
+        transfer_id,
+    )?;
 
     confidential_spl_token::invoke::transfer_with_computation(
         &confidential_spl_token::programs::confidential_spl_token::ID,
         &crate::ID,
-        &[asset_transfer, collateral_transfer],
+        &transfers,
         &arguments,
         borrower_info,
         transfer_account_info,
@@ -379,6 +525,7 @@ pub(crate) fn process_borrow(
             lender_info.key,
             borrower_info.key,
             transfer_account_info.key,
+            lending_pool.total_debt_version,
         )?
         .into(),
         computation_offset,
@@ -396,40 +543,89 @@ pub(crate) fn process_borrow(
                 borrower_info.key.as_ref(),
                 &[loan_bump],
             ],
+            &[
+                b"lending_pool",
+                lender_info.key.as_ref(),
+                &[lending_pool_bump],
+            ],
         ],
     )
 }
 
 pub(crate) fn process_borrow_callback(
     accounts: &[AccountInfo],
-    _instruction_data: &[u8],
+    queued_total_debt_version: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
+    let lending_pool_info = next_account_info(account_info_iter)?;
     let loan_info = next_account_info(account_info_iter)?;
     let transfer_account_info = next_account_info(account_info_iter)?;
     let instructions_sysvar_info = next_account_info(account_info_iter)?;
 
+    // A failed borrow moved no funds, the loan is left inactive.
     let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Borrow transfers failed");
+        return Err(LendingError::TransferFailed.into());
+    }
 
     // Take the custom output data from the computation.
-    let output_data = result.custom_computation_output.unwrap();
-    let encrypted_loan_amount = RescueCiphertext::try_from(&output_data[..])?;
+    let output_data = result.custom_computation_output.ok_or_else(|| {
+        msg!("Borrow computation has no output");
+        ProgramError::InvalidAccountData
+    })?;
+    let encrypted_loan_amount = RescueCiphertext::try_from(&output_data[..32])?;
+    let encrypted_loan_collateral_amount = RescueCiphertext::try_from(&output_data[32..64])?;
+    let loan_is_granted = bool::try_from_slice(&output_data[64..65])?;
+    let encrypted_total_debt = RescueCiphertext::try_from(&output_data[65..97])?;
+
+    // Nothing was lent, the loan stays inactive.
+    if !loan_is_granted {
+        msg!("Collateral below min_collateral_ratio_bps or loan below min_borrow");
+        return Ok(());
+    }
 
-    // Store the encrypted_loan_amount in the loan account.
-    let mut loan = Loan::try_from_slice(&loan_info.try_borrow_data()?)?;
+    // Store the encrypted_loan_amount and encrypted_loan_collateral_amount in the loan account.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
     loan.encrypted_principal = encrypted_loan_amount;
+    loan.encrypted_collateral = encrypted_loan_collateral_amount;
+    loan.encrypted_released_collateral = RescueCiphertext::default();
+    loan.last_update_slot = Clock::get()?.slot;
+    loan.collateral_update_slot = loan.last_update_slot;
+    if !loan.active {
+        update_num_active_loans(lending_pool_info, true)?;
+        // A reopened loan doesn't inherit the health of a previous one.
+        loan.unhealthy_since_slot = 0;
+    }
+    loan.active = true;
+    loan.settled = false;
+    // The repayment status of a previous loan doesn't apply to the new principal.
+    loan.encrypted_repayment_status = RescueCiphertext::default();
     loan_info
         .try_borrow_mut_data()?
-        .copy_from_slice(&loan.try_to_vec()?);
+        .copy_from_slice(&loan.pack()?);
 
-    Ok(())
+    // The pool's total debt includes the new principal, keeping the utilization current.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )?;
+
+    let lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    LendingEvent::LoanOpened {
+        borrower: loan.borrower,
+        lender: lending_pool.lender,
+    }
+    .emit()
 }
 
 pub(crate) fn process_repay(
     accounts: &[AccountInfo],
     computation_offset: u32,
     transfer_id: u32,
+    min_collateral_out: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -455,6 +651,15 @@ pub(crate) fn process_repay(
     // Destination for collateral transfer.
     let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
 
+    // Destination for reserve transfer.
+    let reserve_vault_ata_info = next_account_info(account_info_iter)?;
+
+    // Destination for refund transfer.
+    let asset_borrower_ata_info = next_account_info(account_info_iter)?;
+
+    // Pool liquidity left, for the utilization.
+    let asset_vault_ata_info = next_account_info(account_info_iter)?;
+
     let transfer_account_info = next_account_info(account_info_iter)?;
     let mxe_info = next_account_info(account_info_iter)?;
     let computation_info = next_account_info(account_info_iter)?;
@@ -473,7 +678,7 @@ pub(crate) fn process_repay(
         lender_info.key,
         lending_pool_info,
         asset_mint_info,
-        None,
+        Some(asset_vault_ata_info),
         &crate::ID,
     )?;
 
@@ -481,11 +686,20 @@ pub(crate) fn process_repay(
         lender_info.key,
         borrower_info.key,
         loan_info,
+        lending_pool_info,
         asset_mint_info,
         collateral_mint_info,
         collateral_vault_ata_info,
         Some(asset_repay_ata_info),
     )?;
+    use_next_transfer_id(loan_info, transfer_id)?;
+
+    // Only an outstanding principal can be repaid, a loan that was never drawn or has been repaid
+    // would run the computation on an empty principal.
+    let loan = Loan::unpack(&loan_info.data.borrow())?;
+    if !loan.active || loan.encrypted_principal == RescueCiphertext::default() {
+        return Err(LendingError::LoanInactive.into());
+    }
 
     // Transfer actual_repay_amount from asset_repay_ata to lender.
     let asset_transfer = TransferWithComputationInstruction {
@@ -517,24 +731,102 @@ pub(crate) fn process_repay(
         multisig_signers_infos: &[],
     };
 
-    // Arguments for the encrypted computation.
-    let lending_pool = LendingPool::try_from_slice(&lending_pool_info.data.borrow())?;
-    let loan = Loan::try_from_slice(&loan_info.data.borrow())?;
-    let slots_elapsed = 10;
-    let arguments = [
+    // Transfer the reserve's cut of the interest from asset_repay_ata to the reserve vault.
+    let reserve_transfer = TransferWithComputationInstruction {
+        authority: &Authority::Derived {
+            authority_info: &loan_info.clone(),
+            derived_authority_info: &derived_loan_authority_info.clone(),
+            confidential_spl_token_authority_program:
+                &confidential_spl_token_authority_program_info.clone(),
+        },
+        mint_info: asset_mint_info,
+        source_token_account_info: asset_repay_ata_info,
+        source_token_account_adapter_info: asset_repay_ata_adapter_info,
+        destination_token_account_info: reserve_vault_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Transfer the overpayment from asset_repay_ata back to the borrower.
+    let refund_transfer = TransferWithComputationInstruction {
+        authority: &Authority::Derived {
+            authority_info: &loan_info.clone(),
+            derived_authority_info: &derived_loan_authority_info.clone(),
+            confidential_spl_token_authority_program:
+                &confidential_spl_token_authority_program_info.clone(),
+        },
+        mint_info: asset_mint_info,
+        source_token_account_info: asset_repay_ata_info,
+        source_token_account_adapter_info: asset_repay_ata_adapter_info,
+        destination_token_account_info: asset_borrower_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    if reserve_vault_ata_info.key.to_bytes() != lending_pool.reserve_vault_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Arguments for the encrypted computation, the interest rate follows the pool's utilization
+    // along its rate curve. The utilization is derived from the pool's total debt, kept up to date
+    // by borrows and repays, and the liquidity left in the asset vault.
+    let slots_elapsed = Clock::get()?.slot - loan.last_update_slot;
+    let (base_rate_bps, slope1_bps, slope2_bps, kink_bps) = lending_pool.rate_curve();
+    let mut arguments = vec![
         Argument::ConfidentialTokenAccount(asset_repay_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(asset_lender_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(reserve_vault_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
         Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(asset_borrower_ata_info.key.to_bytes()),
         Argument::EncryptedU64(loan.encrypted_principal),
+        Argument::EncryptedU64(loan.encrypted_collateral),
         Argument::PlaintextU64(slots_elapsed),
-        Argument::PlaintextU16(lending_pool.interest_rate_bps),
+        Argument::PlaintextU16(base_rate_bps),
+        Argument::PlaintextU16(slope1_bps),
+        Argument::PlaintextU16(slope2_bps),
+        Argument::PlaintextU16(kink_bps),
+        Argument::ConfidentialTokenAccount(asset_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+        Argument::PlaintextBool(lending_pool.encrypted_total_debt != RescueCiphertext::default()),
+        Argument::PlaintextU16(lending_pool.reserve_factor_bps),
+        Argument::PlaintextU64(min_collateral_out),
     ];
 
+    // Compounding pools compound the interest once per elapsed slot, up to
+    // MAX_COMPOUNDING_PERIODS periods.
+    if lending_pool.compound {
+        let periods = slots_elapsed.clamp(1, MAX_COMPOUNDING_PERIODS);
+        arguments.push(Argument::PlaintextU64(periods));
+    }
+
+    // Loans with a private repayment status keep the fully repaid flag encrypted.
+    let comp_def_offset = match (lending_pool.compound, loan.reveal_repayment_status) {
+        (false, true) => REPAY_COMP_DEF_OFFSET,
+        (false, false) => REPAY_PRIVATE_COMP_DEF_OFFSET,
+        (true, true) => REPAY_COMPOUND_COMP_DEF_OFFSET,
+        (true, false) => REPAY_COMPOUND_PRIVATE_COMP_DEF_OFFSET,
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[
+            *asset_repay_ata_info.key,
+            *collateral_vault_ata_info.key,
+            *asset_repay_ata_info.key,
+            *asset_repay_ata_info.key,
+        ],
+        transfer_id,
+    )?;
+
     confidential_spl_token::invoke::transfer_with_computation(
         &confidential_spl_token::programs::confidential_spl_token::ID,
         &crate::ID,
-        &[asset_transfer, collateral_transfer],
+        &[
+            asset_transfer,
+            collateral_transfer,
+            reserve_transfer,
+            refund_transfer,
+        ],
         &arguments,
         borrower_info,
         transfer_account_info,
@@ -548,10 +840,11 @@ pub(crate) fn process_repay(
             lender_info.key,
             borrower_info.key,
             transfer_account_info.key,
+            lending_pool.total_debt_version,
         )?
         .into(),
         computation_offset,
-        REPAY_COMP_DEF_OFFSET,
+        comp_def_offset,
         transfer_id,
         &[
             &[
@@ -566,103 +859,2903 @@ pub(crate) fn process_repay(
                 borrower_info.key.as_ref(),
                 &[loan_bump],
             ],
+            &[
+                b"loan",
+                lender_info.key.as_ref(),
+                borrower_info.key.as_ref(),
+                &[loan_bump],
+            ],
+            &[
+                b"loan",
+                lender_info.key.as_ref(),
+                borrower_info.key.as_ref(),
+                &[loan_bump],
+            ],
         ],
     )
 }
 
 pub(crate) fn process_repay_callback(
     accounts: &[AccountInfo],
-    _instruction_data: &[u8],
+    queued_total_debt_version: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
+    let lending_pool_info = next_account_info(account_info_iter)?;
     let loan_info = next_account_info(account_info_iter)?;
     let transfer_account_info = next_account_info(account_info_iter)?;
     let instructions_sysvar_info = next_account_info(account_info_iter)?;
 
-    let result = transfer_result(transfer_account_info, instructions_sysvar_info).unwrap();
+    // A failed repayment moved no funds, the loan is left as it is.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Repay transfers failed");
+        return Err(LendingError::TransferFailed.into());
+    }
 
     // Take the custom output data from the computation.
-    let output_data = result.custom_computation_output.unwrap();
+    let output_data = result.custom_computation_output.ok_or_else(|| {
+        msg!("Repay computation has no output");
+        ProgramError::InvalidAccountData
+    })?;
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
     let remaining_due = RescueCiphertext::try_from(&output_data[..32])?;
-    let loan_is_fully_repaid = bool::try_from_slice(&output_data[32..])?;
 
-    // Update the Loan account.
-    let mut loan = Loan::try_from_slice(&loan_info.try_borrow_data()?)?;
+    // The fully repaid flag is either revealed or an encrypted status.
+    let (loan_is_fully_repaid, encrypted_repayment_status, slippage_offset) =
+        if loan.reveal_repayment_status {
+            (Some(bool::try_from_slice(&output_data[32..33])?), None, 33)
+        } else {
+            (
+                None,
+                Some(RescueCiphertext::try_from(&output_data[32..64])?),
+                64,
+            )
+        };
+    let slippage_exceeded =
+        bool::try_from_slice(&output_data[slippage_offset..slippage_offset + 1])?;
+
+    // No funds have been moved, the loan stays as it is.
+    if slippage_exceeded {
+        msg!("Collateral repayment below min_collateral_out");
+        return Ok(());
+    }
+    let repayment_split_offset = slippage_offset + 1;
+    let encrypted_interest_paid = RescueCiphertext::try_from(
+        &output_data[repayment_split_offset..repayment_split_offset + 32],
+    )?;
+    let encrypted_principal_paid = RescueCiphertext::try_from(
+        &output_data[repayment_split_offset + 32..repayment_split_offset + 64],
+    )?;
+    let encrypted_released_collateral = RescueCiphertext::try_from(
+        &output_data[repayment_split_offset + 64..repayment_split_offset + 96],
+    )?;
+    let encrypted_total_debt = RescueCiphertext::try_from(
+        &output_data[repayment_split_offset + 96..repayment_split_offset + 128],
+    )?;
+
+    // Update the Loan account, a loan with a private status stays active until it is revealed.
+    // Interest on the remaining due accrues from now on.
     loan.encrypted_principal = remaining_due;
-    loan.active = !loan_is_fully_repaid;
+    loan.last_update_slot = Clock::get()?.slot;
+    loan.encrypted_interest_paid = encrypted_interest_paid;
+    loan.encrypted_principal_paid = encrypted_principal_paid;
+    loan.encrypted_released_collateral = encrypted_released_collateral;
+    if let Some(loan_is_fully_repaid) = loan_is_fully_repaid {
+        if loan.active && loan_is_fully_repaid {
+            update_num_active_loans(lending_pool_info, false)?;
+        }
+        loan.active = !loan_is_fully_repaid;
+        loan.settled = loan_is_fully_repaid;
+    }
+    if let Some(encrypted_repayment_status) = encrypted_repayment_status {
+        loan.encrypted_repayment_status = encrypted_repayment_status;
+    }
     loan_info
         .try_borrow_mut_data()?
-        .copy_from_slice(&loan.try_to_vec()?);
+        .copy_from_slice(&loan.pack()?);
 
-    Ok(())
-}
+    // The pool's total debt follows the loan's remaining due, keeping the utilization current.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )?;
 
-pub fn lending_pool_pda(lender: &Pubkey) -> (Pubkey, u8) {
-    Pubkey::find_program_address(&[b"lending_pool", lender.as_ref()], &crate::ID)
+    let lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    LendingEvent::LoanRepaid {
+        borrower: loan.borrower,
+        lender: lending_pool.lender,
+        fully_repaid: loan_is_fully_repaid,
+    }
+    .emit()
 }
 
-pub fn loan_pda(lender: &Pubkey, borrower: &Pubkey) -> (Pubkey, u8) {
-    Pubkey::find_program_address(&[b"loan", lender.as_ref(), borrower.as_ref()], &crate::ID)
-}
+pub(crate) fn process_add_allowed_borrower(
+    accounts: &[AccountInfo],
+    borrower: &[u8; 32],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
 
-fn check_lending_pool(
-    lender: &Pubkey,
-    lending_pool_info: &AccountInfo,
-    asset_mint_info: &AccountInfo,
-    asset_vault_ata_info: Option<&AccountInfo>,
-    lending_pool_owner: &Pubkey,
-) -> Result<(Pubkey, u8), ProgramError> {
-    let (pda, bump) = lending_pool_pda(lender);
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
 
-    if lending_pool_info.key != &pda {
-        return Err(ProgramError::InvalidAccountData);
-    }
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
 
-    if lending_pool_info.owner != lending_pool_owner {
-        return Err(ProgramError::IncorrectProgramId);
-    }
+    lending_pool.add_allowed_borrower(&Pubkey::new_from_array(*borrower))?;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
 
-    let (expected_ata, _) = get_associated_token_address_and_adapter(
-        &pda,
-        asset_mint_info.key,
-        &confidential_spl_token::programs::confidential_spl_token::ID,
-        true,
-    );
+    Ok(())
+}
 
-    if let Some(asset_vault_ata_info) = asset_vault_ata_info {
-        if asset_vault_ata_info.key != &expected_ata {
-            return Err(ProgramError::InvalidAccountData);
-        }
+pub(crate) fn process_reveal_health(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    encryption_pubkey: [u8; 32],
+    nonce: u128,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Only the borrower may reveal the health of their loan.
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
     }
 
-    Ok((pda, bump))
-}
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
 
-fn check_loan(
-    lender: &Pubkey,
-    borrower: &Pubkey,
-    loan_info: &AccountInfo,
-    asset_mint_info: &AccountInfo,
-    collateral_mint_info: &AccountInfo,
-    collateral_vault_ata_info: &AccountInfo,
-    asset_repay_ata_info: Option<&AccountInfo>,
-) -> Result<(Pubkey, u8), ProgramError> {
-    let (loan_pda, bump) = loan_pda(lender, borrower);
+    check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
 
-    if loan_info.key != &loan_pda {
-        return Err(ProgramError::InvalidSeeds);
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
     }
 
-    let (collateral_vault_ata_pda, _) = get_associated_token_address_and_adapter(
-        &loan_pda,
-        collateral_mint_info.key,
-        &confidential_spl_token::programs::confidential_spl_token::ID,
-        true,
-    );
-    if collateral_vault_ata_info.key != &collateral_vault_ata_pda {
-        return Err(ProgramError::InvalidAccountData);
-    }
+    // Arguments for the encrypted computation.
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+    let arguments = [
+        Argument::ArcisPubkey(encryption_pubkey),
+        Argument::PlaintextU128(nonce),
+        Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(loan.encrypted_principal),
+        Argument::PlaintextU64(price),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only encrypts the health to the borrower.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        borrower_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::reveal_health_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        REVEAL_HEALTH_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_top_up_collateral(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Only the borrower may top up the collateral of their loan.
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+
+    check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(loan.encrypted_collateral),
+        Argument::PlaintextU64(amount),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the collateral has already been transferred by the borrower.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        borrower_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::top_up_collateral_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        TOP_UP_COLLATERAL_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_top_up_collateral_callback(
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_collateral = RescueCiphertext::try_from(&output_data[..32])?;
+
+    // Update the Loan account, health factors queued before the top up are discarded.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    loan.encrypted_collateral = encrypted_collateral;
+    loan.collateral_update_slot = Clock::get()?.slot;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_deposit_collateral(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+
+    // Source for collateral transfer.
+    let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
+    let collateral_borrower_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Destination for collateral transfer.
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // The borrower signs the transfer out of their collateral token account.
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+
+    check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    check_borrower_ata(
+        borrower_info.key,
+        collateral_mint_info,
+        collateral_borrower_ata_info,
+    )?;
+
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+
+    let borrower_authority = Authority::Signer {
+        authority_info: borrower_info,
+    };
+
+    // Transfer amount from the borrower to collateral_vault_ata.
+    let deposit_transfer = TransferWithComputationInstruction {
+        authority: &borrower_authority,
+        mint_info: collateral_mint_info,
+        source_token_account_info: collateral_borrower_ata_info,
+        source_token_account_adapter_info: collateral_borrower_ata_adapter_info,
+        destination_token_account_info: collateral_vault_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(loan.encrypted_collateral),
+        Argument::PlaintextU64(amount),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_borrower_ata_info.key],
+        transfer_id,
+    )?;
+
+    // The borrower signs the transfer itself, no seeds are needed.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[deposit_transfer],
+        &arguments,
+        borrower_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::deposit_collateral_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        DEPOSIT_COLLATERAL_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[][..]],
+    )
+}
+
+pub(crate) fn process_deposit_collateral_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // A failed deposit moved no collateral, the loan is left as is.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Deposit transfer failed");
+        return Err(LendingError::TransferFailed.into());
+    }
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_collateral = RescueCiphertext::try_from(&output_data[..32])?;
+
+    // Update the Loan account, health factors queued before the deposit are discarded.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    loan.encrypted_collateral = encrypted_collateral;
+    loan.collateral_update_slot = Clock::get()?.slot;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_accrue_interest(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let asset_vault_ata_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Only the lender may snapshot the interest accrued into the principal.
+    if !lender_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        Some(asset_vault_ata_info),
+        &crate::ID,
+    )?;
+
+    check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+
+    // Arguments for the encrypted computation, interest accrues the same way as on repayment.
+    let lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    let queued_slot = Clock::get()?.slot;
+    let slots_elapsed = queued_slot - loan.last_update_slot;
+    let (base_rate_bps, slope1_bps, slope2_bps, kink_bps) = lending_pool.rate_curve();
+    let mut arguments = vec![
+        Argument::EncryptedU64(loan.encrypted_principal),
+        Argument::PlaintextU64(slots_elapsed),
+        Argument::PlaintextU16(base_rate_bps),
+        Argument::PlaintextU16(slope1_bps),
+        Argument::PlaintextU16(slope2_bps),
+        Argument::PlaintextU16(kink_bps),
+        Argument::ConfidentialTokenAccount(asset_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+        Argument::PlaintextBool(lending_pool.encrypted_total_debt != RescueCiphertext::default()),
+    ];
+    let comp_def_offset = if lending_pool.compound {
+        let periods = slots_elapsed.clamp(1, MAX_COMPOUNDING_PERIODS);
+        arguments.push(Argument::PlaintextU64(periods));
+        ACCRUE_INTEREST_COMPOUND_COMP_DEF_OFFSET
+    } else {
+        ACCRUE_INTEREST_COMP_DEF_OFFSET
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::accrue_interest_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+            loan.encrypted_principal,
+            queued_slot,
+            lending_pool.total_debt_version,
+        )?
+        .into(),
+        computation_offset,
+        comp_def_offset,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_accrue_interest_callback(
+    accounts: &[AccountInfo],
+    queued_principal: RescueCiphertext,
+    queued_slot: u64,
+    queued_total_debt_version: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // A failed computation leaves the principal as it is.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Accrue interest computation failed");
+        return Ok(());
+    }
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_principal = RescueCiphertext::try_from(&output_data[..32])?;
+    let encrypted_total_debt = RescueCiphertext::try_from(&output_data[32..64])?;
+
+    // A loan repaid, liquidated or settled while the accrual was pending has a new principal, the
+    // principal accrued from the queued one is stale.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active || loan.encrypted_principal != queued_principal {
+        msg!("Stale interest accrual, ignored");
+        return Ok(());
+    }
+
+    // Update the Loan account, the interest was accrued up to the slot the accrual was queued at
+    // and accrues from there on.
+    loan.encrypted_principal = encrypted_principal;
+    loan.last_update_slot = queued_slot;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    // The pool's total debt includes the accrued interest.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )
+}
+
+pub(crate) fn process_compute_health_factor(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let caller_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Anyone can compute the health factor, the caller pays for the computation.
+    if !caller_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+
+    check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+
+    // Arguments for the encrypted computation.
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+    let arguments = [
+        Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(loan.encrypted_principal),
+        Argument::PlaintextU64(price),
+        Argument::PlaintextU16(lending_pool.collateral_threshold_bps),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only outputs the health factor.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        caller_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::compute_health_factor_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+            Clock::get()?.slot,
+        )?
+        .into(),
+        computation_offset,
+        HEALTH_FACTOR_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_compute_health_factor_callback(
+    accounts: &[AccountInfo],
+    queued_slot: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_health_factor = RescueCiphertext::try_from(&output_data[..32])?;
+    let is_healthy = bool::try_from_slice(&output_data[32..33])?;
+
+    // A result for a loan closed in the meantime, or computed before the collateral last changed,
+    // is stale and would start or reset the grace period wrongly.
+    if loan_info.data_is_empty() {
+        msg!("Stale health factor, ignored");
+        return Ok(());
+    }
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active || queued_slot < loan.collateral_update_slot {
+        msg!("Stale health factor, ignored");
+        return Ok(());
+    }
+
+    // Update the Loan account.
+    loan.encrypted_health_factor = encrypted_health_factor;
+    // The grace period starts when the loan is first found unhealthy and restarts once it recovers.
+    if is_healthy {
+        loan.unhealthy_since_slot = 0;
+    } else if loan.unhealthy_since_slot == 0 {
+        loan.unhealthy_since_slot = Clock::get()?.slot;
+    }
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_reveal_health_callback(
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    // The output is encrypted to the borrower: their encryption key, the output nonce and the
+    // ciphertext of health_bps.
+    let health_bps_nonce = u128::try_from_slice(&output_data[32..48])?;
+    let encrypted_health_bps = RescueCiphertext::try_from(&output_data[48..80])?;
+
+    // Update the Loan account.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    loan.encrypted_health_bps = encrypted_health_bps;
+    loan.health_bps_nonce = health_bps_nonce;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_preview_borrow(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
+    let borrow_quote_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // The borrower pays for the computation and its quote account.
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (lending_pool_pda, _) = lending_pool_pda(lender_info.key);
+    if lending_pool_info.key != &lending_pool_pda {
+        return Err(LendingError::PoolMismatch.into());
+    }
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    if collateral_mint_info.key.to_bytes() != lending_pool.collateral_mint {
+        return Err(LendingError::MintMismatch.into());
+    }
+
+    // Only the borrower's own collateral can be quoted.
+    check_borrower_ata(
+        borrower_info.key,
+        collateral_mint_info,
+        collateral_borrower_ata_info,
+    )?;
+
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+
+    let (borrow_quote_pda, bump) = borrow_quote_pda(lender_info.key, borrower_info.key);
+    if borrow_quote_info.key != &borrow_quote_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Create borrow_quote_info on the first preview, the callback fills in the quote.
+    if borrow_quote_info.data_is_empty() {
+        let borrow_quote = BorrowQuote {
+            lending_pool: lending_pool_info.key.to_bytes(),
+            borrower: borrower_info.key.to_bytes(),
+            encrypted_max_loan: RescueCiphertext::default(),
+        };
+        let borrow_quote_data = borrow_quote.try_to_vec()?;
+        let lamports = Rent::get()?.minimum_balance(borrow_quote_data.len());
+
+        solana_cpi::invoke_signed(
+            &solana_system_interface::instruction::create_account(
+                borrower_info.key,
+                &borrow_quote_pda,
+                lamports,
+                borrow_quote_data.len() as u64,
+                program_id,
+            ),
+            &[
+                borrower_info.clone(),
+                borrow_quote_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[&[
+                b"borrow_quote",
+                lender_info.key.as_ref(),
+                borrower_info.key.as_ref(),
+                &[bump],
+            ]],
+        )?;
+        borrow_quote_info
+            .try_borrow_mut_data()?
+            .copy_from_slice(&borrow_quote_data);
+    }
+
+    // Arguments for the encrypted computation, the same price and loan to value as `borrow`.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+        Argument::PlaintextU64(price),
+        Argument::PlaintextU16(lending_pool.loan_to_value_bps),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*collateral_borrower_ata_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only outputs the quote.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        borrower_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::preview_borrow_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        PREVIEW_BORROW_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_preview_borrow_callback(
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let borrow_quote_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_max_loan = RescueCiphertext::try_from(&output_data[..32])?;
+
+    // Update the BorrowQuote account.
+    let mut borrow_quote = BorrowQuote::try_from_slice(&borrow_quote_info.try_borrow_data()?)?;
+    borrow_quote.encrypted_max_loan = encrypted_max_loan;
+    borrow_quote_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&borrow_quote.try_to_vec()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_withdraw_liquidity(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let derived_lending_pool_authority_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+
+    // Source for asset transfer.
+    let asset_vault_ata_info = next_account_info(account_info_iter)?;
+    let asset_vault_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Destination for asset transfer.
+    let asset_lender_ata_info = next_account_info(account_info_iter)?;
+
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_program_info = next_account_info(account_info_iter)?;
+
+    // Signers of the lending pool's multisig, if it has one.
+    let multisig_signers_infos = account_info_iter.as_slice();
+
+    // Only the lender may withdraw liquidity.
+    let lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, multisig_signers_infos)?;
+
+    let (_, lending_pool_bump) = check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        Some(asset_vault_ata_info),
+        &crate::ID,
+    )?;
+
+    // Liquidity can only be withdrawn to the lender.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender_info.key,
+        asset_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if asset_lender_ata_info.key != &asset_lender_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Transfer amount from asset_vault_ata to lender.
+    let asset_transfer = TransferWithComputationInstruction {
+        authority: &Authority::Derived {
+            authority_info: &lending_pool_info.clone(),
+            derived_authority_info: &derived_lending_pool_authority_info.clone(),
+            confidential_spl_token_authority_program:
+                &confidential_spl_token_authority_program_info.clone(),
+        },
+        mint_info: asset_mint_info,
+        source_token_account_info: asset_vault_ata_info,
+        source_token_account_adapter_info: asset_vault_ata_adapter_info,
+        destination_token_account_info: asset_lender_ata_info,
+        multisig_signers_infos,
+    };
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(asset_vault_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(asset_lender_ata_info.key.to_bytes()),
+        Argument::PlaintextU64(amount),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*asset_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[asset_transfer],
+        &arguments,
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::withdraw_liquidity_callback(
+            lender_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        WITHDRAW_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[
+            b"lending_pool",
+            lender_info.key.as_ref(),
+            &[lending_pool_bump],
+        ]],
+    )
+}
+
+pub(crate) fn process_withdraw_liquidity_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // The withdrawal has no state to update, it only settles the transfer.
+    transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    Ok(())
+}
+
+pub(crate) fn process_close_loan(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (lending_pool_pda, _) = lending_pool_pda(lender_info.key);
+    if lending_pool_info.key != &lending_pool_pda {
+        return Err(LendingError::PoolMismatch.into());
+    }
+
+    let (loan_pda, _) = loan_pda(lender_info.key, borrower_info.key);
+    if loan_info.key != &loan_pda {
+        return Err(LendingError::LoanPdaMismatch.into());
+    }
+
+    if loan_info.data_is_empty() || loan_info.owner != &crate::ID {
+        return Err(LendingError::LoanNotInitialized.into());
+    }
+
+    // Only loans that owe nothing can be closed, a loan with a private repayment status has to be
+    // revealed as fully repaid first.
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.settled {
+        return Err(LendingError::LoanActive.into());
+    }
+
+    // Free the borrower slot in the lending pool.
+    let mut lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    lending_pool.remove_borrower(borrower_info.key)?;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    // Close loan_info and refund its rent to the borrower. collateral_vault_ata and
+    // asset_repay_ata can't be closed, they stay allocated and any funds left in them can't be
+    // moved anymore.
+    let loan_lamports = loan_info.lamports();
+    **borrower_info.try_borrow_mut_lamports()? += loan_lamports;
+    **loan_info.try_borrow_mut_lamports()? = 0;
+    loan_info.try_borrow_mut_data()?.fill(0);
+
+    Ok(())
+}
+
+pub(crate) fn process_reinitialize_loan(
+    accounts: &[AccountInfo],
+    maturity_slot: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+
+    if !borrower_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (lending_pool_pda, _) = lending_pool_pda(lender_info.key);
+    if lending_pool_info.key != &lending_pool_pda {
+        return Err(LendingError::PoolMismatch.into());
+    }
+    if lending_pool_info.owner != &crate::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let (loan_pda, _) = loan_pda(lender_info.key, borrower_info.key);
+    if loan_info.key != &loan_pda {
+        return Err(LendingError::LoanPdaMismatch.into());
+    }
+
+    if loan_info.data_is_empty() || loan_info.owner != &crate::ID {
+        return Err(LendingError::LoanNotInitialized.into());
+    }
+
+    // A reinitialized loan is a new origination, so it is gated like initialize_loan.
+    let lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    check_loan_origination(&lending_pool, lender_info, borrower_info.key)?;
+
+    // Only loans that owe nothing can be reinitialized, a matured loan whose settlement left
+    // principal due stays unsettled until the remainder is repaid.
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.settled {
+        return Err(LendingError::LoanActive.into());
+    }
+
+    // The previous maturity may have passed already, which would let the lender settle the new
+    // loan right away.
+    if maturity_slot != 0 && maturity_slot <= Clock::get()?.slot {
+        msg!("Maturity slot {} has already passed", maturity_slot);
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Start over from an empty loan under the same repayment terms and the new maturity, transfer
+    // ids already used stay used.
+    let mut new_loan = Loan::new(
+        borrower_info.key,
+        lending_pool_info.key,
+        loan.reveal_repayment_status,
+        maturity_slot,
+    );
+    new_loan.next_transfer_id = loan.next_transfer_id;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&new_loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_price(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    price: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    let lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    // A zero price would let the whole asset vault be borrowed against no collateral value.
+    if price == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let (pda, bump) = price_oracle_pda(lender_info.key);
+    if price_oracle_info.key != &pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    let price_oracle = PriceOracle {
+        lending_pool: lending_pool_info.key.to_bytes(),
+        price,
+    };
+    let price_oracle_data = price_oracle.try_to_vec()?;
+
+    // Create price_oracle_info on the first price update.
+    if price_oracle_info.data_is_empty() {
+        let lamports = Rent::get()?.minimum_balance(price_oracle_data.len());
+
+        solana_cpi::invoke_signed(
+            &solana_system_interface::instruction::create_account(
+                lender_info.key,
+                &pda,
+                lamports,
+                price_oracle_data.len() as u64,
+                program_id,
+            ),
+            &[
+                lender_info.clone(),
+                price_oracle_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[&[b"price_oracle", lender_info.key.as_ref(), &[bump]]],
+        )?;
+    }
+
+    price_oracle_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&price_oracle_data);
+
+    Ok(())
+}
+
+pub(crate) fn process_update_pool_params(
+    accounts: &[AccountInfo],
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    force: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    // Don't change the terms under existing borrowers unless the lender insists.
+    if lending_pool.num_borrowers > 0 && !force {
+        return Err(LendingError::PoolHasBorrowers.into());
+    }
+
+    lending_pool.interest_rate_bps = interest_rate_bps;
+    lending_pool.loan_to_value_bps = loan_to_value_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_compound_interest(
+    accounts: &[AccountInfo],
+    compound: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    lending_pool.compound = compound;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_lender_approval(
+    accounts: &[AccountInfo],
+    require_lender_approval: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    lending_pool.require_lender_approval = require_lender_approval;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_origination_fee(
+    accounts: &[AccountInfo],
+    origination_fee_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if origination_fee_bps > 10_000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    lending_pool.origination_fee_bps = origination_fee_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_min_collateral_ratio(
+    accounts: &[AccountInfo],
+    min_collateral_ratio_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    lending_pool.min_collateral_ratio_bps = min_collateral_ratio_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_collateral_threshold(
+    accounts: &[AccountInfo],
+    collateral_threshold_bps: u16,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+
+    lending_pool.collateral_threshold_bps = collateral_threshold_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    // The loan of every borrower follows, in the order of the pool's borrowers, with its
+    // collateral vault, transfer account and computation account. The health factor of every
+    // active loan is recomputed under the new threshold, the callback flags the unhealthy ones.
+    let queued_slot = Clock::get()?.slot;
+    for (i, borrower) in lending_pool.borrowers[..lending_pool.num_borrowers as usize]
+        .iter()
+        .enumerate()
+    {
+        let loan_info = next_account_info(account_info_iter)?;
+        let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+        let transfer_account_info = next_account_info(account_info_iter)?;
+        let computation_info = next_account_info(account_info_iter)?;
+
+        let borrower = Pubkey::new_from_array(*borrower);
+        check_loan(
+            lender_info.key,
+            &borrower,
+            loan_info,
+            lending_pool_info,
+            asset_mint_info,
+            collateral_mint_info,
+            collateral_vault_ata_info,
+            None,
+        )?;
+
+        let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+        if !loan.active {
+            continue;
+        }
+
+        let arguments = [
+            Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+            Argument::EncryptedU64(loan.encrypted_principal),
+            Argument::PlaintextU64(price),
+            Argument::PlaintextU16(collateral_threshold_bps),
+        ];
+
+        check_transfer_account(
+            transfer_account_info,
+            &[*collateral_vault_ata_info.key],
+            transfer_id,
+        )?;
+
+        let loan_computation_offset = computation_offset
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+
+        // Same computation as compute_health_factor, paid for by the lender.
+        confidential_spl_token::invoke::transfer_with_computation(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &crate::ID,
+            &[],
+            &arguments,
+            lender_info,
+            transfer_account_info,
+            mxe_info,
+            computation_info,
+            system_program_info,
+            token_program_info,
+            arcium_program_info,
+            confidential_transfer_adapter_info,
+            crate::instruction::compute_health_factor_callback(
+                lender_info.key,
+                &borrower,
+                transfer_account_info.key,
+                queued_slot,
+            )?
+            .into(),
+            loan_computation_offset,
+            HEALTH_FACTOR_COMP_DEF_OFFSET,
+            transfer_id,
+            &[],
+        )?;
+    }
+
+    // The multisig signers, if the pool has a multisig, follow the loans.
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    Ok(())
+}
+
+pub(crate) fn process_set_rate_curve(
+    accounts: &[AccountInfo],
+    base_rate_bps: u16,
+    slope1_bps: u16,
+    slope2_bps: u16,
+    kink_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if kink_bps > 10_000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    lending_pool.base_rate_bps = base_rate_bps;
+    lending_pool.slope1_bps = slope1_bps;
+    lending_pool.slope2_bps = slope2_bps;
+    lending_pool.kink_bps = kink_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_reserve(
+    accounts: &[AccountInfo],
+    reserve_factor_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let reserve_vault_ata_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if reserve_factor_bps > 10_000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // The reserve is paid in the asset, so it can only be a token account of the asset mint.
+    check_token_account_mint(reserve_vault_ata_info, &lending_pool.asset_mint)?;
+
+    lending_pool.reserve_factor_bps = reserve_factor_bps;
+    lending_pool.reserve_vault_ata = reserve_vault_ata_info.key.to_bytes();
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_initialize_reserve_vault(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let derived_reserve_authority_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let reserve_vault_ata_info = next_account_info(account_info_iter)?;
+    let reserve_vault_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    let proof_context_state_info = next_account_info(account_info_iter)?;
+    let key_registry_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_program_info = next_account_info(account_info_iter)?;
+    let ata_program_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if asset_mint_info.key.to_bytes() != lending_pool.asset_mint {
+        return Err(LendingError::MintMismatch.into());
+    }
+
+    let (reserve_pda, reserve_bump) = reserve_pda(lender_info.key);
+    if reserve_info.key != &reserve_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if reserve_vault_ata_info.key != &reserve_vault_ata(lender_info.key, asset_mint_info.key) {
+        return Err(LendingError::VaultAtaMismatch.into());
+    }
+
+    // We utilize a derived authority to have simpler callbacks.
+    let authority = Authority::Derived {
+        authority_info: &reserve_info.clone(),
+        derived_authority_info: &derived_reserve_authority_info.clone(),
+        confidential_spl_token_authority_program: &confidential_spl_token_authority_program_info
+            .clone(),
+    };
+
+    // Create reserve_vault_ata_info with reserve_info as authority.
+    confidential_spl_token::invoke::create_account(
+        &crate::ID,
+        lender_info,
+        authority,
+        asset_mint_info,
+        reserve_vault_ata_info,
+        reserve_vault_ata_adapter_info,
+        system_program_info,
+        token_program_info,
+        ata_program_info,
+        confidential_transfer_adapter_info,
+        proof_context_state_info,
+        key_registry_info,
+        &[],
+        &[&[b"reserve", lender_info.key.as_ref(), &[reserve_bump]]],
+    )?;
+
+    // Repayments route the reserve's cut to the program held vault from now on.
+    lending_pool.reserve_vault_ata = reserve_vault_ata_info.key.to_bytes();
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_withdraw_reserve(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let reserve_info = next_account_info(account_info_iter)?;
+    let derived_reserve_authority_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+
+    // Source for reserve transfer.
+    let reserve_vault_ata_info = next_account_info(account_info_iter)?;
+    let reserve_vault_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Destination for reserve transfer.
+    let asset_lender_ata_info = next_account_info(account_info_iter)?;
+
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_program_info = next_account_info(account_info_iter)?;
+
+    // Only the lender, as the admin of the pool, may withdraw the reserve.
+    let lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if asset_mint_info.key.to_bytes() != lending_pool.asset_mint {
+        return Err(LendingError::MintMismatch.into());
+    }
+
+    let (reserve_pda, reserve_bump) = reserve_pda(lender_info.key);
+    if reserve_info.key != &reserve_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Only a reserve vault created by `initialize_reserve_vault` can be withdrawn from, any other
+    // reserve vault is held by its owner.
+    let expected_reserve_vault_ata = reserve_vault_ata(lender_info.key, asset_mint_info.key);
+    if lending_pool.reserve_vault_ata != expected_reserve_vault_ata.to_bytes() {
+        return Err(LendingError::ReserveVaultMismatch.into());
+    }
+    if reserve_vault_ata_info.key != &expected_reserve_vault_ata {
+        return Err(LendingError::VaultAtaMismatch.into());
+    }
+
+    // The reserve can only be withdrawn to the lender.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender_info.key,
+        asset_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if asset_lender_ata_info.key != &asset_lender_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Transfer amount from reserve_vault_ata to lender.
+    let reserve_transfer = TransferWithComputationInstruction {
+        authority: &Authority::Derived {
+            authority_info: &reserve_info.clone(),
+            derived_authority_info: &derived_reserve_authority_info.clone(),
+            confidential_spl_token_authority_program:
+                &confidential_spl_token_authority_program_info.clone(),
+        },
+        mint_info: asset_mint_info,
+        source_token_account_info: reserve_vault_ata_info,
+        source_token_account_adapter_info: reserve_vault_ata_adapter_info,
+        destination_token_account_info: asset_lender_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(reserve_vault_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(asset_lender_ata_info.key.to_bytes()),
+        Argument::PlaintextU64(amount),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*reserve_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[reserve_transfer],
+        &arguments,
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::withdraw_reserve_callback(lender_info.key, transfer_account_info.key)?
+            .into(),
+        computation_offset,
+        WITHDRAW_RESERVE_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"reserve", lender_info.key.as_ref(), &[reserve_bump]]],
+    )
+}
+
+pub(crate) fn process_withdraw_reserve_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _lending_pool_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // The withdrawal has no state to update, it only settles the transfer.
+    transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    Ok(())
+}
+
+pub(crate) fn process_set_liquidation_grace_slots(
+    accounts: &[AccountInfo],
+    liquidation_grace_slots: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    lending_pool.liquidation_grace_slots = liquidation_grace_slots;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_liquidation_bonus(
+    accounts: &[AccountInfo],
+    liquidation_bonus_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    if liquidation_bonus_bps > 10_000 {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    lending_pool.liquidation_bonus_bps = liquidation_bonus_bps;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_liquidation_proceeds_destination(
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let liquidation_proceeds_destination_info = next_account_info(account_info_iter)?;
+
+    let mut lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+    check_multisig_signers(&lending_pool, account_info_iter.as_slice())?;
+
+    // Seized collateral can only be routed to a token account of the collateral mint.
+    check_token_account_mint(
+        liquidation_proceeds_destination_info,
+        &lending_pool.collateral_mint,
+    )?;
+
+    lending_pool.liquidation_proceeds_destination =
+        liquidation_proceeds_destination_info.key.to_bytes();
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_liquidate(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let liquidator_info = next_account_info(account_info_iter)?;
+    let lender_info = next_account_info(account_info_iter)?;
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let derived_loan_authority_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+
+    // Source for asset transfer.
+    let asset_liquidator_ata_info = next_account_info(account_info_iter)?;
+    let asset_liquidator_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Source for collateral transfers.
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Destination for asset transfer.
+    let asset_lender_ata_info = next_account_info(account_info_iter)?;
+
+    // Destination for seized collateral transfer.
+    let collateral_liquidator_ata_info = next_account_info(account_info_iter)?;
+
+    // Destination for residual collateral transfer.
+    let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
+
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_program_info = next_account_info(account_info_iter)?;
+
+    if !liquidator_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+
+    let (_, loan_bump) = check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    // The lender liquidates without repaying, the seized collateral goes to its proceeds
+    // destination. Third-party liquidators repay from their own token accounts and receive the
+    // seized collateral on them.
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    let is_lender = liquidator_info.key == lender_info.key;
+    if is_lender {
+        if collateral_liquidator_ata_info.key.to_bytes()
+            != lending_pool.liquidation_proceeds_destination
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+    } else {
+        let asset_liquidator_ata = get_associated_confidential_token_account_address(
+            liquidator_info.key,
+            asset_mint_info.key,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+        let collateral_liquidator_ata = get_associated_confidential_token_account_address(
+            liquidator_info.key,
+            collateral_mint_info.key,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+        if asset_liquidator_ata_info.key != &asset_liquidator_ata
+            || collateral_liquidator_ata_info.key != &collateral_liquidator_ata
+        {
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    // The repaid principal can only be paid to the lender.
+    let asset_lender_ata = get_associated_confidential_token_account_address(
+        lender_info.key,
+        asset_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if asset_lender_ata_info.key != &asset_lender_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The residual collateral can only be returned to the borrower.
+    let collateral_borrower_ata = get_associated_confidential_token_account_address(
+        borrower_info.key,
+        collateral_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if collateral_borrower_ata_info.key != &collateral_borrower_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let loan = Loan::unpack(&loan_info.data.borrow())?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+
+    // With a grace period, the loan must have been found unhealthy by `compute_health_factor` at
+    // least liquidation_grace_slots ago.
+    if lending_pool.liquidation_grace_slots > 0
+        && (loan.unhealthy_since_slot == 0
+            || Clock::get()?.slot - loan.unhealthy_since_slot
+                < lending_pool.liquidation_grace_slots)
+    {
+        return Err(LendingError::LiquidationGracePeriod.into());
+    }
+
+    let liquidator_authority = Authority::Signer {
+        authority_info: liquidator_info,
+    };
+    let loan_authority = Authority::Derived {
+        authority_info: &loan_info.clone(),
+        derived_authority_info: &derived_loan_authority_info.clone(),
+        confidential_spl_token_authority_program: &confidential_spl_token_authority_program_info
+            .clone(),
+    };
+
+    // Transfer remaining_principal from the liquidator to the lender.
+    let repay_transfer = TransferWithComputationInstruction {
+        authority: &liquidator_authority,
+        mint_info: asset_mint_info,
+        source_token_account_info: asset_liquidator_ata_info,
+        source_token_account_adapter_info: asset_liquidator_ata_adapter_info,
+        destination_token_account_info: asset_lender_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Transfer seized_collateral from collateral_vault_ata to the liquidator.
+    let seize_transfer = TransferWithComputationInstruction {
+        authority: &loan_authority,
+        mint_info: collateral_mint_info,
+        source_token_account_info: collateral_vault_ata_info,
+        source_token_account_adapter_info: collateral_vault_ata_adapter_info,
+        destination_token_account_info: collateral_liquidator_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Transfer residual_collateral from collateral_vault_ata back to borrower.
+    let residual_transfer = TransferWithComputationInstruction {
+        authority: &loan_authority,
+        mint_info: collateral_mint_info,
+        source_token_account_info: collateral_vault_ata_info,
+        source_token_account_adapter_info: collateral_vault_ata_adapter_info,
+        destination_token_account_info: collateral_borrower_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    let loan_bump_seed = [loan_bump];
+    let loan_seeds: &[&[u8]] = &[
+        b"loan",
+        lender_info.key.as_ref(),
+        borrower_info.key.as_ref(),
+        &loan_bump_seed,
+    ];
+
+    // Arguments for the encrypted computation.
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+    let (transfers, arguments, sources, signer_seeds, comp_def_offset) = if is_lender {
+        (
+            vec![seize_transfer, residual_transfer],
+            vec![
+                Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(collateral_liquidator_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+                Argument::EncryptedU64(loan.encrypted_principal),
+                Argument::PlaintextU64(price),
+                Argument::PlaintextU16(lending_pool.collateral_threshold_bps),
+                Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+                Argument::PlaintextBool(
+                    lending_pool.encrypted_total_debt != RescueCiphertext::default(),
+                ),
+            ],
+            vec![
+                *collateral_vault_ata_info.key,
+                *collateral_vault_ata_info.key,
+            ],
+            vec![loan_seeds, loan_seeds],
+            LIQUIDATE_BY_LENDER_COMP_DEF_OFFSET,
+        )
+    } else {
+        (
+            vec![repay_transfer, seize_transfer, residual_transfer],
+            vec![
+                Argument::ConfidentialTokenAccount(asset_liquidator_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(asset_lender_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(collateral_liquidator_ata_info.key.to_bytes()),
+                Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+                Argument::EncryptedU64(loan.encrypted_principal),
+                Argument::PlaintextU64(price),
+                Argument::PlaintextU16(lending_pool.collateral_threshold_bps),
+                Argument::PlaintextU16(lending_pool.liquidation_bonus_bps),
+                Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+                Argument::PlaintextBool(
+                    lending_pool.encrypted_total_debt != RescueCiphertext::default(),
+                ),
+            ],
+            vec![
+                *asset_liquidator_ata_info.key,
+                *collateral_vault_ata_info.key,
+                *collateral_vault_ata_info.key,
+            ],
+            // The liquidator signs its repayment itself.
+            vec![&[][..], loan_seeds, loan_seeds],
+            LIQUIDATE_COMP_DEF_OFFSET,
+        )
+    };
+
+    check_transfer_account(transfer_account_info, &sources, transfer_id)?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &transfers,
+        &arguments,
+        liquidator_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::liquidate_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+            lending_pool.total_debt_version,
+        )?
+        .into(),
+        computation_offset,
+        comp_def_offset,
+        transfer_id,
+        &signer_seeds,
+    )
+}
+
+pub(crate) fn process_liquidate_callback(
+    accounts: &[AccountInfo],
+    queued_total_debt_version: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // A failed liquidation moved no funds, the loan is left as it is.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Liquidation transfers failed");
+        return Err(LendingError::TransferFailed.into());
+    }
+
+    // Take the custom output data from the computation.
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let remaining_due = RescueCiphertext::try_from(&output_data[..32])?;
+    let loan_is_liquidated = bool::try_from_slice(&output_data[32..33])?;
+    let encrypted_total_debt = RescueCiphertext::try_from(&output_data[33..65])?;
+
+    // A healthy loan is left untouched by the computation.
+    if !loan_is_liquidated {
+        return Ok(());
+    }
+
+    // Update the Loan account, the liquidation clears the remaining due and any shortfall of the
+    // seized collateral is written off by the lender.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    loan.encrypted_principal = remaining_due;
+    if loan.active {
+        update_num_active_loans(lending_pool_info, false)?;
+    }
+    loan.active = false;
+    loan.settled = true;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    // The liquidated principal leaves the pool's total debt.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )
+}
+
+pub(crate) fn process_settle_matured_loan(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let derived_loan_authority_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let collateral_mint_info = next_account_info(account_info_iter)?;
+    let price_oracle_info = next_account_info(account_info_iter)?;
+
+    // Source for collateral transfers.
+    let collateral_vault_ata_info = next_account_info(account_info_iter)?;
+    let collateral_vault_ata_adapter_info = next_account_info(account_info_iter)?;
+
+    // Destination for seized collateral transfer.
+    let collateral_lender_ata_info = next_account_info(account_info_iter)?;
+
+    // Destination for residual collateral transfer.
+    let collateral_borrower_ata_info = next_account_info(account_info_iter)?;
+
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_program_info = next_account_info(account_info_iter)?;
+
+    // Only the lender may settle a matured loan.
+    if !lender_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        None,
+        &crate::ID,
+    )?;
+
+    let (_, loan_bump) = check_loan(
+        lender_info.key,
+        borrower_info.key,
+        loan_info,
+        lending_pool_info,
+        asset_mint_info,
+        collateral_mint_info,
+        collateral_vault_ata_info,
+        None,
+    )?;
+
+    // The seized collateral can only be paid to the lender.
+    let collateral_lender_ata = get_associated_confidential_token_account_address(
+        lender_info.key,
+        collateral_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if collateral_lender_ata_info.key != &collateral_lender_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The residual collateral can only be returned to the borrower.
+    let collateral_borrower_ata = get_associated_confidential_token_account_address(
+        borrower_info.key,
+        collateral_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if collateral_borrower_ata_info.key != &collateral_borrower_ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let loan = Loan::unpack(&loan_info.data.borrow())?;
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+
+    let current_slot = Clock::get()?.slot;
+    if loan.maturity_slot == 0 || current_slot < loan.maturity_slot {
+        msg!("Loan matures at slot {}", loan.maturity_slot);
+        return Err(LendingError::LoanNotMatured.into());
+    }
+
+    let loan_authority = Authority::Derived {
+        authority_info: &loan_info.clone(),
+        derived_authority_info: &derived_loan_authority_info.clone(),
+        confidential_spl_token_authority_program: &confidential_spl_token_authority_program_info
+            .clone(),
+    };
+
+    // Transfer seized_collateral from collateral_vault_ata to the lender.
+    let seize_transfer = TransferWithComputationInstruction {
+        authority: &loan_authority,
+        mint_info: collateral_mint_info,
+        source_token_account_info: collateral_vault_ata_info,
+        source_token_account_adapter_info: collateral_vault_ata_adapter_info,
+        destination_token_account_info: collateral_lender_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Transfer residual_collateral from collateral_vault_ata back to borrower.
+    let residual_transfer = TransferWithComputationInstruction {
+        authority: &loan_authority,
+        mint_info: collateral_mint_info,
+        source_token_account_info: collateral_vault_ata_info,
+        source_token_account_adapter_info: collateral_vault_ata_adapter_info,
+        destination_token_account_info: collateral_borrower_ata_info,
+        multisig_signers_infos: &[],
+    };
+
+    // Arguments for the encrypted computation.
+    let price = check_price_account(lender_info.key, lending_pool_info, price_oracle_info)?;
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    let arguments = [
+        Argument::ConfidentialTokenAccount(collateral_vault_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(collateral_lender_ata_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(collateral_borrower_ata_info.key.to_bytes()),
+        Argument::EncryptedU64(loan.encrypted_principal),
+        Argument::PlaintextU64(price),
+        Argument::EncryptedU64(lending_pool.encrypted_total_debt),
+        Argument::PlaintextBool(lending_pool.encrypted_total_debt != RescueCiphertext::default()),
+    ];
+
+    check_transfer_account(
+        transfer_account_info,
+        &[
+            *collateral_vault_ata_info.key,
+            *collateral_vault_ata_info.key,
+        ],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[seize_transfer, residual_transfer],
+        &arguments,
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::settle_matured_loan_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+            lending_pool.total_debt_version,
+        )?
+        .into(),
+        computation_offset,
+        SETTLE_MATURED_LOAN_COMP_DEF_OFFSET,
+        transfer_id,
+        &[
+            &[
+                b"loan",
+                lender_info.key.as_ref(),
+                borrower_info.key.as_ref(),
+                &[loan_bump],
+            ],
+            &[
+                b"loan",
+                lender_info.key.as_ref(),
+                borrower_info.key.as_ref(),
+                &[loan_bump],
+            ],
+        ],
+    )
+}
+
+pub(crate) fn process_settle_matured_loan_callback(
+    accounts: &[AccountInfo],
+    queued_total_debt_version: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // A failed settlement moved no collateral, the loan is left as it is.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Settlement transfers failed");
+        return Err(LendingError::TransferFailed.into());
+    }
+
+    // Take the custom output data from the computation.
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let remaining_due = RescueCiphertext::try_from(&output_data[..32])?;
+    let loan_is_settled = bool::try_from_slice(&output_data[32..33])?;
+    let encrypted_total_debt = RescueCiphertext::try_from(&output_data[33..65])?;
+
+    // Update the Loan account, any principal the collateral didn't cover is left due and keeps the
+    // loan active until it is repaid.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    loan.encrypted_principal = remaining_due;
+    if loan_is_settled {
+        if loan.active {
+            update_num_active_loans(lending_pool_info, false)?;
+        }
+        loan.active = false;
+        loan.settled = true;
+    }
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    // The pool's total debt follows the loan's remaining due.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )
+}
+
+pub(crate) fn process_reveal_repayment_status(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let borrower_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Only the lender may reveal the repayment status.
+    let lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+
+    if loan_info.key != &loan_pda(lender_info.key, borrower_info.key).0 {
+        return Err(LendingError::LoanPdaMismatch.into());
+    }
+
+    let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if loan.reveal_repayment_status {
+        return Err(LendingError::RepaymentStatusPublic.into());
+    }
+    if !loan.active {
+        return Err(LendingError::LoanInactive.into());
+    }
+    if loan.encrypted_repayment_status == RescueCiphertext::default() {
+        return Err(LendingError::RepaymentStatusUnknown.into());
+    }
+
+    // The transfer account is derived from the loan's collateral vault, though no transfer is made.
+    let collateral_vault_ata = get_associated_confidential_token_account_address(
+        loan_info.key,
+        &Pubkey::new_from_array(lending_pool.collateral_mint),
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    check_transfer_account(transfer_account_info, &[collateral_vault_ata], transfer_id)?;
+
+    // No transfers are attached, the computation only reveals the repayment status.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &[Argument::EncryptedU64(loan.encrypted_repayment_status)],
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::reveal_repayment_status_callback(
+            lender_info.key,
+            borrower_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        REVEAL_REPAYMENT_STATUS_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_reveal_repayment_status_callback(
+    accounts: &[AccountInfo],
+    _instruction_data: &[u8],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let loan_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let loan_is_fully_repaid = bool::try_from_slice(&output_data[..1])?;
+
+    // Only a loan still active when the status is revealed is settled by it, the reveal never
+    // reactivates a loan that was closed while the computation was pending.
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if !loan.active || !loan_is_fully_repaid {
+        return Ok(());
+    }
+    update_num_active_loans(lending_pool_info, false)?;
+    loan.active = false;
+    loan.settled = true;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_compute_total_debt(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    encryption_pubkey: [u8; 32],
+    nonce: u128,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lender_info = next_account_info(account_info_iter)?;
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let asset_mint_info = next_account_info(account_info_iter)?;
+    let asset_vault_ata_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    // Only the lender may query the total debt of the pool.
+    let lending_pool = check_lending_pool_authority(lender_info, lending_pool_info)?;
+
+    check_lending_pool(
+        lender_info.key,
+        lending_pool_info,
+        asset_mint_info,
+        Some(asset_vault_ata_info),
+        &crate::ID,
+    )?;
+
+    // The loan of every borrower follows, in the order of the pool's borrowers.
+    let mut principals = Vec::with_capacity(MAX_BORROWERS);
+    for borrower in &lending_pool.borrowers[..lending_pool.num_borrowers as usize] {
+        let loan_info = next_account_info(account_info_iter)?;
+        let (loan_pda, _) = loan_pda(lender_info.key, &Pubkey::new_from_array(*borrower));
+        if loan_info.key != &loan_pda {
+            return Err(LendingError::LoanPdaMismatch.into());
+        }
+
+        let loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+        if loan.active {
+            principals.push(loan.encrypted_principal);
+        }
+    }
+
+    // Arguments for the encrypted computation, unused principal slots are padded and ignored.
+    let num_loans = principals.len();
+    let mut arguments = Vec::with_capacity(MAX_BORROWERS + 3);
+    arguments.push(Argument::ArcisPubkey(encryption_pubkey));
+    arguments.push(Argument::PlaintextU128(nonce));
+    for i in 0..MAX_BORROWERS {
+        let encrypted_principal = match principals.get(i) {
+            Some(encrypted_principal) => *encrypted_principal,
+            None => RescueCiphertext::default(),
+        };
+        arguments.push(Argument::EncryptedU64(encrypted_principal));
+    }
+    arguments.push(Argument::PlaintextU16(num_loans as u16));
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*asset_vault_ata_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only outputs the total debt.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        lender_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::compute_total_debt_callback(
+            lender_info.key,
+            transfer_account_info.key,
+            lending_pool.total_debt_version,
+        )?
+        .into(),
+        computation_offset,
+        TOTAL_DEBT_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_compute_total_debt_callback(
+    accounts: &[AccountInfo],
+    queued_total_debt_version: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let lending_pool_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // A failed computation leaves both totals as they are.
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        msg!("Total debt computation failed");
+        return Ok(());
+    }
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let encrypted_total_debt = RescueCiphertext::try_from(&output_data[..32])?;
+    // The total encrypted to the lender follows: their encryption key, the output nonce and the
+    // ciphertext.
+    let total_debt_for_lender_nonce = u128::try_from_slice(&output_data[64..80])?;
+    let encrypted_total_debt_for_lender = RescueCiphertext::try_from(&output_data[80..112])?;
+
+    // The computed total is kept apart from the running total.
+    let mut lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    lending_pool.encrypted_computed_total_debt = encrypted_total_debt;
+    lending_pool.encrypted_total_debt_for_lender = encrypted_total_debt_for_lender;
+    lending_pool.total_debt_for_lender_nonce = total_debt_for_lender_nonce;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    // Unless a principal changed while the computation was pending, the computed total also
+    // resyncs the running total, e.g. after a stale total was dropped.
+    update_total_debt(
+        lending_pool_info,
+        encrypted_total_debt,
+        queued_total_debt_version,
+    )
+}
+
+/// Replaces the pool's running total debt with the total output by a computation queued at
+/// `queued_total_debt_version`. A total derived from a running total replaced since is stale and
+/// dropped, `compute_total_debt` resyncs the running total.
+fn update_total_debt(
+    lending_pool_info: &AccountInfo,
+    encrypted_total_debt: RescueCiphertext,
+    queued_total_debt_version: u64,
+) -> ProgramResult {
+    let mut lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    if lending_pool.total_debt_version != queued_total_debt_version {
+        msg!("Stale total debt, ignored");
+        return Ok(());
+    }
+    lending_pool.encrypted_total_debt = encrypted_total_debt;
+    lending_pool.total_debt_version += 1;
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+/// Counts a loan becoming active, or no longer active, against the lending pool's capacity.
+fn update_num_active_loans(lending_pool_info: &AccountInfo, activated: bool) -> ProgramResult {
+    let mut lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    if activated {
+        lending_pool.num_active_loans += 1;
+    } else {
+        lending_pool.num_active_loans = lending_pool.num_active_loans.saturating_sub(1);
+    }
+    lending_pool_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&lending_pool.pack()?);
+
+    Ok(())
+}
+
+fn check_lending_pool(
+    lender: &Pubkey,
+    lending_pool_info: &AccountInfo,
+    asset_mint_info: &AccountInfo,
+    asset_vault_ata_info: Option<&AccountInfo>,
+    lending_pool_owner: &Pubkey,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let (pda, bump) = lending_pool_pda(lender);
+
+    if lending_pool_info.key != &pda {
+        return Err(LendingError::PoolMismatch.into());
+    }
+
+    if lending_pool_info.owner != lending_pool_owner {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // Once initialized, the account has to be a lending pool.
+    if lending_pool_owner == &crate::ID {
+        LendingPool::check_discriminator(&lending_pool_info.try_borrow_data()?)?;
+    }
+
+    let (expected_ata, _) = get_associated_token_address_and_adapter(
+        &pda,
+        asset_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    if let Some(asset_vault_ata_info) = asset_vault_ata_info {
+        if asset_vault_ata_info.key != &expected_ata {
+            return Err(LendingError::VaultAtaMismatch.into());
+        }
+    }
+
+    Ok((pda, bump))
+}
+
+/// Checks that the lending pool lets borrower originate a loan: the lender signed if the pool
+/// requires their approval, the borrower is allowlisted and the pool has capacity left.
+fn check_loan_origination(
+    lending_pool: &LendingPool,
+    lender_info: &AccountInfo,
+    borrower: &Pubkey,
+) -> ProgramResult {
+    if lending_pool.require_lender_approval && !lender_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if !lending_pool.is_allowed_borrower(borrower) {
+        return Err(LendingError::NotAllowlisted.into());
+    }
+    if !lending_pool.has_active_loan_capacity() {
+        return Err(LendingError::PoolAtCapacity.into());
+    }
+
+    Ok(())
+}
+
+/// Checks that lender_info signed and owns lending_pool_info, returning the deserialized pool.
+fn check_lending_pool_authority(
+    lender_info: &AccountInfo,
+    lending_pool_info: &AccountInfo,
+) -> Result<LendingPool, ProgramError> {
+    if !lender_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let (pda, _) = lending_pool_pda(lender_info.key);
+
+    if lending_pool_info.key != &pda {
+        return Err(LendingError::PoolMismatch.into());
+    }
+
+    if lending_pool_info.owner != &crate::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let lending_pool = LendingPool::unpack(&lending_pool_info.data.borrow())?;
+    if lending_pool.lender != lender_info.key.to_bytes() {
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(lending_pool)
+}
+
+/// Checks that enough of the lending pool's multisig signers signed among signers_infos.
+fn check_multisig_signers(
+    lending_pool: &LendingPool,
+    signers_infos: &[AccountInfo],
+) -> ProgramResult {
+    let signers = signers_infos
+        .iter()
+        .filter(|signer_info| signer_info.is_signer)
+        .map(|signer_info| *signer_info.key)
+        .collect::<Vec<_>>();
+
+    if !lending_pool.has_multisig_threshold(&signers) {
+        return Err(LendingError::MultisigThresholdNotMet.into());
+    }
+
+    Ok(())
+}
+
+/// Returns the price published in the lender's price oracle account.
+fn check_price_account(
+    lender: &Pubkey,
+    lending_pool_info: &AccountInfo,
+    price_oracle_info: &AccountInfo,
+) -> Result<u64, ProgramError> {
+    let (pda, _) = price_oracle_pda(lender);
+
+    if price_oracle_info.key != &pda {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // The price has not been published yet.
+    if price_oracle_info.owner != &crate::ID {
+        return Err(ProgramError::UninitializedAccount);
+    }
+
+    let price_oracle = PriceOracle::try_from_slice(&price_oracle_info.try_borrow_data()?)?;
+    if price_oracle.lending_pool != lending_pool_info.key.to_bytes() || price_oracle.price == 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(price_oracle.price)
+}
+
+/// Checks that token_account_info is a confidential token account of mint.
+fn check_token_account_mint(
+    token_account_info: &AccountInfo,
+    mint: &[u8; 32],
+) -> Result<(), ProgramError> {
+    if token_account_info.owner != &confidential_spl_token::programs::confidential_spl_token::ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    // The mint is stored in the first 32 bytes of a token account.
+    let data = token_account_info.try_borrow_data()?;
+    if data.len() < 32 || &data[..32] != mint {
+        return Err(LendingError::MintMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Checks that `ata_info` is the borrower's associated token account of `mint_info` and has been
+/// created.
+fn check_borrower_ata(
+    borrower: &Pubkey,
+    mint_info: &AccountInfo,
+    ata_info: &AccountInfo,
+) -> ProgramResult {
+    let ata = get_associated_confidential_token_account_address(
+        borrower,
+        mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if ata_info.key != &ata
+        || ata_info.owner != &confidential_spl_token::programs::confidential_spl_token::ID
+    {
+        return Err(LendingError::BorrowerAtaMissing.into());
+    }
+
+    Ok(())
+}
+
+/// Checks that `transfer_id` is the loan's next transfer id and advances it, so that borrows and
+/// repays of a loan never derive the same transfer account.
+fn use_next_transfer_id(loan_info: &AccountInfo, transfer_id: u32) -> ProgramResult {
+    let mut loan = Loan::unpack(&loan_info.try_borrow_data()?)?;
+    if transfer_id != loan.next_transfer_id {
+        msg!("Expected transfer_id {}", loan.next_transfer_id);
+        return Err(LendingError::TransferIdMismatch.into());
+    }
+
+    loan.next_transfer_id += 1;
+    loan_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&loan.pack()?);
+
+    Ok(())
+}
+
+fn check_loan(
+    lender: &Pubkey,
+    borrower: &Pubkey,
+    loan_info: &AccountInfo,
+    lending_pool_info: &AccountInfo,
+    asset_mint_info: &AccountInfo,
+    collateral_mint_info: &AccountInfo,
+    collateral_vault_ata_info: &AccountInfo,
+    asset_repay_ata_info: Option<&AccountInfo>,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let (loan_pda, bump) = loan_pda(lender, borrower);
+
+    if loan_info.key != &loan_pda {
+        return Err(LendingError::LoanPdaMismatch.into());
+    }
+
+    // Once initialized, the account has to be a loan.
+    if loan_info.owner == &crate::ID {
+        Loan::check_discriminator(&loan_info.try_borrow_data()?)?;
+    }
+
+    // The loan can only use the mints the lender created the lending pool for.
+    let lending_pool = LendingPool::unpack(&lending_pool_info.try_borrow_data()?)?;
+    if asset_mint_info.key.to_bytes() != lending_pool.asset_mint
+        || collateral_mint_info.key.to_bytes() != lending_pool.collateral_mint
+    {
+        return Err(LendingError::MintMismatch.into());
+    }
+
+    let (collateral_vault_ata_pda, _) = get_associated_token_address_and_adapter(
+        &loan_pda,
+        collateral_mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    if collateral_vault_ata_info.key != &collateral_vault_ata_pda {
+        return Err(LendingError::VaultAtaMismatch.into());
+    }
 
     if let Some(asset_repay_ata_info) = asset_repay_ata_info {
         let (asset_repay_ata_pda, _) = get_associated_token_address_and_adapter(
@@ -672,9 +3765,23 @@ fn check_loan(
             true,
         );
         if asset_repay_ata_info.key != &asset_repay_ata_pda {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(LendingError::VaultAtaMismatch.into());
         }
     }
 
     Ok((loan_pda, bump))
 }
+
+/// Checks that `transfer_account_info` is derived from `transfer_id` and the source token account
+/// of each transfer, in order, so a transfer account derived for other transfers is rejected.
+fn check_transfer_account(
+    transfer_account_info: &AccountInfo,
+    sources: &[Pubkey],
+    transfer_id: u32,
+) -> ProgramResult {
+    if *transfer_account_info.key != get_transfer_account_address(sources, transfer_id) {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(())
+}
@@ -1,9 +1,63 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use confidential_spl_token::confidential_transfer_adapter::state::RescueCiphertext;
+use confidential_spl_token::get_associated_confidential_token_account_address;
 use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 use solana_program_error::ProgramResult;
 
+use crate::error::LendingError;
+
 pub const MAX_BORROWERS: usize = 8;
+pub const MAX_ALLOWED_BORROWERS: usize = 16;
+/// Most signers a lending pool multisig can have, as in SPL Token multisigs.
+pub const MAX_MULTISIG_SIGNERS: usize = 11;
+
+/// Length of the discriminator prefixing `LendingPool` and `Loan` account data.
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+/// Checks that data starts with discriminator, so accounts of one type can't be passed as another.
+fn check_discriminator(data: &[u8], discriminator: &[u8; DISCRIMINATOR_LEN]) -> ProgramResult {
+    if data.len() < DISCRIMINATOR_LEN || &data[..DISCRIMINATOR_LEN] != discriminator {
+        return Err(LendingError::DiscriminatorMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Checks the discriminator of data and deserializes the account following it.
+fn unpack<T: BorshDeserialize>(
+    data: &[u8],
+    discriminator: &[u8; DISCRIMINATOR_LEN],
+) -> Result<T, ProgramError> {
+    check_discriminator(data, discriminator)?;
+
+    Ok(T::try_from_slice(&data[DISCRIMINATOR_LEN..])?)
+}
+
+/// Serializes account prefixed by discriminator.
+fn pack<T: BorshSerialize>(
+    account: &T,
+    discriminator: &[u8; DISCRIMINATOR_LEN],
+) -> Result<Vec<u8>, ProgramError> {
+    let mut data = discriminator.to_vec();
+    account.serialize(&mut data)?;
+
+    Ok(data)
+}
+
+/// Price of one unit of `collateral_mint` in units of `asset_mint`, published by the lender.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct PriceOracle {
+    pub lending_pool: [u8; 32],
+    pub price: u64,
+}
+
+/// Most a borrower could borrow against its collateral, as quoted by the last `preview_borrow`.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub struct BorrowQuote {
+    pub lending_pool: [u8; 32],
+    pub borrower: [u8; 32],
+    pub encrypted_max_loan: RescueCiphertext,
+}
 
 #[derive(BorshSerialize, BorshDeserialize, Default)]
 pub struct LendingPool {
@@ -17,9 +71,91 @@ pub struct LendingPool {
 
     pub num_borrowers: u8,
     pub borrowers: [[u8; 32]; MAX_BORROWERS],
+
+    /// Once enabled, only allowlisted borrowers can open loans against the pool.
+    pub allowlist_enabled: bool,
+    pub num_allowed_borrowers: u8,
+    pub allowed_borrowers: [[u8; 32]; MAX_ALLOWED_BORROWERS],
+
+    /// Collateral token account receiving the collateral seized on liquidation by the lender.
+    pub liquidation_proceeds_destination: [u8; 32],
+
+    /// Bonus on the collateral seized on liquidation, paid to third-party liquidators.
+    pub liquidation_bonus_bps: u16,
+
+    /// Fee charged on the loan amount at borrow time, paid to the lender's asset token account.
+    pub origination_fee_bps: u16,
+
+    /// Maximum number of loans with an outstanding principal, 0 for no limit.
+    pub max_active_loans: u16,
+    pub num_active_loans: u16,
+
+    /// Compounds the interest on repayment instead of charging simple interest.
+    pub compound: bool,
+
+    /// Bounds of the amount lent by a single borrow.
+    pub min_borrow: u64,
+    pub max_borrow: u64,
+
+    /// Number of slots a loan must stay unhealthy before it can be liquidated.
+    pub liquidation_grace_slots: u64,
+
+    /// Share of the interest repaid that goes to the `reserve_vault_ata` instead of the lender.
+    pub reserve_factor_bps: u16,
+    pub reserve_vault_ata: [u8; 32],
+
+    /// Loans can only be opened with the lender co-signing `initialize_loan`.
+    pub require_lender_approval: bool,
+
+    /// Running total of the outstanding principal over every active loan, updated by every
+    /// computation changing a loan's principal.
+    pub encrypted_total_debt: RescueCiphertext,
+
+    /// Minimum value of the collateral backing a borrow relative to the loan, 0 disables it.
+    pub min_collateral_ratio_bps: u16,
+
+    /// Interest rate curve keyed on utilization: the rate grows from `base_rate_bps` by up to
+    /// `slope1_bps` until the `kink_bps` utilization and by up to `slope2_bps` above it. While
+    /// `kink_bps` is 0 the curve is disabled and `interest_rate_bps` applies at any utilization.
+    pub base_rate_bps: u16,
+    pub slope1_bps: u16,
+    pub slope2_bps: u16,
+    pub kink_bps: u16,
+
+    /// Withdrawing funds and changing the pool's settings additionally require
+    /// `multisig_threshold` of the `multisig_signers` to sign, 0 while the pool has no multisig.
+    pub multisig_threshold: u8,
+    pub num_multisig_signers: u8,
+    pub multisig_signers: [[u8; 32]; MAX_MULTISIG_SIGNERS],
+
+    /// Bumped on every write of `encrypted_total_debt`, a computation queued on an older version
+    /// derived its total from a replaced running total and its total is dropped.
+    pub total_debt_version: u64,
+
+    /// Outstanding principal summed over every active loan by the last `compute_total_debt`.
+    pub encrypted_computed_total_debt: RescueCiphertext,
+    /// The same total encrypted to the lender's x25519 key with `total_debt_for_lender_nonce`.
+    pub encrypted_total_debt_for_lender: RescueCiphertext,
+    pub total_debt_for_lender_nonce: u128,
 }
 
 impl LendingPool {
+    /// First 8 bytes of `sha256("account:LendingPool")`.
+    pub const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [208, 40, 242, 82, 186, 18, 75, 36];
+
+    pub fn check_discriminator(data: &[u8]) -> ProgramResult {
+        check_discriminator(data, &Self::DISCRIMINATOR)
+    }
+
+    /// Deserializes a lending pool account, rejecting accounts of any other type.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        unpack(data, &Self::DISCRIMINATOR)
+    }
+
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        pack(self, &Self::DISCRIMINATOR)
+    }
+
     pub fn new(
         lender: &Pubkey,
         asset_mint: &Pubkey,
@@ -27,7 +163,26 @@ impl LendingPool {
         interest_rate_bps: u16,
         loan_to_value_bps: u16,
         collateral_threshold_bps: u16,
+        max_active_loans: u16,
+        min_borrow: u64,
+        max_borrow: u64,
     ) -> Self {
+        // The reserve is held by the lender by default.
+        let reserve_vault_ata = get_associated_confidential_token_account_address(
+            lender,
+            asset_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+
+        // Collateral seized by the lender goes to the lender by default.
+        let liquidation_proceeds_destination = get_associated_confidential_token_account_address(
+            lender,
+            collateral_mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+
         Self {
             lender: lender.to_bytes(),
             asset_mint: asset_mint.to_bytes(),
@@ -35,13 +190,37 @@ impl LendingPool {
             interest_rate_bps,
             loan_to_value_bps,
             collateral_threshold_bps,
+            max_active_loans,
+            min_borrow,
+            max_borrow,
+            reserve_vault_ata: reserve_vault_ata.to_bytes(),
+            liquidation_proceeds_destination: liquidation_proceeds_destination.to_bytes(),
             ..Default::default()
         }
     }
 
+    /// Rate curve the interest accrues at, as `(base_rate_bps, slope1_bps, slope2_bps, kink_bps)`,
+    /// flat at `interest_rate_bps` while the curve is disabled.
+    pub fn rate_curve(&self) -> (u16, u16, u16, u16) {
+        if self.kink_bps == 0 {
+            return (self.interest_rate_bps, 0, 0, 10_000);
+        }
+
+        (
+            self.base_rate_bps,
+            self.slope1_bps,
+            self.slope2_bps,
+            self.kink_bps,
+        )
+    }
+
+    pub fn has_active_loan_capacity(&self) -> bool {
+        self.max_active_loans == 0 || self.num_active_loans < self.max_active_loans
+    }
+
     pub fn add_borrower(&mut self, borrower: &Pubkey) -> ProgramResult {
         if self.num_borrowers as usize >= MAX_BORROWERS {
-            return Err(ProgramError::InvalidAccountData);
+            return Err(LendingError::TooManyBorrowers.into());
         }
 
         let borrower_idx = self.num_borrowers as usize;
@@ -70,6 +249,89 @@ impl LendingPool {
 
         Ok(idx)
     }
+
+    /// Removes borrower by moving the last borrower into its slot.
+    pub fn remove_borrower(&mut self, borrower: &Pubkey) -> ProgramResult {
+        let borrower_idx = self.find_borrower(borrower)?;
+        let last_idx = self.num_borrowers as usize - 1;
+
+        self.borrowers[borrower_idx] = self.borrowers[last_idx];
+        self.borrowers[last_idx] = [0; 32];
+        self.num_borrowers -= 1;
+
+        Ok(())
+    }
+
+    /// Adds borrower to the allowlist and enables it, adding a borrower twice is a no-op.
+    pub fn add_allowed_borrower(&mut self, borrower: &Pubkey) -> ProgramResult {
+        self.allowlist_enabled = true;
+
+        if self.is_allowed_borrower(borrower) {
+            return Ok(());
+        }
+
+        if self.num_allowed_borrowers as usize >= MAX_ALLOWED_BORROWERS {
+            return Err(LendingError::AllowlistFull.into());
+        }
+
+        let idx = self.num_allowed_borrowers as usize;
+        self.allowed_borrowers[idx] = borrower.to_bytes();
+        self.num_allowed_borrowers += 1;
+
+        Ok(())
+    }
+
+    /// Sets the multisig authorizing the pool's sensitive operations, `multisig_threshold` of 0
+    /// with no `multisig_signers` leaves the pool without a multisig.
+    pub fn set_multisig(
+        &mut self,
+        multisig_threshold: u8,
+        multisig_signers: &[[u8; 32]],
+    ) -> ProgramResult {
+        if multisig_signers.len() > MAX_MULTISIG_SIGNERS
+            || (multisig_threshold == 0) != multisig_signers.is_empty()
+            || multisig_threshold as usize > multisig_signers.len()
+        {
+            return Err(LendingError::InvalidMultisig.into());
+        }
+
+        for (i, signer) in multisig_signers.iter().enumerate() {
+            if multisig_signers[..i].contains(signer) {
+                return Err(LendingError::InvalidMultisig.into());
+            }
+        }
+
+        self.multisig_signers = Default::default();
+        self.multisig_signers[..multisig_signers.len()].copy_from_slice(multisig_signers);
+        self.multisig_threshold = multisig_threshold;
+        self.num_multisig_signers = multisig_signers.len() as u8;
+
+        Ok(())
+    }
+
+    /// Whether at least `multisig_threshold` of the pool's multisig signers are among `signers`,
+    /// each signer counting once.
+    pub fn has_multisig_threshold(&self, signers: &[Pubkey]) -> bool {
+        let num_signed = self.multisig_signers[..self.num_multisig_signers as usize]
+            .iter()
+            .filter(|multisig_signer| {
+                signers
+                    .iter()
+                    .any(|signer| &signer.to_bytes() == *multisig_signer)
+            })
+            .count();
+
+        num_signed >= self.multisig_threshold as usize
+    }
+
+    pub fn is_allowed_borrower(&self, borrower: &Pubkey) -> bool {
+        if !self.allowlist_enabled {
+            return true;
+        }
+
+        let borrower = borrower.to_bytes();
+        self.allowed_borrowers[..self.num_allowed_borrowers as usize].contains(&borrower)
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Default, PartialEq, Clone, Copy)]
@@ -80,10 +342,73 @@ pub struct Loan {
     pub encrypted_principal: RescueCiphertext,
     pub encrypted_collateral: RescueCiphertext,
     pub last_update_slot: u64,
+    /// Whether repayments reveal if the loan has been fully repaid, otherwise the status is kept in
+    /// `encrypted_repayment_status` until the lender reveals it.
+    pub reveal_repayment_status: bool,
+    pub encrypted_repayment_status: RescueCiphertext,
+    /// Collateral to debt ratio in basis points as of the last `reveal_health`, encrypted to the
+    /// borrower's x25519 key with `health_bps_nonce`.
+    pub encrypted_health_bps: RescueCiphertext,
+    pub health_bps_nonce: u128,
+    /// Health factor in basis points, as of the last `compute_health_factor`.
+    pub encrypted_health_factor: RescueCiphertext,
+    /// Slot at which `compute_health_factor` first found the loan unhealthy, 0 while healthy.
+    pub unhealthy_since_slot: u64,
+    /// Transfer id the next `borrow` or `repay` of the loan must use.
+    pub next_transfer_id: u32,
+    /// Slot from which the lender can settle the loan against its collateral, 0 for no maturity.
+    pub maturity_slot: u64,
+    /// Split of the last repayment between the interest and the principal it paid off.
+    pub encrypted_interest_paid: RescueCiphertext,
+    pub encrypted_principal_paid: RescueCiphertext,
+    /// Collateral released to the borrower by the repayments of the loan so far.
+    pub encrypted_released_collateral: RescueCiphertext,
+    /// Whether the loan owes nothing: never borrowed against, fully repaid, settled at maturity or
+    /// liquidated. Only settled loans can be closed or reinitialized.
+    pub settled: bool,
+    /// Slot at which the collateral last changed by a `borrow` or `top_up_collateral`, health
+    /// factors queued before it are discarded.
+    pub collateral_update_slot: u64,
 }
 
 impl Loan {
-    pub fn new(borrower: &Pubkey, lending_pool: &Pubkey) -> Self {
+    /// First 8 bytes of `sha256("account:Loan")`.
+    pub const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [20, 195, 70, 117, 165, 227, 182, 1];
+
+    pub fn check_discriminator(data: &[u8]) -> ProgramResult {
+        check_discriminator(data, &Self::DISCRIMINATOR)
+    }
+
+    /// Deserializes a loan account, rejecting accounts of any other type.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        unpack(data, &Self::DISCRIMINATOR)
+    }
+
+    pub fn pack(&self) -> Result<Vec<u8>, ProgramError> {
+        pack(self, &Self::DISCRIMINATOR)
+    }
+
+    /// Outstanding principal, including the interest accrued up to `last_update_slot`.
+    pub fn encrypted_principal(&self) -> RescueCiphertext {
+        self.encrypted_principal
+    }
+
+    /// Collateral held in the collateral vault for the loan.
+    pub fn encrypted_collateral(&self) -> RescueCiphertext {
+        self.encrypted_collateral
+    }
+
+    /// Whether the loan has been borrowed against and not fully settled yet.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn new(
+        borrower: &Pubkey,
+        lending_pool: &Pubkey,
+        reveal_repayment_status: bool,
+        maturity_slot: u64,
+    ) -> Self {
         Self {
             borrower: borrower.to_bytes(),
             lending_pool: lending_pool.to_bytes(),
@@ -91,6 +416,99 @@ impl Loan {
             encrypted_principal: RescueCiphertext::default(),
             encrypted_collateral: RescueCiphertext::default(),
             last_update_slot: 0,
+            reveal_repayment_status,
+            encrypted_repayment_status: RescueCiphertext::default(),
+            encrypted_health_bps: RescueCiphertext::default(),
+            health_bps_nonce: 0,
+            encrypted_health_factor: RescueCiphertext::default(),
+            unhealthy_since_slot: 0,
+            next_transfer_id: 0,
+            maturity_slot,
+            encrypted_interest_paid: RescueCiphertext::default(),
+            encrypted_principal_paid: RescueCiphertext::default(),
+            encrypted_released_collateral: RescueCiphertext::default(),
+            settled: true,
+            collateral_update_slot: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::hash::hash;
+
+    #[test]
+    fn test_discriminators() {
+        assert_eq!(
+            LendingPool::DISCRIMINATOR,
+            hash(b"account:LendingPool").to_bytes()[..DISCRIMINATOR_LEN]
+        );
+        assert_eq!(
+            Loan::DISCRIMINATOR,
+            hash(b"account:Loan").to_bytes()[..DISCRIMINATOR_LEN]
+        );
+    }
+
+    #[test]
+    fn test_multisig() {
+        let mut lending_pool = LendingPool::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            100,
+            10_000,
+            1,
+            0,
+            0,
+            0,
+        );
+        // Without a multisig the lender alone authorizes the pool.
+        assert!(lending_pool.has_multisig_threshold(&[]));
+
+        let signers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let signer_bytes = signers.map(|signer| signer.to_bytes());
+        for (multisig_threshold, multisig_signers) in [
+            (0, &signer_bytes[..]),
+            (1, &[][..]),
+            (4, &signer_bytes[..]),
+            (2, &[signer_bytes[0], signer_bytes[0]][..]),
+        ] {
+            assert_eq!(
+                ProgramError::from(LendingError::InvalidMultisig),
+                lending_pool
+                    .set_multisig(multisig_threshold, multisig_signers)
+                    .err()
+                    .unwrap()
+            );
+        }
+
+        lending_pool.set_multisig(2, &signer_bytes).unwrap();
+        assert!(!lending_pool.has_multisig_threshold(&[signers[0]]));
+        // A signer passed twice only counts once.
+        assert!(!lending_pool.has_multisig_threshold(&[signers[0], signers[0]]));
+        assert!(!lending_pool.has_multisig_threshold(&[signers[0], Pubkey::new_unique()]));
+        assert!(lending_pool.has_multisig_threshold(&[signers[0], signers[2]]));
+    }
+
+    #[test]
+    fn test_unpack_rejects_other_account_types() {
+        let loan = Loan::new(&Pubkey::new_unique(), &Pubkey::new_unique(), true, 0);
+        let data = loan.pack().unwrap();
+        assert!(Loan::unpack(&data).unwrap() == loan);
+
+        assert_eq!(
+            ProgramError::from(LendingError::DiscriminatorMismatch),
+            LendingPool::unpack(&data).err().unwrap()
+        );
+        // Accounts without a discriminator are rejected as well.
+        assert_eq!(
+            ProgramError::from(LendingError::DiscriminatorMismatch),
+            Loan::unpack(&data[DISCRIMINATOR_LEN..]).err().unwrap()
+        );
+    }
+}
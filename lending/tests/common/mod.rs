@@ -0,0 +1,188 @@
+//! Shared assertions for the integration tests.
+
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
+use confidential_spl_token_test::{Actor, ConfidentialSPLTokenTest};
+use solana_pubkey::Pubkey;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+/// Asserts that `err` is `expected`, raised by the instruction at `instruction_index`.
+pub fn assert_instruction_error<E: Into<TransactionError>>(
+    err: E,
+    instruction_index: u8,
+    expected: InstructionError,
+) {
+    assert_eq!(
+        TransactionError::InstructionError(instruction_index, expected),
+        err.into()
+    );
+}
+
+/// Balance assertions on confidential token accounts addressed directly.
+pub trait TokenAccountBalances {
+    /// Asserts the decrypted pending + available balance of `token_account`.
+    async fn assert_total_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    );
+
+    /// Asserts the decrypted pending balance of `token_account`.
+    async fn assert_pending_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    );
+
+    /// Asserts the decrypted available balance of `token_account`.
+    async fn assert_available_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    );
+
+    /// Asserts the decrypted pending and available balances of `token_account`, and that they add
+    /// up to its total balance.
+    async fn assert_balances(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected_pending: u64,
+        expected_available: u64,
+    );
+}
+
+impl TokenAccountBalances for ConfidentialSPLTokenTest {
+    async fn assert_total_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self.total_balance(token_account, mxe_pubkey).await.unwrap();
+        assert_eq!(expected, actual, "total balance of {token_account}");
+    }
+
+    async fn assert_pending_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self
+            .pending_balance(token_account, mxe_pubkey)
+            .await
+            .unwrap();
+        assert_eq!(expected, actual, "pending balance of {token_account}");
+    }
+
+    async fn assert_available_balance(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self
+            .available_balance(token_account, mxe_pubkey)
+            .await
+            .unwrap();
+        assert_eq!(expected, actual, "available balance of {token_account}");
+    }
+
+    async fn assert_balances(
+        &mut self,
+        token_account: &Pubkey,
+        mxe_pubkey: &Pubkey,
+        expected_pending: u64,
+        expected_available: u64,
+    ) {
+        self.assert_pending_balance(token_account, mxe_pubkey, expected_pending)
+            .await;
+        self.assert_available_balance(token_account, mxe_pubkey, expected_available)
+            .await;
+        self.assert_total_balance(
+            token_account,
+            mxe_pubkey,
+            expected_pending + expected_available,
+        )
+        .await;
+    }
+}
+
+/// Balance assertions on an actor's associated confidential token account.
+pub trait ActorBalances {
+    /// Asserts the decrypted pending + available balance of the actor's `mint` account.
+    async fn assert_total_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    );
+
+    /// Asserts the decrypted pending balance of the actor's `mint` account.
+    async fn assert_pending_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    );
+
+    /// Asserts the decrypted available balance of the actor's `mint` account.
+    async fn assert_available_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    );
+}
+
+impl ActorBalances for Actor {
+    async fn assert_total_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self.total_balance(test, mint).await.unwrap();
+        assert_eq!(
+            expected,
+            actual,
+            "total {mint} balance of {}",
+            self.pubkey()
+        );
+    }
+
+    async fn assert_pending_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self.pending_balance(test, mint).await.unwrap();
+        assert_eq!(
+            expected,
+            actual,
+            "pending {mint} balance of {}",
+            self.pubkey()
+        );
+    }
+
+    async fn assert_available_balance(
+        &self,
+        test: &mut ConfidentialSPLTokenTest,
+        mint: &Pubkey,
+        expected: u64,
+    ) {
+        let actual = self.available_balance(test, mint).await.unwrap();
+        assert_eq!(
+            expected,
+            actual,
+            "available {mint} balance of {}",
+            self.pubkey()
+        );
+    }
+}
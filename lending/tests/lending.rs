@@ -1,15 +1,44 @@
+mod common;
+
 use std::cmp::min;
 
-use borsh::BorshDeserialize;
-use confidential_spl_token::get_associated_confidential_token_account_address;
-use confidential_spl_token_test::{processor, tokio, ConfidentialSPLTokenTest, CustomProgram};
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{assert_instruction_error, ActorBalances, TokenAccountBalances};
+use confidential_spl_token::{
+    confidential_spl_token_authority::derive_authority,
+    get_associated_confidential_token_account_address, get_single_transfer_account_address,
+    get_transfer_account_address,
+};
+use confidential_spl_token_test::{
+    processor, tokio, Actor, ConfidentialSPLTokenTest, CustomProgram,
+};
 use lending::{
-    processor::{lending_pool_pda, loan_pda, BORROW_COMP_DEF_OFFSET, REPAY_COMP_DEF_OFFSET},
-    state::Loan,
+    addresses::{self, lending_pool_pda, loan_pda, price_oracle_pda},
+    client::{load_lending_pool, load_loan, simulate_borrow, BorrowOutcome},
+    error::LendingError,
+    instruction::{
+        LendingInstruction, BORROW_COMPUTE_UNITS, INITIALIZE_LOAN_COMPUTE_UNITS,
+        REPAY_COMPUTE_UNITS,
+    },
+    processor::{
+        ACCRUE_INTEREST_COMPOUND_COMP_DEF_OFFSET, ACCRUE_INTEREST_COMP_DEF_OFFSET,
+        BORROW_COMP_DEF_OFFSET, DEPOSIT_COLLATERAL_COMP_DEF_OFFSET, HEALTH_FACTOR_COMP_DEF_OFFSET,
+        LIQUIDATE_BY_LENDER_COMP_DEF_OFFSET, LIQUIDATE_COMP_DEF_OFFSET, MAX_COMPOUNDING_PERIODS,
+        PREVIEW_BORROW_COMP_DEF_OFFSET, REPAY_COMPOUND_COMP_DEF_OFFSET,
+        REPAY_COMPOUND_PRIVATE_COMP_DEF_OFFSET, REPAY_COMP_DEF_OFFSET,
+        REPAY_PRIVATE_COMP_DEF_OFFSET, REVEAL_HEALTH_COMP_DEF_OFFSET,
+        REVEAL_REPAYMENT_STATUS_COMP_DEF_OFFSET, SETTLE_MATURED_LOAN_COMP_DEF_OFFSET,
+        TOP_UP_COLLATERAL_COMP_DEF_OFFSET, TOTAL_DEBT_COMP_DEF_OFFSET, WITHDRAW_COMP_DEF_OFFSET,
+        WITHDRAW_RESERVE_COMP_DEF_OFFSET,
+    },
+    state::{BorrowQuote, LendingPool, Loan, MAX_BORROWERS},
 };
 use solana_pubkey::Pubkey;
 use solana_sdk::{
-    compute_budget::ComputeBudgetInstruction, signature::Keypair, signer::Signer,
+    compute_budget::ComputeBudgetInstruction,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    signature::Keypair,
+    signer::Signer,
     transaction::Transaction,
 };
 
@@ -44,6 +73,17 @@ async fn test_lending() {
         .await
         .unwrap();
 
+    // Setup deposit_collateral computation definition account.
+    let compiled_deposit_collateral_circuit =
+        lending_encrypted_ixs::encrypted_computations::deposit_collateral();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        DEPOSIT_COLLATERAL_COMP_DEF_OFFSET,
+        compiled_deposit_collateral_circuit,
+    )
+    .await
+    .unwrap();
+
     // Setup Mints.
     let asset_mint_authority = Keypair::new();
     let asset_mint = test
@@ -90,13 +130,9 @@ async fn test_lending() {
     .await;
     lender.deposit(&mut test, &asset_mint, asset_amount).await;
     lender.apply_pending_balance(&mut test, &asset_mint).await;
-    assert_eq!(
-        asset_amount,
-        lender
-            .available_balance(&mut test, &asset_mint)
-            .await
-            .unwrap()
-    );
+    lender
+        .assert_available_balance(&mut test, &asset_mint, asset_amount)
+        .await;
 
     // Initialize lending pool.
     let interest_rate_bps = 1;
@@ -110,6 +146,9 @@ async fn test_lending() {
             interest_rate_bps,
             loan_to_value_bps,
             collateral_threshold_bps,
+            0,
+            0,
+            u64::MAX,
         )
         .unwrap()],
         Some(&test.get_payer().pubkey()),
@@ -120,6 +159,16 @@ async fn test_lending() {
         .await
         .unwrap();
 
+    // Publish the price of the collateral.
+    let price = 1u64;
+    let set_price_tx = Transaction::new_signed_with_payer(
+        &[lending::instruction::set_price(&lender.pubkey(), price).unwrap()],
+        Some(&test.get_payer().pubkey()),
+        &[&test.get_payer(), &lender.signer_keypair()],
+        test.get_recent_blockhash(),
+    );
+    test.process_transaction(set_price_tx, false).await.unwrap();
+
     // Setup borrower.
     let borrower = test.new_actor().await;
     borrower
@@ -156,27 +205,22 @@ async fn test_lending() {
     borrower
         .apply_pending_balance(&mut test, &collateral_mint)
         .await;
-    assert_eq!(
-        collateral_amount,
-        borrower
-            .available_balance(&mut test, &collateral_mint)
-            .await
-            .unwrap()
-    );
+    borrower
+        .assert_available_balance(&mut test, &collateral_mint, collateral_amount)
+        .await;
 
     // Initialize loan.
     let init_loan_tx = Transaction::new_signed_with_payer(
-        &[
-            ComputeBudgetInstruction::set_compute_unit_price(1),
-            ComputeBudgetInstruction::set_compute_unit_limit(400_000),
-            lending::instruction::initialize_loan(
-                &lender.pubkey(),
-                &borrower.pubkey(),
-                &asset_mint,
-                &collateral_mint,
-            )
-            .unwrap(),
-        ],
+        &lending::instruction::initialize_loan_with_budget(
+            &lender.pubkey(),
+            &borrower.pubkey(),
+            &asset_mint,
+            &collateral_mint,
+            true,
+            0,
+            1,
+        )
+        .unwrap(),
         Some(&test.get_payer().pubkey()),
         &[&test.get_payer(), &borrower.signer_keypair()],
         test.get_recent_blockhash(),
@@ -191,28 +235,39 @@ async fn test_lending() {
         &confidential_spl_token::programs::confidential_spl_token::ID,
         true,
     );
-    borrower
-        .transfer(
-            &mut test,
+    let deposit_collateral_tx = Transaction::new_signed_with_payer(
+        &[lending::instruction::deposit_collateral(
+            &lender.pubkey(),
+            &borrower.pubkey(),
+            &asset_mint,
             &collateral_mint,
+            0,
+            0,
             collateral_amount,
-            &collateral_vault_ata,
         )
-        .await;
+        .unwrap()],
+        Some(&test.get_payer().pubkey()),
+        &[&test.get_payer(), &borrower.signer_keypair()],
+        test.get_recent_blockhash(),
+    );
+    test.process_transaction(deposit_collateral_tx, false)
+        .await
+        .unwrap();
 
     // Check that borrower has deposited all of their tokens into the collateral_vault_ata.
+    borrower
+        .assert_total_balance(&mut test, &collateral_mint, 0)
+        .await;
+    test.assert_total_balance(&collateral_vault_ata, &mxe_pubkey, collateral_amount)
+        .await;
+
+    // The deposit is recorded as the loan's collateral.
+    let loan = load_loan(&test.get_account(&loan_account).await.unwrap().data).unwrap();
     assert_eq!(
-        0,
-        borrower
-            .total_balance(&mut test, &collateral_mint)
-            .await
-            .unwrap()
-    );
-    assert_eq!(
-        collateral_amount,
-        test.total_balance(&collateral_vault_ata, &mxe_pubkey)
-            .await
+        test.get_mxe(&mxe_pubkey)
             .unwrap()
+            .rescue_decrypt(loan.encrypted_collateral),
+        collateral_amount
     );
 
     // Lender provides liquidity into the asset_vault_ata.
@@ -228,19 +283,11 @@ async fn test_lending() {
         .await;
 
     // Check that lender has deposited all of their tokens into the pool.
-    assert_eq!(
-        0,
-        lender
-            .available_balance(&mut test, &asset_mint)
-            .await
-            .unwrap()
-    );
-    assert_eq!(
-        asset_amount,
-        test.pending_balance(&asset_vault_ata, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    lender
+        .assert_available_balance(&mut test, &asset_mint, 0)
+        .await;
+    test.assert_pending_balance(&asset_vault_ata, &mxe_pubkey, asset_amount)
+        .await;
 
     // Borrower borrows tokens.
     let borrow_transfer_id = 0;
@@ -250,6 +297,7 @@ async fn test_lending() {
             &borrower.pubkey(),
             &asset_mint,
             &collateral_mint,
+            &price_oracle_pda(&lender.pubkey()).0,
             1,
             borrow_transfer_id,
         )
@@ -261,57 +309,51 @@ async fn test_lending() {
     test.process_transaction(borrow_tx, false).await.unwrap();
 
     // Compute the expected values that should have been computed correctly in the MXE based on the encrypted balances.
-    let price = 1u64;
-    let max_loan_amount = mul_base_points(collateral_amount, price * loan_to_value_bps as u64);
-    let loan_amount = min(max_loan_amount, asset_amount);
-    let loan_collateral_amount = div_base_points(loan_amount, price * loan_to_value_bps as u64);
-    let collateral_excess_amount = collateral_amount - loan_collateral_amount;
+    let lending_pool_state =
+        load_lending_pool(&test.get_account(&lending_pool).await.unwrap().data).unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        ..
+    } = simulate_borrow(collateral_amount, asset_amount, price, &lending_pool_state);
 
     // The borrower should have received loan_amount of asset.
-    assert_eq!(
-        loan_amount,
-        borrower
-            .total_balance(&mut test, &asset_mint)
-            .await
-            .unwrap()
-    );
+    borrower
+        .assert_total_balance(&mut test, &asset_mint, loan_amount)
+        .await;
 
     // The borrower should have recieved (back) collateral_excess_amount of collateral.
-    assert_eq!(
-        collateral_excess_amount,
-        borrower
-            .total_balance(&mut test, &collateral_mint)
-            .await
-            .unwrap()
-    );
+    borrower
+        .assert_total_balance(&mut test, &collateral_mint, collateral_excess_amount)
+        .await;
 
     // The asset_vault_ata should now have asset_amount - loan_amount.
-    assert_eq!(
-        asset_amount - loan_amount,
-        test.pending_balance(&asset_vault_ata, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    test.assert_pending_balance(&asset_vault_ata, &mxe_pubkey, asset_amount - loan_amount)
+        .await;
 
     // The collateral_vault_ata should now have loan_collateral_amount.
-    assert_eq!(
-        loan_collateral_amount,
-        test.total_balance(&collateral_vault_ata, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    test.assert_total_balance(&collateral_vault_ata, &mxe_pubkey, loan_collateral_amount)
+        .await;
 
     // Check updated state in loan account.
-    let loan = Loan::try_from_slice(&test.get_account(&loan_account).await.unwrap().data).unwrap();
+    let loan = load_loan(&test.get_account(&loan_account).await.unwrap().data).unwrap();
     assert_eq!(
         test.get_mxe(&mxe_pubkey)
             .unwrap()
             .rescue_decrypt(loan.encrypted_principal),
         loan_amount
     );
+    assert_eq!(
+        test.get_mxe(&mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_collateral),
+        loan_collateral_amount
+    );
 
-    // TODO: Simulate slots elapsing to accrue interest.
-    let slots_elapsed = 10;
+    // Let interest accrue on the loan.
+    let borrow_slot = loan.last_update_slot;
+    test.warp_to_slot(borrow_slot + 10).unwrap();
 
     // Borrower (partially) repays loan.
     let repay_amount = 100;
@@ -327,12 +369,8 @@ async fn test_lending() {
         .await;
 
     // The asset_repay_ata should now have repay_amount.
-    assert_eq!(
-        repay_amount,
-        test.pending_balance(&asset_repay_ata, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    test.assert_pending_balance(&asset_repay_ata, &mxe_pubkey, repay_amount)
+        .await;
 
     // Borrower calls repay instruction to receive collateral.
     let repay_transfer_id = 1;
@@ -342,8 +380,13 @@ async fn test_lending() {
             &borrower.pubkey(),
             &asset_mint,
             &collateral_mint,
+            &lender.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &asset_mint,
+            ),
             2,
             repay_transfer_id,
+            0,
         )
         .unwrap()],
         Some(&test.get_payer().pubkey()),
@@ -355,6 +398,9 @@ async fn test_lending() {
     let remaining_principal = loan_amount;
     let locked_collateral = loan_collateral_amount;
 
+    let loan = load_loan(&test.get_account(&loan_account).await.unwrap().data).unwrap();
+    let slots_elapsed = loan.last_update_slot - borrow_slot;
+    assert!(slots_elapsed >= 10);
     let interest_accrued = mul_base_points(
         remaining_principal,
         interest_rate_bps as u64 * slots_elapsed,
@@ -365,8 +411,6 @@ async fn test_lending() {
     let collateral_repayment = (actual_repay_amount / total_due) * locked_collateral;
     let loan_is_fully_repaid = remaining_due.eq(&0);
 
-    let loan = Loan::try_from_slice(&test.get_account(&loan_account).await.unwrap().data).unwrap();
-
     // Check that the Loan account contains the correct (public and encrypted) computation outputs.
     assert_eq!(loan.active, !loan_is_fully_repaid);
     assert_eq!(
@@ -377,36 +421,5200 @@ async fn test_lending() {
     );
 
     // Check that borrower has received collateral_repayment in collateral (previousl balance: collateral_excess_amount).
-    assert_eq!(
-        collateral_repayment + collateral_excess_amount,
-        borrower
-            .pending_balance(&mut test, &collateral_mint)
-            .await
-            .unwrap()
-    );
+    borrower
+        .assert_pending_balance(
+            &mut test,
+            &collateral_mint,
+            collateral_repayment + collateral_excess_amount,
+        )
+        .await;
     // Check that collateral_vault_ata has transfered collateral_repayment.
-    assert_eq!(
+    test.assert_total_balance(
+        &collateral_vault_ata,
+        &mxe_pubkey,
         loan_collateral_amount - collateral_repayment,
-        test.total_balance(&collateral_vault_ata, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    )
+    .await;
 
     // Check that lender has received actual_repay_amount in asset.
-    assert_eq!(
-        actual_repay_amount,
-        lender
-            .pending_balance(&mut test, &asset_mint)
+    lender
+        .assert_pending_balance(&mut test, &asset_mint, actual_repay_amount)
+        .await;
+    // Check that repay_ata is empty.
+    test.assert_pending_balance(&asset_repay_ata, &mxe_pubkey, 0)
+        .await;
+}
+
+#[tokio::test]
+async fn test_loan_allowlist() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let allowed_borrower = new_borrower(&mut ctx, 100).await;
+    let other_borrower = new_borrower(&mut ctx, 100).await;
+
+    // Lender allowlists a single borrower.
+    let add_allowed_borrower_tx = ctx.lender_tx(&[lending::instruction::add_allowed_borrower(
+        &ctx.lender.pubkey(),
+        &allowed_borrower.pubkey(),
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(add_allowed_borrower_tx, false)
+        .await
+        .unwrap();
+
+    let lending_pool = ctx.lending_pool().await;
+    assert!(lending_pool.allowlist_enabled);
+    assert!(lending_pool.is_allowed_borrower(&allowed_borrower.pubkey()));
+    assert!(!lending_pool.is_allowed_borrower(&other_borrower.pubkey()));
+
+    // The allowlisted borrower can open a loan.
+    let init_loan_tx = ctx.initialize_loan_tx(&allowed_borrower);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+
+    // Any other borrower is rejected.
+    let init_loan_tx = ctx.initialize_loan_tx(&other_borrower);
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::NotAllowlisted as u32),
+    );
+    assert_eq!(1, ctx.lending_pool().await.num_borrowers);
+}
+
+#[tokio::test]
+async fn test_lender_approval() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let set_lender_approval_tx =
+        ctx.lender_tx(&[
+            lending::instruction::set_lender_approval(&ctx.lender.pubkey(), true).unwrap(),
+        ]);
+    ctx.test
+        .process_transaction(set_lender_approval_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.lending_pool().await.require_lender_approval);
+
+    // A loan opened by the borrower alone is rejected.
+    let borrower = new_borrower(&mut ctx, 100).await;
+    let init_loan_tx = ctx.initialize_loan_tx(&borrower);
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 2, InstructionError::MissingRequiredSignature);
+
+    // The loan can be opened once the lender co-signs it.
+    let init_loan_tx = Transaction::new_signed_with_payer(
+        &[
+            ComputeBudgetInstruction::set_compute_unit_limit(INITIALIZE_LOAN_COMPUTE_UNITS),
+            lending::instruction::initialize_loan_with_lender_approval(
+                &ctx.lender.pubkey(),
+                &borrower.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                true,
+                0,
+            )
+            .unwrap(),
+        ],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &borrower.signer_keypair(),
+            &ctx.lender.signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(1, ctx.lending_pool().await.num_borrowers);
+}
+
+#[tokio::test]
+async fn test_initialize_loan_with_full_borrower_list() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    // Fill every borrower slot of the lending pool, each borrower borrowing against their own
+    // collateral.
+    let collateral_amount = 100;
+    let mut borrowers = Vec::new();
+    for i in 0..MAX_BORROWERS {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+        let borrow_tx = ctx.borrow_tx(&borrower, 1 + i as u32, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
             .await
-            .unwrap()
+            .unwrap();
+        borrowers.push(borrower);
+    }
+
+    // Every loan is tracked by the lending pool.
+    let lending_pool = ctx.lending_pool().await;
+    assert_eq!(MAX_BORROWERS, lending_pool.num_borrowers as usize);
+    assert_eq!(MAX_BORROWERS, lending_pool.num_active_loans as usize);
+    for borrower in &borrowers {
+        assert!(lending_pool.find_borrower(&borrower.pubkey()).is_ok());
+        let loan = ctx.loan(borrower).await;
+        assert!(loan.active);
+        assert_eq!(
+            collateral_amount,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(loan.encrypted_principal)
+        );
+    }
+
+    let borrower = new_borrower(&mut ctx, 100).await;
+    let init_loan_tx = ctx.initialize_loan_tx(&borrower);
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::TooManyBorrowers as u32),
     );
-    // Check that repay_ata is empty.
     assert_eq!(
-        0,
-        test.pending_balance(&asset_repay_ata, &mxe_pubkey)
-            .await
-            .unwrap()
+        MAX_BORROWERS,
+        ctx.lending_pool().await.num_borrowers as usize
+    );
+}
+
+#[tokio::test]
+async fn test_reinitialize_loan_with_dirty_collateral_vault() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let dust = 1;
+    let borrower = new_borrower(&mut ctx, collateral_amount + dust).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // Borrower repays the loan in full, releasing all of its collateral.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    // Leave a dust balance behind in the loan's collateral vault and close the loan.
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            dust,
+            &collateral_vault_ata,
+        )
+        .await;
+    let close_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[lending::instruction::close_loan(&ctx.lender.pubkey(), &borrower.pubkey()).unwrap()],
+    );
+    ctx.test
+        .process_transaction(close_loan_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(0, ctx.lending_pool().await.num_borrowers);
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, dust)
+        .await;
+
+    // Initializing a loan again on top of the dirty collateral vault is rejected.
+    let init_loan_tx = ctx.initialize_loan_tx(&borrower);
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::CollateralVaultNotEmpty as u32),
+    );
+    assert_eq!(0, ctx.lending_pool().await.num_borrowers);
+}
+
+#[tokio::test]
+async fn test_borrow_before_initialize_loan() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+    let borrower = new_borrower(&mut ctx, 100).await;
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::LoanNotInitialized as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_transfer_id_reuse() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // Borrows and repays must use the loan's transfer ids in order.
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 1);
+    let err = ctx
+        .test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::TransferIdMismatch as u32),
+    );
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(1, ctx.loan(&borrower).await.next_transfer_id);
+
+    // The borrow's transfer id can't be reused by a repay.
+    let repay_tx = ctx.repay_tx(&borrower, 3, 0, 0);
+    let err = ctx
+        .test
+        .process_transaction(repay_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::TransferIdMismatch as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_borrow_missing_borrower_ata() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    // The borrower never created its asset and collateral token accounts.
+    let borrower = ctx.test.new_actor().await;
+    let init_loan_tx = ctx.initialize_loan_tx(&borrower);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::BorrowerAtaMissing as u32),
     );
+    assert_eq!(0, ctx.loan(&borrower).await.next_transfer_id);
+}
+
+#[tokio::test]
+async fn test_borrow_mismatched_transfer_account() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // A transfer account derived for a single transfer out of the asset vault doesn't match the
+    // borrow's three transfers.
+    let asset_vault_ata = ctx.asset_vault_ata();
+    let transfer_account = get_transfer_account_address(
+        &[
+            asset_vault_ata,
+            ctx.collateral_vault_ata(&borrower),
+            asset_vault_ata,
+        ],
+        0,
+    );
+    let mut borrow_ix = lending::instruction::borrow(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        &price_oracle_pda(&ctx.lender.pubkey()).0,
+        1,
+        0,
+    )
+    .unwrap();
+    borrow_ix
+        .accounts
+        .iter_mut()
+        .find(|meta| meta.pubkey == transfer_account)
+        .unwrap()
+        .pubkey = get_single_transfer_account_address(&asset_vault_ata, 0);
+    let borrow_tx = ctx.borrower_tx(&borrower, &[borrow_ix]);
+    let err = ctx
+        .test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidSeeds);
+    assert!(!ctx.loan(&borrower).await.active);
+
+    // The transfer account derived by the builder goes through.
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.loan(&borrower).await.active);
+}
+
+#[tokio::test]
+async fn test_initialize_loan_mint_mismatch() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+    let borrower = new_borrower(&mut ctx, 100).await;
+
+    // A loan can't be opened against another collateral mint than the lending pool's.
+    let other_mint = ctx
+        .test
+        .create_mint(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            9,
+            &Keypair::new(),
+        )
+        .await
+        .pubkey();
+    let init_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &lending::instruction::initialize_loan_with_budget(
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.asset_mint,
+            &other_mint,
+            false,
+            0,
+            1,
+        )
+        .unwrap(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::MintMismatch as u32),
+    );
+    assert!(ctx
+        .test
+        .get_account(&loan_pda(&ctx.lender.pubkey(), &borrower.pubkey()).0)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_borrow_callback_without_settled_transfer() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // A callback for a transfer that never settled is rejected instead of opening the loan.
+    let borrow_callback_instruction = Instruction {
+        program_id: lending::ID,
+        accounts: vec![
+            AccountMeta::new(lending_pool_pda(&ctx.lender.pubkey()).0, false),
+            AccountMeta::new(loan_pda(&ctx.lender.pubkey(), &borrower.pubkey()).0, false),
+            AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            AccountMeta::new_readonly(
+                confidential_spl_token::programs::instruction_sysvar::ID,
+                false,
+            ),
+        ],
+        data: LendingInstruction::BorrowCallback {
+            queued_total_debt_version: 0,
+        }
+        .try_to_vec()
+        .unwrap(),
+    };
+    let borrow_callback_tx = ctx.borrower_tx(&borrower, &[borrow_callback_instruction]);
+    assert!(ctx
+        .test
+        .process_transaction(borrow_callback_tx, false)
+        .await
+        .is_err());
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.encrypted_principal == Default::default());
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+
+    // The borrower can still borrow as usual.
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.loan(&borrower).await.active);
+}
+
+#[tokio::test]
+async fn test_borrow_active_loan() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let loan = ctx.loan(&borrower).await;
+    assert!(loan.active);
+
+    // Borrowing again would orphan the outstanding principal.
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 1);
+    let err = ctx
+        .test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::LoanAlreadyActive as u32),
+    );
+    assert!(loan.encrypted_principal == ctx.loan(&borrower).await.encrypted_principal);
+}
+
+#[tokio::test]
+async fn test_repay_with_other_loans_asset_repay_ata() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let other_borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &other_borrower, collateral_amount).await;
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // The borrower can't repay their loan out of another loan's asset repay account.
+    let mut repay_instruction = lending::instruction::repay(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        &ctx.reserve_vault_ata,
+        2,
+        1,
+        0,
+    )
+    .unwrap();
+    assert_eq!(
+        ctx.asset_repay_ata(&borrower),
+        repay_instruction.accounts[7].pubkey
+    );
+    repay_instruction.accounts[7].pubkey = ctx.asset_repay_ata(&other_borrower);
+    let repay_tx = ctx.borrower_tx(&borrower, &[repay_instruction]);
+    let err = ctx
+        .test
+        .process_transaction(repay_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::VaultAtaMismatch as u32),
+    );
+    assert!(ctx.loan(&borrower).await.active);
+}
+
+#[tokio::test]
+async fn test_max_active_loans() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 1).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let other_borrower = new_borrower(&mut ctx, collateral_amount).await;
+
+    // The first loan fills the pool.
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(1, ctx.lending_pool().await.num_active_loans);
+
+    // No other loan can be opened.
+    let init_loan_tx = ctx.initialize_loan_tx(&other_borrower);
+    let err = ctx
+        .test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::PoolAtCapacity as u32),
+    );
+
+    // Once the first loan is fully repaid, there is room for another one.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    assert!(!ctx.loan(&borrower).await.active);
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+
+    open_loan(&mut ctx, &other_borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&other_borrower, 3, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(1, ctx.lending_pool().await.num_active_loans);
+}
+
+#[tokio::test]
+async fn test_borrow_repay_conserves_supply() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let supply_before = ctx.supply(&borrower).await;
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(supply_before, ctx.supply(&borrower).await);
+
+    // Borrower repays the loan in full.
+    let loan_amount = collateral_amount;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            loan_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    assert!(!ctx.loan(&borrower).await.active);
+
+    // No asset or collateral has been created or destroyed along the way.
+    assert_eq!(supply_before, ctx.supply(&borrower).await);
+}
+
+#[tokio::test]
+async fn test_borrow_with_price_oracle() {
+    let liquidity = 1000;
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(liquidity, 1, loan_to_value_bps, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // A borrow priced by any other account than the lender's price oracle is rejected.
+    let forged_borrow_instruction = lending::instruction::borrow(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        &lending_pool_pda(&ctx.lender.pubkey()).0,
+        1,
+        0,
+    )
+    .unwrap();
+    let forged_borrow_tx = ctx.borrower_tx(&borrower, &[forged_borrow_instruction]);
+    let err = ctx
+        .test
+        .process_transaction(forged_borrow_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidAccountData);
+
+    // One collateral token is now worth two asset tokens.
+    let price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        ..
+    } = simulate_borrow(
+        collateral_amount,
+        liquidity,
+        price,
+        &ctx.lending_pool().await,
+    );
+    assert_eq!(
+        2 * mul_base_points(collateral_amount, loan_to_value_bps as u64),
+        loan_amount
+    );
+    assert_eq!(
+        loan_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.loan(&borrower).await.encrypted_principal)
+    );
+    ctx.test
+        .assert_total_balance(
+            &ctx.collateral_vault_ata(&borrower),
+            &ctx.mxe_pubkey,
+            loan_collateral_amount,
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn test_preview_borrow() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 1, 0).await;
+
+    let price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    // The borrower gets a quote before committing any collateral.
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let preview_borrow_tx = ctx.borrower_tx(
+        &borrower,
+        &[lending::instruction::preview_borrow(
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.collateral_mint,
+            1,
+            0,
+        )
+        .unwrap()],
+    );
+    ctx.test
+        .process_transaction(preview_borrow_tx, false)
+        .await
+        .unwrap();
+
+    let borrow_quote = addresses::borrow_quote_pda(&ctx.lender.pubkey(), &borrower.pubkey()).0;
+    let borrow_quote =
+        BorrowQuote::try_from_slice(&ctx.test.get_account(&borrow_quote).await.unwrap().data)
+            .unwrap();
+    assert_eq!(
+        price * mul_base_points(collateral_amount, loan_to_value_bps as u64),
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(borrow_quote.encrypted_max_loan)
+    );
+
+    // No funds have been moved.
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, collateral_amount)
+        .await;
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, 0)
+        .await;
+}
+
+#[tokio::test]
+async fn test_borrow_extreme_price() {
+    let mut ctx = setup_lending(1000, 1, 5000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), u64::MAX).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // The collateral kept in the vault never exceeds the deposit, the rest is returned.
+    let loan_collateral_amount = ctx
+        .test
+        .total_balance(&ctx.collateral_vault_ata(&borrower), &ctx.mxe_pubkey)
+        .await
+        .unwrap();
+    let collateral_excess_amount = borrower
+        .total_balance(&mut ctx.test, &ctx.collateral_mint)
+        .await
+        .unwrap();
+    assert!(loan_collateral_amount <= collateral_amount);
+    assert_eq!(
+        collateral_amount,
+        loan_collateral_amount + collateral_excess_amount
+    );
+}
+
+#[tokio::test]
+async fn test_borrow_near_overflow_price() {
+    let liquidity = 1000;
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(liquidity, 1, loan_to_value_bps, 1, 0).await;
+
+    let collateral_amount = 1;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // price * loan_to_value_bps exceeds u64::MAX, it must saturate rather than wrap to a tiny ratio.
+    let price = u64::MAX / loan_to_value_bps as u64 + 1;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let BorrowOutcome { loan_amount, .. } = simulate_borrow(
+        collateral_amount,
+        liquidity,
+        price,
+        &ctx.lending_pool().await,
+    );
+    assert_eq!(liquidity, loan_amount);
+    assert_eq!(
+        loan_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.loan(&borrower).await.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_repay_interest_principal_split() {
+    let interest_rate_bps = 100;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+
+    ctx.test.warp_to_slot(borrow_slot + 10).unwrap();
+    let repay_amount = 50;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    // The repayment pays off the interest accrued first, the rest goes to the principal.
+    let loan = ctx.loan(&borrower).await;
+    let loan_amount = collateral_amount;
+    let interest_accrued = mul_base_points(
+        loan_amount,
+        interest_rate_bps as u64 * (loan.last_update_slot - borrow_slot),
+    );
+    assert!(interest_accrued > 0);
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    let interest_paid = mxe.rescue_decrypt(loan.encrypted_interest_paid);
+    let principal_paid = mxe.rescue_decrypt(loan.encrypted_principal_paid);
+    assert_eq!(min(repay_amount, interest_accrued), interest_paid);
+    assert_eq!(repay_amount, interest_paid + principal_paid);
+    assert_eq!(
+        loan_amount - principal_paid,
+        mxe.rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_repay_interest_accrues_with_slots() {
+    let interest_rate_bps = 10;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    // Both borrowers take the same loan and repay the same amount, the second one much later.
+    let collateral_amount = 100;
+    let repay_amount = 50;
+    let mut remaining_dues = Vec::new();
+    for (i, slots_to_wait) in [0, 1000].into_iter().enumerate() {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+        let borrow_tx = ctx.borrow_tx(&borrower, 1 + 2 * i as u32, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
+            .await
+            .unwrap();
+        let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+        assert_ne!(0, borrow_slot);
+
+        if slots_to_wait > 0 {
+            ctx.test.warp_to_slot(borrow_slot + slots_to_wait).unwrap();
+        }
+
+        let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+        borrower
+            .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+            .await;
+        borrower
+            .transfer(
+                &mut ctx.test,
+                &ctx.asset_mint,
+                repay_amount,
+                &asset_repay_ata,
+            )
+            .await;
+        let repay_tx = ctx.repay_tx(&borrower, 2 + 2 * i as u32, 1, 0);
+        ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+        // Interest accrued over every slot since the borrow.
+        let loan = ctx.loan(&borrower).await;
+        let slots_elapsed = loan.last_update_slot - borrow_slot;
+        assert!(slots_elapsed >= slots_to_wait);
+        let loan_amount = collateral_amount;
+        let remaining_due = loan_amount
+            + mul_base_points(loan_amount, interest_rate_bps as u64 * slots_elapsed)
+            - repay_amount;
+        assert_eq!(
+            remaining_due,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(loan.encrypted_principal)
+        );
+        remaining_dues.push(remaining_due);
+    }
+
+    assert!(remaining_dues[1] > remaining_dues[0]);
+}
+
+#[tokio::test]
+async fn test_repay_refunds_overpayment() {
+    let interest_rate_bps = 10;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+    ctx.test.warp_to_slot(borrow_slot + 100).unwrap();
+
+    // The borrower can't know the encrypted interest, so it funds the loan with a buffer on top.
+    let buffer = 50;
+    let asset_borrower_ata = borrower.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+            &ctx.asset_mint_authority,
+            buffer,
+            &asset_borrower_ata,
+        )
+        .await;
+    borrower
+        .deposit(&mut ctx.test, &ctx.asset_mint, buffer)
+        .await;
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    let loan_amount = collateral_amount;
+    let repay_amount = loan_amount + buffer;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let lender_balance = ctx
+        .lender
+        .total_balance(&mut ctx.test, &ctx.asset_mint)
+        .await
+        .unwrap();
+
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    // The loan is repaid in one shot and everything paid above total_due is refunded.
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    let slots_elapsed = loan.last_update_slot - borrow_slot;
+    let total_due =
+        loan_amount + mul_base_points(loan_amount, interest_rate_bps as u64 * slots_elapsed);
+    assert!(total_due < repay_amount);
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, repay_amount - total_due)
+        .await;
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, lender_balance + total_due)
+        .await;
+    ctx.test
+        .assert_total_balance(&asset_repay_ata, &ctx.mxe_pubkey, 0)
+        .await;
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, collateral_amount)
+        .await;
+}
+
+#[tokio::test]
+async fn test_partial_repays_release_all_collateral() {
+    let interest_rate_bps = 10;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    let collateral_amount = 97;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+
+    // Partial repayments release collateral in proportion, rounding down.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    for (repay_amount, computation_offset, transfer_id) in [(31, 2, 1), (29, 3, 2)] {
+        let last_update_slot = ctx.loan(&borrower).await.last_update_slot;
+        ctx.test.warp_to_slot(last_update_slot + 10).unwrap();
+        borrower
+            .transfer(
+                &mut ctx.test,
+                &ctx.asset_mint,
+                repay_amount,
+                &asset_repay_ata,
+            )
+            .await;
+        let repay_tx = ctx.repay_tx(&borrower, computation_offset, transfer_id, 0);
+        ctx.test.process_transaction(repay_tx, false).await.unwrap();
+        assert!(ctx.loan(&borrower).await.active);
+    }
+    let locked_collateral = ctx
+        .test
+        .total_balance(&collateral_vault_ata, &ctx.mxe_pubkey)
+        .await
+        .unwrap();
+    assert!(locked_collateral > 0);
+    assert_eq!(
+        collateral_amount - locked_collateral,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.loan(&borrower).await.encrypted_released_collateral)
+    );
+
+    // The final repayment, with a buffer above the interest, releases whatever is left.
+    let buffer = 50;
+    let asset_borrower_ata = borrower.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+            &ctx.asset_mint_authority,
+            buffer,
+            &asset_borrower_ata,
+        )
+        .await;
+    borrower
+        .deposit(&mut ctx.test, &ctx.asset_mint, buffer)
+        .await;
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    let repay_amount = collateral_amount - 31 - 29 + buffer;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 4, 3, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, 0)
+        .await;
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, collateral_amount)
+        .await;
+    assert_eq!(
+        collateral_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_released_collateral)
+    );
+}
+
+#[tokio::test]
+async fn test_compute_total_debt() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    // Three borrowers borrow against different amounts of collateral.
+    let mut borrowers = Vec::new();
+    for (i, collateral_amount) in [100, 200, 300].into_iter().enumerate() {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+        let borrow_tx = ctx.borrow_tx(&borrower, 1 + i as u32, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
+            .await
+            .unwrap();
+        borrowers.push(borrower);
+    }
+
+    // Every borrow already added its principal to the pool's total debt.
+    assert_eq!(
+        600,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.lending_pool().await.encrypted_total_debt)
+    );
+
+    let borrower_keys: Vec<Pubkey> = borrowers.iter().map(|b| b.pubkey()).collect();
+    let total_debt_tx = ctx.lender_tx(&[lending::instruction::compute_total_debt(
+        &ctx.lender.pubkey(),
+        &ctx.asset_mint,
+        &borrower_keys,
+        4,
+        0,
+        &ctx.lender.x25519_pubkey(),
+        0,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(total_debt_tx, false)
+        .await
+        .unwrap();
+
+    // The total decrypts to the sum of every loan's outstanding principal.
+    let mut principals = Vec::new();
+    for borrower in &borrowers {
+        principals.push(ctx.loan(borrower).await.encrypted_principal);
+    }
+    let lending_pool = ctx.lending_pool().await;
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    let expected_total_debt: u64 = principals
+        .into_iter()
+        .map(|principal| mxe.rescue_decrypt(principal))
+        .sum();
+    assert_eq!(600, expected_total_debt);
+    assert_eq!(
+        expected_total_debt,
+        mxe.rescue_decrypt(lending_pool.encrypted_computed_total_debt)
+    );
+    assert_eq!(
+        expected_total_debt,
+        mxe.rescue_decrypt(lending_pool.encrypted_total_debt)
+    );
+    // Only the lender can read the total encrypted to them.
+    assert_eq!(
+        expected_total_debt,
+        ctx.lender.shared_decrypt(
+            &mxe,
+            lending_pool.total_debt_for_lender_nonce,
+            lending_pool.encrypted_total_debt_for_lender
+        )
+    );
+}
+
+#[tokio::test]
+async fn test_stale_total_debt() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    // Two borrowers borrow in the same transaction, both computations are queued on the same
+    // running total.
+    let mut borrowers = Vec::new();
+    for collateral_amount in [100, 200] {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+        borrowers.push(borrower);
+    }
+    let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+        2 * BORROW_COMPUTE_UNITS,
+    )];
+    for (i, borrower) in borrowers.iter().enumerate() {
+        instructions.push(
+            lending::instruction::borrow(
+                &ctx.lender.pubkey(),
+                &borrower.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                &price_oracle_pda(&ctx.lender.pubkey()).0,
+                1 + i as u32,
+                0,
+            )
+            .unwrap(),
+        );
+    }
+    let borrow_tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &borrowers[0].signer_keypair(),
+            &borrowers[1].signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // The first borrow replaced the running total the second one was derived from, the second
+    // total is dropped.
+    let lending_pool = ctx.lending_pool().await;
+    assert_eq!(1, lending_pool.total_debt_version);
+    assert_eq!(
+        100,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(lending_pool.encrypted_total_debt)
+    );
+
+    // compute_total_debt sums both loans and resyncs the running total.
+    let borrower_keys: Vec<Pubkey> = borrowers.iter().map(|b| b.pubkey()).collect();
+    let total_debt_tx = ctx.lender_tx(&[lending::instruction::compute_total_debt(
+        &ctx.lender.pubkey(),
+        &ctx.asset_mint,
+        &borrower_keys,
+        3,
+        0,
+        &ctx.lender.x25519_pubkey(),
+        0,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(total_debt_tx, false)
+        .await
+        .unwrap();
+    let lending_pool = ctx.lending_pool().await;
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    assert_eq!(2, lending_pool.total_debt_version);
+    assert_eq!(
+        300,
+        mxe.rescue_decrypt(lending_pool.encrypted_computed_total_debt)
+    );
+    assert_eq!(300, mxe.rescue_decrypt(lending_pool.encrypted_total_debt));
+
+    // An interest accrual adds the interest to the running total.
+    let last_update_slot = ctx.loan(&borrowers[0]).await.last_update_slot;
+    ctx.test.warp_to_slot(last_update_slot + 100).unwrap();
+    let accrue_interest_tx = ctx.lender_tx(&[lending::instruction::accrue_interest(
+        &ctx.lender.pubkey(),
+        &borrowers[0].pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        4,
+        1,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(accrue_interest_tx, false)
+        .await
+        .unwrap();
+    let principal = ctx.loan(&borrowers[0]).await.encrypted_principal;
+    let lending_pool = ctx.lending_pool().await;
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    let principal = mxe.rescue_decrypt(principal);
+    assert!(principal > 100);
+    assert_eq!(3, lending_pool.total_debt_version);
+    assert_eq!(
+        principal + 200,
+        mxe.rescue_decrypt(lending_pool.encrypted_total_debt)
+    );
+}
+
+#[tokio::test]
+async fn test_close_loan() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // A loan with an outstanding principal can't be closed.
+    let close_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[lending::instruction::close_loan(&ctx.lender.pubkey(), &borrower.pubkey()).unwrap()],
+    );
+    let err = ctx
+        .test
+        .process_transaction(close_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanActive as u32),
+    );
+
+    // Borrower repays the loan in full.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.settled);
+    // The repayment cleared the loan's share of the pool's total debt.
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.lending_pool().await.encrypted_total_debt)
+    );
+
+    let loan_account = loan_pda(&ctx.lender.pubkey(), &borrower.pubkey()).0;
+    let loan_rent = ctx.test.get_account(&loan_account).await.unwrap().lamports;
+    let borrower_lamports = ctx
+        .test
+        .get_account(&borrower.pubkey())
+        .await
+        .map_or(0, |account| account.lamports);
+    let close_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[lending::instruction::close_loan(&ctx.lender.pubkey(), &borrower.pubkey()).unwrap()],
+    );
+    ctx.test
+        .process_transaction(close_loan_tx, false)
+        .await
+        .unwrap();
+
+    // The borrower slot is freed and the loan account is closed, its rent going to the borrower.
+    let lending_pool = ctx.lending_pool().await;
+    assert_eq!(0, lending_pool.num_borrowers);
+    assert!(lending_pool.find_borrower(&borrower.pubkey()).is_err());
+    assert!(ctx.test.get_account(&loan_account).await.is_none());
+    assert_eq!(
+        borrower_lamports + loan_rent,
+        ctx.test
+            .get_account(&borrower.pubkey())
+            .await
+            .unwrap()
+            .lamports
+    );
+
+    // The vault token accounts can't be closed, they stay allocated.
+    assert!(ctx
+        .test
+        .get_account(&ctx.collateral_vault_ata(&borrower))
+        .await
+        .is_some());
+
+    // A closed loan can't be reinitialized.
+    let reinitialize_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[
+            lending::instruction::reinitialize_loan(&ctx.lender.pubkey(), &borrower.pubkey(), 0)
+                .unwrap(),
+        ],
+    );
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanNotInitialized as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_reinitialize_loan() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // A loan with an outstanding principal can't be reinitialized.
+    let reinitialize_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[
+            lending::instruction::reinitialize_loan(&ctx.lender.pubkey(), &borrower.pubkey(), 0)
+                .unwrap(),
+        ],
+    );
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanActive as u32),
+    );
+
+    // Borrower repays the loan in full.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    assert!(!ctx.loan(&borrower).await.active);
+
+    let reinitialize_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[
+            lending::instruction::reinitialize_loan(&ctx.lender.pubkey(), &borrower.pubkey(), 0)
+                .unwrap(),
+        ],
+    );
+    ctx.test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap();
+
+    // The loan starts over empty but keeps its borrower slot and transfer ids.
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.encrypted_principal == Default::default());
+    assert!(loan.encrypted_collateral == Default::default());
+    assert_eq!(0, loan.last_update_slot);
+    assert_eq!(2, loan.next_transfer_id);
+    assert_eq!(1, ctx.lending_pool().await.num_borrowers);
+
+    // The same loan account can be borrowed against again.
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.collateral_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 3, 2);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.loan(&borrower).await.active);
+    assert_eq!(
+        collateral_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.loan(&borrower).await.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_reinitialize_loan_origination_checks() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let other_borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // Borrower repays the loan in full.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    // The lender now requires their approval and allowlists another borrower only.
+    let lender_tx = ctx.lender_tx(&[
+        lending::instruction::set_lender_approval(&ctx.lender.pubkey(), true).unwrap(),
+        lending::instruction::add_allowed_borrower(&ctx.lender.pubkey(), &other_borrower.pubkey())
+            .unwrap(),
+    ]);
+    ctx.test
+        .process_transaction(lender_tx, false)
+        .await
+        .unwrap();
+
+    // A loan reinitialized by the borrower alone is rejected.
+    let reinitialize_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[
+            lending::instruction::reinitialize_loan(&ctx.lender.pubkey(), &borrower.pubkey(), 0)
+                .unwrap(),
+        ],
+    );
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::MissingRequiredSignature);
+
+    // Co-signed by the lender, the borrower is still not allowlisted.
+    let reinitialize_loan_ix = lending::instruction::reinitialize_loan_with_lender_approval(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        0,
+    )
+    .unwrap();
+    let reinitialize_loan_tx = Transaction::new_signed_with_payer(
+        &[reinitialize_loan_ix.clone()],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &borrower.signer_keypair(),
+            &ctx.lender.signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::NotAllowlisted as u32),
+    );
+
+    // Once allowlisted, the loan is reinitialized.
+    let add_allowed_borrower_tx = ctx.lender_tx(&[lending::instruction::add_allowed_borrower(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(add_allowed_borrower_tx, false)
+        .await
+        .unwrap();
+    let reinitialize_loan_tx = Transaction::new_signed_with_payer(
+        &[reinitialize_loan_ix],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &borrower.signer_keypair(),
+            &ctx.lender.signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(2, ctx.loan(&borrower).await.next_transfer_id);
+}
+
+#[tokio::test]
+async fn test_repay_inactive_loan() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // A loan that hasn't been drawn has nothing to repay.
+    let repay_tx = ctx.repay_tx(&borrower, 1, 0, 0);
+    let err = ctx
+        .test
+        .process_transaction(repay_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::LoanInactive as u32),
+    );
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // Borrower repays the loan in full.
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 3, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    assert!(!ctx.loan(&borrower).await.active);
+
+    // The repaid loan can't be repaid again.
+    let repay_tx = ctx.repay_tx(&borrower, 4, 2, 0);
+    let err = ctx
+        .test
+        .process_transaction(repay_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        2,
+        InstructionError::Custom(LendingError::LoanInactive as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_reveal_repayment_status_without_repayment() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let init_loan_tx = ctx.initialize_loan_with_reveal_policy_tx(&borrower, false);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+
+    // A loan that hasn't been drawn has no repayment status to reveal.
+    let reveal_tx = ctx.lender_tx(&[lending::instruction::reveal_repayment_status(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.collateral_mint,
+        1,
+        0,
+    )
+    .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanInactive as u32),
+    );
+
+    // Neither has a drawn loan before its first repayment.
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let reveal_tx = ctx.lender_tx(&[lending::instruction::reveal_repayment_status(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.collateral_mint,
+        3,
+        1,
+    )
+    .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::RepaymentStatusUnknown as u32),
+    );
+    let loan = ctx.loan(&borrower).await;
+    assert!(loan.active);
+    assert_eq!(1, ctx.lending_pool().await.num_active_loans);
+}
+
+#[tokio::test]
+async fn test_withdraw_liquidity() {
+    let liquidity = 1000;
+    let mut ctx = setup_lending(liquidity, 1, 10_000, 1, 0).await;
+
+    let asset_vault_ata = ctx.asset_vault_ata();
+    let asset_lender_ata = ctx.lender.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    let lender_balance = ctx
+        .test
+        .total_balance(&asset_lender_ata, &ctx.mxe_pubkey)
+        .await
+        .unwrap();
+
+    // Withdrawing more than the vault holds moves no funds.
+    for (computation_offset, transfer_id, amount, withdrawn) in
+        [(1, 0, liquidity + 1, 0), (2, 1, 400, 400)]
+    {
+        let withdraw_tx = ctx.lender_tx(&[lending::instruction::withdraw_liquidity(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+            computation_offset,
+            transfer_id,
+            amount,
+        )
+        .unwrap()]);
+        ctx.test
+            .process_transaction(withdraw_tx, false)
+            .await
+            .unwrap();
+
+        ctx.test
+            .assert_total_balance(&asset_vault_ata, &ctx.mxe_pubkey, liquidity - withdrawn)
+            .await;
+        ctx.test
+            .assert_total_balance(
+                &asset_lender_ata,
+                &ctx.mxe_pubkey,
+                lender_balance + withdrawn,
+            )
+            .await;
+    }
+
+    // Only the lender can withdraw liquidity.
+    let borrower = new_borrower(&mut ctx, 100).await;
+    let mut forged_withdraw_instruction =
+        lending::instruction::withdraw_liquidity(&ctx.lender.pubkey(), &ctx.asset_mint, 3, 2, 100)
+            .unwrap();
+    forged_withdraw_instruction.accounts[0].is_signer = false;
+    let forged_withdraw_tx = ctx.borrower_tx(&borrower, &[forged_withdraw_instruction]);
+    let err = ctx
+        .test
+        .process_transaction(forged_withdraw_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::MissingRequiredSignature);
+}
+
+#[tokio::test]
+async fn test_repay_compound_interest() {
+    let interest_rate_bps = 100;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    let set_compound_interest_tx =
+        ctx.lender_tx(&[
+            lending::instruction::set_compound_interest(&ctx.lender.pubkey(), true).unwrap(),
+        ]);
+    ctx.test
+        .process_transaction(set_compound_interest_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.lending_pool().await.compound);
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+    ctx.test.warp_to_slot(borrow_slot + 100).unwrap();
+
+    // Borrower partially repays the loan.
+    let repay_amount = 50;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    let loan = ctx.loan(&borrower).await;
+    let slots_elapsed = loan.last_update_slot - borrow_slot;
+    let loan_amount = collateral_amount;
+
+    // Interest is compounded once per period over every slot since the borrow.
+    let periods = slots_elapsed.clamp(1, MAX_COMPOUNDING_PERIODS);
+    let period_interest_rate_bps = interest_rate_bps as u64 * slots_elapsed / periods;
+    let mut compound_total_due = loan_amount;
+    for _ in 0..periods {
+        compound_total_due += mul_base_points(compound_total_due, period_interest_rate_bps);
+    }
+    let simple_total_due =
+        loan_amount + mul_base_points(loan_amount, interest_rate_bps as u64 * slots_elapsed);
+    assert!(compound_total_due > simple_total_due);
+
+    assert_eq!(
+        compound_total_due - repay_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_accrue_interest() {
+    let interest_rate_bps = 100;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    // Both borrowers take the same loan and repay the same amount 300 slots later, the first one
+    // has the interest accrued into the principal every 100 slots in between.
+    let collateral_amount = 100;
+    let repay_amount = 50;
+    let mut remaining_dues = Vec::new();
+    for (i, accruals) in [2, 0].into_iter().enumerate() {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+        let computation_offset = 10 * i as u32;
+        let borrow_tx = ctx.borrow_tx(&borrower, computation_offset + 1, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
+            .await
+            .unwrap();
+        let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+
+        let loan_amount = collateral_amount;
+        let mut principal = loan_amount;
+        let mut last_update_slot = borrow_slot;
+        for accrual in 1..=accruals {
+            ctx.test
+                .warp_to_slot(borrow_slot + 100 * accrual as u64)
+                .unwrap();
+            let accrue_interest_tx = ctx.lender_tx(&[lending::instruction::accrue_interest(
+                &ctx.lender.pubkey(),
+                &borrower.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                computation_offset + 1 + accrual,
+                accrual,
+            )
+            .unwrap()]);
+            ctx.test
+                .process_transaction(accrue_interest_tx, false)
+                .await
+                .unwrap();
+
+            // The interest accrued since the last update is now part of the principal.
+            let loan = ctx.loan(&borrower).await;
+            principal += mul_base_points(
+                principal,
+                interest_rate_bps as u64 * (loan.last_update_slot - last_update_slot),
+            );
+            last_update_slot = loan.last_update_slot;
+            assert_eq!(
+                principal,
+                ctx.test
+                    .get_mxe(&ctx.mxe_pubkey)
+                    .unwrap()
+                    .rescue_decrypt(loan.encrypted_principal)
+            );
+        }
+
+        ctx.test.warp_to_slot(borrow_slot + 300).unwrap();
+        let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+        borrower
+            .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+            .await;
+        borrower
+            .transfer(
+                &mut ctx.test,
+                &ctx.asset_mint,
+                repay_amount,
+                &asset_repay_ata,
+            )
+            .await;
+        let repay_tx = ctx.repay_tx(&borrower, computation_offset + 9, 1, 0);
+        ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+        // The repayment charges interest on the accrued principal.
+        let loan = ctx.loan(&borrower).await;
+        let remaining_due = principal
+            + mul_base_points(
+                principal,
+                interest_rate_bps as u64 * (loan.last_update_slot - last_update_slot),
+            )
+            - repay_amount;
+        assert_eq!(
+            remaining_due,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(loan.encrypted_principal)
+        );
+        remaining_dues.push(remaining_due);
+    }
+
+    // Accruing in between compounds the interest, so more is left due than after a one-shot
+    // repayment.
+    assert!(remaining_dues[0] > remaining_dues[1]);
+}
+
+#[tokio::test]
+async fn test_accrue_interest_after_repay() {
+    let mut ctx = setup_lending(1000, 10, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+    ctx.test.warp_to_slot(borrow_slot + 100).unwrap();
+
+    // The borrower funds a full repayment with a buffer on top of the interest.
+    let buffer = 50;
+    let asset_borrower_ata = borrower.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+            &ctx.asset_mint_authority,
+            buffer,
+            &asset_borrower_ata,
+        )
+        .await;
+    borrower
+        .deposit(&mut ctx.test, &ctx.asset_mint, buffer)
+        .await;
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            collateral_amount + buffer,
+            &asset_repay_ata,
+        )
+        .await;
+
+    // The lender queues an accrual and the full repayment is queued while it is pending.
+    let instructions = [
+        ComputeBudgetInstruction::set_compute_unit_limit(2 * REPAY_COMPUTE_UNITS),
+        lending::instruction::repay(
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &ctx.reserve_vault_ata,
+            2,
+            1,
+            0,
+        )
+        .unwrap(),
+        lending::instruction::accrue_interest(
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            3,
+            2,
+        )
+        .unwrap(),
+    ];
+    let tx = Transaction::new_signed_with_payer(
+        &instructions,
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &ctx.lender.signer_keypair(),
+            &borrower.signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test.process_transaction(tx, false).await.unwrap();
+
+    // The accrual computed from the principal before the repayment is ignored, the repaid loan
+    // doesn't owe its old principal again.
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.settled);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_rate_curve() {
+    let liquidity = 500;
+    let max_active_loans = 4;
+    let mut ctx = setup_lending(liquidity, 100, 10_000, 1, max_active_loans).await;
+
+    // The kink can't lie beyond full utilization.
+    let set_rate_curve_tx = ctx.lender_tx(&[lending::instruction::set_rate_curve(
+        &ctx.lender.pubkey(),
+        100,
+        400,
+        5000,
+        10_001,
+    )
+    .unwrap()]);
+    let result = ctx.test.process_transaction(set_rate_curve_tx, false).await;
+    assert_instruction_error(result.unwrap_err(), 0, InstructionError::InvalidArgument);
+
+    // The rate grows by up to 4% until 50% utilization, and by up to 50% above it.
+    let set_rate_curve_tx = ctx.lender_tx(&[lending::instruction::set_rate_curve(
+        &ctx.lender.pubkey(),
+        100,
+        400,
+        5000,
+        5000,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(set_rate_curve_tx, false)
+        .await
+        .unwrap();
+    let rate_bps = |utilization_bps: u64| {
+        let below_kink = utilization_bps.min(5000);
+        100 + 400 * below_kink / 5000 + 5000 * (utilization_bps - below_kink) / 5000
+    };
+
+    let collateral_amount = 100;
+    let mut borrowers = Vec::new();
+    for _ in 0..max_active_loans {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+        borrowers.push(borrower);
+    }
+    let borrower_keys: Vec<Pubkey> = borrowers.iter().map(|b| b.pubkey()).collect();
+
+    // The first loan accrues interest alone at 20% utilization, then with every loan drawn at
+    // above 80% utilization. Utilization is the total debt over the debt plus the asset vault.
+    let mut principal = collateral_amount;
+    let mut interests = Vec::new();
+    let phases = [&borrowers[..1], &borrowers[1..]];
+    let mut computation_offset = 0;
+    let mut vault_balance = liquidity;
+    for (phase, new_loans) in phases.into_iter().enumerate() {
+        for borrower in new_loans {
+            computation_offset += 1;
+            let borrow_tx = ctx.borrow_tx(borrower, computation_offset, 0);
+            ctx.test
+                .process_transaction(borrow_tx, false)
+                .await
+                .unwrap();
+            vault_balance -= collateral_amount;
+        }
+
+        computation_offset += 1;
+        let total_debt_tx = ctx.lender_tx(&[lending::instruction::compute_total_debt(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+            &borrower_keys,
+            computation_offset,
+            phase as u32,
+            &ctx.lender.x25519_pubkey(),
+            computation_offset as u128,
+        )
+        .unwrap()]);
+        ctx.test
+            .process_transaction(total_debt_tx, false)
+            .await
+            .unwrap();
+        let total_debt = principal + (liquidity - collateral_amount - vault_balance);
+        assert_eq!(
+            total_debt,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(ctx.lending_pool().await.encrypted_total_debt)
+        );
+        let utilization_bps = total_debt * 10_000 / (total_debt + vault_balance);
+
+        let last_update_slot = ctx.loan(&borrowers[0]).await.last_update_slot;
+        ctx.test.warp_to_slot(last_update_slot + 10).unwrap();
+        computation_offset += 1;
+        let accrue_interest_tx = ctx.lender_tx(&[lending::instruction::accrue_interest(
+            &ctx.lender.pubkey(),
+            &borrowers[0].pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            computation_offset,
+            phase as u32 + 1,
+        )
+        .unwrap()]);
+        ctx.test
+            .process_transaction(accrue_interest_tx, false)
+            .await
+            .unwrap();
+
+        let loan = ctx.loan(&borrowers[0]).await;
+        let interest = mul_base_points(
+            principal,
+            rate_bps(utilization_bps) * (loan.last_update_slot - last_update_slot),
+        );
+        principal += interest;
+        assert_eq!(
+            principal,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(loan.encrypted_principal)
+        );
+        interests.push(interest);
+    }
+
+    // The same loan pays more interest over the same slots once the pool is highly utilized.
+    assert!(interests[1] > interests[0]);
+}
+
+#[tokio::test]
+async fn test_settle_matured_loan() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 1, 0).await;
+    ctx.lender
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        )
+        .await;
+
+    let collateral_amount = 100;
+    let maturity_slot = 1000;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let init_loan_tx = ctx.initialize_loan_with_options_tx(&borrower, true, maturity_slot);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    assert_eq!(maturity_slot, ctx.loan(&borrower).await.maturity_slot);
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        ..
+    } = simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+    assert!(ctx.loan(&borrower).await.last_update_slot < maturity_slot);
+
+    // The healthy loan can't be settled before its maturity.
+    let (lender, borrower_pubkey) = (ctx.lender.pubkey(), borrower.pubkey());
+    let (asset_mint, collateral_mint) = (ctx.asset_mint, ctx.collateral_mint);
+    let settle_ix = move |computation_offset| {
+        lending::instruction::settle_matured_loan(
+            &lender,
+            &borrower_pubkey,
+            &asset_mint,
+            &collateral_mint,
+            computation_offset,
+            1,
+        )
+        .unwrap()
+    };
+    let settle_tx = ctx.lender_tx(&[settle_ix(2)]);
+    let err = ctx
+        .test
+        .process_transaction(settle_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanNotMatured as u32),
+    );
+
+    // Once matured, the lender seizes the collateral worth the outstanding principal at the
+    // oracle's current price.
+    let price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    ctx.test.warp_to_slot(maturity_slot).unwrap();
+    let settle_tx = ctx.lender_tx(&[settle_ix(3)]);
+    ctx.test
+        .process_transaction(settle_tx, false)
+        .await
+        .unwrap();
+
+    let seized_collateral = min(loan_amount.div_ceil(price), loan_collateral_amount);
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, seized_collateral)
+        .await;
+
+    // The excess collateral goes back to the borrower.
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_excess_amount + loan_collateral_amount - seized_collateral,
+        )
+        .await;
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, 0)
+        .await;
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+    // The pool's total debt follows the settled principal.
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.lending_pool().await.encrypted_total_debt)
+    );
+
+    // The settled loan can't be reinitialized with its past maturity, only with a new one.
+    let reinitialize_loan_tx = |ctx: &LendingTest, maturity_slot| {
+        ctx.borrower_tx(
+            &borrower,
+            &[lending::instruction::reinitialize_loan(
+                &ctx.lender.pubkey(),
+                &borrower.pubkey(),
+                maturity_slot,
+            )
+            .unwrap()],
+        )
+    };
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx(&ctx, maturity_slot), false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidArgument);
+
+    let new_maturity_slot = 2 * maturity_slot;
+    ctx.test
+        .process_transaction(reinitialize_loan_tx(&ctx, new_maturity_slot), false)
+        .await
+        .unwrap();
+    assert_eq!(new_maturity_slot, ctx.loan(&borrower).await.maturity_slot);
+}
+
+#[tokio::test]
+async fn test_settle_matured_loan_shortfall() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+    ctx.lender
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        )
+        .await;
+
+    // The loan is opened while the collateral is worth twice the oracle's later price.
+    let borrow_price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[
+            lending::instruction::set_price(&ctx.lender.pubkey(), borrow_price).unwrap(),
+        ]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    let collateral_amount = 100;
+    let maturity_slot = 1000;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let init_loan_tx = ctx.initialize_loan_with_options_tx(&borrower, true, maturity_slot);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        ..
+    } = simulate_borrow(
+        collateral_amount,
+        1000,
+        borrow_price,
+        &ctx.lending_pool().await,
+    );
+
+    // At maturity the collateral no longer covers the principal.
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), 1).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    ctx.test.warp_to_slot(maturity_slot).unwrap();
+    let settle_tx = ctx.lender_tx(&[lending::instruction::settle_matured_loan(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        2,
+        1,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(settle_tx, false)
+        .await
+        .unwrap();
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, loan_collateral_amount)
+        .await;
+
+    // The remainder is still due, so the loan stays active.
+    let loan = ctx.loan(&borrower).await;
+    assert!(loan.active);
+    assert_eq!(
+        loan_amount - loan_collateral_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+    assert_eq!(1, ctx.lending_pool().await.num_active_loans);
+
+    // And can't be reinitialized, which would wipe the remainder.
+    let reinitialize_loan_tx = ctx.borrower_tx(
+        &borrower,
+        &[
+            lending::instruction::reinitialize_loan(&ctx.lender.pubkey(), &borrower.pubkey(), 0)
+                .unwrap(),
+        ],
+    );
+    let err = ctx
+        .test
+        .process_transaction(reinitialize_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanActive as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_settle_matured_loan_rounding() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 1, 0).await;
+    ctx.lender
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        )
+        .await;
+
+    let collateral_amount = 100;
+    let maturity_slot = 1000;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let init_loan_tx = ctx.initialize_loan_with_options_tx(&borrower, true, maturity_slot);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        ..
+    } = simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+
+    // At maturity the price doesn't divide the principal.
+    let price = 3;
+    assert_ne!(0, loan_amount % price);
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    ctx.test.warp_to_slot(maturity_slot).unwrap();
+    let settle_tx = ctx.lender_tx(&[lending::instruction::settle_matured_loan(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        2,
+        1,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(settle_tx, false)
+        .await
+        .unwrap();
+
+    // The seized collateral is rounded up to cover the whole principal, the rest goes back to the
+    // borrower.
+    let seized_collateral = loan_amount.div_ceil(price);
+    assert!(seized_collateral < loan_collateral_amount);
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, seized_collateral)
+        .await;
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_excess_amount + loan_collateral_amount - seized_collateral,
+        )
+        .await;
+
+    // No remainder below the price is left due, the loan is settled.
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.settled);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+}
+
+#[tokio::test]
+async fn test_initialize_lending_pool_borrow_limits() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    // A max_borrow of 0, or below min_borrow, leaves no amount a borrow could be granted.
+    let lender = ctx.test.new_actor().await;
+    for (min_borrow, max_borrow) in [(0, 0), (60, 20)] {
+        let init_lending_pool_tx = Transaction::new_signed_with_payer(
+            &[lending::instruction::initialize_lending_pool(
+                &lender.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                1,
+                10_000,
+                1,
+                0,
+                min_borrow,
+                max_borrow,
+            )
+            .unwrap()],
+            Some(&ctx.test.get_payer().pubkey()),
+            &[&ctx.test.get_payer(), &lender.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        );
+        let err = ctx
+            .test
+            .process_transaction(init_lending_pool_tx, false)
+            .await
+            .unwrap_err();
+        assert_instruction_error(err, 0, InstructionError::InvalidArgument);
+    }
+}
+
+#[tokio::test]
+async fn test_borrow_limits() {
+    let (min_borrow, max_borrow) = (20, 60);
+    let mut ctx =
+        setup_lending_with_borrow_limits(1000, 1, 10_000, 1, 0, min_borrow, max_borrow).await;
+
+    // A borrow above max_borrow is capped, the excess collateral is returned.
+    // A borrow below min_borrow lends nothing, keeps the collateral locked and leaves the loan
+    // inactive.
+    for (i, (collateral_amount, loan_amount, locked_collateral)) in
+        [(100, max_borrow, max_borrow), (10, 0, 10)]
+            .into_iter()
+            .enumerate()
+    {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+        let borrow_tx = ctx.borrow_tx(&borrower, 1 + i as u32, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
+            .await
+            .unwrap();
+
+        let loan = ctx.loan(&borrower).await;
+        if loan_amount > 0 {
+            assert!(loan.active);
+            assert_eq!(
+                loan_amount,
+                ctx.test
+                    .get_mxe(&ctx.mxe_pubkey)
+                    .unwrap()
+                    .rescue_decrypt(loan.encrypted_principal)
+            );
+        } else {
+            assert!(!loan.active);
+            assert!(loan.encrypted_principal == Default::default());
+        }
+        borrower
+            .assert_total_balance(&mut ctx.test, &ctx.asset_mint, loan_amount)
+            .await;
+        ctx.test
+            .assert_total_balance(
+                &ctx.collateral_vault_ata(&borrower),
+                &ctx.mxe_pubkey,
+                locked_collateral,
+            )
+            .await;
+    }
+    assert_eq!(1, ctx.lending_pool().await.num_active_loans);
+}
+
+#[tokio::test]
+async fn test_borrow_below_min_collateral_ratio() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 1, 0).await;
+
+    // One collateral token is worth three asset tokens, the collateral backing a borrow must be
+    // worth twice the loan.
+    let price = 3;
+    let min_collateral_ratio_bps = 20_000;
+    let set_params_tx = ctx.lender_tx(&[
+        lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap(),
+        lending::instruction::set_min_collateral_ratio(
+            &ctx.lender.pubkey(),
+            min_collateral_ratio_bps,
+        )
+        .unwrap(),
+    ]);
+    ctx.test
+        .process_transaction(set_params_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        min_collateral_ratio_bps,
+        ctx.lending_pool().await.min_collateral_ratio_bps
+    );
+
+    // A single collateral token allows a loan of 1, but rounds the collateral backing it down to 0.
+    // The borrow is rejected and no funds are moved.
+    let collateral_amount = 1;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert!(loan.encrypted_principal == Default::default());
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, 0)
+        .await;
+    ctx.test
+        .assert_total_balance(
+            &ctx.collateral_vault_ata(&borrower),
+            &ctx.mxe_pubkey,
+            collateral_amount,
+        )
+        .await;
+
+    // A sufficient deposit keeps the collateral ratio and borrows as usual.
+    let collateral_amount = 100;
+    let other_borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &other_borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&other_borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let loan = ctx.loan(&other_borrower).await;
+    assert!(loan.active);
+    assert_eq!(
+        price * mul_base_points(collateral_amount, loan_to_value_bps as u64),
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_withdraw_liquidity_multisig() {
+    let liquidity = 1000;
+    let multisig_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let mut ctx = setup_lending_with_multisig(
+        liquidity,
+        1,
+        10_000,
+        1,
+        0,
+        0,
+        u64::MAX,
+        2,
+        &multisig_signers,
+    )
+    .await;
+
+    let asset_vault_ata = ctx.asset_vault_ata();
+    let withdraw_ix = |ctx: &LendingTest, signers: &[&Keypair]| {
+        lending::instruction::with_multisig_signers(
+            lending::instruction::withdraw_liquidity(
+                &ctx.lender.pubkey(),
+                &ctx.asset_mint,
+                1,
+                0,
+                400,
+            )
+            .unwrap(),
+            &signers
+                .iter()
+                .map(|signer| signer.pubkey())
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    // The lender alone, or with a single multisig signer, can't withdraw.
+    let lender_withdraw_tx = ctx.lender_tx(&[withdraw_ix(&ctx, &[])]);
+    let single_signer_withdraw_tx = Transaction::new_signed_with_payer(
+        &[withdraw_ix(&ctx, &[&multisig_signers[0]])],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &ctx.lender.signer_keypair(),
+            &multisig_signers[0],
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    for withdraw_tx in [lender_withdraw_tx, single_signer_withdraw_tx] {
+        let err = ctx
+            .test
+            .process_transaction(withdraw_tx, false)
+            .await
+            .unwrap_err();
+        assert_instruction_error(
+            err,
+            0,
+            InstructionError::Custom(LendingError::MultisigThresholdNotMet as u32),
+        );
+    }
+    ctx.test
+        .assert_total_balance(&asset_vault_ata, &ctx.mxe_pubkey, liquidity)
+        .await;
+
+    // Two of the three multisig signers authorize the withdrawal.
+    let withdraw_tx = Transaction::new_signed_with_payer(
+        &[withdraw_ix(
+            &ctx,
+            &[&multisig_signers[0], &multisig_signers[2]],
+        )],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[
+            &ctx.test.get_payer(),
+            &ctx.lender.signer_keypair(),
+            &multisig_signers[0],
+            &multisig_signers[2],
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(withdraw_tx, false)
+        .await
+        .unwrap();
+    ctx.test
+        .assert_total_balance(&asset_vault_ata, &ctx.mxe_pubkey, liquidity - 400)
+        .await;
+
+    // Parameter updates need the multisig as well.
+    let update_pool_params_tx = ctx.lender_tx(&[lending::instruction::update_pool_params(
+        &ctx.lender.pubkey(),
+        2,
+        10_000,
+        false,
+    )
+    .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(update_pool_params_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::MultisigThresholdNotMet as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_pool_settings_multisig() {
+    let multisig_signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+    let mut ctx =
+        setup_lending_with_multisig(1000, 1, 10_000, 1, 0, 0, u64::MAX, 2, &multisig_signers).await;
+
+    let lender = ctx.lender.pubkey();
+    let reserve_vault_ata = ctx.reserve_vault_ata;
+    let settings_ixs = [
+        lending::instruction::set_price(&lender, 2).unwrap(),
+        lending::instruction::set_collateral_threshold(
+            &lender,
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &[],
+            2,
+            0,
+            0,
+        )
+        .unwrap(),
+        lending::instruction::set_rate_curve(&lender, 100, 400, 2000, 8000).unwrap(),
+        lending::instruction::set_reserve(&lender, &reserve_vault_ata, 1000).unwrap(),
+        lending::instruction::set_origination_fee(&lender, 100).unwrap(),
+        lending::instruction::set_min_collateral_ratio(&lender, 12_000).unwrap(),
+        lending::instruction::set_liquidation_bonus(&lender, 500).unwrap(),
+        lending::instruction::withdraw_reserve(&lender, &ctx.asset_mint, 0, 0, 1).unwrap(),
+    ];
+
+    // Neither the lender alone nor with a single multisig signer may change the settings or
+    // withdraw the reserve.
+    for settings_ix in settings_ixs {
+        for signers in [&[][..], &[&multisig_signers[1]][..]] {
+            let settings_tx = ctx.lender_multisig_tx(&[settings_ix.clone()], signers);
+            let err = ctx
+                .test
+                .process_transaction(settings_tx, false)
+                .await
+                .unwrap_err();
+            assert_instruction_error(
+                err,
+                0,
+                InstructionError::Custom(LendingError::MultisigThresholdNotMet as u32),
+            );
+        }
+    }
+    assert_eq!(1, ctx.lending_pool().await.collateral_threshold_bps);
+
+    // Two of the three multisig signers authorize the change.
+    let set_collateral_threshold_tx = ctx.lender_multisig_tx(
+        &[lending::instruction::set_collateral_threshold(
+            &lender,
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &[],
+            2,
+            0,
+            0,
+        )
+        .unwrap()],
+        &[&multisig_signers[0], &multisig_signers[1]],
+    );
+    ctx.test
+        .process_transaction(set_collateral_threshold_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(2, ctx.lending_pool().await.collateral_threshold_bps);
+}
+
+#[tokio::test]
+async fn test_update_pool_params() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let update_pool_params_tx = ctx.lender_tx(&[lending::instruction::update_pool_params(
+        &ctx.lender.pubkey(),
+        2,
+        5000,
+        false,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(update_pool_params_tx, false)
+        .await
+        .unwrap();
+
+    // The collateral threshold is left to set_collateral_threshold.
+    let lending_pool = ctx.lending_pool().await;
+    assert_eq!(2, lending_pool.interest_rate_bps);
+    assert_eq!(5000, lending_pool.loan_to_value_bps);
+    assert_eq!(1, lending_pool.collateral_threshold_bps);
+
+    // Once a borrower opened a loan, the parameters can only be updated by force.
+    let borrower = new_borrower(&mut ctx, 100).await;
+    open_loan(&mut ctx, &borrower, 100).await;
+    for force in [false, true] {
+        let update_pool_params_tx = ctx.lender_tx(&[lending::instruction::update_pool_params(
+            &ctx.lender.pubkey(),
+            3,
+            6000,
+            force,
+        )
+        .unwrap()]);
+        let result = ctx
+            .test
+            .process_transaction(update_pool_params_tx, false)
+            .await;
+        if force {
+            result.unwrap();
+        } else {
+            assert_instruction_error(
+                result.unwrap_err(),
+                0,
+                InstructionError::Custom(LendingError::PoolHasBorrowers as u32),
+            );
+            assert_eq!(2, ctx.lending_pool().await.interest_rate_bps);
+        }
+    }
+
+    let lending_pool = ctx.lending_pool().await;
+    assert_eq!(3, lending_pool.interest_rate_bps);
+    assert_eq!(6000, lending_pool.loan_to_value_bps);
+    assert_eq!(1, lending_pool.collateral_threshold_bps);
+}
+
+#[tokio::test]
+async fn test_derived_authority_signs_vault_transfers() {
+    let liquidity = 1000;
+    let mut ctx = setup_lending(liquidity, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // A borrow that substitutes the derived lending pool authority with an authority derived
+    // from the borrower must not be able to move funds out of the asset_vault_ata.
+    let mut forged_borrow_instruction = lending::instruction::borrow(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        &price_oracle_pda(&ctx.lender.pubkey()).0,
+        1,
+        0,
+    )
+    .unwrap();
+    let derived_lending_pool_authority =
+        derive_authority(&lending_pool_pda(&ctx.lender.pubkey()).0).0;
+    assert_eq!(
+        derived_lending_pool_authority,
+        forged_borrow_instruction.accounts[3].pubkey
+    );
+    forged_borrow_instruction.accounts[3].pubkey = derive_authority(&borrower.pubkey()).0;
+    let forged_borrow_tx = ctx.borrower_tx(&borrower, &[forged_borrow_instruction]);
+    assert!(ctx
+        .test
+        .process_transaction(forged_borrow_tx, false)
+        .await
+        .is_err());
+
+    let asset_vault_ata = ctx.asset_vault_ata();
+    ctx.test
+        .assert_total_balance(&asset_vault_ata, &ctx.mxe_pubkey, liquidity)
+        .await;
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, 0)
+        .await;
+
+    // The program's borrow path, signing with the derived authority, moves the funds.
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    borrower
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, collateral_amount)
+        .await;
+    ctx.test
+        .assert_total_balance(
+            &asset_vault_ata,
+            &ctx.mxe_pubkey,
+            liquidity - collateral_amount,
+        )
+        .await;
+}
+
+#[tokio::test]
+async fn test_liquidation() {
+    let loan_to_value_bps = 5000;
+    let collateral_threshold_bps = 6000;
+    let liquidity = 1000;
+    let mut ctx = setup_lending(liquidity, 1, loan_to_value_bps, collateral_threshold_bps, 0).await;
+
+    // One collateral token is worth four asset tokens at borrow time.
+    let borrow_price = 4;
+    let set_price_tx =
+        ctx.lender_tx(&[
+            lending::instruction::set_price(&ctx.lender.pubkey(), borrow_price).unwrap(),
+        ]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        ..
+    } = simulate_borrow(
+        collateral_amount,
+        liquidity,
+        borrow_price,
+        &ctx.lending_pool().await,
+    );
+    assert_eq!(
+        div_base_points(loan_amount, borrow_price * loan_to_value_bps as u64),
+        loan_collateral_amount
+    );
+
+    // The collateral price drops, leaving the loan under-collateralized, the liquidation is priced
+    // at the oracle's new price.
+    let price = 3;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    assert!(
+        mul_base_points(
+            loan_collateral_amount * price,
+            collateral_threshold_bps as u64
+        ) < loan_amount
+    );
+
+    // A third-party liquidator repays the loan_amount to liquidate the loan.
+    let liquidator = new_liquidator(&mut ctx, loan_amount).await;
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 2, 1);
+    ctx.test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap();
+
+    // The lender is made whole.
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, loan_amount)
+        .await;
+
+    // The liquidator seizes the collateral worth the loan_amount, the rest goes to the borrower.
+    let seized_collateral = min(loan_amount / price, loan_collateral_amount);
+    liquidator
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, seized_collateral)
+        .await;
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_excess_amount + loan_collateral_amount - seized_collateral,
+        )
+        .await;
+    ctx.test
+        .assert_total_balance(&ctx.collateral_vault_ata(&borrower), &ctx.mxe_pubkey, 0)
+        .await;
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+    assert_eq!(0, ctx.lending_pool().await.num_active_loans);
+    // The liquidated principal left the pool's total debt.
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.lending_pool().await.encrypted_total_debt)
+    );
+}
+
+#[tokio::test]
+async fn test_liquidation_bonus() {
+    // A collateral threshold below the loan to value makes the loan liquidatable right away.
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 4000, 0).await;
+
+    // A bonus above 100% is rejected.
+    let liquidation_bonus_bps = 1000;
+    for liquidation_bonus_bps in [10_001, liquidation_bonus_bps] {
+        let set_bonus_tx = ctx.lender_tx(&[lending::instruction::set_liquidation_bonus(
+            &ctx.lender.pubkey(),
+            liquidation_bonus_bps,
+        )
+        .unwrap()]);
+        let result = ctx.test.process_transaction(set_bonus_tx, false).await;
+        if liquidation_bonus_bps > 10_000 {
+            assert_instruction_error(result.unwrap_err(), 0, InstructionError::InvalidArgument);
+        } else {
+            result.unwrap();
+        }
+    }
+    assert_eq!(
+        liquidation_bonus_bps,
+        ctx.lending_pool().await.liquidation_bonus_bps
+    );
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        ..
+    } = simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+
+    // A third-party liquidator repays the loan_amount to liquidate the unhealthy loan.
+    let liquidator = new_liquidator(&mut ctx, loan_amount).await;
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 2, 1);
+    ctx.test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap();
+
+    // The lender is repaid the loan_amount.
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, loan_amount)
+        .await;
+    liquidator
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, 0)
+        .await;
+
+    // The liquidator receives the collateral worth the loan_amount plus the bonus.
+    let price = 1;
+    let debt_collateral_amount = loan_amount / price;
+    let liquidation_bonus = debt_collateral_amount * liquidation_bonus_bps as u64 / 10_000;
+    let seized_collateral = debt_collateral_amount + liquidation_bonus;
+    assert!(liquidation_bonus > 0);
+    liquidator
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, seized_collateral)
+        .await;
+
+    // The residual collateral goes back to the borrower.
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_excess_amount + loan_collateral_amount - seized_collateral,
+        )
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, 0)
+        .await;
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_liquidation_proceeds_destination() {
+    // A collateral threshold below the loan to value makes the loan liquidatable right away.
+    let mut ctx = setup_lending(1000, 1, 10_000, 5_000, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // Seized collateral is routed to the lender by default.
+    assert_eq!(
+        ctx.lender
+            .ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.collateral_mint,
+            )
+            .to_bytes(),
+        ctx.lending_pool().await.liquidation_proceeds_destination
+    );
+
+    // A destination of the wrong mint is rejected.
+    let set_destination_tx =
+        ctx.lender_tx(
+            &[lending::instruction::set_liquidation_proceeds_destination(
+                &ctx.lender.pubkey(),
+                &ctx.lender.ata(
+                    &confidential_spl_token::programs::confidential_spl_token::ID,
+                    &ctx.asset_mint,
+                ),
+            )
+            .unwrap()],
+        );
+    let err = ctx
+        .test
+        .process_transaction(set_destination_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::MintMismatch as u32),
+    );
+
+    // Lender routes seized collateral to a treasury instead.
+    let treasury = ctx.test.new_actor().await;
+    treasury
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        )
+        .await;
+    let treasury_ata = treasury.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.collateral_mint,
+    );
+    let set_destination_tx =
+        ctx.lender_tx(
+            &[lending::instruction::set_liquidation_proceeds_destination(
+                &ctx.lender.pubkey(),
+                &treasury_ata,
+            )
+            .unwrap()],
+        );
+    ctx.test
+        .process_transaction(set_destination_tx, false)
+        .await
+        .unwrap();
+
+    // The lender liquidates the unhealthy loan without repaying it.
+    let liquidate_tx = ctx.lender_liquidate_tx(&borrower, &treasury_ata, 2, 1);
+    ctx.test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap();
+
+    // The collateral backing the loan_amount lands in the treasury, not with the lender.
+    let price = 1;
+    let loan_amount = collateral_amount;
+    let seized_collateral = min(loan_amount / price, collateral_amount);
+    treasury
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, seized_collateral)
+        .await;
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.collateral_mint, 0)
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, 0)
+        .await;
+
+    let loan = ctx.loan(&borrower).await;
+    assert!(!loan.active);
+    assert_eq!(
+        0,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_liquidate_to_other_collateral_ata() {
+    // A collateral threshold below the loan to value makes the loan liquidatable right away.
+    let mut ctx = setup_lending(1000, 1, 10_000, 5_000, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // A third-party liquidator can't route the seized collateral to the lender's destination, nor
+    // to any other account than its own collateral ATA.
+    let loan_amount = collateral_amount;
+    let liquidator = new_liquidator(&mut ctx, loan_amount).await;
+    let liquidation_proceeds_destination =
+        Pubkey::new_from_array(ctx.lending_pool().await.liquidation_proceeds_destination);
+    let liquidate_tx = Transaction::new_signed_with_payer(
+        &[lending::instruction::liquidate(
+            &liquidator.pubkey(),
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &liquidation_proceeds_destination,
+            2,
+            1,
+        )
+        .unwrap()],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[&ctx.test.get_payer(), &liquidator.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidAccountData);
+
+    // Nor repay from another account than its own asset ATA.
+    let mut liquidate_instruction = lending::instruction::liquidate(
+        &liquidator.pubkey(),
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        &liquidator.ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        ),
+        2,
+        1,
+    )
+    .unwrap();
+    liquidate_instruction.accounts[9].pubkey = ctx.asset_repay_ata(&borrower);
+    let liquidate_tx = Transaction::new_signed_with_payer(
+        &[liquidate_instruction],
+        Some(&ctx.test.get_payer().pubkey()),
+        &[&ctx.test.get_payer(), &liquidator.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidAccountData);
+
+    // The loan is still active.
+    assert!(ctx.loan(&borrower).await.active);
+}
+
+#[tokio::test]
+async fn test_repay_reserve_factor() {
+    let interest_rate_bps = 100;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    // The lender routes a cut of the interest to a treasury.
+    let treasury = ctx.test.new_actor().await;
+    treasury
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+        )
+        .await;
+    let treasury_ata = treasury.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    let reserve_factor_bps = 2000;
+    let set_reserve_tx = ctx.lender_tx(&[lending::instruction::set_reserve(
+        &ctx.lender.pubkey(),
+        &treasury_ata,
+        reserve_factor_bps,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(set_reserve_tx, false)
+        .await
+        .unwrap();
+    ctx.reserve_vault_ata = treasury_ata;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+    ctx.test.warp_to_slot(borrow_slot + 100).unwrap();
+
+    // Borrower partially repays the loan, the repayment pays off the interest first.
+    let repay_amount = 100;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    let slots_elapsed = ctx.loan(&borrower).await.last_update_slot - borrow_slot;
+    let loan_amount = collateral_amount;
+    let interest_accrued = mul_base_points(loan_amount, interest_rate_bps as u64 * slots_elapsed);
+    let reserve_amount = mul_base_points(
+        min(repay_amount, interest_accrued),
+        reserve_factor_bps as u64,
+    );
+    assert!(reserve_amount > 0);
+
+    let lender_receipt = ctx
+        .lender
+        .total_balance(&mut ctx.test, &ctx.asset_mint)
+        .await
+        .unwrap();
+    let reserve_receipt = treasury
+        .total_balance(&mut ctx.test, &ctx.asset_mint)
+        .await
+        .unwrap();
+    assert_eq!(reserve_amount, reserve_receipt);
+    assert_eq!(repay_amount, lender_receipt + reserve_receipt);
+}
+
+#[tokio::test]
+async fn test_withdraw_reserve() {
+    let interest_rate_bps = 100;
+    let mut ctx = setup_lending(1000, interest_rate_bps, 10_000, 1, 0).await;
+
+    // The default reserve vault is the lender's own token account, the program can't withdraw it.
+    let withdraw_reserve_tx = ctx.lender_tx(&[lending::instruction::withdraw_reserve(
+        &ctx.lender.pubkey(),
+        &ctx.asset_mint,
+        1,
+        0,
+        1,
+    )
+    .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(withdraw_reserve_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::ReserveVaultMismatch as u32),
+    );
+
+    let initialize_reserve_vault_tx =
+        ctx.lender_tx(&[lending::instruction::initialize_reserve_vault(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+        )
+        .unwrap()]);
+    ctx.test
+        .process_transaction(initialize_reserve_vault_tx, false)
+        .await
+        .unwrap();
+    let reserve_vault_ata = addresses::reserve_vault_ata(&ctx.lender.pubkey(), &ctx.asset_mint);
+    assert_eq!(
+        reserve_vault_ata.to_bytes(),
+        ctx.lending_pool().await.reserve_vault_ata
+    );
+
+    let reserve_factor_bps = 2000;
+    let set_reserve_tx = ctx.lender_tx(&[lending::instruction::set_reserve(
+        &ctx.lender.pubkey(),
+        &reserve_vault_ata,
+        reserve_factor_bps,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(set_reserve_tx, false)
+        .await
+        .unwrap();
+    ctx.reserve_vault_ata = reserve_vault_ata;
+
+    // A partial repayment accrues the reserve's cut of the interest.
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let borrow_slot = ctx.loan(&borrower).await.last_update_slot;
+    ctx.test.warp_to_slot(borrow_slot + 100).unwrap();
+
+    let repay_amount = 100;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    let slots_elapsed = ctx.loan(&borrower).await.last_update_slot - borrow_slot;
+    let interest_accrued =
+        mul_base_points(collateral_amount, interest_rate_bps as u64 * slots_elapsed);
+    let reserve_amount = mul_base_points(
+        min(repay_amount, interest_accrued),
+        reserve_factor_bps as u64,
+    );
+    assert!(reserve_amount > 0);
+    ctx.test
+        .assert_total_balance(&reserve_vault_ata, &ctx.mxe_pubkey, reserve_amount)
+        .await;
+
+    let asset_lender_ata = ctx.lender.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.asset_mint,
+    );
+    let lender_balance = ctx
+        .test
+        .total_balance(&asset_lender_ata, &ctx.mxe_pubkey)
+        .await
+        .unwrap();
+
+    // Withdrawing more than the reserve holds withdraws all of it.
+    let withdraw_reserve_tx = ctx.lender_tx(&[lending::instruction::withdraw_reserve(
+        &ctx.lender.pubkey(),
+        &ctx.asset_mint,
+        3,
+        0,
+        reserve_amount + 1,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(withdraw_reserve_tx, false)
+        .await
+        .unwrap();
+    ctx.test
+        .assert_total_balance(&reserve_vault_ata, &ctx.mxe_pubkey, 0)
+        .await;
+    ctx.test
+        .assert_total_balance(
+            &asset_lender_ata,
+            &ctx.mxe_pubkey,
+            lender_balance + reserve_amount,
+        )
+        .await;
+
+    // Only the lender can withdraw the reserve.
+    let mut forged_withdraw_reserve_instruction =
+        lending::instruction::withdraw_reserve(&ctx.lender.pubkey(), &ctx.asset_mint, 4, 1, 1)
+            .unwrap();
+    forged_withdraw_reserve_instruction.accounts[0].is_signer = false;
+    let forged_withdraw_reserve_tx =
+        ctx.borrower_tx(&borrower, &[forged_withdraw_reserve_instruction]);
+    let err = ctx
+        .test
+        .process_transaction(forged_withdraw_reserve_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::MissingRequiredSignature);
+}
+
+#[tokio::test]
+async fn test_repay_slippage_guard() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let loan_amount = collateral_amount;
+
+    // Borrower funds a partial repayment.
+    let repay_amount = 50;
+    let asset_repay_ata = ctx.asset_repay_ata(&borrower);
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            repay_amount,
+            &asset_repay_ata,
+        )
+        .await;
+
+    // The borrower expects all the collateral back, which a partial repayment can't release.
+    let repay_tx = ctx.repay_tx(&borrower, 2, 1, collateral_amount);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+
+    // The repayment was aborted: no funds moved and the loan is unchanged.
+    ctx.test
+        .assert_total_balance(&asset_repay_ata, &ctx.mxe_pubkey, repay_amount)
+        .await;
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, 0)
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    ctx.test
+        .assert_total_balance(&collateral_vault_ata, &ctx.mxe_pubkey, collateral_amount)
+        .await;
+    let loan = ctx.loan(&borrower).await;
+    assert!(loan.active);
+    assert_eq!(
+        loan_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_principal)
+    );
+
+    // Without a slippage bound the same repayment goes through.
+    let repay_tx = ctx.repay_tx(&borrower, 3, 2, 0);
+    ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, repay_amount)
+        .await;
+}
+
+#[tokio::test]
+async fn test_repayment_status_reveal_policy() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 1, 0).await;
+
+    let collateral_amount = 100;
+    let public_borrower = new_borrower(&mut ctx, collateral_amount).await;
+    let private_borrower = new_borrower(&mut ctx, collateral_amount).await;
+
+    for (computation_offset, borrower, reveal_repayment_status) in
+        [(1, &public_borrower, true), (3, &private_borrower, false)]
+    {
+        let init_loan_tx =
+            ctx.initialize_loan_with_reveal_policy_tx(borrower, reveal_repayment_status);
+        ctx.test
+            .process_transaction(init_loan_tx, false)
+            .await
+            .unwrap();
+        let collateral_vault_ata = ctx.collateral_vault_ata(borrower);
+        borrower
+            .transfer(
+                &mut ctx.test,
+                &ctx.collateral_mint,
+                collateral_amount,
+                &collateral_vault_ata,
+            )
+            .await;
+        assert_eq!(
+            reveal_repayment_status,
+            ctx.loan(borrower).await.reveal_repayment_status
+        );
+
+        let borrow_tx = ctx.borrow_tx(borrower, computation_offset, 0);
+        ctx.test
+            .process_transaction(borrow_tx, false)
+            .await
+            .unwrap();
+
+        // Borrower repays the loan in full.
+        let asset_repay_ata = ctx.asset_repay_ata(borrower);
+        borrower
+            .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+            .await;
+        borrower
+            .transfer(
+                &mut ctx.test,
+                &ctx.asset_mint,
+                collateral_amount,
+                &asset_repay_ata,
+            )
+            .await;
+        let repay_tx = ctx.repay_tx(borrower, computation_offset + 1, 1, 0);
+        ctx.test.process_transaction(repay_tx, false).await.unwrap();
+    }
+
+    // A revealed repayment closes the loan right away.
+    assert!(!ctx.loan(&public_borrower).await.active);
+
+    // A private repayment keeps the status encrypted and the loan open.
+    let loan = ctx.loan(&private_borrower).await;
+    assert!(loan.active);
+    assert_eq!(
+        1,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_repayment_status)
+    );
+    assert!(!loan.settled);
+
+    // The loan can't be closed until its repayment is revealed.
+    let close_loan_tx = ctx.borrower_tx(
+        &private_borrower,
+        &[
+            lending::instruction::close_loan(&ctx.lender.pubkey(), &private_borrower.pubkey())
+                .unwrap(),
+        ],
+    );
+    let err = ctx
+        .test
+        .process_transaction(close_loan_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanActive as u32),
+    );
+
+    // Only the lender can reveal it.
+    let mut forged_reveal_instruction = lending::instruction::reveal_repayment_status(
+        &private_borrower.pubkey(),
+        &private_borrower.pubkey(),
+        &ctx.collateral_mint,
+        5,
+        2,
+    )
+    .unwrap();
+    forged_reveal_instruction.accounts[2].pubkey = lending_pool_pda(&ctx.lender.pubkey()).0;
+    let forged_reveal_tx = ctx.borrower_tx(&private_borrower, &[forged_reveal_instruction]);
+    assert!(ctx
+        .test
+        .process_transaction(forged_reveal_tx, false)
+        .await
+        .is_err());
+
+    let reveal_tx = ctx.lender_tx(&[lending::instruction::reveal_repayment_status(
+        &ctx.lender.pubkey(),
+        &private_borrower.pubkey(),
+        &ctx.collateral_mint,
+        5,
+        2,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap();
+    let loan = ctx.loan(&private_borrower).await;
+    assert!(!loan.active);
+    assert!(loan.settled);
+
+    // Revealed loans have nothing to reveal.
+    let reveal_tx = ctx.lender_tx(&[lending::instruction::reveal_repayment_status(
+        &ctx.lender.pubkey(),
+        &public_borrower.pubkey(),
+        &ctx.collateral_mint,
+        6,
+        2,
+    )
+    .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::RepaymentStatusPublic as u32),
+    );
+}
+
+#[tokio::test]
+async fn test_borrow_origination_fee_transfer() {
+    let liquidity = 200;
+    let mut ctx = setup_lending(liquidity, 1, 10_000, 1, 0).await;
+
+    // Lender charges a 1% origination fee.
+    let origination_fee_bps = 100;
+    let set_origination_fee_tx = ctx.lender_tx(&[lending::instruction::set_origination_fee(
+        &ctx.lender.pubkey(),
+        origination_fee_bps,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(set_origination_fee_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        origination_fee_bps,
+        ctx.lending_pool().await.origination_fee_bps
+    );
+
+    // The loan is capped by the liquidity, so part of the collateral is returned.
+    let collateral_amount = 500;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        collateral_excess_amount,
+        origination_fee,
+        ..
+    } = simulate_borrow(collateral_amount, liquidity, 1, &ctx.lending_pool().await);
+    assert_eq!(
+        mul_base_points(loan_amount, origination_fee_bps as u64),
+        origination_fee
+    );
+    assert_ne!(0, origination_fee);
+    assert_ne!(0, collateral_excess_amount);
+
+    // The borrower receives the loan minus the fee.
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.asset_mint,
+            loan_amount - origination_fee,
+        )
+        .await;
+    // The borrower gets the excess collateral back.
+    borrower
+        .assert_total_balance(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_excess_amount,
+        )
+        .await;
+    // The lender receives the fee.
+    ctx.lender
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, origination_fee)
+        .await;
+    let asset_vault_ata = ctx.asset_vault_ata();
+    ctx.test
+        .assert_total_balance(&asset_vault_ata, &ctx.mxe_pubkey, liquidity - loan_amount)
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    ctx.test
+        .assert_total_balance(
+            &collateral_vault_ata,
+            &ctx.mxe_pubkey,
+            loan_collateral_amount,
+        )
+        .await;
+
+    // The principal still covers the full loan amount.
+    assert_eq!(
+        loan_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.loan(&borrower).await.encrypted_principal)
+    );
+}
+
+#[tokio::test]
+async fn test_reveal_health() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 1, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+
+    // Revealing the health of a loan without principal is rejected.
+    let reveal_health_ix = lending::instruction::reveal_health(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        1,
+        0,
+        &borrower.x25519_pubkey(),
+        1,
+    )
+    .unwrap();
+    let reveal_tx = ctx.borrower_tx(&borrower, &[reveal_health_ix]);
+    let err = ctx
+        .test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanInactive as u32),
+    );
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        ..
+    } = simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+
+    // Only the borrower can reveal the health of their loan.
+    let mut forged_reveal_health_ix = lending::instruction::reveal_health(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        3,
+        2,
+        &borrower.x25519_pubkey(),
+        3,
+    )
+    .unwrap();
+    forged_reveal_health_ix.accounts[0].is_signer = false;
+    let forged_reveal_tx = ctx.lender_tx(&[forged_reveal_health_ix]);
+    let err = ctx
+        .test
+        .process_transaction(forged_reveal_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::MissingRequiredSignature);
+
+    let reveal_health_ix = lending::instruction::reveal_health(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        4,
+        3,
+        &borrower.x25519_pubkey(),
+        4,
+    )
+    .unwrap();
+    let reveal_tx = ctx.borrower_tx(&borrower, &[reveal_health_ix]);
+    ctx.test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap();
+
+    // The health is only readable by the borrower.
+    assert_eq!(
+        loan_collateral_amount * 10_000 / loan_amount,
+        ctx.revealed_health_bps(&borrower).await
+    );
+
+    // The collateral is valued at the oracle's current price.
+    let price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    let reveal_health_ix = lending::instruction::reveal_health(
+        &ctx.lender.pubkey(),
+        &borrower.pubkey(),
+        &ctx.asset_mint,
+        &ctx.collateral_mint,
+        5,
+        4,
+        &borrower.x25519_pubkey(),
+        5,
+    )
+    .unwrap();
+    let reveal_tx = ctx.borrower_tx(&borrower, &[reveal_health_ix]);
+    ctx.test
+        .process_transaction(reveal_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        loan_collateral_amount * price * 10_000 / loan_amount,
+        ctx.revealed_health_bps(&borrower).await
+    );
+}
+
+#[tokio::test]
+async fn test_compute_health_factor() {
+    let loan_to_value_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, loan_to_value_bps, 5000, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    let BorrowOutcome {
+        loan_amount,
+        loan_collateral_amount,
+        ..
+    } = simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+
+    // Anyone, here the lender, can compute the health factor of a loan, the health factor is
+    // recomputed with the current collateral threshold.
+    for (collateral_threshold_bps, computation_offset) in [(5000, 2), (20_000, 3)] {
+        let set_collateral_threshold_tx =
+            ctx.lender_tx(&[lending::instruction::set_collateral_threshold(
+                &ctx.lender.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                &[borrower.pubkey()],
+                collateral_threshold_bps,
+                computation_offset + 10,
+                computation_offset + 10,
+            )
+            .unwrap()]);
+        ctx.test
+            .process_transaction(set_collateral_threshold_tx, false)
+            .await
+            .unwrap();
+
+        let compute_health_factor_tx =
+            ctx.lender_tx(&[lending::instruction::compute_health_factor(
+                &ctx.lender.pubkey(),
+                &ctx.lender.pubkey(),
+                &borrower.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                computation_offset,
+                computation_offset,
+            )
+            .unwrap()]);
+        ctx.test
+            .process_transaction(compute_health_factor_tx, false)
+            .await
+            .unwrap();
+
+        let loan = ctx.loan(&borrower).await;
+        assert_eq!(
+            loan_collateral_amount * collateral_threshold_bps as u64 / 10_000 * 10_000
+                / loan_amount,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(loan.encrypted_health_factor)
+        );
+    }
+
+    // The collateral is valued at the oracle's current price.
+    let price = 2;
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+    let compute_health_factor_tx = ctx.lender_tx(&[ctx.compute_health_factor_ix(&borrower, 4, 4)]);
+    ctx.test
+        .process_transaction(compute_health_factor_tx, false)
+        .await
+        .unwrap();
+    let loan = ctx.loan(&borrower).await;
+    assert_eq!(
+        loan_collateral_amount * price * 20_000 / 10_000 * 10_000 / loan_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_health_factor)
+    );
+}
+
+#[tokio::test]
+async fn test_health_factor_near_overflow_price() {
+    let collateral_threshold_bps = 5000;
+    let mut ctx = setup_lending(1000, 1, 5000, collateral_threshold_bps, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let BorrowOutcome { loan_amount, .. } =
+        simulate_borrow(collateral_amount, 1000, 1, &ctx.lending_pool().await);
+
+    // price * collateral_threshold_bps exceeds u64::MAX, wrapping around would value the
+    // collateral below the loan.
+    let price = u64::MAX / collateral_threshold_bps as u64 + 1;
+    assert!(
+        collateral_amount * price.wrapping_mul(collateral_threshold_bps as u64) / 10_000
+            < loan_amount
+    );
+    let set_price_tx =
+        ctx.lender_tx(&[lending::instruction::set_price(&ctx.lender.pubkey(), price).unwrap()]);
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    // The saturated ratio keeps the loan healthy.
+    let compute_health_factor_tx = ctx.lender_tx(&[ctx.compute_health_factor_ix(&borrower, 2, 1)]);
+    ctx.test
+        .process_transaction(compute_health_factor_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(0, ctx.loan(&borrower).await.unhealthy_since_slot);
+
+    // Nor can it be liquidated.
+    let liquidator = new_liquidator(&mut ctx, loan_amount).await;
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 3, 2);
+    ctx.test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.loan(&borrower).await.active);
+    liquidator
+        .assert_total_balance(&mut ctx.test, &ctx.asset_mint, loan_amount)
+        .await;
+}
+
+#[tokio::test]
+async fn test_top_up_collateral() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 5000, 0).await;
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let top_up_ix = |ctx: &LendingTest, computation_offset, amount| {
+        lending::instruction::top_up_collateral(
+            &ctx.lender.pubkey(),
+            &borrower.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            computation_offset,
+            computation_offset,
+            amount,
+        )
+        .unwrap()
+    };
+
+    // Only active loans can be topped up.
+    let top_up_tx = ctx.borrower_tx(&borrower, &[top_up_ix(&ctx, 1, collateral_amount)]);
+    let err = ctx
+        .test
+        .process_transaction(top_up_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LoanInactive as u32),
+    );
+
+    let borrow_tx = ctx.borrow_tx(&borrower, 2, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+    let compute_health_factor_tx = ctx.lender_tx(&[ctx.compute_health_factor_ix(&borrower, 3, 3)]);
+    ctx.test
+        .process_transaction(compute_health_factor_tx, false)
+        .await
+        .unwrap();
+    let health_factor = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_decrypt(ctx.loan(&borrower).await.encrypted_health_factor);
+
+    // Borrower transfers more collateral into the collateral_vault_ata and tops up the loan.
+    let top_up_amount = 100;
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+            &ctx.collateral_mint_authority,
+            top_up_amount,
+            &borrower.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.collateral_mint,
+            ),
+        )
+        .await;
+    borrower
+        .deposit(&mut ctx.test, &ctx.collateral_mint, top_up_amount)
+        .await;
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.collateral_mint)
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            top_up_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    let top_up_slot = 100;
+    ctx.test.warp_to_slot(top_up_slot).unwrap();
+    let top_up_tx = ctx.borrower_tx(&borrower, &[top_up_ix(&ctx, 4, top_up_amount)]);
+    ctx.test
+        .process_transaction(top_up_tx, false)
+        .await
+        .unwrap();
+    let loan = ctx.loan(&borrower).await;
+    assert_eq!(
+        collateral_amount + top_up_amount,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(loan.encrypted_collateral)
+    );
+    // Health factors queued before the top up are stale from now on.
+    assert_eq!(top_up_slot, loan.collateral_update_slot);
+
+    // The topped up collateral improves the health factor.
+    let compute_health_factor_tx = ctx.lender_tx(&[ctx.compute_health_factor_ix(&borrower, 5, 5)]);
+    ctx.test
+        .process_transaction(compute_health_factor_tx, false)
+        .await
+        .unwrap();
+    let topped_up_health_factor = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_decrypt(ctx.loan(&borrower).await.encrypted_health_factor);
+    assert!(topped_up_health_factor > health_factor);
+}
+
+#[tokio::test]
+async fn test_liquidation_grace_period() {
+    // A collateral threshold below the loan to value makes the loan unhealthy right away.
+    let mut ctx = setup_lending(1000, 1, 10_000, 5000, 0).await;
+    let liquidation_grace_slots = 100;
+    let set_grace_slots_tx = ctx.lender_tx(&[lending::instruction::set_liquidation_grace_slots(
+        &ctx.lender.pubkey(),
+        liquidation_grace_slots,
+    )
+    .unwrap()]);
+    ctx.test
+        .process_transaction(set_grace_slots_tx, false)
+        .await
+        .unwrap();
+
+    let collateral_amount = 100;
+    let borrower = new_borrower(&mut ctx, collateral_amount).await;
+    open_loan(&mut ctx, &borrower, collateral_amount).await;
+    let borrow_tx = ctx.borrow_tx(&borrower, 1, 0);
+    ctx.test
+        .process_transaction(borrow_tx, false)
+        .await
+        .unwrap();
+
+    // The liquidator holds the loan_amount to repay.
+    let loan_amount = collateral_amount;
+    let liquidator = new_liquidator(&mut ctx, loan_amount).await;
+
+    // The loan can't be liquidated before a health check found it unhealthy.
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 2, 2);
+    let err = ctx
+        .test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LiquidationGracePeriod as u32),
+    );
+
+    let compute_health_factor_tx = ctx.lender_tx(&[ctx.compute_health_factor_ix(&borrower, 3, 3)]);
+    ctx.test
+        .process_transaction(compute_health_factor_tx, false)
+        .await
+        .unwrap();
+    assert_ne!(0, ctx.loan(&borrower).await.unhealthy_since_slot);
+
+    // Liquidating within the grace period is too early.
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 4, 4);
+    let err = ctx
+        .test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(LendingError::LiquidationGracePeriod as u32),
+    );
+
+    // Once the loan recovers, the grace period is reset. Changing the threshold rechecks the loan.
+    for (collateral_threshold_bps, computation_offset, is_healthy) in
+        [(20_000, 5, true), (5000, 6, false)]
+    {
+        let health_check_tx = ctx.lender_tx(&[lending::instruction::set_collateral_threshold(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &[borrower.pubkey()],
+            collateral_threshold_bps,
+            computation_offset,
+            computation_offset,
+        )
+        .unwrap()]);
+        ctx.test
+            .process_transaction(health_check_tx, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            is_healthy,
+            ctx.loan(&borrower).await.unhealthy_since_slot == 0
+        );
+    }
+
+    // After the grace period, the loan is liquidated.
+    let unhealthy_since_slot = ctx.loan(&borrower).await.unhealthy_since_slot;
+    ctx.test
+        .warp_to_slot(unhealthy_since_slot + liquidation_grace_slots)
+        .unwrap();
+    let liquidate_tx = ctx.liquidate_tx(&liquidator, &borrower, 7, 7);
+    ctx.test
+        .process_transaction(liquidate_tx, false)
+        .await
+        .unwrap();
+    assert!(!ctx.loan(&borrower).await.active);
+}
+
+#[tokio::test]
+async fn test_set_collateral_threshold() {
+    let mut ctx = setup_lending(1000, 1, 10_000, 20_000, 0).await;
+
+    // Two borrowers draw their loans, the second one then adds as much collateral again. A third
+    // borrower never draws its loan.
+    let collateral_amount = 100;
+    let mut borrowers = Vec::new();
+    for i in 0..3 {
+        let borrower = new_borrower(&mut ctx, collateral_amount).await;
+        open_loan(&mut ctx, &borrower, collateral_amount).await;
+        if i < 2 {
+            let borrow_tx = ctx.borrow_tx(&borrower, 1 + i, 0);
+            ctx.test
+                .process_transaction(borrow_tx, false)
+                .await
+                .unwrap();
+        }
+        borrowers.push(borrower);
+    }
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+            &ctx.collateral_mint_authority,
+            collateral_amount,
+            &borrowers[1].ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.collateral_mint,
+            ),
+        )
+        .await;
+    borrowers[1]
+        .deposit(&mut ctx.test, &ctx.collateral_mint, collateral_amount)
+        .await;
+    borrowers[1]
+        .apply_pending_balance(&mut ctx.test, &ctx.collateral_mint)
+        .await;
+    let collateral_vault_ata = ctx.collateral_vault_ata(&borrowers[1]);
+    borrowers[1]
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
+    let borrower_keys: Vec<Pubkey> = borrowers.iter().map(|b| b.pubkey()).collect();
+
+    // The loan of every borrower of the pool has to be passed along.
+    let set_collateral_threshold_tx =
+        ctx.lender_tx(&[lending::instruction::set_collateral_threshold(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &borrower_keys[..2],
+            7500,
+            10,
+            1,
+        )
+        .unwrap()]);
+    let err = ctx
+        .test
+        .process_transaction(set_collateral_threshold_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::NotEnoughAccountKeys);
+
+    // Under the new threshold the first loan is unhealthy (100 × 75% < 100) and gets flagged, the
+    // second one stays healthy (200 × 75% >= 100).
+    let set_collateral_threshold_tx =
+        ctx.lender_tx(&[lending::instruction::set_collateral_threshold(
+            &ctx.lender.pubkey(),
+            &ctx.asset_mint,
+            &ctx.collateral_mint,
+            &borrower_keys,
+            7500,
+            10,
+            1,
+        )
+        .unwrap()]);
+    ctx.test
+        .process_transaction(set_collateral_threshold_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(7500, ctx.lending_pool().await.collateral_threshold_bps);
+
+    let unhealthy_loan = ctx.loan(&borrowers[0]).await;
+    let healthy_loan = ctx.loan(&borrowers[1]).await;
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    assert_ne!(0, unhealthy_loan.unhealthy_since_slot);
+    assert_eq!(
+        7500,
+        mxe.rescue_decrypt(unhealthy_loan.encrypted_health_factor)
+    );
+    assert_eq!(0, healthy_loan.unhealthy_since_slot);
+    assert_eq!(
+        15_000,
+        mxe.rescue_decrypt(healthy_loan.encrypted_health_factor)
+    );
+
+    // The undrawn loan has no health factor to compute.
+    let undrawn_loan = ctx.loan(&borrowers[2]).await;
+    assert_eq!(0, undrawn_loan.unhealthy_since_slot);
+    assert!(undrawn_loan.encrypted_health_factor == Default::default());
+}
+
+struct LendingTest {
+    test: ConfidentialSPLTokenTest,
+    mxe_pubkey: Pubkey,
+    asset_mint: Pubkey,
+    asset_mint_authority: Keypair,
+    collateral_mint: Pubkey,
+    collateral_mint_authority: Keypair,
+    lender: Actor,
+    /// Reserve vault of the lending pool, passed to repayments.
+    reserve_vault_ata: Pubkey,
+}
+
+impl LendingTest {
+    async fn lending_pool(&mut self) -> LendingPool {
+        let lending_pool = lending_pool_pda(&self.lender.pubkey()).0;
+        load_lending_pool(&self.test.get_account(&lending_pool).await.unwrap().data).unwrap()
+    }
+
+    async fn loan(&mut self, borrower: &Actor) -> Loan {
+        let loan = loan_pda(&self.lender.pubkey(), &borrower.pubkey()).0;
+        load_loan(&self.test.get_account(&loan).await.unwrap().data).unwrap()
+    }
+
+    /// Decrypts, with the borrower's key, the health of their loan as of their last `reveal_health`.
+    async fn revealed_health_bps(&mut self, borrower: &Actor) -> u64 {
+        let loan = self.loan(borrower).await;
+        let mxe = self.test.get_mxe(&self.mxe_pubkey).unwrap();
+        borrower.shared_decrypt(&mxe, loan.health_bps_nonce, loan.encrypted_health_bps)
+    }
+
+    fn asset_vault_ata(&self) -> Pubkey {
+        addresses::asset_vault_ata(&self.lender.pubkey(), &self.asset_mint)
+    }
+
+    fn collateral_vault_ata(&self, borrower: &Actor) -> Pubkey {
+        addresses::collateral_vault_ata(
+            &self.lender.pubkey(),
+            &borrower.pubkey(),
+            &self.collateral_mint,
+        )
+    }
+
+    fn asset_repay_ata(&self, borrower: &Actor) -> Pubkey {
+        addresses::asset_repay_ata(&self.lender.pubkey(), &borrower.pubkey(), &self.asset_mint)
+    }
+
+    /// Returns the `(asset, collateral)` totals held across every token account involved in the
+    /// loan of `borrower`.
+    async fn supply(&mut self, borrower: &Actor) -> (u64, u64) {
+        let token_program = confidential_spl_token::programs::confidential_spl_token::ID;
+        let asset_accounts = [
+            self.lender.ata(&token_program, &self.asset_mint),
+            borrower.ata(&token_program, &self.asset_mint),
+            self.asset_vault_ata(),
+            self.asset_repay_ata(borrower),
+        ];
+        let collateral_accounts = [
+            self.lender.ata(&token_program, &self.collateral_mint),
+            borrower.ata(&token_program, &self.collateral_mint),
+            self.collateral_vault_ata(borrower),
+        ];
+
+        let mut asset_supply = 0;
+        for account in asset_accounts {
+            asset_supply += self
+                .test
+                .total_balance(&account, &self.mxe_pubkey)
+                .await
+                .unwrap();
+        }
+        let mut collateral_supply = 0;
+        for account in collateral_accounts {
+            collateral_supply += self
+                .test
+                .total_balance(&account, &self.mxe_pubkey)
+                .await
+                .unwrap();
+        }
+
+        (asset_supply, collateral_supply)
+    }
+
+    fn lender_tx(&self, instructions: &[Instruction]) -> Transaction {
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.test.get_payer().pubkey()),
+            &[&self.test.get_payer(), &self.lender.signer_keypair()],
+            self.test.get_recent_blockhash(),
+        )
+    }
+
+    /// Transaction of the lender with the `multisig_signers` attached to every instruction.
+    fn lender_multisig_tx(
+        &self,
+        instructions: &[Instruction],
+        multisig_signers: &[&Keypair],
+    ) -> Transaction {
+        let multisig_signer_keys = multisig_signers
+            .iter()
+            .map(|signer| signer.pubkey())
+            .collect::<Vec<_>>();
+        let instructions = instructions
+            .iter()
+            .map(|instruction| {
+                lending::instruction::with_multisig_signers(
+                    instruction.clone(),
+                    &multisig_signer_keys,
+                )
+            })
+            .collect::<Vec<_>>();
+        let payer = self.test.get_payer();
+        let lender = self.lender.signer_keypair();
+        let mut signers = vec![&payer, &lender];
+        signers.extend_from_slice(multisig_signers);
+        Transaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &signers,
+            self.test.get_recent_blockhash(),
+        )
+    }
+
+    fn borrower_tx(&self, borrower: &Actor, instructions: &[Instruction]) -> Transaction {
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.test.get_payer().pubkey()),
+            &[&self.test.get_payer(), &borrower.signer_keypair()],
+            self.test.get_recent_blockhash(),
+        )
+    }
+
+    fn initialize_loan_tx(&self, borrower: &Actor) -> Transaction {
+        self.initialize_loan_with_reveal_policy_tx(borrower, true)
+    }
+
+    fn initialize_loan_with_reveal_policy_tx(
+        &self,
+        borrower: &Actor,
+        reveal_repayment_status: bool,
+    ) -> Transaction {
+        self.initialize_loan_with_options_tx(borrower, reveal_repayment_status, 0)
+    }
+
+    fn initialize_loan_with_options_tx(
+        &self,
+        borrower: &Actor,
+        reveal_repayment_status: bool,
+        maturity_slot: u64,
+    ) -> Transaction {
+        self.borrower_tx(
+            borrower,
+            &lending::instruction::initialize_loan_with_budget(
+                &self.lender.pubkey(),
+                &borrower.pubkey(),
+                &self.asset_mint,
+                &self.collateral_mint,
+                reveal_repayment_status,
+                maturity_slot,
+                1,
+            )
+            .unwrap(),
+        )
+    }
+
+    fn borrow_tx(
+        &self,
+        borrower: &Actor,
+        computation_offset: u32,
+        transfer_id: u32,
+    ) -> Transaction {
+        self.borrower_tx(
+            borrower,
+            &lending::instruction::borrow_with_budget(
+                &self.lender.pubkey(),
+                &borrower.pubkey(),
+                &self.asset_mint,
+                &self.collateral_mint,
+                &price_oracle_pda(&self.lender.pubkey()).0,
+                computation_offset,
+                transfer_id,
+                1,
+            )
+            .unwrap(),
+        )
+    }
+
+    fn compute_health_factor_ix(
+        &self,
+        borrower: &Actor,
+        computation_offset: u32,
+        transfer_id: u32,
+    ) -> Instruction {
+        lending::instruction::compute_health_factor(
+            &self.lender.pubkey(),
+            &self.lender.pubkey(),
+            &borrower.pubkey(),
+            &self.asset_mint,
+            &self.collateral_mint,
+            computation_offset,
+            transfer_id,
+        )
+        .unwrap()
+    }
+
+    /// Liquidation by the lender, the seized collateral goes to `liquidation_proceeds_destination`.
+    fn lender_liquidate_tx(
+        &self,
+        borrower: &Actor,
+        liquidation_proceeds_destination: &Pubkey,
+        computation_offset: u32,
+        transfer_id: u32,
+    ) -> Transaction {
+        self.lender_tx(&[lending::instruction::liquidate(
+            &self.lender.pubkey(),
+            &self.lender.pubkey(),
+            &borrower.pubkey(),
+            &self.asset_mint,
+            &self.collateral_mint,
+            liquidation_proceeds_destination,
+            computation_offset,
+            transfer_id,
+        )
+        .unwrap()])
+    }
+
+    /// Liquidation of the loan of `borrower`, the seized collateral goes to the liquidator's ATA.
+    fn liquidate_tx(
+        &self,
+        liquidator: &Actor,
+        borrower: &Actor,
+        computation_offset: u32,
+        transfer_id: u32,
+    ) -> Transaction {
+        Transaction::new_signed_with_payer(
+            &[lending::instruction::liquidate(
+                &liquidator.pubkey(),
+                &self.lender.pubkey(),
+                &borrower.pubkey(),
+                &self.asset_mint,
+                &self.collateral_mint,
+                &liquidator.ata(
+                    &confidential_spl_token::programs::confidential_spl_token::ID,
+                    &self.collateral_mint,
+                ),
+                computation_offset,
+                transfer_id,
+            )
+            .unwrap()],
+            Some(&self.test.get_payer().pubkey()),
+            &[&self.test.get_payer(), &liquidator.signer_keypair()],
+            self.test.get_recent_blockhash(),
+        )
+    }
+
+    fn repay_tx(
+        &self,
+        borrower: &Actor,
+        computation_offset: u32,
+        transfer_id: u32,
+        min_collateral_out: u64,
+    ) -> Transaction {
+        self.borrower_tx(
+            borrower,
+            &lending::instruction::repay_with_budget(
+                &self.lender.pubkey(),
+                &borrower.pubkey(),
+                &self.asset_mint,
+                &self.collateral_mint,
+                &self.reserve_vault_ata,
+                computation_offset,
+                transfer_id,
+                min_collateral_out,
+                1,
+            )
+            .unwrap(),
+        )
+    }
+}
+
+/// Sets up an initialized lending pool whose asset vault holds `liquidity` tokens.
+async fn setup_lending(
+    liquidity: u64,
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    collateral_threshold_bps: u16,
+    max_active_loans: u16,
+) -> LendingTest {
+    setup_lending_with_borrow_limits(
+        liquidity,
+        interest_rate_bps,
+        loan_to_value_bps,
+        collateral_threshold_bps,
+        max_active_loans,
+        0,
+        u64::MAX,
+    )
+    .await
+}
+
+/// Same as `setup_lending`, but single borrows are bounded by `[min_borrow, max_borrow]`.
+async fn setup_lending_with_borrow_limits(
+    liquidity: u64,
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    collateral_threshold_bps: u16,
+    max_active_loans: u16,
+    min_borrow: u64,
+    max_borrow: u64,
+) -> LendingTest {
+    setup_lending_with_multisig(
+        liquidity,
+        interest_rate_bps,
+        loan_to_value_bps,
+        collateral_threshold_bps,
+        max_active_loans,
+        min_borrow,
+        max_borrow,
+        0,
+        &[],
+    )
+    .await
+}
+
+/// Same as `setup_lending_with_borrow_limits`, but the pool requires `multisig_threshold` of the
+/// `multisig_signers` to withdraw funds or change its settings.
+async fn setup_lending_with_multisig(
+    liquidity: u64,
+    interest_rate_bps: u16,
+    loan_to_value_bps: u16,
+    collateral_threshold_bps: u16,
+    max_active_loans: u16,
+    min_borrow: u64,
+    max_borrow: u64,
+    multisig_threshold: u8,
+    multisig_signers: &[Keypair],
+) -> LendingTest {
+    let mut test = ConfidentialSPLTokenTest::new(vec![CustomProgram {
+        program_name: "lending",
+        program_id: lending::ID,
+        processor: processor!(lending::process_instruction),
+    }])
+    .await;
+
+    let mxe_pubkey = test
+        .enable_confidential_token_accounts_for_program(&lending::ID)
+        .await;
+
+    let compiled_borrow_circuit = lending_encrypted_ixs::encrypted_computations::borrow();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        BORROW_COMP_DEF_OFFSET,
+        compiled_borrow_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_repay_circuit = lending_encrypted_ixs::encrypted_computations::repay();
+    test.create_comp_def_for_test(&lending::ID, REPAY_COMP_DEF_OFFSET, compiled_repay_circuit)
+        .await
+        .unwrap();
+    let compiled_liquidate_circuit = lending_encrypted_ixs::encrypted_computations::liquidate();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        LIQUIDATE_COMP_DEF_OFFSET,
+        compiled_liquidate_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_liquidate_by_lender_circuit =
+        lending_encrypted_ixs::encrypted_computations::liquidate_by_lender();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        LIQUIDATE_BY_LENDER_COMP_DEF_OFFSET,
+        compiled_liquidate_by_lender_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_repay_private_circuit =
+        lending_encrypted_ixs::encrypted_computations::repay_private();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        REPAY_PRIVATE_COMP_DEF_OFFSET,
+        compiled_repay_private_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_reveal_repayment_status_circuit =
+        lending_encrypted_ixs::encrypted_computations::reveal_repayment_status();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        REVEAL_REPAYMENT_STATUS_COMP_DEF_OFFSET,
+        compiled_reveal_repayment_status_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_reveal_health_circuit =
+        lending_encrypted_ixs::encrypted_computations::reveal_health();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        REVEAL_HEALTH_COMP_DEF_OFFSET,
+        compiled_reveal_health_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_health_factor_circuit =
+        lending_encrypted_ixs::encrypted_computations::health_factor();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        HEALTH_FACTOR_COMP_DEF_OFFSET,
+        compiled_health_factor_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_top_up_collateral_circuit =
+        lending_encrypted_ixs::encrypted_computations::top_up_collateral();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        TOP_UP_COLLATERAL_COMP_DEF_OFFSET,
+        compiled_top_up_collateral_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_accrue_interest_circuit =
+        lending_encrypted_ixs::encrypted_computations::accrue_interest();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        ACCRUE_INTEREST_COMP_DEF_OFFSET,
+        compiled_accrue_interest_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_accrue_interest_compound_circuit =
+        lending_encrypted_ixs::encrypted_computations::accrue_interest_compound();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        ACCRUE_INTEREST_COMPOUND_COMP_DEF_OFFSET,
+        compiled_accrue_interest_compound_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_settle_matured_loan_circuit =
+        lending_encrypted_ixs::encrypted_computations::settle_matured_loan();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        SETTLE_MATURED_LOAN_COMP_DEF_OFFSET,
+        compiled_settle_matured_loan_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_total_debt_circuit = lending_encrypted_ixs::encrypted_computations::total_debt();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        TOTAL_DEBT_COMP_DEF_OFFSET,
+        compiled_total_debt_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_withdraw_circuit = lending_encrypted_ixs::encrypted_computations::withdraw();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        WITHDRAW_COMP_DEF_OFFSET,
+        compiled_withdraw_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_withdraw_reserve_circuit =
+        lending_encrypted_ixs::encrypted_computations::withdraw_reserve();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        WITHDRAW_RESERVE_COMP_DEF_OFFSET,
+        compiled_withdraw_reserve_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_preview_borrow_circuit =
+        lending_encrypted_ixs::encrypted_computations::preview_borrow();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        PREVIEW_BORROW_COMP_DEF_OFFSET,
+        compiled_preview_borrow_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_repay_compound_circuit =
+        lending_encrypted_ixs::encrypted_computations::repay_compound();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        REPAY_COMPOUND_COMP_DEF_OFFSET,
+        compiled_repay_compound_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_repay_compound_private_circuit =
+        lending_encrypted_ixs::encrypted_computations::repay_compound_private();
+    test.create_comp_def_for_test(
+        &lending::ID,
+        REPAY_COMPOUND_PRIVATE_COMP_DEF_OFFSET,
+        compiled_repay_compound_private_circuit,
+    )
+    .await
+    .unwrap();
+
+    let asset_mint_authority = Keypair::new();
+    let asset_mint = test
+        .create_mint(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            9,
+            &asset_mint_authority,
+        )
+        .await
+        .pubkey();
+    let collateral_mint_authority = Keypair::new();
+    let collateral_mint = test
+        .create_mint(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            9,
+            &collateral_mint_authority,
+        )
+        .await
+        .pubkey();
+
+    let lender = test.new_actor().await;
+    lender
+        .create_ata(
+            &mut test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &asset_mint,
+        )
+        .await;
+    lender
+        .create_ata(
+            &mut test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &collateral_mint,
+        )
+        .await;
+    test.mint_to_account(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &asset_mint,
+        &asset_mint_authority,
+        liquidity,
+        &lender.ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &asset_mint,
+        ),
+    )
+    .await;
+    lender.deposit(&mut test, &asset_mint, liquidity).await;
+    lender.apply_pending_balance(&mut test, &asset_mint).await;
+
+    let mut ctx = LendingTest {
+        test,
+        mxe_pubkey,
+        asset_mint,
+        asset_mint_authority,
+        collateral_mint,
+        collateral_mint_authority,
+        reserve_vault_ata: lender.ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &asset_mint,
+        ),
+        lender,
+    };
+
+    let init_lending_pool_tx =
+        ctx.lender_tx(
+            &[lending::instruction::initialize_lending_pool_with_multisig(
+                &ctx.lender.pubkey(),
+                &ctx.asset_mint,
+                &ctx.collateral_mint,
+                interest_rate_bps,
+                loan_to_value_bps,
+                collateral_threshold_bps,
+                max_active_loans,
+                min_borrow,
+                max_borrow,
+                multisig_threshold,
+                &multisig_signers
+                    .iter()
+                    .map(|signer| signer.pubkey())
+                    .collect::<Vec<_>>(),
+            )
+            .unwrap()],
+        );
+    ctx.test
+        .process_transaction(init_lending_pool_tx, false)
+        .await
+        .unwrap();
+
+    let set_price_tx = ctx.lender_multisig_tx(
+        &[lending::instruction::set_price(&ctx.lender.pubkey(), 1).unwrap()],
+        &multisig_signers.iter().collect::<Vec<_>>(),
+    );
+    ctx.test
+        .process_transaction(set_price_tx, false)
+        .await
+        .unwrap();
+
+    // Lender provides liquidity into the asset_vault_ata.
+    let asset_vault_ata = ctx.asset_vault_ata();
+    ctx.lender
+        .transfer(&mut ctx.test, &ctx.asset_mint, liquidity, &asset_vault_ata)
+        .await;
+
+    ctx
+}
+
+/// Creates a new borrower with asset and collateral token accounts, holding `collateral_amount`
+/// available collateral.
+async fn new_borrower(ctx: &mut LendingTest, collateral_amount: u64) -> Actor {
+    let borrower = ctx.test.new_actor().await;
+    borrower
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+        )
+        .await;
+    borrower
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+        )
+        .await;
+
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.collateral_mint,
+            &ctx.collateral_mint_authority,
+            collateral_amount,
+            &borrower.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.collateral_mint,
+            ),
+        )
+        .await;
+    borrower
+        .deposit(&mut ctx.test, &ctx.collateral_mint, collateral_amount)
+        .await;
+    borrower
+        .apply_pending_balance(&mut ctx.test, &ctx.collateral_mint)
+        .await;
+
+    borrower
+}
+
+/// Creates a liquidator holding `asset_amount` to repay the loans it liquidates.
+async fn new_liquidator(ctx: &mut LendingTest, asset_amount: u64) -> Actor {
+    let liquidator = ctx.test.new_actor().await;
+    for mint in [ctx.asset_mint, ctx.collateral_mint] {
+        liquidator
+            .create_ata(
+                &mut ctx.test,
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &mint,
+            )
+            .await;
+    }
+
+    ctx.test
+        .mint_to_account(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.asset_mint,
+            &ctx.asset_mint_authority,
+            asset_amount,
+            &liquidator.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.asset_mint,
+            ),
+        )
+        .await;
+    liquidator
+        .deposit(&mut ctx.test, &ctx.asset_mint, asset_amount)
+        .await;
+    liquidator
+        .apply_pending_balance(&mut ctx.test, &ctx.asset_mint)
+        .await;
+
+    liquidator
+}
+
+/// Initializes a loan for borrower and deposits `collateral_amount` into its collateral vault.
+async fn open_loan(ctx: &mut LendingTest, borrower: &Actor, collateral_amount: u64) {
+    let init_loan_tx = ctx.initialize_loan_tx(borrower);
+    ctx.test
+        .process_transaction(init_loan_tx, false)
+        .await
+        .unwrap();
+
+    let collateral_vault_ata = ctx.collateral_vault_ata(borrower);
+    borrower
+        .transfer(
+            &mut ctx.test,
+            &ctx.collateral_mint,
+            collateral_amount,
+            &collateral_vault_ata,
+        )
+        .await;
 }
 
 fn mul_base_points(a: u64, bps: u64) -> u64 {
@@ -0,0 +1,373 @@
+use arcis::imports::*;
+use confidential_spl_token::{ConfidentialTokenAccount, ConfidentialTransfer};
+
+/// Number of salaries rescaled by a single `apply_cola_adjustment` computation.
+const COLA_BATCH_SIZE: usize = 8;
+
+/// Maximum number of employees on a payroll, every salary is summed by `query_runway`.
+const MAX_EMPLOYEES: usize = 64;
+
+#[encrypted]
+pub mod encrypted_computations {
+    use super::*;
+
+    #[instruction]
+    pub fn apply_cola_adjustment(
+        mxe: Mxe,
+        salaries: [Enc<Mxe, u64>; COLA_BATCH_SIZE],
+        factor_bps: u16,
+    ) -> [Enc<Mxe, u64>; COLA_BATCH_SIZE] {
+        // Scale every salary by factor_bps (e.g. 10_300 for a 3% raise).
+        salaries.map(|salary| mxe.from_arcis(salary.to_arcis() * BasePoints(factor_bps)))
+    }
+
+    #[instruction]
+    pub fn query_runway(
+        payroll_token_account: ConfidentialTokenAccount,
+        salaries: [Enc<Mxe, u64>; MAX_EMPLOYEES],
+        num_employees: u16,
+    ) -> u64 {
+        // Sum the salaries of every employee, unused slots are ignored.
+        let mut total_salaries = 0;
+        for (i, salary) in salaries.iter().enumerate() {
+            total_salaries += if (i as u16) < num_employees {
+                salary.to_arcis()
+            } else {
+                0
+            };
+        }
+
+        // Number of full payroll runs the vault can cover.
+        let balance = payroll_token_account.encrypted_balance();
+        let runway = if total_salaries.eq(0) {
+            u64::MAX
+        } else {
+            balance / total_salaries
+        };
+
+        runway.reveal()
+    }
+
+    #[instruction]
+    pub fn claim_salary_streamed(
+        mxe: Mxe,
+        auditor: Shared,
+        payroll_token_account: ConfidentialTokenAccount,
+        employee_token_account: ConfidentialTokenAccount,
+        salary: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        slots_per_period: u64,
+        shortfall: Enc<Mxe, u64>,
+        shortfall_outstanding: bool,
+        total_claimed: Enc<Mxe, u64>,
+        has_claimed: bool,
+        audited: bool,
+    ) -> (
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Shared, u64>,
+    ) {
+        // The salary accrues pro-rata for every slot since the last claim, the shortfall of
+        // earlier underfunded claims is due on top of it.
+        let shortfall = if shortfall_outstanding {
+            shortfall.to_arcis()
+        } else {
+            0
+        };
+        let due = salary.to_arcis() * slots_elapsed / slots_per_period + shortfall;
+
+        // Never pay out more than the vault holds, the rest carries over to the next claim.
+        let payout = min(due.clone(), payroll_token_account.encrypted_balance());
+        let remaining_shortfall = due - payout.clone();
+        let is_paid_in_full = remaining_shortfall.eq(0);
+
+        // The running total of the employee's claims, counting this one.
+        let total_claimed = if has_claimed {
+            total_claimed.to_arcis()
+        } else {
+            0
+        };
+        let total_claimed = total_claimed + payout.clone();
+
+        // The amount paid is encrypted to the auditor, nothing is disclosed without one.
+        let audited_amount = if audited { payout.clone() } else { 0 };
+
+        // Transfer of payout to the employee.
+        let salary_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employee_token_account,
+            payout,
+        );
+
+        (
+            salary_transfer,
+            mxe.from_arcis(remaining_shortfall),
+            is_paid_in_full.reveal(),
+            mxe.from_arcis(total_claimed),
+            auditor.from_arcis(audited_amount),
+        )
+    }
+
+    #[instruction]
+    pub fn claim_salary_capped(
+        mxe: Mxe,
+        auditor: Shared,
+        payroll_token_account: ConfidentialTokenAccount,
+        employee_token_account: ConfidentialTokenAccount,
+        salary: Enc<Mxe, u64>,
+        claimed_this_period: Enc<Mxe, u64>,
+        max_per_period: u64,
+        last_claim_slot: u64,
+        period_start_slot: u64,
+        period_end_slot: u64,
+        total_claimed: Enc<Mxe, u64>,
+        has_claimed: bool,
+        audited: bool,
+    ) -> (
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        Enc<Mxe, u64>,
+        Enc<Shared, u64>,
+    ) {
+        // The claimed amount resets once the last claim falls outside the current period.
+        let claimed = if last_claim_slot >= period_start_slot && last_claim_slot < period_end_slot {
+            claimed_this_period.to_arcis()
+        } else {
+            0
+        };
+
+        // Only pay what is left under the cap, and never more than the vault holds.
+        let remaining_cap = max_per_period - min(claimed.clone(), max_per_period);
+        let payout = min(
+            min(salary.to_arcis(), remaining_cap),
+            payroll_token_account.encrypted_balance(),
+        );
+
+        // The running total of the employee's claims, counting this one.
+        let total_claimed = if has_claimed {
+            total_claimed.to_arcis()
+        } else {
+            0
+        };
+        let total_claimed = total_claimed + payout.clone();
+
+        // The amount paid is encrypted to the auditor, nothing is disclosed without one.
+        let audited_amount = if audited { payout.clone() } else { 0 };
+
+        // Transfer of payout to the employee.
+        let salary_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employee_token_account,
+            payout.clone(),
+        );
+
+        (
+            salary_transfer,
+            mxe.from_arcis(claimed + payout),
+            mxe.from_arcis(total_claimed),
+            auditor.from_arcis(audited_amount),
+        )
+    }
+
+    #[instruction]
+    pub fn employer_withdraw(
+        mxe: Mxe,
+        payroll_token_account: ConfidentialTokenAccount,
+        employer_token_account: ConfidentialTokenAccount,
+        amount: u64,
+    ) -> ConfidentialTransfer {
+        // Withdraw at most what the vault holds, so the transfer can't fail.
+        let withdraw_amount = min(amount, payroll_token_account.encrypted_balance());
+
+        confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employer_token_account,
+            withdraw_amount,
+        )
+    }
+
+    #[instruction]
+    pub fn pay_employee(
+        mxe: Mxe,
+        auditor: Shared,
+        payroll_token_account: ConfidentialTokenAccount,
+        employee_token_account: ConfidentialTokenAccount,
+        amount: Enc<Mxe, u64>,
+        total_claimed: Enc<Mxe, u64>,
+        has_claimed: bool,
+        audited: bool,
+    ) -> (ConfidentialTransfer, Enc<Mxe, u64>, Enc<Shared, u64>) {
+        let amount = amount.to_arcis();
+
+        // The running total of the employee's claims, counting this payment. It only stands if
+        // the transfer succeeds.
+        let total_claimed = if has_claimed {
+            total_claimed.to_arcis()
+        } else {
+            0
+        };
+        let total_claimed = total_claimed + amount.clone();
+
+        // The amount paid is encrypted to the auditor, nothing is disclosed without one.
+        let audited_amount = if audited { amount.clone() } else { 0 };
+
+        // Transfer of the full amount to the employee, it fails if the vault can't cover it.
+        let transfer = confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employee_token_account,
+            amount,
+        );
+
+        (
+            transfer,
+            mxe.from_arcis(total_claimed),
+            auditor.from_arcis(audited_amount),
+        )
+    }
+
+    #[instruction]
+    pub fn claim_salary_repaying_advance(
+        mxe: Mxe,
+        auditor: Shared,
+        payroll_token_account: ConfidentialTokenAccount,
+        employee_token_account: ConfidentialTokenAccount,
+        salary: Enc<Mxe, u64>,
+        advance: Enc<Mxe, u64>,
+        slots_elapsed: u64,
+        advance_interest_rate_bps: u16,
+        shortfall: Enc<Mxe, u64>,
+        shortfall_outstanding: bool,
+        total_claimed: Enc<Mxe, u64>,
+        has_claimed: bool,
+        audited: bool,
+    ) -> (
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Shared, u64>,
+    ) {
+        let advance = advance.to_arcis();
+
+        // The shortfall of earlier underfunded claims is due on top of the salary.
+        let shortfall = if shortfall_outstanding {
+            shortfall.to_arcis()
+        } else {
+            0
+        };
+        let due = salary.to_arcis() + shortfall;
+
+        // The advance accrues interest for every slot it has been outstanding.
+        let interest_accrued =
+            advance.clone() * BasePoints(advance_interest_rate_bps * slots_elapsed);
+        let advance_due = advance + interest_accrued;
+
+        // Deduct as much of the advance as the amount due covers, the rest carries over.
+        let deducted = min(due.clone(), advance_due.clone());
+        let net_due = due - deducted.clone();
+        let remaining_advance = advance_due - deducted;
+        let advance_is_settled = remaining_advance.eq(0);
+
+        // Never pay out more than the vault holds, the rest carries over to the next claim.
+        let payout = min(net_due.clone(), payroll_token_account.encrypted_balance());
+        let remaining_shortfall = net_due - payout.clone();
+        let is_paid_in_full = remaining_shortfall.eq(0);
+
+        // The running total of the employee's claims, counting this one.
+        let total_claimed = if has_claimed {
+            total_claimed.to_arcis()
+        } else {
+            0
+        };
+        let total_claimed = total_claimed + payout.clone();
+
+        // The amount paid is encrypted to the auditor, nothing is disclosed without one.
+        let audited_amount = if audited { payout.clone() } else { 0 };
+
+        // Transfer of payout to the employee.
+        let salary_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employee_token_account,
+            payout,
+        );
+
+        (
+            salary_transfer,
+            mxe.from_arcis(remaining_advance),
+            advance_is_settled.reveal(),
+            mxe.from_arcis(remaining_shortfall),
+            is_paid_in_full.reveal(),
+            mxe.from_arcis(total_claimed),
+            auditor.from_arcis(audited_amount),
+        )
+    }
+
+    #[instruction]
+    pub fn claim_salary(
+        mxe: Mxe,
+        auditor: Shared,
+        payroll_token_account: ConfidentialTokenAccount,
+        employee_token_account: ConfidentialTokenAccount,
+        salary: Enc<Mxe, u64>,
+        shortfall: Enc<Mxe, u64>,
+        shortfall_outstanding: bool,
+        total_claimed: Enc<Mxe, u64>,
+        has_claimed: bool,
+        audited: bool,
+    ) -> (
+        ConfidentialTransfer,
+        Enc<Mxe, u64>,
+        bool,
+        Enc<Mxe, u64>,
+        Enc<Shared, u64>,
+    ) {
+        // The shortfall of earlier underfunded claims is due on top of the salary.
+        let shortfall = if shortfall_outstanding {
+            shortfall.to_arcis()
+        } else {
+            0
+        };
+        let due = salary.to_arcis() + shortfall;
+
+        // Never pay out more than the vault holds, the rest carries over to the next claim.
+        let payout = min(due.clone(), payroll_token_account.encrypted_balance());
+        let remaining_shortfall = due - payout.clone();
+        let is_paid_in_full = remaining_shortfall.eq(0);
+
+        // The running total of the employee's claims, counting this one.
+        let total_claimed = if has_claimed {
+            total_claimed.to_arcis()
+        } else {
+            0
+        };
+        let total_claimed = total_claimed + payout.clone();
+
+        // The amount paid is encrypted to the auditor, nothing is disclosed without one.
+        let audited_amount = if audited { payout.clone() } else { 0 };
+
+        // Transfer of payout to the employee.
+        let salary_transfer = confidential_spl_token::transfer(
+            &mxe,
+            &payroll_token_account,
+            &employee_token_account,
+            payout,
+        );
+
+        (
+            salary_transfer,
+            mxe.from_arcis(remaining_shortfall),
+            is_paid_in_full.reveal(),
+            mxe.from_arcis(total_claimed),
+            auditor.from_arcis(audited_amount),
+        )
+    }
+}
@@ -0,0 +1,43 @@
+//! Addresses of the accounts used by the payroll program, derived from the employer, employees
+//! and payroll mint so clients can compute every account an instruction needs.
+
+use confidential_spl_token::get_associated_confidential_token_account_address;
+use solana_program::pubkey::Pubkey;
+
+/// Payroll of `employer`, the employer that initialized it. The address doesn't change when the
+/// payroll is handed over with `transfer_employer`.
+pub fn payroll_pda(employer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"payroll", employer.as_ref()], &crate::ID)
+}
+
+/// Token account of the payroll the salaries are paid from.
+pub fn payroll_token_account(employer: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        &payroll_pda(employer).0,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    )
+}
+
+/// Token account the salary of `employee` is paid to.
+pub fn employee_token_account(employee: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_confidential_token_account_address(
+        employee,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    )
+}
+
+pub fn claim_receipt_pda(payroll: &Pubkey, employee: &Pubkey, transfer_id: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            b"claim_receipt",
+            payroll.as_ref(),
+            employee.as_ref(),
+            &transfer_id.to_le_bytes(),
+        ],
+        &crate::ID,
+    )
+}
@@ -0,0 +1,47 @@
+use solana_program::program_error::ProgramError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayrollError {
+    /// The employee already claimed their salary within the payroll's claim cooldown.
+    AlreadyClaimed,
+    /// The employee's pay is frozen by the employer.
+    EmployeeFrozen,
+    /// The employee has to repay their outstanding advance before claiming as usual.
+    AdvanceOutstanding,
+    /// The employee has no outstanding advance to repay.
+    NoAdvanceOutstanding,
+    /// The payroll already holds `MAX_EMPLOYEES` employees.
+    EmployeeLimitReached,
+    /// The employer hasn't enabled streamed salaries.
+    StreamingDisabled,
+    /// The payroll still has employees, they have to be removed first.
+    EmployeesRemaining,
+    /// The employee's claims are capped, they have to claim with `claim_salary_capped`.
+    ClaimCapped,
+    /// The employee's claims aren't capped.
+    ClaimNotCapped,
+    /// The employee's vesting cliff hasn't been reached yet.
+    BeforeVestingCliff,
+    /// The employer paused all claims on the payroll.
+    PayrollPaused,
+    /// The employee is already on the payroll, their salary is changed with `update_salary`.
+    EmployeeAlreadyExists,
+    /// The salary is the default ciphertext, it was never encrypted.
+    SalaryUninitialized,
+    /// The signer is not the payroll's employer.
+    NotEmployer,
+    /// The mint is not the payroll's mint.
+    MintMismatch,
+    /// The payroll account is not derived from the employer.
+    PayrollMismatch,
+    /// The employee has been deactivated by the employer.
+    EmployeeInactive,
+    /// The employer enabled streamed salaries, they have to be claimed with `claim_salary_streamed`.
+    StreamingEnabled,
+}
+
+impl From<PayrollError> for ProgramError {
+    fn from(e: PayrollError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
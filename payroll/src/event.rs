@@ -0,0 +1,39 @@
+//! Events logged with `sol_log_data` so indexers can follow claims from the transaction logs.
+//! Events never carry encrypted amounts, only public keys and outcomes.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{entrypoint::ProgramResult, log::sol_log_data};
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayrollEvent {
+    /// The employee's claim settled, `succeeded` is false if its transfer failed and the employee
+    /// can claim again.
+    ClaimOutcome { employee: [u8; 32], succeeded: bool },
+}
+
+impl PayrollEvent {
+    /// Logs the Borsh serialized event.
+    pub fn emit(&self) -> ProgramResult {
+        sol_log_data(&[&self.try_to_vec()?]);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_outcome_roundtrip() {
+        let event = PayrollEvent::ClaimOutcome {
+            employee: [1; 32],
+            succeeded: false,
+        };
+        let data = event.try_to_vec().unwrap();
+
+        // Variant index, the employee and the flag, nothing else.
+        assert_eq!(1 + 32 + 1, data.len());
+        assert_eq!(event, PayrollEvent::try_from_slice(&data).unwrap());
+    }
+}
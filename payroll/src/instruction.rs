@@ -4,19 +4,24 @@ use confidential_spl_token::{
     confidential_transfer_adapter::state::RescueCiphertext, get_adapter_address,
     get_arcium_processor_accounts, get_associated_confidential_token_account_address,
     get_create_account_proof_context_state_address, get_key_registry_address,
-    get_single_transfer_account_address, programs::system_program,
+    get_transfer_account_address, programs::system_program,
 };
 use solana_instruction::{AccountMeta, Instruction};
 use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
+use crate::addresses::{claim_receipt_pda, payroll_pda};
+
 #[repr(u8)]
 #[derive(BorshDeserialize, BorshSerialize)]
 pub enum PayrollInstruction {
-    Initialize,
+    Initialize {
+        claim_cooldown_slots: u64,
+    },
 
     AddEmployee {
         employee: [u8; 32],
         encrypted_salary: RescueCiphertext,
+        vesting_cliff_slot: u64,
     },
 
     ClaimSalary {
@@ -24,11 +29,126 @@ pub enum PayrollInstruction {
         transfer_id: u32,
     },
     ClaimSalaryCallback,
+
+    ApplyColaAdjustment {
+        factor_bps: u16,
+        start: u8,
+        count: u8,
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    ApplyColaAdjustmentCallback {
+        salaries: Vec<RescueCiphertext>,
+    },
+
+    SetEmployeeFrozen {
+        employee: [u8; 32],
+        frozen: bool,
+    },
+
+    SetAdvanceInterestRate {
+        advance_interest_rate_bps: u16,
+    },
+
+    ClaimAdvance {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+
+    ClaimSalaryRepayingAdvance {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    ClaimSalaryRepayingAdvanceCallback,
+
+    QueryRunway {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    QueryRunwayCallback,
+
+    RemoveEmployee {
+        employee: [u8; 32],
+    },
+
+    UpdateSalary {
+        employee: [u8; 32],
+        encrypted_salary: RescueCiphertext,
+    },
+
+    SetSlotsPerPeriod {
+        slots_per_period: u64,
+    },
+
+    ClaimSalaryStreamed {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+
+    ClaimAllSalaries {
+        computation_offset_base: u32,
+        transfer_id_base: u32,
+    },
+
+    EmployerWithdraw {
+        computation_offset: u32,
+        transfer_id: u32,
+        amount: u64,
+    },
+    EmployerWithdrawCallback,
+
+    ClosePayroll {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+
+    SetClaimCap {
+        employee: [u8; 32],
+        max_per_period: u64,
+    },
+
+    ClaimSalaryCapped {
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    ClaimSalaryCappedCallback,
+
+    PayBonus {
+        employee: [u8; 32],
+        encrypted_bonus: RescueCiphertext,
+        computation_offset: u32,
+        transfer_id: u32,
+    },
+    PayBonusCallback,
+
+    TransferEmployer {
+        new_employer: [u8; 32],
+    },
+
+    SetPaused {
+        paused: bool,
+    },
+
+    ClaimAdvanceCallback,
+
+    ClosePayrollCallback,
+
+    SetEmployeeActive {
+        employee: [u8; 32],
+        active: bool,
+    },
+
+    SetAuditor {
+        auditor: [u8; 32],
+    },
 }
 
-pub fn initialize(employer: &Pubkey, mint: &Pubkey) -> Result<Instruction, ProgramError> {
-    let (payroll_pda, _) =
-        Pubkey::find_program_address(&[b"payroll", employer.as_ref()], &crate::ID);
+pub fn initialize(
+    employer: &Pubkey,
+    mint: &Pubkey,
+    claim_cooldown_slots: u64,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
     let derived_authority = derive_authority(&payroll_pda).0;
     let ata = get_associated_confidential_token_account_address(
         &payroll_pda,
@@ -67,7 +187,10 @@ pub fn initialize(employer: &Pubkey, mint: &Pubkey) -> Result<Instruction, Progr
             false,
         ),
     ];
-    let data = PayrollInstruction::Initialize.try_to_vec()?;
+    let data = PayrollInstruction::Initialize {
+        claim_cooldown_slots,
+    }
+    .try_to_vec()?;
 
     Ok(Instruction {
         program_id: crate::ID,
@@ -78,20 +201,20 @@ pub fn initialize(employer: &Pubkey, mint: &Pubkey) -> Result<Instruction, Progr
 
 pub fn add_employee(
     employer: &Pubkey,
+    payroll: &Pubkey,
     employee: &Pubkey,
     encrypted_salary: RescueCiphertext,
+    vesting_cliff_slot: u64,
 ) -> Result<Instruction, ProgramError> {
-    let (payroll_pda, _) =
-        Pubkey::find_program_address(&[b"payroll", employer.as_ref()], &crate::ID);
-
     let accounts = vec![
         AccountMeta::new(*employer, true),
-        AccountMeta::new(payroll_pda, false),
-        AccountMeta::new_readonly(*employee, false),
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(system_program::ID, false),
     ];
     let data = PayrollInstruction::AddEmployee {
         employee: employee.to_bytes(),
         encrypted_salary,
+        vesting_cliff_slot,
     }
     .try_to_vec()?;
 
@@ -102,37 +225,92 @@ pub fn add_employee(
     })
 }
 
-pub fn claim_salary(
+/// Removes `employee` from the payroll, freeing their slot.
+pub fn remove_employee(
+    employer: &Pubkey,
+    payroll: &Pubkey,
     employee: &Pubkey,
-    employee_token_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::RemoveEmployee {
+        employee: employee.to_bytes(),
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Replaces the encrypted salary of `employee`, later claims pay out the new salary.
+pub fn update_salary(
     employer: &Pubkey,
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    encrypted_salary: RescueCiphertext,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::UpdateSalary {
+        employee: employee.to_bytes(),
+        encrypted_salary,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Closes a payroll without employees. The remaining funds of the payroll token account are
+/// withdrawn to the employer, and once the withdrawal succeeded the payroll's rent is refunded to
+/// the employer.
+///
+/// Note: only the payroll account is closed. Confidential token accounts can't be closed, so the
+/// emptied payroll token account stays allocated and its rent is not refunded. Initializing the
+/// payroll again reuses it.
+pub fn close_payroll(
+    employer: &Pubkey,
+    payroll: &Pubkey,
     mint: &Pubkey,
     computation_offset: u32,
     transfer_id: u32,
 ) -> Result<Instruction, ProgramError> {
-    let (payroll_pda, _) =
-        Pubkey::find_program_address(&[b"payroll", employer.as_ref()], &crate::ID);
-    let derived_authority = derive_authority(&payroll_pda).0;
+    let derived_authority = derive_authority(payroll).0;
     let ata = get_associated_confidential_token_account_address(
-        &payroll_pda,
+        payroll,
         mint,
         &confidential_spl_token::programs::confidential_spl_token::ID,
         true,
     );
     let adapter = get_adapter_address(&ata);
-    let transfer_account = get_single_transfer_account_address(&ata, transfer_id);
+    let employer_ata = get_associated_confidential_token_account_address(
+        employer,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
     let [mxe_account, computation_account] =
         get_arcium_processor_accounts(&crate::ID, computation_offset);
 
     let accounts = vec![
-        AccountMeta::new(*employee, true),
-        AccountMeta::new(*employee_token_account, false),
-        AccountMeta::new(*employer, false),
-        AccountMeta::new(payroll_pda, false),       // authority
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),          // authority
         AccountMeta::new(derived_authority, false), // derived authority
         AccountMeta::new_readonly(*mint, false),
         AccountMeta::new(ata, false),
         AccountMeta::new(adapter, false),
+        AccountMeta::new(employer_ata, false),
         AccountMeta::new(transfer_account, false),
         AccountMeta::new(mxe_account, false),
         AccountMeta::new(computation_account, false),
@@ -151,7 +329,7 @@ pub fn claim_salary(
             false,
         ),
     ];
-    let data = PayrollInstruction::ClaimSalary {
+    let data = PayrollInstruction::ClosePayroll {
         computation_offset,
         transfer_id,
     }
@@ -164,35 +342,1020 @@ pub fn claim_salary(
     })
 }
 
-pub(crate) fn claim_salary_callback(
-    employee_token_account: &Pubkey,
+/// Freezes or unfreezes the salary claims of `employee`.
+pub fn set_employee_frozen(
     employer: &Pubkey,
-    mint: &Pubkey,
-    transfer_id: u32,
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    frozen: bool,
 ) -> Result<Instruction, ProgramError> {
-    let (payroll_pda, _) =
-        Pubkey::find_program_address(&[b"payroll", employer.as_ref()], &crate::ID);
-    let ata = get_associated_confidential_token_account_address(
-        &payroll_pda,
-        mint,
-        &confidential_spl_token::programs::confidential_spl_token::ID,
-        true,
-    );
-    let transfer_account = get_single_transfer_account_address(&ata, transfer_id);
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetEmployeeFrozen {
+        employee: employee.to_bytes(),
+        frozen,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
 
+/// Deactivates or reactivates `employee`. Inactive employees can't claim but, unlike removed
+/// employees, keep their claim history.
+pub fn set_employee_active(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    active: bool,
+) -> Result<Instruction, ProgramError> {
     let accounts = vec![
-        AccountMeta::new_readonly(*employer, false),
-        AccountMeta::new(payroll_pda, false),
-        AccountMeta::new_readonly(*mint, false),
-        AccountMeta::new_readonly(ata, false),
-        AccountMeta::new_readonly(*employee_token_account, false),
-        AccountMeta::new_readonly(transfer_account, false),
-        AccountMeta::new_readonly(
-            confidential_spl_token::programs::instruction_sysvar::ID,
-            false,
-        ),
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
     ];
-    let data = PayrollInstruction::ClaimSalaryCallback.try_to_vec()?;
+    let data = PayrollInstruction::SetEmployeeActive {
+        employee: employee.to_bytes(),
+        active,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Caps what the employee can claim per period with `claim_salary_capped`, `0` removes the cap.
+/// Capped employees can't take advances, so an outstanding advance has to be repaid first.
+pub fn set_claim_cap(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    max_per_period: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetClaimCap {
+        employee: employee.to_bytes(),
+        max_per_period,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Sets the interest charged per slot on salary advances.
+pub fn set_advance_interest_rate(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    advance_interest_rate_bps: u16,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetAdvanceInterestRate {
+        advance_interest_rate_bps,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Sets the number of slots a full salary is streamed over, `0` disables streaming.
+///
+/// While streaming is enabled, salaries are only claimed with `claim_salary_streamed` and
+/// `claim_all_salaries` pays the streamed salaries.
+pub fn set_slots_per_period(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    slots_per_period: u64,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetSlotsPerPeriod { slots_per_period }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Pauses or resumes every salary claim on the payroll.
+pub fn set_paused(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    paused: bool,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetPaused { paused }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Has every claim on the payroll disclose the amount paid, encrypted to the auditor's x25519
+/// `auditor` key. The ciphertext of an employee's last claim is stored with them in the payroll,
+/// an all zeroes key stops auditing.
+pub fn set_auditor(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    auditor: &[u8; 32],
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::SetAuditor { auditor: *auditor }.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Hands the payroll over to `new_employer`. The payroll keeps the address derived from the
+/// employer that initialized it, which is why the employer's instructions take it explicitly.
+pub fn transfer_employer(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    new_employer: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+    ];
+    let data = PayrollInstruction::TransferEmployer {
+        new_employer: new_employer.to_bytes(),
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Returns the ordered instructions setting up a payroll for `employees`, each given with their
+/// encrypted salary. Funding the payroll token account is a confidential transfer by the employer
+/// and has to happen in between initialization and the first claim.
+pub fn setup_payroll_instructions(
+    employer: &Pubkey,
+    mint: &Pubkey,
+    claim_cooldown_slots: u64,
+    employees: &[(Pubkey, RescueCiphertext)],
+) -> Result<Vec<Instruction>, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+
+    let mut instructions = vec![initialize(employer, mint, claim_cooldown_slots)?];
+    for (employee, encrypted_salary) in employees {
+        instructions.push(add_employee(
+            employer,
+            &payroll_pda,
+            employee,
+            *encrypted_salary,
+            0,
+        )?);
+    }
+
+    Ok(instructions)
+}
+
+/// Claims the salary along with any outstanding shortfall, paying at most what the payroll's
+/// token account holds. The rest is recorded as the employee's shortfall.
+pub fn claim_salary(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let derived_authority = derive_authority(&payroll_pda).0;
+    let ata = get_associated_confidential_token_account_address(
+        &payroll_pda,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let adapter = get_adapter_address(&ata);
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*employee, true),
+        AccountMeta::new(*employee_token_account, false),
+        AccountMeta::new(*employer, false),
+        AccountMeta::new(payroll_pda, false),       // authority
+        AccountMeta::new(derived_authority, false), // derived authority
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(ata, false),
+        AccountMeta::new(adapter, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ClaimSalary {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Pays every employee on the payroll, `employees` is given in payroll order. Employee `i` is paid
+/// with computation offset `computation_offset_base + i` and transfer id `transfer_id_base + i`.
+/// Like `claim_salary`, each payout is capped to the vault balance and any shortfall recorded.
+pub fn claim_all_salaries(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    employees: &[Pubkey],
+    computation_offset_base: u32,
+    transfer_id_base: u32,
+) -> Result<Instruction, ProgramError> {
+    let derived_authority = derive_authority(payroll).0;
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let adapter = get_adapter_address(&ata);
+    let [mxe_account, _] = get_arcium_processor_accounts(&crate::ID, computation_offset_base);
+
+    let mut accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),          // authority
+        AccountMeta::new(derived_authority, false), // derived authority
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(ata, false),
+        AccountMeta::new(adapter, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    // Each employee is paid with their own token, transfer and computation accounts.
+    for (i, employee) in employees.iter().enumerate() {
+        let employee_token_account = get_associated_confidential_token_account_address(
+            employee,
+            mint,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        );
+        let transfer_id = transfer_id_base
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let computation_offset = computation_offset_base
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+        let [_, computation_account] =
+            get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+        accounts.push(AccountMeta::new(employee_token_account, false));
+        accounts.push(AccountMeta::new(transfer_account, false));
+        accounts.push(AccountMeta::new(computation_account, false));
+    }
+    let data = PayrollInstruction::ClaimAllSalaries {
+        computation_offset_base,
+        transfer_id_base,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Same as `claim_salary`, additionally writing a `ClaimReceipt` once the transfer succeeded.
+/// The receipt is closed and its rent refunded to the employee if the transfer fails.
+pub fn claim_salary_with_receipt(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let (claim_receipt, _) = claim_receipt_pda(&payroll_pda, employee, transfer_id);
+
+    let mut instruction = claim_salary(
+        employee,
+        employee_token_account,
+        employer,
+        mint,
+        computation_offset,
+        transfer_id,
+    )?;
+    instruction
+        .accounts
+        .push(AccountMeta::new(claim_receipt, false));
+
+    Ok(instruction)
+}
+
+/// Claims the next salary ahead of time, it is repaid with interest through
+/// `claim_salary_repaying_advance`. Capped employees and employees within the claim cooldown can't
+/// take an advance.
+pub fn claim_advance(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let ata = get_associated_confidential_token_account_address(
+        &payroll_pda,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // The advance is paid through a computation updating the claimed total, so the transfer
+    // account is a computation one.
+    let mut instruction = claim_salary(
+        employee,
+        employee_token_account,
+        employer,
+        mint,
+        computation_offset,
+        transfer_id,
+    )?;
+    instruction.accounts[8].pubkey = get_transfer_account_address(&[ata], transfer_id);
+    instruction.data = PayrollInstruction::ClaimAdvance {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(instruction)
+}
+
+pub(crate) fn claim_advance_callback(
+    employee: &Pubkey,
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    let accounts = vec![
+        AccountMeta::new_readonly(*employer, false),
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(ata, false),
+        AccountMeta::new_readonly(*employee, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ClaimAdvanceCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Claims the salary minus the outstanding advance and its accrued interest, plus any shortfall.
+/// Like `claim_salary`, the payout is capped to the vault balance.
+pub fn claim_salary_repaying_advance(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let ata = get_associated_confidential_token_account_address(
+        &payroll_pda,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // The payout is computed by the MXE, so the transfer account is a computation one.
+    let mut instruction = claim_salary(
+        employee,
+        employee_token_account,
+        employer,
+        mint,
+        computation_offset,
+        transfer_id,
+    )?;
+    instruction.accounts[8].pubkey = get_transfer_account_address(&[ata], transfer_id);
+    instruction.data = PayrollInstruction::ClaimSalaryRepayingAdvance {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(instruction)
+}
+
+/// Claims the salary accrued since the last claim, pro-rata to the slots elapsed.
+pub fn claim_salary_streamed(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let ata = get_associated_confidential_token_account_address(
+        &payroll_pda,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // The payout is computed by the MXE, so the transfer account is a computation one.
+    let mut instruction = claim_salary(
+        employee,
+        employee_token_account,
+        employer,
+        mint,
+        computation_offset,
+        transfer_id,
+    )?;
+    instruction.accounts[8].pubkey = get_transfer_account_address(&[ata], transfer_id);
+    instruction.data = PayrollInstruction::ClaimSalaryStreamed {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(instruction)
+}
+
+pub(crate) fn claim_salary_repaying_advance_callback(
+    employee: &Pubkey,
+    payroll: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*employee, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ClaimSalaryRepayingAdvanceCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Claims the salary, clamped so the claims of the current period don't exceed the cap.
+pub fn claim_salary_capped(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let (payroll_pda, _) = payroll_pda(employer);
+    let ata = get_associated_confidential_token_account_address(
+        &payroll_pda,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    // The payout is computed by the MXE, so the transfer account is a computation one.
+    let mut instruction = claim_salary(
+        employee,
+        employee_token_account,
+        employer,
+        mint,
+        computation_offset,
+        transfer_id,
+    )?;
+    instruction.accounts[8].pubkey = get_transfer_account_address(&[ata], transfer_id);
+    instruction.data = PayrollInstruction::ClaimSalaryCapped {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(instruction)
+}
+
+pub(crate) fn claim_salary_capped_callback(
+    employee: &Pubkey,
+    payroll: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*employee, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ClaimSalaryCappedCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn claim_salary_callback(
+    employee: &Pubkey,
+    employee_token_account: &Pubkey,
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    transfer_account: &Pubkey,
+    claim_receipt: Option<&Pubkey>,
+) -> Result<Instruction, ProgramError> {
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*employer, false),
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(ata, false),
+        AccountMeta::new(*employee, false),
+        AccountMeta::new_readonly(*employee_token_account, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    if let Some(claim_receipt) = claim_receipt {
+        accounts.push(AccountMeta::new(*claim_receipt, false));
+    }
+    let data = PayrollInstruction::ClaimSalaryCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Rescales the salaries of the employees in `[start, start + count)` by `factor_bps`. Salaries
+/// updated while the computation is pending keep their new value.
+pub fn apply_cola_adjustment(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    factor_bps: u16,
+    start: u8,
+    count: u8,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ApplyColaAdjustment {
+        factor_bps,
+        start,
+        count,
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Reveals how many full payroll runs the payroll token account can cover, the runway is stored in
+/// the payroll and set as return data of the callback.
+pub fn query_runway(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new_readonly(*payroll, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::QueryRunway {
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn query_runway_callback(
+    payroll: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::QueryRunwayCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Withdraws up to `amount` of unspent funds from the payroll token account to the employer.
+pub fn employer_withdraw(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> Result<Instruction, ProgramError> {
+    let derived_authority = derive_authority(payroll).0;
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let adapter = get_adapter_address(&ata);
+    let employer_ata = get_associated_confidential_token_account_address(
+        employer,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),          // authority
+        AccountMeta::new(derived_authority, false), // derived authority
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(ata, false),
+        AccountMeta::new(adapter, false),
+        AccountMeta::new(employer_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::EmployerWithdraw {
+        computation_offset,
+        transfer_id,
+        amount,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn employer_withdraw_callback(
+    payroll: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new_readonly(*payroll, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::EmployerWithdrawCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+/// Pays a one-time bonus to an employee's token account, separately from their salary claims. The
+/// bonus counts towards the employee's claimed total.
+pub fn pay_bonus(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    mint: &Pubkey,
+    encrypted_bonus: RescueCiphertext,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Result<Instruction, ProgramError> {
+    let derived_authority = derive_authority(payroll).0;
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+    let adapter = get_adapter_address(&ata);
+    let employee_ata = get_associated_confidential_token_account_address(
+        employee,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    let transfer_account = get_transfer_account_address(&[ata], transfer_id);
+    let [mxe_account, computation_account] =
+        get_arcium_processor_accounts(&crate::ID, computation_offset);
+
+    let accounts = vec![
+        AccountMeta::new(*employer, true),
+        AccountMeta::new(*payroll, false),          // authority
+        AccountMeta::new(derived_authority, false), // derived authority
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new(ata, false),
+        AccountMeta::new(adapter, false),
+        AccountMeta::new(employee_ata, false),
+        AccountMeta::new(transfer_account, false),
+        AccountMeta::new(mxe_account, false),
+        AccountMeta::new(computation_account, false),
+        AccountMeta::new_readonly(system_program::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(confidential_spl_token::programs::arcium::ID, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_transfer_adapter::ID,
+            false,
+        ),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::confidential_spl_token_authority::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::PayBonus {
+        employee: employee.to_bytes(),
+        encrypted_bonus,
+        computation_offset,
+        transfer_id,
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn pay_bonus_callback(
+    payroll: &Pubkey,
+    employee: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*employee, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::PayBonusCallback.try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn apply_cola_adjustment_callback(
+    payroll: &Pubkey,
+    mint: &Pubkey,
+    transfer_account: &Pubkey,
+    employees: &[Pubkey],
+    salaries: &[RescueCiphertext],
+) -> Result<Instruction, ProgramError> {
+    let ata = get_associated_confidential_token_account_address(
+        payroll,
+        mint,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    let mut accounts = vec![
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*mint, false),
+        AccountMeta::new_readonly(ata, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    // The employees whose salaries are rescaled, in the order of the computation's outputs.
+    accounts.extend(
+        employees
+            .iter()
+            .map(|employee| AccountMeta::new_readonly(*employee, false)),
+    );
+    // The salaries the computation rescaled, salaries updated in the meantime are kept.
+    let data = PayrollInstruction::ApplyColaAdjustmentCallback {
+        salaries: salaries.to_vec(),
+    }
+    .try_to_vec()?;
+
+    Ok(Instruction {
+        program_id: crate::ID,
+        accounts,
+        data,
+    })
+}
+
+pub(crate) fn close_payroll_callback(
+    employer: &Pubkey,
+    payroll: &Pubkey,
+    transfer_account: &Pubkey,
+) -> Result<Instruction, ProgramError> {
+    let accounts = vec![
+        AccountMeta::new(*employer, false),
+        AccountMeta::new(*payroll, false),
+        AccountMeta::new_readonly(*transfer_account, false),
+        AccountMeta::new_readonly(
+            confidential_spl_token::programs::instruction_sysvar::ID,
+            false,
+        ),
+    ];
+    let data = PayrollInstruction::ClosePayrollCallback.try_to_vec()?;
 
     Ok(Instruction {
         program_id: crate::ID,
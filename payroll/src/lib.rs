@@ -1,5 +1,8 @@
 #![allow(unexpected_cfgs)]
 
+pub mod addresses;
+pub mod error;
+pub mod event;
 pub mod instruction;
 pub mod processor;
 pub mod state;
@@ -22,16 +25,19 @@ pub fn process_instruction(
 ) -> ProgramResult {
     match PayrollInstruction::try_from_slice(instruction_data) {
         Ok(instruction) => match instruction {
-            PayrollInstruction::Initialize => {
+            PayrollInstruction::Initialize {
+                claim_cooldown_slots,
+            } => {
                 msg!("Initialize");
-                process_initialize(program_id, accounts)
+                process_initialize(program_id, accounts, claim_cooldown_slots)
             }
             PayrollInstruction::AddEmployee {
                 employee,
                 encrypted_salary,
+                vesting_cliff_slot,
             } => {
                 msg!("AddEmployee");
-                process_add_employee(accounts, &employee, encrypted_salary)
+                process_add_employee(accounts, &employee, encrypted_salary, vesting_cliff_slot)
             }
             PayrollInstruction::ClaimSalary {
                 computation_offset,
@@ -44,6 +50,175 @@ pub fn process_instruction(
                 msg!("ClaimSalaryCallback");
                 process_claim_salary_callback(accounts)
             }
+            PayrollInstruction::ApplyColaAdjustment {
+                factor_bps,
+                start,
+                count,
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ApplyColaAdjustment");
+                process_apply_cola_adjustment(
+                    accounts,
+                    factor_bps,
+                    start,
+                    count,
+                    computation_offset,
+                    transfer_id,
+                )
+            }
+            PayrollInstruction::ApplyColaAdjustmentCallback { salaries } => {
+                msg!("ApplyColaAdjustmentCallback");
+                process_apply_cola_adjustment_callback(accounts, &salaries)
+            }
+            PayrollInstruction::SetEmployeeFrozen { employee, frozen } => {
+                msg!("SetEmployeeFrozen");
+                process_set_employee_frozen(accounts, &employee, frozen)
+            }
+            PayrollInstruction::SetAdvanceInterestRate {
+                advance_interest_rate_bps,
+            } => {
+                msg!("SetAdvanceInterestRate");
+                process_set_advance_interest_rate(accounts, advance_interest_rate_bps)
+            }
+            PayrollInstruction::ClaimAdvance {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ClaimAdvance");
+                process_claim_advance(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::ClaimSalaryRepayingAdvance {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ClaimSalaryRepayingAdvance");
+                process_claim_salary_repaying_advance(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::ClaimSalaryRepayingAdvanceCallback => {
+                msg!("ClaimSalaryRepayingAdvanceCallback");
+                process_claim_salary_repaying_advance_callback(accounts)
+            }
+            PayrollInstruction::QueryRunway {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("QueryRunway");
+                process_query_runway(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::QueryRunwayCallback => {
+                msg!("QueryRunwayCallback");
+                process_query_runway_callback(accounts)
+            }
+            PayrollInstruction::RemoveEmployee { employee } => {
+                msg!("RemoveEmployee");
+                process_remove_employee(accounts, &employee)
+            }
+            PayrollInstruction::UpdateSalary {
+                employee,
+                encrypted_salary,
+            } => {
+                msg!("UpdateSalary");
+                process_update_salary(accounts, &employee, encrypted_salary)
+            }
+            PayrollInstruction::SetSlotsPerPeriod { slots_per_period } => {
+                msg!("SetSlotsPerPeriod");
+                process_set_slots_per_period(accounts, slots_per_period)
+            }
+            PayrollInstruction::ClaimSalaryStreamed {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ClaimSalaryStreamed");
+                process_claim_salary_streamed(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::ClaimAllSalaries {
+                computation_offset_base,
+                transfer_id_base,
+            } => {
+                msg!("ClaimAllSalaries");
+                process_claim_all_salaries(accounts, computation_offset_base, transfer_id_base)
+            }
+            PayrollInstruction::EmployerWithdraw {
+                computation_offset,
+                transfer_id,
+                amount,
+            } => {
+                msg!("EmployerWithdraw");
+                process_employer_withdraw(accounts, computation_offset, transfer_id, amount)
+            }
+            PayrollInstruction::EmployerWithdrawCallback => {
+                msg!("EmployerWithdrawCallback");
+                process_employer_withdraw_callback(accounts)
+            }
+            PayrollInstruction::ClosePayroll {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ClosePayroll");
+                process_close_payroll(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::SetClaimCap {
+                employee,
+                max_per_period,
+            } => {
+                msg!("SetClaimCap");
+                process_set_claim_cap(accounts, &employee, max_per_period)
+            }
+            PayrollInstruction::ClaimSalaryCapped {
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("ClaimSalaryCapped");
+                process_claim_salary_capped(accounts, computation_offset, transfer_id)
+            }
+            PayrollInstruction::ClaimSalaryCappedCallback => {
+                msg!("ClaimSalaryCappedCallback");
+                process_claim_salary_capped_callback(accounts)
+            }
+            PayrollInstruction::PayBonus {
+                employee,
+                encrypted_bonus,
+                computation_offset,
+                transfer_id,
+            } => {
+                msg!("PayBonus");
+                process_pay_bonus(
+                    accounts,
+                    &employee,
+                    encrypted_bonus,
+                    computation_offset,
+                    transfer_id,
+                )
+            }
+            PayrollInstruction::PayBonusCallback => {
+                msg!("PayBonusCallback");
+                process_pay_bonus_callback(accounts)
+            }
+            PayrollInstruction::TransferEmployer { new_employer } => {
+                msg!("TransferEmployer");
+                process_transfer_employer(accounts, &new_employer)
+            }
+            PayrollInstruction::SetPaused { paused } => {
+                msg!("SetPaused");
+                process_set_paused(accounts, paused)
+            }
+            PayrollInstruction::ClaimAdvanceCallback => {
+                msg!("ClaimAdvanceCallback");
+                process_claim_advance_callback(accounts)
+            }
+            PayrollInstruction::ClosePayrollCallback => {
+                msg!("ClosePayrollCallback");
+                process_close_payroll_callback(accounts)
+            }
+            PayrollInstruction::SetEmployeeActive { employee, active } => {
+                msg!("SetEmployeeActive");
+                process_set_employee_active(accounts, &employee, active)
+            }
+            PayrollInstruction::SetAuditor { auditor } => {
+                msg!("SetAuditor");
+                process_set_auditor(accounts, &auditor)
+            }
         },
         Err(e) => panic!("Failed to deserialize instruction {}", e),
     }
@@ -1,21 +1,34 @@
-use crate::state::{Employee, Payroll, MAX_EMPLOYEES};
+use crate::addresses::{claim_receipt_pda, payroll_pda};
+use crate::error::PayrollError;
+use crate::event::PayrollEvent;
+use crate::state::{ClaimReceipt, Employee, Payroll, MAX_EMPLOYEES};
 use borsh::{BorshDeserialize, BorshSerialize};
 use confidential_spl_token::confidential_spl_token_authority::Authority;
+use confidential_spl_token::confidential_transfer_adapter::arcium_types::Argument;
 use confidential_spl_token::confidential_transfer_adapter::state::{
     RescueCiphertext, TransferStatus,
 };
-use confidential_spl_token::{get_associated_confidential_token_account_address, transfer_result};
+use confidential_spl_token::invoke::TransferWithComputationInstruction;
+use confidential_spl_token::{
+    get_associated_confidential_token_account_address, get_transfer_account_address,
+    transfer_result,
+};
 use solana_program::rent::Rent;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint::ProgramResult,
     msg,
+    program::set_return_data,
     program_error::ProgramError,
     pubkey::Pubkey,
     sysvar::{clock::Clock, Sysvar},
 };
 
-pub(crate) fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+pub(crate) fn process_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    claim_cooldown_slots: u64,
+) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let employer_info = next_account_info(account_info_iter)?;
@@ -35,6 +48,10 @@ pub(crate) fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo])
     if !employer_info.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
+    // Without a cooldown an employee could claim their salary several times in the same slot.
+    if claim_cooldown_slots == 0 {
+        return Err(ProgramError::InvalidArgument);
+    }
 
     // We utilize a derived authority to pass our signed invokations through.
     let authority = Authority::derived(
@@ -43,15 +60,15 @@ pub(crate) fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo])
         confidential_spl_token_authority_info,
     );
 
+    let payroll = Payroll::new(employer_info.key, mint_info.key, claim_cooldown_slots);
     let (pda, bump) = check_payroll(
-        employer_info,
+        &payroll,
         payroll_info,
         mint_info,
         payroll_token_account_info,
     )?;
 
     // Create payroll_info.
-    let payroll = Payroll::new(employer_info.key, mint_info.key);
     let payroll_data = payroll.try_to_vec()?;
     let lamports = Rent::get()?.minimum_balance(payroll_data.len());
 
@@ -76,6 +93,16 @@ pub(crate) fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo])
         .try_borrow_mut_data()?
         .copy_from_slice(&payroll_data);
 
+    // A payroll closed by the employer leaves its emptied token account behind, confidential token
+    // accounts can't be closed. The reinitialized payroll derives the same address and authority,
+    // so it takes the existing account over.
+    if !payroll_token_account_info.data_is_empty() {
+        if payroll_token_account_info.owner != token_program_info.key {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        return Ok(());
+    }
+
     // Create a confidential SPL token account with payroll_info as the authority.
     confidential_spl_token::invoke::create_account(
         &crate::ID,
@@ -99,44 +126,71 @@ pub(crate) fn process_add_employee(
     accounts: &[AccountInfo],
     employee: &[u8; 32],
     encrypted_salary: RescueCiphertext,
+    vesting_cliff_slot: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
     let employer_info = next_account_info(account_info_iter)?;
     let payroll_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
 
     if !employer_info.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    // The salary is encrypted, only an uninitialized ciphertext can be told apart.
+    if encrypted_salary == RescueCiphertext::default() {
+        return Err(PayrollError::SalaryUninitialized.into());
+    }
+
     // Deserialize payroll.
     let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
 
     if payroll.employer != employer_info.key.to_bytes() {
-        return Err(ProgramError::IllegalOwner);
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // A second entry for the same employee would split their claim state.
+    if payroll
+        .find_employee(&Pubkey::new_from_array(*employee))
+        .is_ok()
+    {
+        return Err(PayrollError::EmployeeAlreadyExists.into());
     }
 
-    if payroll.num_employees as usize >= MAX_EMPLOYEES {
-        return Err(ProgramError::AccountDataTooSmall);
+    if payroll.employees.len() >= MAX_EMPLOYEES {
+        return Err(PayrollError::EmployeeLimitReached.into());
     }
 
     // Add new employee to payroll.
-    payroll.employees[payroll.num_employees as usize] = Employee {
+    payroll.employees.push(Employee {
         key: *employee,
         encrypted_salary,
         last_claimed_slot: 0,
         previous_claimed_slot: 0,
-    };
-    payroll.num_employees += 1;
-
-    // Write updates into payroll_info data.
-    payroll_info
-        .try_borrow_mut_data()?
-        .copy_from_slice(&payroll.try_to_vec()?);
+        frozen: false,
+        encrypted_advance: RescueCiphertext::default(),
+        advance_slot: 0,
+        advance_outstanding: false,
+        max_per_period: 0,
+        claimed_this_period: RescueCiphertext::default(),
+        vesting_cliff_slot,
+        last_claim_succeeded: false,
+        encrypted_shortfall: RescueCiphertext::default(),
+        shortfall_outstanding: false,
+        start_slot: Clock::get()?.slot,
+        total_claimed: RescueCiphertext::default(),
+        active: true,
+        encrypted_audit_amount: RescueCiphertext::default(),
+        audit_amount_nonce: 0,
+    });
 
-    Ok(())
+    // Write updates into payroll_info data, growing it by the new employee.
+    write_resized_payroll(&payroll, payroll_info, employer_info, system_program_info)
 }
 
+pub const CLAIM_SALARY_COMP_DEF_OFFSET: u32 = 6;
+
 pub(crate) fn process_claim_salary(
     accounts: &[AccountInfo],
     computation_offset: u32,
@@ -161,10 +215,15 @@ pub(crate) fn process_claim_salary(
     let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
     let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
 
+    // Optional account receiving a ClaimReceipt once the transfer succeeded.
+    let claim_receipt_info = next_account_info(account_info_iter).ok();
+
     if !employee_info.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    check_employee_token_account(employee_info.key, mint_info, employee_token_account_info)?;
+
     // We utilize a derived authority to pass our signed invokations through.
     let authority = Authority::derived(
         payroll_info,
@@ -172,25 +231,51 @@ pub(crate) fn process_claim_salary(
         confidential_spl_token_authority_info,
     );
 
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
     let (_, bump) = check_payroll(
-        employer_info,
+        &payroll,
         payroll_info,
         mint_info,
         payroll_token_account_info,
     )?;
 
-    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
-
     if mint_info.key.to_bytes() != payroll.mint {
-        return Err(ProgramError::InvalidAccountOwner);
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
     }
 
-    // For simplicity, allow claim once per slot (could be per epoch, or time-based).
+    // Employees can claim once per claim cooldown, unless something holds their salary back.
     let employee_idx = payroll.find_employee(employee_info.key)?;
+    if !payroll.employees[employee_idx].active {
+        return Err(PayrollError::EmployeeInactive.into());
+    }
+    if payroll.employees[employee_idx].frozen {
+        return Err(PayrollError::EmployeeFrozen.into());
+    }
+    if payroll.employees[employee_idx].advance_outstanding {
+        return Err(PayrollError::AdvanceOutstanding.into());
+    }
+    if payroll.employees[employee_idx].max_per_period != 0 {
+        return Err(PayrollError::ClaimCapped.into());
+    }
+    // Streamed salaries accrue per slot, a full salary on top would pay the same slots twice.
+    if payroll.slots_per_period != 0 {
+        return Err(PayrollError::StreamingEnabled.into());
+    }
     let clock = Clock::get()?;
-    if payroll.employees[employee_idx].last_claimed_slot == clock.slot {
-        msg!("Already claimed in this slot");
-        return Err(ProgramError::Custom(0));
+    if clock.slot < payroll.employees[employee_idx].vesting_cliff_slot {
+        msg!(
+            "Claims open at slot {}",
+            payroll.employees[employee_idx].vesting_cliff_slot
+        );
+        return Err(PayrollError::BeforeVestingCliff.into());
+    }
+    if payroll.employees[employee_idx].in_claim_cooldown(clock.slot, payroll.claim_cooldown_slots) {
+        msg!("Already claimed within the claim cooldown");
+        return Err(PayrollError::AlreadyClaimed.into());
     }
     payroll.employees[employee_idx].previous_claimed_slot =
         payroll.employees[employee_idx].last_claimed_slot;
@@ -200,28 +285,76 @@ pub(crate) fn process_claim_salary(
         .try_borrow_mut_data()?
         .copy_from_slice(&payroll.try_to_vec()?);
 
-    // claim_salary_callback should be called after the payroll transfer has been attemped.
-    let callback_instruction = crate::instruction::claim_salary_callback(
-        employee_token_account_info.key,
-        employer_info.key,
-        mint_info.key,
-        transfer_id,
-    )?
-    .into();
+    // Create the claim receipt account, it is populated by the callback.
+    if let Some(claim_receipt_info) = claim_receipt_info {
+        let (claim_receipt_pda, claim_receipt_bump) =
+            claim_receipt_pda(payroll_info.key, employee_info.key, transfer_id);
+        if *claim_receipt_info.key != claim_receipt_pda {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        let receipt_len = ClaimReceipt::default().try_to_vec()?.len();
+        solana_cpi::invoke_signed(
+            &solana_system_interface::instruction::create_account(
+                employee_info.key,
+                claim_receipt_info.key,
+                Rent::get()?.minimum_balance(receipt_len),
+                receipt_len as u64,
+                &crate::ID,
+            ),
+            &[
+                employee_info.clone(),
+                claim_receipt_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[&[
+                b"claim_receipt",
+                payroll_info.key.as_ref(),
+                employee_info.key.as_ref(),
+                &transfer_id.to_le_bytes(),
+                &[claim_receipt_bump],
+            ]],
+        )?;
+    }
+
+    // Arguments for the encrypted computation.
+    let employee = &payroll.employees[employee_idx];
+    let arguments = [
+        Argument::ArcisPubkey(payroll.auditor),
+        Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(employee.encrypted_salary),
+        Argument::EncryptedU64(employee.encrypted_shortfall),
+        Argument::PlaintextBool(employee.shortfall_outstanding),
+        Argument::EncryptedU64(employee.total_claimed),
+        Argument::PlaintextBool(employee.total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(payroll.is_audited()),
+    ];
 
-    // The encrypted transfer amount is the employees encrypted salary.
-    let encrypted_amount = payroll.employees[employee_idx].encrypted_salary.into();
+    // Transfer the salary, capped to the vault balance, to the employee.
+    let salary_transfer = TransferWithComputationInstruction {
+        authority: &authority,
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
 
-    // Transfer salary from payroll_token_account_info to employee_token_account_info.
-    confidential_spl_token::invoke::transfer(
+    // claim_salary_callback should be called after the payroll transfer has been attemped.
+    confidential_spl_token::invoke::transfer_with_computation(
         &confidential_spl_token::programs::confidential_spl_token::ID,
         &crate::ID,
+        &[salary_transfer],
+        &arguments,
         employee_info,
-        authority,
-        mint_info,
-        payroll_token_account_info,
-        payroll_token_account_adapter_info,
-        employee_token_account_info,
         transfer_account_info,
         mxe_info,
         computation_info,
@@ -229,12 +362,20 @@ pub(crate) fn process_claim_salary(
         token_program_info,
         arcium_program_info,
         confidential_transfer_adapter_info,
-        &[],
-        callback_instruction,
-        encrypted_amount,
+        crate::instruction::claim_salary_callback(
+            employee_info.key,
+            employee_token_account_info.key,
+            employer_info.key,
+            payroll_info.key,
+            mint_info.key,
+            transfer_account_info.key,
+            claim_receipt_info.map(|info| info.key),
+        )?
+        .into(),
         computation_offset,
+        CLAIM_SALARY_COMP_DEF_OFFSET,
         transfer_id,
-        &[&[b"payroll", employer_info.key.as_ref(), &[bump]]],
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
     )
 }
 
@@ -245,53 +386,2077 @@ pub(crate) fn process_claim_salary_callback(accounts: &[AccountInfo]) -> Program
     let payroll_info = next_account_info(account_info_iter)?;
     let mint_info = next_account_info(account_info_iter)?;
     let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let employee_info = next_account_info(account_info_iter)?;
     let _employee_token_account_info = next_account_info(account_info_iter)?;
     let transfer_account_info = next_account_info(account_info_iter)?;
     let instructions_sysvar_info = next_account_info(account_info_iter)?;
+    let claim_receipt_info = next_account_info(account_info_iter).ok();
 
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
     check_payroll(
-        employer_info,
+        &payroll,
         payroll_info,
         mint_info,
         payroll_token_account_info,
     )?;
 
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+
     // Check if the transfer was successfull.
     let transfer_output = transfer_result(transfer_account_info, instructions_sysvar_info);
-    if let Ok(output) = transfer_output {
-        if output.status == TransferStatus::Success {
-            return Ok(());
+    let claim_succeeded =
+        matches!(&transfer_output, Ok(output) if output.status == TransferStatus::Success);
+    payroll.employees[employee_idx].last_claim_succeeded = claim_succeeded;
+
+    if claim_succeeded {
+        // Take the shortfall left by an underfunded vault and the employee's new claimed total
+        // from the computation output, claim_salary_streamed has the same outputs.
+        let output_data = transfer_output?
+            .custom_computation_output
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let employee = &mut payroll.employees[employee_idx];
+        employee.encrypted_shortfall = RescueCiphertext::try_from(&output_data[..32])?;
+        employee.shortfall_outstanding = !bool::try_from_slice(&output_data[32..33])?;
+        employee.total_claimed = RescueCiphertext::try_from(&output_data[33..65])?;
+        record_audit_amount(employee, &output_data[65..])?;
+
+        if let Some(claim_receipt_info) = claim_receipt_info {
+            let receipt = ClaimReceipt {
+                slot: payroll.employees[employee_idx].last_claimed_slot,
+                transfer_account: transfer_account_info.key.to_bytes(),
+                employee: employee_info.key.to_bytes(),
+            };
+            claim_receipt_info
+                .try_borrow_mut_data()?
+                .copy_from_slice(&receipt.try_to_vec()?);
+        }
+    } else {
+        // Since the transfer has failed, we allow the employee to claim their salary again.
+        payroll.employees[employee_idx].last_claimed_slot =
+            payroll.employees[employee_idx].previous_claimed_slot;
+
+        // Close the receipt created by claim_salary and refund its rent to the employee.
+        if let Some(claim_receipt_info) = claim_receipt_info {
+            let claim_receipt_lamports = claim_receipt_info.lamports();
+            **employee_info.try_borrow_mut_lamports()? += claim_receipt_lamports;
+            **claim_receipt_info.try_borrow_mut_lamports()? = 0;
+            claim_receipt_info.try_borrow_mut_data()?.fill(0);
+        }
+    }
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    PayrollEvent::ClaimOutcome {
+        employee: employee_info.key.to_bytes(),
+        succeeded: claim_succeeded,
+    }
+    .emit()
+}
+
+pub const APPLY_COLA_COMP_DEF_OFFSET: u32 = 0;
+
+/// Number of salaries rescaled by a single `apply_cola_adjustment` computation.
+pub const COLA_BATCH_SIZE: usize = 8;
+
+pub(crate) fn process_apply_cola_adjustment(
+    accounts: &[AccountInfo],
+    factor_bps: u16,
+    start: u8,
+    count: u8,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // Only rescale the employees in [start, start + count), this allows large payrolls to be
+    // adjusted over several transactions to stay within the compute budget.
+    let (start, count) = (start as usize, count as usize);
+    if count == 0 || count > COLA_BATCH_SIZE || start + count > payroll.employees.len() {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // Arguments for the encrypted computation, unused salary slots are padded and ignored.
+    let mut arguments = Vec::with_capacity(COLA_BATCH_SIZE + 1);
+    for i in 0..COLA_BATCH_SIZE {
+        let encrypted_salary = if i < count {
+            payroll.employees[start + i].encrypted_salary
+        } else {
+            RescueCiphertext::default()
+        };
+        arguments.push(Argument::EncryptedU64(encrypted_salary));
+    }
+    arguments.push(Argument::PlaintextU16(factor_bps));
+
+    // The callback looks the employees up by key, they may have been removed or reordered by then.
+    let employees: Vec<Pubkey> = payroll.employees[start..start + count]
+        .iter()
+        .map(|employee| Pubkey::new_from_array(employee.key))
+        .collect();
+    let salaries: Vec<RescueCiphertext> = payroll.employees[start..start + count]
+        .iter()
+        .map(|employee| employee.encrypted_salary)
+        .collect();
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only outputs the rescaled salaries.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        employer_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::apply_cola_adjustment_callback(
+            payroll_info.key,
+            mint_info.key,
+            transfer_account_info.key,
+            &employees,
+            &salaries,
+        )?
+        .into(),
+        computation_offset,
+        APPLY_COLA_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_apply_cola_adjustment_callback(
+    accounts: &[AccountInfo],
+    salaries: &[RescueCiphertext],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payroll_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+    let employee_infos = account_info_iter.as_slice();
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    // Every employee of the batch comes with the salary that was rescaled.
+    if employee_infos.len() > COLA_BATCH_SIZE || employee_infos.len() != salaries.len() {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        return Ok(());
+    }
+
+    // Take the rescaled salaries from the computation output.
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    if output_data.len() < employee_infos.len() * 32 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    for (i, (employee_info, salary)) in employee_infos.iter().zip(salaries).enumerate() {
+        // Employees removed since the computation was queued are skipped, and so are salaries
+        // updated in the meantime, the rescaled salary is stale.
+        if let Ok(employee_idx) = payroll.find_employee(employee_info.key) {
+            let employee = &mut payroll.employees[employee_idx];
+            if employee.encrypted_salary == *salary {
+                employee.encrypted_salary =
+                    RescueCiphertext::try_from(&output_data[i * 32..(i + 1) * 32])?;
+            }
         }
     }
 
-    // TODO: Since the transfer has failed, we allow the employee to claim their salary again.
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
 
     Ok(())
 }
 
-fn check_payroll(
-    employer_info: &AccountInfo,
-    payroll_info: &AccountInfo,
-    mint_info: &AccountInfo,
-    payroll_token_account_info: &AccountInfo,
-) -> Result<(Pubkey, u8), ProgramError> {
-    let (pda, bump) =
-        Pubkey::find_program_address(&[b"payroll", employer_info.key.as_ref()], &crate::ID);
+pub(crate) fn process_remove_employee(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
 
-    if *payroll_info.key != pda {
-        return Err(ProgramError::InvalidAccountOwner);
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let ata = get_associated_confidential_token_account_address(
-        payroll_info.key,
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    payroll.remove_employee(&Pubkey::new_from_array(*employee))?;
+
+    // Write updates into payroll_info data, shrinking it by the removed employee.
+    let payroll_data = payroll.try_to_vec()?;
+    payroll_info.resize(payroll_data.len())?;
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll_data);
+
+    // Refund the rent freed by the removed employee to the employer.
+    let excess_lamports = payroll_info
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(payroll_data.len()));
+    **payroll_info.try_borrow_mut_lamports()? -= excess_lamports;
+    **employer_info.try_borrow_mut_lamports()? += excess_lamports;
+
+    Ok(())
+}
+
+pub(crate) fn process_close_payroll(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let employer_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    if !payroll.employees.is_empty() {
+        return Err(PayrollError::EmployeesRemaining.into());
+    }
+
+    // The remaining funds can only be withdrawn to the employer.
+    let employer_token_account = get_associated_confidential_token_account_address(
+        employer_info.key,
         mint_info.key,
         &confidential_spl_token::programs::confidential_spl_token::ID,
-        true,
+        false,
     );
-
-    if *payroll_token_account_info.key != ata {
+    if *employer_token_account_info.key != employer_token_account {
         return Err(ProgramError::InvalidAccountData);
     }
 
-    Ok((pda, bump))
+    // Withdraw the whole balance, employer_withdraw caps the amount at what the vault holds.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employer_token_account_info.key.to_bytes()),
+        Argument::PlaintextU64(u64::MAX),
+    ];
+
+    // Transfer the balance from payroll_token_account_info to employer_token_account_info.
+    let withdraw_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employer_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    // payroll_info is closed in the callback, once the payroll token account is emptied. The token
+    // account itself can't be closed and stays allocated, a later `initialize` reuses it.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[withdraw_transfer],
+        &arguments,
+        employer_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::close_payroll_callback(
+            employer_info.key,
+            payroll_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        EMPLOYER_WITHDRAW_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_close_payroll_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    // If the withdrawal failed, the payroll stays open and the employer may retry.
+    if result.status != TransferStatus::Success {
+        return Ok(());
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    // Employees added while the withdrawal was pending keep the payroll open.
+    if payroll.employer != employer_info.key.to_bytes() || !payroll.employees.is_empty() {
+        return Ok(());
+    }
+
+    // Close payroll_info and refund its rent to the employer.
+    //
+    // Note: the payroll token account is not closed. Confidential token accounts can't be closed,
+    // so it stays allocated with its rent, emptied by the withdrawal above, until the employer
+    // initializes the payroll again. Its encrypted balance
+    // isn't revealed either, the withdrawal capped to the vault balance is what leaves it at zero.
+    let payroll_lamports = payroll_info.lamports();
+    **employer_info.try_borrow_mut_lamports()? += payroll_lamports;
+    **payroll_info.try_borrow_mut_lamports()? = 0;
+    payroll_info.try_borrow_mut_data()?.fill(0);
+
+    Ok(())
+}
+
+pub(crate) fn process_update_salary(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+    encrypted_salary: RescueCiphertext,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if encrypted_salary == RescueCiphertext::default() {
+        return Err(PayrollError::SalaryUninitialized.into());
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    let employee_idx = payroll.find_employee(&Pubkey::new_from_array(*employee))?;
+    payroll.employees[employee_idx].encrypted_salary = encrypted_salary;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_employee_frozen(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+    frozen: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    let employee_idx = payroll.find_employee(&Pubkey::new_from_array(*employee))?;
+    payroll.employees[employee_idx].frozen = frozen;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+/// Deactivates or reactivates an employee, keeping their claim history either way.
+pub(crate) fn process_set_employee_active(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+    active: bool,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    let employee_idx = payroll.find_employee(&Pubkey::new_from_array(*employee))?;
+    payroll.employees[employee_idx].active = active;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+/// Caps what the employee can claim per period, resetting the amount claimed this period.
+pub(crate) fn process_set_claim_cap(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+    max_per_period: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // Capped employees can't repay an advance, so an outstanding one has to be settled first.
+    let employee_idx = payroll.find_employee(&Pubkey::new_from_array(*employee))?;
+    if payroll.employees[employee_idx].advance_outstanding {
+        return Err(PayrollError::AdvanceOutstanding.into());
+    }
+    payroll.employees[employee_idx].max_per_period = max_per_period;
+    payroll.employees[employee_idx].claimed_this_period = RescueCiphertext::default();
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_set_advance_interest_rate(
+    accounts: &[AccountInfo],
+    advance_interest_rate_bps: u16,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    payroll.advance_interest_rate_bps = advance_interest_rate_bps;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub const PAY_EMPLOYEE_COMP_DEF_OFFSET: u32 = 7;
+
+pub(crate) fn process_claim_advance(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employee_info = next_account_info(account_info_iter)?;
+    let employee_token_account_info = next_account_info(account_info_iter)?;
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employee_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_employee_token_account(employee_info.key, mint_info, employee_token_account_info)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if mint_info.key.to_bytes() != payroll.mint {
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
+    }
+
+    // Only a single advance can be outstanding at a time.
+    let claim_cooldown_slots = payroll.claim_cooldown_slots;
+    let (auditor, audited) = (payroll.auditor, payroll.is_audited());
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+    if !employee.active {
+        return Err(PayrollError::EmployeeInactive.into());
+    }
+    if employee.frozen {
+        return Err(PayrollError::EmployeeFrozen.into());
+    }
+    if employee.advance_outstanding {
+        return Err(PayrollError::AdvanceOutstanding.into());
+    }
+    // Advances pay a full salary, which would bypass the claim cap.
+    if employee.max_per_period != 0 {
+        return Err(PayrollError::ClaimCapped.into());
+    }
+    let clock = Clock::get()?;
+    if clock.slot < employee.vesting_cliff_slot {
+        msg!("Claims open at slot {}", employee.vesting_cliff_slot);
+        return Err(PayrollError::BeforeVestingCliff.into());
+    }
+    if employee.in_claim_cooldown(clock.slot, claim_cooldown_slots) {
+        msg!("Already claimed within the claim cooldown");
+        return Err(PayrollError::AlreadyClaimed.into());
+    }
+    // The advance is recorded upfront to block a second one, the callback clears it if the
+    // transfer fails.
+    employee.encrypted_advance = employee.encrypted_salary;
+    employee.advance_slot = clock.slot;
+    employee.advance_outstanding = true;
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ArcisPubkey(auditor),
+        Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(employee.encrypted_salary),
+        Argument::EncryptedU64(employee.total_claimed),
+        Argument::PlaintextBool(employee.total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(audited),
+    ];
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    // Transfer the advance from payroll_token_account_info to employee_token_account_info.
+    let advance_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[advance_transfer],
+        &arguments,
+        employee_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::claim_advance_callback(
+            employee_info.key,
+            employer_info.key,
+            payroll_info.key,
+            mint_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        PAY_EMPLOYEE_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_claim_advance_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let employee_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+
+    let transfer_output = transfer_result(transfer_account_info, instructions_sysvar_info);
+    if matches!(&transfer_output, Ok(output) if output.status == TransferStatus::Success) {
+        // The advance counts towards the employee's claimed total.
+        let output_data = transfer_output?
+            .custom_computation_output
+            .ok_or(ProgramError::InvalidAccountData)?;
+        employee.total_claimed = RescueCiphertext::try_from(&output_data[..32])?;
+        record_audit_amount(employee, &output_data[32..])?;
+    } else {
+        // Since the transfer has failed, no advance was paid.
+        employee.encrypted_advance = RescueCiphertext::default();
+        employee.advance_slot = 0;
+        employee.advance_outstanding = false;
+    }
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub const CLAIM_SALARY_REPAYING_ADVANCE_COMP_DEF_OFFSET: u32 = 1;
+
+pub(crate) fn process_claim_salary_repaying_advance(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employee_info = next_account_info(account_info_iter)?;
+    let employee_token_account_info = next_account_info(account_info_iter)?;
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employee_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_employee_token_account(employee_info.key, mint_info, employee_token_account_info)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if mint_info.key.to_bytes() != payroll.mint {
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
+    }
+
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let advance_interest_rate_bps = payroll.advance_interest_rate_bps;
+    let claim_cooldown_slots = payroll.claim_cooldown_slots;
+    let (auditor, audited) = (payroll.auditor, payroll.is_audited());
+    let employee = &mut payroll.employees[employee_idx];
+    if !employee.active {
+        return Err(PayrollError::EmployeeInactive.into());
+    }
+    if employee.frozen {
+        return Err(PayrollError::EmployeeFrozen.into());
+    }
+    if !employee.advance_outstanding {
+        return Err(PayrollError::NoAdvanceOutstanding.into());
+    }
+    if employee.max_per_period != 0 {
+        return Err(PayrollError::ClaimCapped.into());
+    }
+    let clock = Clock::get()?;
+    if employee.in_claim_cooldown(clock.slot, claim_cooldown_slots) {
+        msg!("Already claimed within the claim cooldown");
+        return Err(PayrollError::AlreadyClaimed.into());
+    }
+    employee.previous_claimed_slot = employee.last_claimed_slot;
+    employee.last_claimed_slot = clock.slot;
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ArcisPubkey(auditor),
+        Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(employee.encrypted_salary),
+        Argument::EncryptedU64(employee.encrypted_advance),
+        Argument::PlaintextU64(clock.slot - employee.advance_slot),
+        Argument::PlaintextU16(advance_interest_rate_bps),
+        Argument::EncryptedU64(employee.encrypted_shortfall),
+        Argument::PlaintextBool(employee.shortfall_outstanding),
+        Argument::EncryptedU64(employee.total_claimed),
+        Argument::PlaintextBool(employee.total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(audited),
+    ];
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    // Transfer the salary left after repaying the advance, capped to the vault balance, to the
+    // employee.
+    let salary_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[salary_transfer],
+        &arguments,
+        employee_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::claim_salary_repaying_advance_callback(
+            employee_info.key,
+            payroll_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        CLAIM_SALARY_REPAYING_ADVANCE_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_claim_salary_repaying_advance_callback(
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payroll_info = next_account_info(account_info_iter)?;
+    let employee_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+
+    let transfer_output = transfer_result(transfer_account_info, instructions_sysvar_info);
+    let claim_succeeded =
+        matches!(&transfer_output, Ok(output) if output.status == TransferStatus::Success);
+    employee.last_claim_succeeded = claim_succeeded;
+
+    if claim_succeeded {
+        // Take the remaining advance, the shortfall left by an underfunded vault and the
+        // employee's new claimed total from the computation output.
+        let output_data = transfer_output?
+            .custom_computation_output
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        // Interest on an unsettled remainder accrues from this claim on.
+        employee.encrypted_advance = RescueCiphertext::try_from(&output_data[..32])?;
+        employee.advance_slot = employee.last_claimed_slot;
+        employee.advance_outstanding = !bool::try_from_slice(&output_data[32..33])?;
+        employee.encrypted_shortfall = RescueCiphertext::try_from(&output_data[33..65])?;
+        employee.shortfall_outstanding = !bool::try_from_slice(&output_data[65..66])?;
+        employee.total_claimed = RescueCiphertext::try_from(&output_data[66..98])?;
+        record_audit_amount(employee, &output_data[98..])?;
+    } else {
+        // Since the transfer has failed, nothing was repaid and the employee can claim again.
+        employee.last_claimed_slot = employee.previous_claimed_slot;
+    }
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    PayrollEvent::ClaimOutcome {
+        employee: employee_info.key.to_bytes(),
+        succeeded: claim_succeeded,
+    }
+    .emit()
+}
+
+pub const QUERY_RUNWAY_COMP_DEF_OFFSET: u32 = 2;
+
+pub(crate) fn process_query_runway(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // Arguments for the encrypted computation, the computation covers a full payroll and unused
+    // salary slots are padded and ignored.
+    let num_employees = payroll.employees.len();
+    let mut arguments = Vec::with_capacity(MAX_EMPLOYEES + 2);
+    arguments.push(Argument::ConfidentialTokenAccount(
+        payroll_token_account_info.key.to_bytes(),
+    ));
+    for i in 0..MAX_EMPLOYEES {
+        let encrypted_salary = match payroll.employees.get(i) {
+            Some(employee) => employee.encrypted_salary,
+            None => RescueCiphertext::default(),
+        };
+        arguments.push(Argument::EncryptedU64(encrypted_salary));
+    }
+    arguments.push(Argument::PlaintextU16(num_employees as u16));
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    // No transfers are attached, the computation only reveals the runway.
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[],
+        &arguments,
+        employer_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::query_runway_callback(payroll_info.key, transfer_account_info.key)?
+            .into(),
+        computation_offset,
+        QUERY_RUNWAY_COMP_DEF_OFFSET,
+        transfer_id,
+        &[],
+    )
+}
+
+pub(crate) fn process_query_runway_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payroll_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let runway = u64::try_from_slice(&output_data[..8])?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    payroll.runway = runway;
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    set_return_data(&runway.to_le_bytes());
+
+    Ok(())
+}
+
+pub(crate) fn process_set_slots_per_period(
+    accounts: &[AccountInfo],
+    slots_per_period: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    payroll.slots_per_period = slots_per_period;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+/// Pauses or resumes every salary claim on the payroll.
+pub(crate) fn process_set_paused(accounts: &[AccountInfo], paused: bool) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    payroll.paused = paused;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+/// Sets the x25519 public key of the auditor claims disclose the amount paid to, all zeroes stops
+/// auditing.
+pub(crate) fn process_set_auditor(accounts: &[AccountInfo], auditor: &[u8; 32]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    payroll.auditor = *auditor;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub(crate) fn process_transfer_employer(
+    accounts: &[AccountInfo],
+    new_employer: &[u8; 32],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // The payroll token account's authority is the payroll PDA, so only the employer changes.
+    payroll.employer = *new_employer;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+pub const CLAIM_SALARY_STREAMED_COMP_DEF_OFFSET: u32 = 3;
+
+pub(crate) fn process_claim_salary_streamed(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employee_info = next_account_info(account_info_iter)?;
+    let employee_token_account_info = next_account_info(account_info_iter)?;
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employee_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_employee_token_account(employee_info.key, mint_info, employee_token_account_info)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if mint_info.key.to_bytes() != payroll.mint {
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
+    }
+
+    let slots_per_period = payroll.slots_per_period;
+    if slots_per_period == 0 {
+        return Err(PayrollError::StreamingDisabled.into());
+    }
+
+    let claim_cooldown_slots = payroll.claim_cooldown_slots;
+    let (auditor, audited) = (payroll.auditor, payroll.is_audited());
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+    if !employee.active {
+        return Err(PayrollError::EmployeeInactive.into());
+    }
+    if employee.frozen {
+        return Err(PayrollError::EmployeeFrozen.into());
+    }
+    if employee.advance_outstanding {
+        return Err(PayrollError::AdvanceOutstanding.into());
+    }
+    if employee.max_per_period != 0 {
+        return Err(PayrollError::ClaimCapped.into());
+    }
+    let clock = Clock::get()?;
+    if clock.slot < employee.vesting_cliff_slot {
+        msg!("Claims open at slot {}", employee.vesting_cliff_slot);
+        return Err(PayrollError::BeforeVestingCliff.into());
+    }
+    if employee.in_claim_cooldown(clock.slot, claim_cooldown_slots) {
+        msg!("Already claimed within the claim cooldown");
+        return Err(PayrollError::AlreadyClaimed.into());
+    }
+    employee.previous_claimed_slot = employee.last_claimed_slot;
+    employee.last_claimed_slot = clock.slot;
+
+    // The salary only accrues while the employee is on the payroll.
+    let slots_elapsed = clock
+        .slot
+        .checked_sub(employee.accrual_start_slot())
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ArcisPubkey(auditor),
+        Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(employee.encrypted_salary),
+        Argument::PlaintextU64(slots_elapsed),
+        Argument::PlaintextU64(slots_per_period),
+        Argument::EncryptedU64(employee.encrypted_shortfall),
+        Argument::PlaintextBool(employee.shortfall_outstanding),
+        Argument::EncryptedU64(employee.total_claimed),
+        Argument::PlaintextBool(employee.total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(audited),
+    ];
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    // Transfer the accrued salary and any shortfall, capped to the vault balance, to the employee.
+    let salary_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    // The computation has the same outputs as claim_salary, so the claim is settled the same way.
+    let callback_instruction = crate::instruction::claim_salary_callback(
+        employee_info.key,
+        employee_token_account_info.key,
+        employer_info.key,
+        payroll_info.key,
+        mint_info.key,
+        transfer_account_info.key,
+        None,
+    )?;
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[salary_transfer],
+        &arguments,
+        employee_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        callback_instruction.into(),
+        computation_offset,
+        CLAIM_SALARY_STREAMED_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub const CLAIM_SALARY_CAPPED_COMP_DEF_OFFSET: u32 = 5;
+
+pub(crate) fn process_claim_salary_capped(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employee_info = next_account_info(account_info_iter)?;
+    let employee_token_account_info = next_account_info(account_info_iter)?;
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employee_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    check_employee_token_account(employee_info.key, mint_info, employee_token_account_info)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if mint_info.key.to_bytes() != payroll.mint {
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
+    }
+
+    let clock = Clock::get()?;
+    let (period_start_slot, period_end_slot) = payroll.period_bounds(clock.slot);
+    let claim_cooldown_slots = payroll.claim_cooldown_slots;
+    let (auditor, audited) = (payroll.auditor, payroll.is_audited());
+
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+    if !employee.active {
+        return Err(PayrollError::EmployeeInactive.into());
+    }
+    if employee.frozen {
+        return Err(PayrollError::EmployeeFrozen.into());
+    }
+    if employee.advance_outstanding {
+        return Err(PayrollError::AdvanceOutstanding.into());
+    }
+    if employee.max_per_period == 0 {
+        return Err(PayrollError::ClaimNotCapped.into());
+    }
+    if clock.slot < employee.vesting_cliff_slot {
+        msg!("Claims open at slot {}", employee.vesting_cliff_slot);
+        return Err(PayrollError::BeforeVestingCliff.into());
+    }
+    if employee.in_claim_cooldown(clock.slot, claim_cooldown_slots) {
+        msg!("Already claimed within the claim cooldown");
+        return Err(PayrollError::AlreadyClaimed.into());
+    }
+    employee.previous_claimed_slot = employee.last_claimed_slot;
+    employee.last_claimed_slot = clock.slot;
+
+    // Nothing has been claimed against the cap since it was set, so there is no period to carry
+    // the claimed amount over from.
+    let last_claim_slot = if employee.claimed_this_period == RescueCiphertext::default() {
+        u64::MAX
+    } else {
+        employee.previous_claimed_slot
+    };
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ArcisPubkey(auditor),
+        Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(employee.encrypted_salary),
+        Argument::EncryptedU64(employee.claimed_this_period),
+        Argument::PlaintextU64(employee.max_per_period),
+        Argument::PlaintextU64(last_claim_slot),
+        Argument::PlaintextU64(period_start_slot),
+        Argument::PlaintextU64(period_end_slot),
+        Argument::EncryptedU64(employee.total_claimed),
+        Argument::PlaintextBool(employee.total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(audited),
+    ];
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    // Transfer the salary left under the cap to the employee.
+    let salary_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[salary_transfer],
+        &arguments,
+        employee_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::claim_salary_capped_callback(
+            employee_info.key,
+            payroll_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        CLAIM_SALARY_CAPPED_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_claim_salary_capped_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payroll_info = next_account_info(account_info_iter)?;
+    let employee_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+
+    let transfer_output = transfer_result(transfer_account_info, instructions_sysvar_info);
+    let claim_succeeded =
+        matches!(&transfer_output, Ok(output) if output.status == TransferStatus::Success);
+    employee.last_claim_succeeded = claim_succeeded;
+
+    if claim_succeeded {
+        // Take the amount claimed this period and the employee's new claimed total from the
+        // computation output.
+        let output_data = transfer_output?
+            .custom_computation_output
+            .ok_or(ProgramError::InvalidAccountData)?;
+        employee.claimed_this_period = RescueCiphertext::try_from(&output_data[..32])?;
+        employee.total_claimed = RescueCiphertext::try_from(&output_data[32..64])?;
+        record_audit_amount(employee, &output_data[64..])?;
+    } else {
+        // Since the transfer has failed, nothing counts against the cap and the employee can
+        // claim again.
+        employee.last_claimed_slot = employee.previous_claimed_slot;
+    }
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    PayrollEvent::ClaimOutcome {
+        employee: employee_info.key.to_bytes(),
+        succeeded: claim_succeeded,
+    }
+    .emit()
+}
+
+/// Number of accounts passed per employee to `claim_all_salaries`: their token account, the
+/// transfer account and the computation account.
+const ACCOUNTS_PER_EMPLOYEE: usize = 3;
+
+pub(crate) fn process_claim_all_salaries(
+    accounts: &[AccountInfo],
+    computation_offset_base: u32,
+    transfer_id_base: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+    let employee_infos = account_info_iter.as_slice();
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+    if mint_info.key.to_bytes() != payroll.mint {
+        return Err(PayrollError::MintMismatch.into());
+    }
+
+    if payroll.paused {
+        return Err(PayrollError::PayrollPaused.into());
+    }
+
+    let num_employees = payroll.employees.len();
+    if employee_infos.len() != num_employees * ACCOUNTS_PER_EMPLOYEE {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    // Mark every payable employee as claimed before transferring, employees which can't claim
+    // right now are skipped.
+    let clock = Clock::get()?;
+    let claim_cooldown_slots = payroll.claim_cooldown_slots;
+    let mut payable = vec![false; num_employees];
+    for (i, employee) in payroll.employees.iter_mut().enumerate() {
+        if !employee.active
+            || employee.frozen
+            || employee.advance_outstanding
+            || employee.max_per_period != 0
+            || clock.slot < employee.vesting_cliff_slot
+            || employee.in_claim_cooldown(clock.slot, claim_cooldown_slots)
+        {
+            continue;
+        }
+        employee.previous_claimed_slot = employee.last_claimed_slot;
+        employee.last_claimed_slot = clock.slot;
+        payable[i] = true;
+    }
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    for (i, employee_accounts) in employee_infos.chunks(ACCOUNTS_PER_EMPLOYEE).enumerate() {
+        if !payable[i] {
+            continue;
+        }
+
+        let employee_token_account_info = &employee_accounts[0];
+        let transfer_account_info = &employee_accounts[1];
+        let computation_info = &employee_accounts[2];
+
+        // Salaries can only be paid to the employee's own token account.
+        let employee = Pubkey::new_from_array(payroll.employees[i].key);
+        check_employee_token_account(&employee, mint_info, employee_token_account_info)?;
+
+        let transfer_id = transfer_id_base
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+        let computation_offset = computation_offset_base
+            .checked_add(i as u32)
+            .ok_or(ProgramError::InvalidArgument)?;
+
+        // Each salary is paid like a claim by the employee, through the claim_salary computation
+        // or, while streaming is enabled, the claim_salary_streamed one.
+        let employee_state = &payroll.employees[i];
+        let mut arguments = vec![
+            Argument::ArcisPubkey(payroll.auditor),
+            Argument::PlaintextU128(audit_nonce(clock.slot, transfer_id)),
+            Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+            Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+            Argument::EncryptedU64(employee_state.encrypted_salary),
+        ];
+        let comp_def_offset = if payroll.slots_per_period != 0 {
+            let slots_elapsed = clock
+                .slot
+                .checked_sub(employee_state.accrual_start_slot())
+                .ok_or(ProgramError::ArithmeticOverflow)?;
+            arguments.extend([
+                Argument::PlaintextU64(slots_elapsed),
+                Argument::PlaintextU64(payroll.slots_per_period),
+            ]);
+            CLAIM_SALARY_STREAMED_COMP_DEF_OFFSET
+        } else {
+            CLAIM_SALARY_COMP_DEF_OFFSET
+        };
+        arguments.extend([
+            Argument::EncryptedU64(employee_state.encrypted_shortfall),
+            Argument::PlaintextBool(employee_state.shortfall_outstanding),
+            Argument::EncryptedU64(employee_state.total_claimed),
+            Argument::PlaintextBool(employee_state.total_claimed != RescueCiphertext::default()),
+            Argument::PlaintextBool(payroll.is_audited()),
+        ]);
+
+        // Transfer the salary, capped to the vault balance, to the employee.
+        let salary_transfer = TransferWithComputationInstruction {
+            authority: &Authority::derived(
+                payroll_info,
+                derived_authority_info,
+                confidential_spl_token_authority_info,
+            ),
+            mint_info,
+            source_token_account_info: payroll_token_account_info,
+            source_token_account_adapter_info: payroll_token_account_adapter_info,
+            destination_token_account_info: employee_token_account_info,
+            multisig_signers_infos: &[],
+        };
+
+        check_transfer_account(
+            transfer_account_info,
+            &[*payroll_token_account_info.key],
+            transfer_id,
+        )?;
+
+        confidential_spl_token::invoke::transfer_with_computation(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &crate::ID,
+            &[salary_transfer],
+            &arguments,
+            employer_info,
+            transfer_account_info,
+            mxe_info,
+            computation_info,
+            system_program_info,
+            token_program_info,
+            arcium_program_info,
+            confidential_transfer_adapter_info,
+            crate::instruction::claim_salary_callback(
+                &employee,
+                employee_token_account_info.key,
+                employer_info.key,
+                payroll_info.key,
+                mint_info.key,
+                transfer_account_info.key,
+                None,
+            )?
+            .into(),
+            computation_offset,
+            comp_def_offset,
+            transfer_id,
+            &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+        )?;
+    }
+
+    Ok(())
+}
+
+pub const EMPLOYER_WITHDRAW_COMP_DEF_OFFSET: u32 = 4;
+
+pub(crate) fn process_employer_withdraw(
+    accounts: &[AccountInfo],
+    computation_offset: u32,
+    transfer_id: u32,
+    amount: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let employer_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // Funds can only be withdrawn to the employer.
+    let employer_token_account = get_associated_confidential_token_account_address(
+        employer_info.key,
+        mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if *employer_token_account_info.key != employer_token_account {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Arguments for the encrypted computation.
+    let arguments = [
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employer_token_account_info.key.to_bytes()),
+        Argument::PlaintextU64(amount),
+    ];
+
+    // Transfer amount from payroll_token_account_info to employer_token_account_info.
+    let withdraw_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employer_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[withdraw_transfer],
+        &arguments,
+        employer_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::employer_withdraw_callback(
+            payroll_info.key,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        EMPLOYER_WITHDRAW_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_employer_withdraw_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let _payroll_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // The withdrawal has no state to update, it only settles the transfer.
+    transfer_result(transfer_account_info, instructions_sysvar_info)?;
+
+    Ok(())
+}
+
+pub(crate) fn process_pay_bonus(
+    accounts: &[AccountInfo],
+    employee: &[u8; 32],
+    encrypted_bonus: RescueCiphertext,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let employer_info = next_account_info(account_info_iter)?;
+    let payroll_info = next_account_info(account_info_iter)?;
+    let derived_authority_info = next_account_info(account_info_iter)?;
+    let mint_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_info = next_account_info(account_info_iter)?;
+    let payroll_token_account_adapter_info = next_account_info(account_info_iter)?;
+    let employee_token_account_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let mxe_info = next_account_info(account_info_iter)?;
+    let computation_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let arcium_program_info = next_account_info(account_info_iter)?;
+    let confidential_transfer_adapter_info = next_account_info(account_info_iter)?;
+    let confidential_spl_token_authority_info = next_account_info(account_info_iter)?;
+
+    if !employer_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let (_, bump) = check_payroll(
+        &payroll,
+        payroll_info,
+        mint_info,
+        payroll_token_account_info,
+    )?;
+
+    if payroll.employer != employer_info.key.to_bytes() {
+        return Err(PayrollError::NotEmployer.into());
+    }
+
+    // Bonuses are only paid to employees on the payroll, only their claimed total is updated.
+    let employee = Pubkey::new_from_array(*employee);
+    let employee_idx = payroll.find_employee(&employee)?;
+    check_employee_token_account(&employee, mint_info, employee_token_account_info)?;
+
+    // Arguments for the encrypted computation.
+    let total_claimed = payroll.employees[employee_idx].total_claimed;
+    let arguments = [
+        Argument::ArcisPubkey(payroll.auditor),
+        Argument::PlaintextU128(audit_nonce(Clock::get()?.slot, transfer_id)),
+        Argument::ConfidentialTokenAccount(payroll_token_account_info.key.to_bytes()),
+        Argument::ConfidentialTokenAccount(employee_token_account_info.key.to_bytes()),
+        Argument::EncryptedU64(encrypted_bonus),
+        Argument::EncryptedU64(total_claimed),
+        Argument::PlaintextBool(total_claimed != RescueCiphertext::default()),
+        Argument::PlaintextBool(payroll.is_audited()),
+    ];
+
+    // Transfer the bonus from payroll_token_account_info to employee_token_account_info.
+    let bonus_transfer = TransferWithComputationInstruction {
+        authority: &Authority::derived(
+            payroll_info,
+            derived_authority_info,
+            confidential_spl_token_authority_info,
+        ),
+        mint_info,
+        source_token_account_info: payroll_token_account_info,
+        source_token_account_adapter_info: payroll_token_account_adapter_info,
+        destination_token_account_info: employee_token_account_info,
+        multisig_signers_infos: &[],
+    };
+
+    check_transfer_account(
+        transfer_account_info,
+        &[*payroll_token_account_info.key],
+        transfer_id,
+    )?;
+
+    confidential_spl_token::invoke::transfer_with_computation(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &crate::ID,
+        &[bonus_transfer],
+        &arguments,
+        employer_info,
+        transfer_account_info,
+        mxe_info,
+        computation_info,
+        system_program_info,
+        token_program_info,
+        arcium_program_info,
+        confidential_transfer_adapter_info,
+        crate::instruction::pay_bonus_callback(
+            payroll_info.key,
+            &employee,
+            transfer_account_info.key,
+        )?
+        .into(),
+        computation_offset,
+        PAY_EMPLOYEE_COMP_DEF_OFFSET,
+        transfer_id,
+        &[&[b"payroll", payroll.original_employer.as_ref(), &[bump]]],
+    )
+}
+
+pub(crate) fn process_pay_bonus_callback(accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payroll_info = next_account_info(account_info_iter)?;
+    let employee_info = next_account_info(account_info_iter)?;
+    let transfer_account_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    let result = transfer_result(transfer_account_info, instructions_sysvar_info)?;
+    if result.status != TransferStatus::Success {
+        return Ok(());
+    }
+
+    // The bonus counts towards the employee's claimed total.
+    let output_data = result
+        .custom_computation_output
+        .ok_or(ProgramError::InvalidAccountData)?;
+    let mut payroll = Payroll::try_from_slice(&payroll_info.try_borrow_data()?)?;
+    let employee_idx = payroll.find_employee(employee_info.key)?;
+    let employee = &mut payroll.employees[employee_idx];
+    employee.total_claimed = RescueCiphertext::try_from(&output_data[..32])?;
+    record_audit_amount(employee, &output_data[32..])?;
+
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll.try_to_vec()?);
+
+    Ok(())
+}
+
+/// Nonce the amount paid by a claim is encrypted to the auditor with. Claims settling in the same
+/// slot have distinct transfer ids, so no nonce is used twice.
+fn audit_nonce(slot: u64, transfer_id: u32) -> u128 {
+    ((slot as u128) << 32) | transfer_id as u128
+}
+
+/// Stores the amount paid to `employee`, encrypted to the auditor, from the `audit_output` of a
+/// claim computation: the auditor's key, the output nonce and the ciphertext of the amount.
+fn record_audit_amount(employee: &mut Employee, audit_output: &[u8]) -> ProgramResult {
+    employee.audit_amount_nonce = u128::try_from_slice(&audit_output[32..48])?;
+    employee.encrypted_audit_amount = RescueCiphertext::try_from(&audit_output[48..80])?;
+
+    Ok(())
+}
+
+/// Writes `payroll` into `payroll_info`, growing the account to fit. The rent for the additional
+/// space is paid by the employer.
+fn write_resized_payroll(
+    payroll: &Payroll,
+    payroll_info: &AccountInfo,
+    employer_info: &AccountInfo,
+    system_program_info: &AccountInfo,
+) -> ProgramResult {
+    let payroll_data = payroll.try_to_vec()?;
+
+    let missing_lamports = Rent::get()?
+        .minimum_balance(payroll_data.len())
+        .saturating_sub(payroll_info.lamports());
+    if missing_lamports > 0 {
+        solana_cpi::invoke(
+            &solana_system_interface::instruction::transfer(
+                employer_info.key,
+                payroll_info.key,
+                missing_lamports,
+            ),
+            &[
+                employer_info.clone(),
+                payroll_info.clone(),
+                system_program_info.clone(),
+            ],
+        )?;
+    }
+
+    payroll_info.resize(payroll_data.len())?;
+    payroll_info
+        .try_borrow_mut_data()?
+        .copy_from_slice(&payroll_data);
+
+    Ok(())
+}
+
+/// Checks that `payroll_info` is the PDA of `payroll`, derived from the employer that initialized
+/// it, and `payroll_token_account_info` its token account.
+fn check_payroll(
+    payroll: &Payroll,
+    payroll_info: &AccountInfo,
+    mint_info: &AccountInfo,
+    payroll_token_account_info: &AccountInfo,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let (pda, bump) = payroll_pda(&Pubkey::new_from_array(payroll.original_employer));
+
+    if *payroll_info.key != pda {
+        return Err(PayrollError::PayrollMismatch.into());
+    }
+
+    let ata = get_associated_confidential_token_account_address(
+        payroll_info.key,
+        mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        true,
+    );
+
+    if *payroll_token_account_info.key != ata {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok((pda, bump))
+}
+
+/// Checks that `employee_token_account_info` is the employee's own token account for the payroll
+/// mint, so a salary can't be redirected to another account.
+fn check_employee_token_account(
+    employee: &Pubkey,
+    mint_info: &AccountInfo,
+    employee_token_account_info: &AccountInfo,
+) -> ProgramResult {
+    let employee_token_account = get_associated_confidential_token_account_address(
+        employee,
+        mint_info.key,
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        false,
+    );
+    if *employee_token_account_info.key != employee_token_account {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(())
+}
+
+/// Checks that `transfer_account_info` is the transfer account of a computation whose transfers
+/// come out of `sources`, in order.
+fn check_transfer_account(
+    transfer_account_info: &AccountInfo,
+    sources: &[Pubkey],
+    transfer_id: u32,
+) -> ProgramResult {
+    if *transfer_account_info.key != get_transfer_account_address(sources, transfer_id) {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(())
 }
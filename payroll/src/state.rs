@@ -1,8 +1,9 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use confidential_spl_token::confidential_transfer_adapter::state::RescueCiphertext;
-use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey};
 
-pub const MAX_EMPLOYEES: usize = 8;
+/// Maximum number of employees on a payroll, the payroll account grows with every employee added.
+pub const MAX_EMPLOYEES: usize = 64;
 
 #[derive(BorshSerialize, BorshDeserialize, Default, PartialEq, Copy, Clone)]
 pub struct Employee {
@@ -10,42 +11,124 @@ pub struct Employee {
     pub encrypted_salary: RescueCiphertext,
     pub last_claimed_slot: u64,
     pub previous_claimed_slot: u64,
+    /// Frozen employees stay on the payroll but can't claim their salary.
+    pub frozen: bool,
+    /// Salary claimed ahead of time, including the interest accrued up to `advance_slot`.
+    pub encrypted_advance: RescueCiphertext,
+    pub advance_slot: u64,
+    pub advance_outstanding: bool,
+    /// Most the employee can claim with `claim_salary_capped` per period, `0` disables the cap.
+    pub max_per_period: u64,
+    /// Amount claimed in the period of the last claim, reset once the period rolls over.
+    pub claimed_this_period: RescueCiphertext,
+    /// Slot before which the employee can't claim their salary.
+    pub vesting_cliff_slot: u64,
+    /// Whether the transfer of the employee's last settled claim succeeded.
+    pub last_claim_succeeded: bool,
+    /// Salary `claim_salary` couldn't pay out of an underfunded vault, due on the next claim.
+    pub encrypted_shortfall: RescueCiphertext,
+    pub shortfall_outstanding: bool,
+    /// Slot the employee was added at, streamed salary accrues from it until their first claim.
+    pub start_slot: u64,
+    /// Running total paid out to the employee by successful claims, advances and bonuses.
+    pub total_claimed: RescueCiphertext,
+    /// Inactive employees are soft-disabled instead of removed: they can't claim, but keep their
+    /// slot, claim history and `total_claimed` until reactivated.
+    pub active: bool,
+    /// Amount paid by the employee's last successful claim, encrypted to the payroll's auditor
+    /// with `audit_amount_nonce`.
+    pub encrypted_audit_amount: RescueCiphertext,
+    pub audit_amount_nonce: u128,
+}
+
+impl Employee {
+    /// Whether the employee claimed less than `claim_cooldown_slots` slots before `slot`, employees
+    /// who never claimed aren't cooling down.
+    pub fn in_claim_cooldown(&self, slot: u64, claim_cooldown_slots: u64) -> bool {
+        self.last_claimed_slot != 0
+            && slot < self.last_claimed_slot.saturating_add(claim_cooldown_slots)
+    }
+
+    /// Slot the salary streamed to a claim accrues from: the previous claim, or the employee's
+    /// start slot if they never claimed.
+    pub fn accrual_start_slot(&self) -> u64 {
+        self.previous_claimed_slot.max(self.start_slot)
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Default)]
 pub struct Payroll {
     pub employer: [u8; 32],
     pub mint: [u8; 32],
-    pub num_employees: u8,
-    pub employees: [Employee; MAX_EMPLOYEES],
+    pub employees: Vec<Employee>,
+    /// Interest charged per slot on salary advances.
+    pub advance_interest_rate_bps: u16,
+    /// Number of full payroll runs the vault covered as of the last `query_runway`.
+    pub runway: u64,
+    /// Number of slots a full salary is streamed over by `claim_salary_streamed`, `0` disables
+    /// streaming. Claim caps are tracked over the same periods.
+    pub slots_per_period: u64,
+    /// Paused payrolls reject every salary claim, the employer can still manage employees.
+    pub paused: bool,
+    /// Number of slots an employee has to wait after a claim before claiming again.
+    pub claim_cooldown_slots: u64,
+    /// Employer that initialized the payroll, the payroll PDA stays derived from it after
+    /// `transfer_employer`.
+    pub original_employer: [u8; 32],
+    /// x25519 public key of the auditor the amount paid by every claim is encrypted to, all zeroes
+    /// while the payroll isn't audited.
+    pub auditor: [u8; 32],
 }
 
 impl Payroll {
-    pub fn new(employer: &Pubkey, mint: &Pubkey) -> Self {
+    pub fn new(employer: &Pubkey, mint: &Pubkey, claim_cooldown_slots: u64) -> Self {
         Self {
             employer: employer.to_bytes(),
             mint: mint.to_bytes(),
+            claim_cooldown_slots,
+            original_employer: employer.to_bytes(),
             ..Default::default()
         }
     }
 
+    /// Whether claims disclose the amount paid to the payroll's auditor.
+    pub fn is_audited(&self) -> bool {
+        self.auditor != [0; 32]
+    }
+
     pub fn find_employee(&self, employee: &Pubkey) -> Result<usize, ProgramError> {
         let employee = employee.to_bytes();
 
-        let mut found = false;
-        let mut idx = 0;
-        for i in 0..self.num_employees as usize {
-            if self.employees[i].key == employee {
-                found = true;
-                idx = i;
-                break;
-            }
-        }
+        self.employees
+            .iter()
+            .position(|e| e.key == employee)
+            .ok_or(ProgramError::InvalidAccountData)
+    }
 
-        if !found {
-            return Err(ProgramError::InvalidAccountData);
+    /// First and last (exclusive) slot of the claim cap period containing `slot`, a single period
+    /// spans every slot while `slots_per_period` is unset.
+    pub fn period_bounds(&self, slot: u64) -> (u64, u64) {
+        if self.slots_per_period == 0 {
+            return (0, u64::MAX);
         }
 
-        Ok(idx)
+        let period_start = slot - slot % self.slots_per_period;
+        (period_start, period_start + self.slots_per_period)
     }
+
+    /// Removes employee by moving the last employee into its slot.
+    pub fn remove_employee(&mut self, employee: &Pubkey) -> ProgramResult {
+        let employee_idx = self.find_employee(employee)?;
+        self.employees.swap_remove(employee_idx);
+
+        Ok(())
+    }
+}
+
+/// Proof of payment for a successful salary claim.
+#[derive(BorshSerialize, BorshDeserialize, Default, PartialEq, Debug)]
+pub struct ClaimReceipt {
+    pub slot: u64,
+    pub transfer_account: [u8; 32],
+    pub employee: [u8; 32],
 }
@@ -1,7 +1,37 @@
-use confidential_spl_token::get_associated_confidential_token_account_address;
-use confidential_spl_token_test::{processor, tokio, ConfidentialSPLTokenTest, CustomProgram};
+#[path = "../../lending/tests/common/mod.rs"]
+mod common;
+
+use std::cmp::min;
+
+use borsh::BorshDeserialize;
+use common::{assert_instruction_error, ActorBalances, TokenAccountBalances};
+use confidential_spl_token::{
+    confidential_transfer_adapter::state::RescueCiphertext,
+    get_associated_confidential_token_account_address, get_single_transfer_account_address,
+    get_transfer_account_address,
+};
+use confidential_spl_token_test::{
+    processor, tokio, Actor, ConfidentialSPLTokenTest, CustomProgram,
+};
+use payroll::{
+    addresses::{self, claim_receipt_pda},
+    error::PayrollError,
+    processor::{
+        APPLY_COLA_COMP_DEF_OFFSET, CLAIM_SALARY_CAPPED_COMP_DEF_OFFSET,
+        CLAIM_SALARY_COMP_DEF_OFFSET, CLAIM_SALARY_REPAYING_ADVANCE_COMP_DEF_OFFSET,
+        CLAIM_SALARY_STREAMED_COMP_DEF_OFFSET, EMPLOYER_WITHDRAW_COMP_DEF_OFFSET,
+        PAY_EMPLOYEE_COMP_DEF_OFFSET, QUERY_RUNWAY_COMP_DEF_OFFSET,
+    },
+    state::{ClaimReceipt, Payroll, MAX_EMPLOYEES},
+};
 use solana_pubkey::Pubkey;
-use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    program_error::ProgramError,
+    signature::Keypair,
+    signer::Signer,
+    transaction::Transaction,
+};
 
 #[tokio::test]
 async fn test_payroll() {
@@ -61,15 +91,16 @@ async fn test_payroll() {
     employer.deposit(&mut test, &mint, 1000).await;
     employer.apply_pending_balance(&mut test, &mint).await;
 
-    assert_eq!(0, employer.pending_balance(&mut test, &mint).await.unwrap());
-    assert_eq!(
-        1000,
-        employer.available_balance(&mut test, &mint).await.unwrap()
+    let employer_token_account = employer.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &mint,
     );
+    test.assert_balances(&employer_token_account, &mxe_pubkey, 0, 1000)
+        .await;
 
     // Create payroll account with associated confidetial SPL token account.
     let initialize_instruction =
-        payroll::instruction::initialize(&employer.pubkey(), &mint).unwrap();
+        payroll::instruction::initialize(&employer.pubkey(), &mint, 1).unwrap();
     let initialize_tx = Transaction::new_signed_with_payer(
         &[initialize_instruction],
         Some(&employer.pubkey()),
@@ -91,18 +122,10 @@ async fn test_payroll() {
         .transfer(&mut test, &mint, 1000, &payroll_token_account)
         .await;
 
-    assert_eq!(
-        1000,
-        test.pending_balance(&payroll_token_account, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
-    assert_eq!(
-        0,
-        test.available_balance(&payroll_token_account, &mxe_pubkey)
-            .await
-            .unwrap()
-    );
+    test.assert_balances(&payroll_token_account, &mxe_pubkey, 1000, 0)
+        .await;
+    test.assert_balances(&employer_token_account, &mxe_pubkey, 0, 0)
+        .await;
 
     // Add employee.
     let salary = 100;
@@ -110,8 +133,10 @@ async fn test_payroll() {
     let add_employee_tx = Transaction::new_signed_with_payer(
         &[payroll::instruction::add_employee(
             &employer.pubkey(),
+            &payroll,
             &employee.pubkey(),
             encrypted_salary,
+            0,
         )
         .unwrap()],
         Some(&employer.pubkey()),
@@ -146,26 +171,2566 @@ async fn test_payroll() {
         .unwrap();
 
     // Verify that employee has received the salary.
-    assert_eq!(
-        salary,
-        employee.pending_balance(&mut test, &mint).await.unwrap()
+    let employee_token_account = employee.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &mint,
+    );
+    test.assert_balances(&employee_token_account, &mxe_pubkey, salary, 0)
+        .await;
+
+    // Verify that the program has been deducted the salary amount.
+    test.assert_balances(&payroll_token_account, &mxe_pubkey, 0, 1000 - salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_apply_cola_adjustment() {
+    let mut ctx = setup_payroll(1000).await;
+
+    // Add two employees.
+    let salaries = [100, 200];
+    let mut employees = Vec::new();
+    for salary in salaries {
+        employees.push(add_employee(&mut ctx, salary).await);
+    }
+
+    // Apply a 3% raise to both employees.
+    let factor_bps = 10_300;
+    let cola_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::apply_cola_adjustment(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            factor_bps,
+            0,
+            salaries.len() as u8,
+            1,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test.process_transaction(cola_tx, false).await.unwrap();
+
+    // Check that the stored salaries have been rescaled.
+    let payroll = ctx.payroll().await;
+    for (i, salary) in salaries.iter().enumerate() {
+        assert_eq!(
+            salary * factor_bps as u64 / 10_000,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(payroll.employees[i].encrypted_salary)
+        );
+    }
+
+    // Each employee claims the rescaled salary.
+    for (i, (employee, salary)) in employees.iter().zip(salaries).enumerate() {
+        let claim_salary_tx = claim_salary_tx(&ctx, employee, 2 + i as u32, 1 + i as u32);
+        ctx.test
+            .process_transaction(claim_salary_tx, false)
+            .await
+            .unwrap();
+
+        employee
+            .assert_pending_balance(
+                &mut ctx.test,
+                &ctx.mint,
+                salary * factor_bps as u64 / 10_000,
+            )
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn test_apply_cola_adjustment_keeps_updated_salary() {
+    let mut ctx = setup_payroll(1000).await;
+    let salary = 100;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    // The salary is updated while the adjustment is pending, the rescaled old salary is dropped.
+    let new_salary = 250;
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(new_salary);
+    let cola_tx = Transaction::new_signed_with_payer(
+        &[
+            payroll::instruction::apply_cola_adjustment(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &ctx.mint,
+                10_300,
+                0,
+                1,
+                1,
+                0,
+            )
+            .unwrap(),
+            payroll::instruction::update_salary(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &employee.pubkey(),
+                encrypted_salary,
+            )
+            .unwrap(),
+        ],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
     );
+    ctx.test.process_transaction(cola_tx, false).await.unwrap();
+
     assert_eq!(
+        new_salary,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.payroll().await.employees[0].encrypted_salary)
+    );
+}
+
+#[tokio::test]
+async fn test_close_payroll() {
+    let funding = 1000;
+    let mut ctx = setup_payroll(funding).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    let close_payroll_tx = |ctx: &PayrollTest| {
+        Transaction::new_signed_with_payer(
+            &[payroll::instruction::close_payroll(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &ctx.mint,
+                1,
+                0,
+            )
+            .unwrap()],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        )
+    };
+
+    // The payroll can't be closed while it has employees.
+    let err = ctx
+        .test
+        .process_transaction(close_payroll_tx(&ctx), false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
         0,
-        employee.available_balance(&mut test, &mint).await.unwrap()
+        InstructionError::Custom(PayrollError::EmployeesRemaining as u32),
     );
 
-    // Verify that the program has been deducted the salary amount.
+    let remove_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::remove_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(remove_employee_tx, false)
+        .await
+        .unwrap();
+
+    // Once all employees are removed, the payroll is closed and its funds return to the employer.
+    ctx.test
+        .process_transaction(close_payroll_tx(&ctx), false)
+        .await
+        .unwrap();
+    assert!(ctx.test.get_account(&ctx.payroll).await.is_none());
+
+    // The emptied payroll token account can't be closed and stays allocated.
+    let payroll_token_account = ctx.payroll_token_account;
+    assert!(ctx.test.get_account(&payroll_token_account).await.is_some());
+    ctx.test
+        .assert_total_balance(&payroll_token_account, &ctx.mxe_pubkey, 0)
+        .await;
+    let employer_token_account = ctx.employer.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
+    );
+    ctx.test
+        .assert_total_balance(&employer_token_account, &ctx.mxe_pubkey, funding)
+        .await;
+
+    // The employer isn't locked out, initializing the payroll again takes over the leftover
+    // payroll token account.
+    let initialize_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::initialize(&ctx.employer.pubkey(), &ctx.mint, 1).unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(initialize_tx, true)
+        .await
+        .unwrap();
+    assert!(ctx.payroll().await.employees.is_empty());
+
+    // The reused token account funds the new payroll.
+    ctx.employer
+        .apply_pending_balance(&mut ctx.test, &ctx.mint)
+        .await;
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, funding, &payroll_token_account)
+        .await;
+    ctx.test
+        .assert_total_balance(&payroll_token_account, &ctx.mxe_pubkey, funding)
+        .await;
+}
+
+#[tokio::test]
+async fn test_update_salary() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    // Claim at the old salary.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+
+    // Employer gives the employee a raise.
+    let new_salary = 250;
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(new_salary);
+    let update_salary_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::update_salary(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            encrypted_salary,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(update_salary_tx, false)
+        .await
+        .unwrap();
     assert_eq!(
-        1000 - salary,
-        test.available_balance(&payroll_token_account, &mxe_pubkey)
-            .await
+        new_salary,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
             .unwrap()
+            .rescue_decrypt(ctx.payroll().await.employees[0].encrypted_salary)
+    );
+
+    // Claim at the new salary.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100 + new_salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_salary_receipt() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    // Claim the salary and request a receipt.
+    let transfer_id = 0;
+    let claim_salary_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::claim_salary_with_receipt(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            transfer_id,
+        )
+        .unwrap()],
+        Some(&employee.pubkey()),
+        &[&employee.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
     );
+    ctx.test
+        .process_transaction(claim_salary_tx, false)
+        .await
+        .unwrap();
+
+    // The receipt matches the claim.
+    let (claim_receipt, _) = claim_receipt_pda(&ctx.payroll, &employee.pubkey(), transfer_id);
+    let receipt =
+        ClaimReceipt::try_from_slice(&ctx.test.get_account(&claim_receipt).await.unwrap().data)
+            .unwrap();
+    let payroll = ctx.payroll().await;
     assert_eq!(
+        ClaimReceipt {
+            slot: payroll.employees[0].last_claimed_slot,
+            transfer_account: get_transfer_account_address(
+                &[ctx.payroll_token_account],
+                transfer_id
+            )
+            .to_bytes(),
+            employee: employee.pubkey().to_bytes(),
+        },
+        receipt
+    );
+    assert_ne!(0, receipt.slot);
+}
+
+#[tokio::test]
+async fn test_frozen_employee_claim() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    // Employer freezes the employee.
+    let freeze_tx = set_employee_frozen_tx(&ctx, &employee, true);
+    ctx.test
+        .process_transaction(freeze_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.payroll().await.employees[0].frozen);
+
+    // The frozen employee can't claim.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
         0,
-        test.pending_balance(&payroll_token_account, &mxe_pubkey)
-            .await
+        InstructionError::Custom(PayrollError::EmployeeFrozen as u32),
+    );
+    assert_eq!(0, ctx.payroll().await.employees[0].last_claimed_slot);
+
+    // Once unfrozen, the claim goes through.
+    let unfreeze_tx = set_employee_frozen_tx(&ctx, &employee, false);
+    ctx.test
+        .process_transaction(unfreeze_tx, false)
+        .await
+        .unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+}
+
+#[tokio::test]
+async fn test_inactive_employee_claim() {
+    let salary = 100;
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, salary).await;
+    assert!(ctx.payroll().await.employees[0].active);
+
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    let claimed_state = ctx.payroll().await.employees[0];
+
+    // Employer deactivates the employee, their claim history is kept.
+    let set_employee_active_tx = |ctx: &PayrollTest, active: bool| {
+        Transaction::new_signed_with_payer(
+            &[payroll::instruction::set_employee_active(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &employee.pubkey(),
+                active,
+            )
+            .unwrap()],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        )
+    };
+    let deactivate_tx = set_employee_active_tx(&ctx, false);
+    ctx.test
+        .process_transaction(deactivate_tx, false)
+        .await
+        .unwrap();
+    let employee_state = ctx.payroll().await.employees[0];
+    assert!(!employee_state.active);
+    assert_eq!(
+        claimed_state.last_claimed_slot,
+        employee_state.last_claimed_slot
+    );
+    assert!(claimed_state.total_claimed == employee_state.total_claimed);
+
+    // The inactive employee can't claim.
+    ctx.test
+        .warp_to_slot(claimed_state.last_claimed_slot + 1)
+        .unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::EmployeeInactive as u32),
+    );
+
+    // Once reactivated, the claim goes through and adds to the kept total.
+    let reactivate_tx = set_employee_active_tx(&ctx, true);
+    ctx.test
+        .process_transaction(reactivate_tx, false)
+        .await
+        .unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 2 * salary)
+        .await;
+    assert_eq!(
+        2 * salary,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
             .unwrap()
+            .rescue_decrypt(ctx.payroll().await.employees[0].total_claimed)
+    );
+}
+
+#[tokio::test]
+async fn test_claim_salary_foreign_token_account() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+    let other_employee = add_employee(&mut ctx, 200).await;
+
+    // The employee can't have its salary paid into another employee's token account.
+    let claim_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_salary(
+            &employee.pubkey(),
+            &other_employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidAccountData);
+    assert_eq!(0, ctx.payroll().await.employees[0].last_claimed_slot);
+    other_employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 0)
+        .await;
+
+    // Its own token account is paid as usual.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_salary_mint_mismatch() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    // The salary is only paid in the payroll's mint.
+    let other_mint = Pubkey::new_unique();
+    let claim_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_salary(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &other_mint,
+            ),
+            &ctx.employer.pubkey(),
+            &other_mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::MintMismatch as u32),
+    );
+    assert_eq!(0, ctx.payroll().await.employees[0].last_claimed_slot);
+}
+
+#[tokio::test]
+async fn test_claim_salary_mismatched_transfer_account() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    // The claim is paid through a computation, a transfer account derived for a single transfer
+    // is rejected.
+    let mut claim_salary_ix = payroll::instruction::claim_salary(
+        &employee.pubkey(),
+        &employee.ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.mint,
+        ),
+        &ctx.employer.pubkey(),
+        &ctx.mint,
+        1,
+        0,
+    )
+    .unwrap();
+    let transfer_account = get_transfer_account_address(&[ctx.payroll_token_account], 0);
+    claim_salary_ix
+        .accounts
+        .iter_mut()
+        .find(|meta| meta.pubkey == transfer_account)
+        .unwrap()
+        .pubkey = get_single_transfer_account_address(&ctx.payroll_token_account, 0);
+    let claim_tx = employee_tx(&ctx, &employee, claim_salary_ix);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidSeeds);
+    assert_eq!(0, ctx.payroll().await.employees[0].last_claimed_slot);
+
+    // The transfer account derived by the builder goes through.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_all_salaries() {
+    let mut ctx = setup_payroll(1000).await;
+
+    let salaries = [100, 200, 300];
+    let mut employees = Vec::new();
+    for salary in salaries {
+        employees.push(add_employee(&mut ctx, salary).await);
+    }
+
+    // The second employee claims by themselves in the same slot as the payroll run, they are
+    // skipped by it.
+    let claim_salary_ix = payroll::instruction::claim_salary(
+        &employees[1].pubkey(),
+        &employees[1].ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.mint,
+        ),
+        &ctx.employer.pubkey(),
+        &ctx.mint,
+        1,
+        0,
+    )
+    .unwrap();
+    let claim_all_salaries_ix = payroll::instruction::claim_all_salaries(
+        &ctx.employer.pubkey(),
+        &ctx.payroll,
+        &ctx.mint,
+        &employees
+            .iter()
+            .map(|employee| employee.pubkey())
+            .collect::<Vec<_>>(),
+        2,
+        1,
+    )
+    .unwrap();
+    let claim_all_salaries_tx = Transaction::new_signed_with_payer(
+        &[claim_salary_ix, claim_all_salaries_ix],
+        Some(&ctx.employer.pubkey()),
+        &[
+            &ctx.employer.signer_keypair(),
+            &employees[1].signer_keypair(),
+        ],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(claim_all_salaries_tx, false)
+        .await
+        .unwrap();
+
+    // Every employee has been paid exactly once.
+    for (employee, salary) in employees.iter().zip(salaries) {
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, salary)
+            .await;
+    }
+    let payroll_token_account = ctx.payroll_token_account;
+    ctx.test
+        .assert_total_balance(
+            &payroll_token_account,
+            &ctx.mxe_pubkey,
+            1000 - salaries.iter().sum::<u64>(),
+        )
+        .await;
+
+    // Transfer ids past u32::MAX are rejected instead of wrapping around.
+    let employee_keys = employees
+        .iter()
+        .map(|employee| employee.pubkey())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ProgramError::InvalidArgument,
+        payroll::instruction::claim_all_salaries(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            &employee_keys,
+            3,
+            u32::MAX,
+        )
+        .unwrap_err()
+    );
+}
+
+#[tokio::test]
+async fn test_claim_all_salaries_underfunded() {
+    let funding = 250;
+    let mut ctx = setup_payroll(funding).await;
+
+    let salaries = [100, 200];
+    let mut employees = Vec::new();
+    for salary in salaries {
+        employees.push(add_employee(&mut ctx, salary).await);
+    }
+
+    let claim_all_salaries_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::claim_all_salaries(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            &employees
+                .iter()
+                .map(|employee| employee.pubkey())
+                .collect::<Vec<_>>(),
+            1,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(claim_all_salaries_tx, false)
+        .await
+        .unwrap();
+
+    // The first salary is paid in full, the second one gets what is left in the vault and the
+    // rest is recorded as a shortfall.
+    let payouts = [salaries[0], funding - salaries[0]];
+    for (employee, payout) in employees.iter().zip(payouts) {
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, payout)
+            .await;
+    }
+    let payroll = ctx.payroll().await;
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    for (i, payout) in payouts.into_iter().enumerate() {
+        assert!(payroll.employees[i].last_claim_succeeded);
+        assert_eq!(
+            payout,
+            mxe.rescue_decrypt(payroll.employees[i].total_claimed)
+        );
+    }
+    assert!(!payroll.employees[0].shortfall_outstanding);
+    assert!(payroll.employees[1].shortfall_outstanding);
+    assert_eq!(
+        salaries[1] - payouts[1],
+        mxe.rescue_decrypt(payroll.employees[1].encrypted_shortfall)
+    );
+}
+
+#[tokio::test]
+async fn test_claim_all_salaries_streamed() {
+    let mut ctx = setup_payroll(1000).await;
+
+    let salaries = [100, 200];
+    let mut employees = Vec::new();
+    for salary in salaries {
+        employees.push(add_employee(&mut ctx, salary).await);
+    }
+
+    let slots_per_period = 10_000;
+    let set_slots_per_period_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_slots_per_period(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            slots_per_period,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_slots_per_period_tx, false)
+        .await
+        .unwrap();
+
+    // While streaming is enabled, the payroll run pays the salary accrued since the start slot
+    // instead of a full salary.
+    let slot = 5_000;
+    let start_slots = ctx
+        .payroll()
+        .await
+        .employees
+        .iter()
+        .map(|employee| employee.start_slot)
+        .collect::<Vec<_>>();
+    ctx.test.warp_to_slot(slot).unwrap();
+    let claim_all_salaries_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::claim_all_salaries(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            &employees
+                .iter()
+                .map(|employee| employee.pubkey())
+                .collect::<Vec<_>>(),
+            1,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(claim_all_salaries_tx, false)
+        .await
+        .unwrap();
+
+    for ((employee, salary), start_slot) in employees.iter().zip(salaries).zip(start_slots) {
+        let claimed = salary * (slot - start_slot) / slots_per_period;
+        assert!(claimed < salary);
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, claimed)
+            .await;
+    }
+}
+
+#[tokio::test]
+async fn test_employer_withdraw() {
+    let funding = 1000;
+    let mut ctx = setup_payroll(funding).await;
+
+    let payroll_token_account = ctx.payroll_token_account;
+    let employer_token_account = ctx.employer.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
     );
+
+    // Withdrawing more than the payroll holds only withdraws what is left.
+    let mut withdrawn = 0;
+    for (computation_offset, transfer_id, amount) in [(1, 0, 400), (2, 1, funding)] {
+        let withdraw_tx = Transaction::new_signed_with_payer(
+            &[payroll::instruction::employer_withdraw(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &ctx.mint,
+                computation_offset,
+                transfer_id,
+                amount,
+            )
+            .unwrap()],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        );
+        ctx.test
+            .process_transaction(withdraw_tx, false)
+            .await
+            .unwrap();
+        withdrawn += min(amount, funding - withdrawn);
+
+        ctx.test
+            .assert_total_balance(&payroll_token_account, &ctx.mxe_pubkey, funding - withdrawn)
+            .await;
+        ctx.test
+            .assert_total_balance(&employer_token_account, &ctx.mxe_pubkey, withdrawn)
+            .await;
+    }
+    assert_eq!(funding, withdrawn);
+}
+
+#[tokio::test]
+async fn test_claim_salary_streamed() {
+    let funding = 1000;
+    let salary = 100;
+    let mut ctx = setup_payroll(funding).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let claim_streamed_tx = |ctx: &PayrollTest, computation_offset: u32, transfer_id: u32| {
+        employee_tx(
+            ctx,
+            &employee,
+            payroll::instruction::claim_salary_streamed(
+                &employee.pubkey(),
+                &employee.ata(
+                    &confidential_spl_token::programs::confidential_spl_token::ID,
+                    &ctx.mint,
+                ),
+                &ctx.employer.pubkey(),
+                &ctx.mint,
+                computation_offset,
+                transfer_id,
+            )
+            .unwrap(),
+        )
+    };
+
+    // Streaming is disabled by default.
+    let claim_tx = claim_streamed_tx(&ctx, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::StreamingDisabled as u32),
+    );
+
+    // A full salary is streamed over 10_000 slots.
+    let slots_per_period = 10_000;
+    let set_slots_per_period_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_slots_per_period(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            slots_per_period,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_slots_per_period_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(slots_per_period, ctx.payroll().await.slots_per_period);
+
+    // A full salary can't be claimed on top of the streamed one.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::StreamingEnabled as u32),
+    );
+
+    // Each claim pays the salary accrued since the previous one, the first claim at slot 1_000
+    // is paid from the employee's start slot and the second one at slot 2_500 for the 1_500 slots
+    // since the first one.
+    let mut received = 0;
+    for (slot, computation_offset, transfer_id) in [(1_000, 1, 0), (2_500, 2, 1)] {
+        let employee_state = ctx.payroll().await.employees[0];
+        let previous_claimed_slot = employee_state.last_claimed_slot;
+        let accrued_from = previous_claimed_slot.max(employee_state.start_slot);
+        ctx.test.warp_to_slot(slot).unwrap();
+        let claim_tx = claim_streamed_tx(&ctx, computation_offset, transfer_id);
+        ctx.test.process_transaction(claim_tx, false).await.unwrap();
+
+        let employee_state = ctx.payroll().await.employees[0];
+        assert_eq!(previous_claimed_slot, employee_state.previous_claimed_slot);
+        assert_eq!(slot, employee_state.last_claimed_slot);
+        let claimed = salary * (slot - accrued_from) / slots_per_period;
+        assert_ne!(0, claimed);
+        received += claimed;
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, received)
+            .await;
+    }
+
+    // The payout is clamped to the payroll balance.
+    ctx.test.warp_to_slot(1_000_000).unwrap();
+    let claim_tx = claim_streamed_tx(&ctx, 3, 2);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, funding)
+        .await;
+    let payroll_token_account = ctx.payroll_token_account;
+    ctx.test
+        .assert_balances(&payroll_token_account, &ctx.mxe_pubkey, 0, 0)
+        .await;
+
+    // The unpaid salary is recorded as a shortfall, and the claimed total adds up every payout.
+    let employee_state = ctx.payroll().await.employees[0];
+    assert!(employee_state.shortfall_outstanding);
+    assert_eq!(
+        funding,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(employee_state.total_claimed)
+    );
+}
+
+#[tokio::test]
+async fn test_claim_salary_streamed_first_claim_after_hire() {
+    let salary = 100;
+    let mut ctx = setup_payroll(1000).await;
+
+    let slots_per_period = 10_000;
+    let set_slots_per_period_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_slots_per_period(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            slots_per_period,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_slots_per_period_tx, false)
+        .await
+        .unwrap();
+
+    // The employee is hired long after genesis.
+    let start_slot = 50_000;
+    ctx.test.warp_to_slot(start_slot).unwrap();
+    let employee = add_employee(&mut ctx, salary).await;
+    assert_eq!(start_slot, ctx.payroll().await.employees[0].start_slot);
+
+    // The first claim is only paid for the slots since the hire, not since genesis.
+    ctx.test.warp_to_slot(start_slot + 5_000).unwrap();
+    let claim_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_salary_streamed(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, salary / 2)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_salary_capped() {
+    let salary = 100;
+    let max_per_period = 150;
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let claim_capped_tx = |ctx: &PayrollTest, computation_offset: u32, transfer_id: u32| {
+        employee_tx(
+            ctx,
+            &employee,
+            payroll::instruction::claim_salary_capped(
+                &employee.pubkey(),
+                &employee.ata(
+                    &confidential_spl_token::programs::confidential_spl_token::ID,
+                    &ctx.mint,
+                ),
+                &ctx.employer.pubkey(),
+                &ctx.mint,
+                computation_offset,
+                transfer_id,
+            )
+            .unwrap(),
+        )
+    };
+
+    // Claims aren't capped by default.
+    let claim_tx = claim_capped_tx(&ctx, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::ClaimNotCapped as u32),
+    );
+
+    // Cap the claims over periods of 10_000 slots.
+    let set_claim_cap_tx = Transaction::new_signed_with_payer(
+        &[
+            payroll::instruction::set_slots_per_period(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                10_000,
+            )
+            .unwrap(),
+            payroll::instruction::set_claim_cap(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &employee.pubkey(),
+                max_per_period,
+            )
+            .unwrap(),
+        ],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_claim_cap_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        max_per_period,
+        ctx.payroll().await.employees[0].max_per_period
+    );
+
+    // Capped employees can't bypass the cap with a regular claim.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::ClaimCapped as u32),
+    );
+
+    // Nor with an advance.
+    let claim_advance_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_advance(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(claim_advance_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::ClaimCapped as u32),
+    );
+
+    // The first claim of the period is paid in full, the second is clamped to the remaining cap,
+    // and a claim in the next period is paid in full again.
+    let mut received = 0;
+    for (slot, computation_offset, transfer_id, payout, claimed) in [
+        (1_000, 1, 0, salary, salary),
+        (2_000, 2, 1, max_per_period - salary, max_per_period),
+        (12_000, 3, 2, salary, salary),
+    ] {
+        ctx.test.warp_to_slot(slot).unwrap();
+        let claim_tx = claim_capped_tx(&ctx, computation_offset, transfer_id);
+        ctx.test.process_transaction(claim_tx, false).await.unwrap();
+
+        received += payout;
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, received)
+            .await;
+        let employee_state = ctx.payroll().await.employees[0];
+        let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+        assert_eq!(
+            claimed,
+            mxe.rescue_decrypt(employee_state.claimed_this_period)
+        );
+        assert_eq!(received, mxe.rescue_decrypt(employee_state.total_claimed));
+    }
+}
+
+#[tokio::test]
+async fn test_pay_bonus() {
+    let salary = 100;
+    let bonus = 250;
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let encrypted_bonus = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(bonus);
+    let pay_bonus_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::pay_bonus(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            &ctx.mint,
+            encrypted_bonus,
+            1,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(pay_bonus_tx, false)
+        .await
+        .unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, bonus)
+        .await;
+
+    // The bonus doesn't count as a claim, the salary can still be claimed in the same slot. It is
+    // part of the claimed total though.
+    let payroll = ctx.payroll().await;
+    assert_eq!(0, payroll.employees[0].last_claimed_slot);
+    assert_eq!(0, payroll.employees[0].previous_claimed_slot);
+    assert_eq!(
+        bonus,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(payroll.employees[0].total_claimed)
+    );
+
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, bonus + salary)
+        .await;
+    assert_eq!(
+        bonus + salary,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(ctx.payroll().await.employees[0].total_claimed)
+    );
+}
+
+#[tokio::test]
+async fn test_transfer_employer() {
+    let mut ctx = setup_payroll(1000).await;
+    let new_employer = ctx.test.new_actor().await;
+
+    let transfer_employer_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::transfer_employer(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &new_employer.pubkey(),
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(transfer_employer_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        new_employer.pubkey().to_bytes(),
+        ctx.payroll().await.employer
+    );
+
+    // The old employer can no longer add employees.
+    let employee = Pubkey::new_unique();
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(100);
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::add_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee,
+            encrypted_salary,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::NotEmployer as u32),
+    );
+
+    // The new employer can, on the payroll at its original address.
+    let add_employee_ix = payroll::instruction::add_employee(
+        &new_employer.pubkey(),
+        &ctx.payroll,
+        &employee,
+        encrypted_salary,
+        0,
+    )
+    .unwrap();
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[add_employee_ix],
+        Some(&new_employer.pubkey()),
+        &[&new_employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(employee.to_bytes(), ctx.payroll().await.employees[0].key);
+
+    // The new employer can withdraw from the payroll token account.
+    new_employer
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.mint,
+        )
+        .await;
+    let new_employer_token_account = new_employer.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
+    );
+    let withdraw_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::employer_withdraw(
+            &new_employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            1,
+            0,
+            400,
+        )
+        .unwrap()],
+        Some(&new_employer.pubkey()),
+        &[&new_employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(withdraw_tx, false)
+        .await
+        .unwrap();
+    ctx.test
+        .assert_total_balance(&new_employer_token_account, &ctx.mxe_pubkey, 400)
+        .await;
+
+    // And close the payroll once its employees are removed, receiving the remaining funds.
+    let close_payroll_tx = Transaction::new_signed_with_payer(
+        &[
+            payroll::instruction::remove_employee(&new_employer.pubkey(), &ctx.payroll, &employee)
+                .unwrap(),
+            payroll::instruction::close_payroll(
+                &new_employer.pubkey(),
+                &ctx.payroll,
+                &ctx.mint,
+                2,
+                1,
+            )
+            .unwrap(),
+        ],
+        Some(&new_employer.pubkey()),
+        &[&new_employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(close_payroll_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.test.get_account(&ctx.payroll).await.is_none());
+    ctx.test
+        .assert_total_balance(&new_employer_token_account, &ctx.mxe_pubkey, 1000)
+        .await;
+}
+
+#[tokio::test]
+async fn test_vesting_cliff() {
+    let salary = 100;
+    let vesting_cliff_slot = 5_000;
+    let mut ctx = setup_payroll(1000).await;
+
+    let employee = ctx.test.new_actor().await;
+    employee
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.mint,
+        )
+        .await;
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(salary);
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::add_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            encrypted_salary,
+            vesting_cliff_slot,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap();
+    assert_eq!(
+        vesting_cliff_slot,
+        ctx.payroll().await.employees[0].vesting_cliff_slot
+    );
+
+    // Claims are rejected before the cliff.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::BeforeVestingCliff as u32),
+    );
+
+    // Once past the cliff, the employee can claim.
+    ctx.test.warp_to_slot(vesting_cliff_slot).unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_pause_payroll() {
+    let salary = 100;
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let set_paused_tx = |ctx: &PayrollTest, paused: bool| {
+        Transaction::new_signed_with_payer(
+            &[
+                payroll::instruction::set_paused(&ctx.employer.pubkey(), &ctx.payroll, paused)
+                    .unwrap(),
+            ],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        )
+    };
+
+    let pause_tx = set_paused_tx(&ctx, true);
+    ctx.test.process_transaction(pause_tx, false).await.unwrap();
+    assert!(ctx.payroll().await.paused);
+
+    // Claims are rejected while paused.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::PayrollPaused as u32),
+    );
+
+    // The employer can still manage employees.
+    add_employee(&mut ctx, salary).await;
+    assert_eq!(2, ctx.payroll().await.employees.len());
+
+    let resume_tx = set_paused_tx(&ctx, false);
+    ctx.test
+        .process_transaction(resume_tx, false)
+        .await
+        .unwrap();
+    assert!(!ctx.payroll().await.paused);
+
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_audited() {
+    // The payroll covers a salary and a half.
+    let salary = 100;
+    let mut ctx = setup_payroll(150).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let auditor = ctx.test.new_actor().await;
+    let set_auditor_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_auditor(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &auditor.x25519_pubkey(),
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_auditor_tx, false)
+        .await
+        .unwrap();
+    assert!(ctx.payroll().await.is_audited());
+
+    // The auditor can read the salary paid by the claim.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    assert_eq!(salary, ctx.audited_amount(&auditor, 0).await);
+
+    // An underfunded claim discloses what was actually paid.
+    let claimed_slot = ctx.payroll().await.employees[0].last_claimed_slot;
+    ctx.test.warp_to_slot(claimed_slot + 1).unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 150)
+        .await;
+    assert_eq!(50, ctx.audited_amount(&auditor, 0).await);
+}
+
+#[tokio::test]
+async fn test_claim_cooldown() {
+    let salary = 100;
+    let claim_cooldown_slots = 50;
+    let mut ctx = setup_payroll_with_claim_cooldown(1000, claim_cooldown_slots).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    let claimed_slot = ctx.payroll().await.employees[0].last_claimed_slot;
+
+    // Claiming again before the cooldown passed fails.
+    ctx.test
+        .warp_to_slot(claimed_slot + claim_cooldown_slots - 1)
+        .unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::AlreadyClaimed as u32),
+    );
+    assert_eq!(
+        claimed_slot,
+        ctx.payroll().await.employees[0].last_claimed_slot
+    );
+
+    // The cooldown can't be bypassed with an advance either.
+    let claim_advance_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_advance(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            2,
+            1,
+        )
+        .unwrap(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(claim_advance_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::AlreadyClaimed as u32),
+    );
+    assert!(!ctx.payroll().await.employees[0].advance_outstanding);
+
+    // Once the cooldown passed, the employee can claim again.
+    ctx.test
+        .warp_to_slot(claimed_slot + claim_cooldown_slots)
+        .unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    assert_eq!(
+        claimed_slot + claim_cooldown_slots,
+        ctx.payroll().await.employees[0].last_claimed_slot
+    );
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 2 * salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_outcome_recorded() {
+    // The payroll covers a single salary.
+    let salary = 100;
+    let mut ctx = setup_payroll(150).await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    let payroll = ctx.payroll().await;
+    assert!(payroll.employees[0].last_claim_succeeded);
+    assert_eq!(
+        salary,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(payroll.employees[0].total_claimed)
+    );
+
+    // The second claim can't be fully covered, it pays what the vault holds and still succeeds.
+    let claimed_slot = payroll.employees[0].last_claimed_slot;
+    ctx.test.warp_to_slot(claimed_slot + 1).unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    let payroll = ctx.payroll().await;
+    assert!(payroll.employees[0].last_claim_succeeded);
+    assert!(payroll.employees[0].last_claimed_slot > claimed_slot);
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 150)
+        .await;
+
+    // The claimed total adds up what was actually paid out.
+    assert_eq!(
+        150,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(payroll.employees[0].total_claimed)
+    );
+}
+
+#[tokio::test]
+async fn test_claim_salary_underfunded() {
+    // The employer keeps part of the funding to top up the payroll later.
+    let salary = 700;
+    let mut ctx = setup_employer(1400).await;
+    let initialize_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::initialize(&ctx.employer.pubkey(), &ctx.mint, 1).unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(initialize_tx, true)
+        .await
+        .unwrap();
+    let payroll_token_account = ctx.payroll_token_account;
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, 500, &payroll_token_account)
+        .await;
+    let employee = add_employee(&mut ctx, salary).await;
+
+    // The vault can't cover the salary, the employee is paid what it holds.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 500)
+        .await;
+    ctx.test
+        .assert_total_balance(&payroll_token_account, &ctx.mxe_pubkey, 0)
+        .await;
+
+    // The partial claim counts, and the shortfall is recorded.
+    let payroll = ctx.payroll().await;
+    let claimed_slot = payroll.employees[0].last_claimed_slot;
+    assert_ne!(0, claimed_slot);
+    assert!(payroll.employees[0].last_claim_succeeded);
+    assert!(payroll.employees[0].shortfall_outstanding);
+    assert_eq!(
+        salary - 500,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(payroll.employees[0].encrypted_shortfall)
+    );
+
+    // Once the payroll is topped up, the next claim pays the shortfall on top of the salary.
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, 900, &payroll_token_account)
+        .await;
+    ctx.test.warp_to_slot(claimed_slot + 1).unwrap();
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 2 * salary)
+        .await;
+    assert!(!ctx.payroll().await.employees[0].shortfall_outstanding);
+}
+
+#[tokio::test]
+async fn test_salary_advance_interest() {
+    let mut ctx = setup_payroll(1000).await;
+    let salary = 100;
+    let employee = add_employee(&mut ctx, salary).await;
+    let employee_token_account = employee.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
+    );
+
+    // Employer charges 1% interest per slot on advances.
+    let advance_interest_rate_bps = 100;
+    let set_rate_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_advance_interest_rate(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            advance_interest_rate_bps,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(set_rate_tx, false)
+        .await
+        .unwrap();
+
+    // Employee claims their next salary ahead of time.
+    let claim_advance_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_advance(
+            &employee.pubkey(),
+            &employee_token_account,
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    ctx.test
+        .process_transaction(claim_advance_tx, false)
+        .await
+        .unwrap();
+    let mut received = salary;
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, received)
+        .await;
+    let employee_state = ctx.payroll().await.employees[0];
+    assert!(employee_state.advance_outstanding);
+    assert_eq!(
+        received,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(employee_state.total_claimed)
+    );
+
+    // The employee can't be capped until the advance is repaid.
+    let set_claim_cap_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_claim_cap(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            salary,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(set_claim_cap_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::AdvanceOutstanding as u32),
+    );
+
+    // Regular claims are rejected until the advance is repaid.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 2, 1);
+    let err = ctx
+        .test
+        .process_transaction(claim_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::AdvanceOutstanding as u32),
+    );
+
+    // Later claims are reduced by the advance plus the interest accrued, until it is settled.
+    let mut advance = salary;
+    for (computation_offset, transfer_id) in [(3, 2), (4, 3)] {
+        let employee_state = ctx.payroll().await.employees[0];
+        if !employee_state.advance_outstanding {
+            break;
+        }
+
+        let claim_tx = employee_tx(
+            &ctx,
+            &employee,
+            payroll::instruction::claim_salary_repaying_advance(
+                &employee.pubkey(),
+                &employee_token_account,
+                &ctx.employer.pubkey(),
+                &ctx.mint,
+                computation_offset,
+                transfer_id,
+            )
+            .unwrap(),
+        );
+        ctx.test.process_transaction(claim_tx, false).await.unwrap();
+
+        let claimed_state = ctx.payroll().await.employees[0];
+        let slots_elapsed = claimed_state.last_claimed_slot - employee_state.advance_slot;
+        let interest_accrued = advance * advance_interest_rate_bps as u64 * slots_elapsed / 10_000;
+        let advance_due = advance + interest_accrued;
+        let deducted = min(salary, advance_due);
+        received += salary - deducted;
+        advance = advance_due - deducted;
+
+        employee
+            .assert_pending_balance(&mut ctx.test, &ctx.mint, received)
+            .await;
+        assert_eq!(
+            advance,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(claimed_state.encrypted_advance)
+        );
+        assert_eq!(advance != 0, claimed_state.advance_outstanding);
+        assert_eq!(
+            received,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(claimed_state.total_claimed)
+        );
+    }
+    assert!(!ctx.payroll().await.employees[0].advance_outstanding);
+
+    // Once settled, regular claims pay the full salary again.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 5, 4);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, received + salary)
+        .await;
+}
+
+#[tokio::test]
+async fn test_claim_advance_failed() {
+    // The employer keeps part of the funding to top up the payroll later.
+    let salary = 100;
+    let mut ctx = setup_employer(200).await;
+    let initialize_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::initialize(&ctx.employer.pubkey(), &ctx.mint, 1).unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(initialize_tx, true)
+        .await
+        .unwrap();
+    let payroll_token_account = ctx.payroll_token_account;
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, 50, &payroll_token_account)
+        .await;
+    let employee = add_employee(&mut ctx, salary).await;
+    let employee_token_account = employee.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
+    );
+    let claim_advance_tx = |ctx: &PayrollTest, computation_offset, transfer_id| {
+        employee_tx(
+            ctx,
+            &employee,
+            payroll::instruction::claim_advance(
+                &employee.pubkey(),
+                &employee_token_account,
+                &ctx.employer.pubkey(),
+                &ctx.mint,
+                computation_offset,
+                transfer_id,
+            )
+            .unwrap(),
+        )
+    };
+
+    // The vault can't cover the advance, the transfer fails and no advance is recorded.
+    let advance_tx = claim_advance_tx(&ctx, 1, 0);
+    ctx.test
+        .process_transaction(advance_tx, false)
+        .await
+        .unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 0)
+        .await;
+    let employee_state = ctx.payroll().await.employees[0];
+    assert!(!employee_state.advance_outstanding);
+    assert_eq!(0, employee_state.advance_slot);
+    assert!(employee_state.encrypted_advance == RescueCiphertext::default());
+
+    // Once the payroll is topped up, the advance can be claimed again.
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, 150, &payroll_token_account)
+        .await;
+    let advance_tx = claim_advance_tx(&ctx, 2, 1);
+    ctx.test
+        .process_transaction(advance_tx, false)
+        .await
+        .unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, salary)
+        .await;
+    assert!(ctx.payroll().await.employees[0].advance_outstanding);
+}
+
+#[tokio::test]
+async fn test_claim_salary_repaying_advance_underfunded() {
+    let salary = 100;
+    let mut ctx = setup_payroll(120).await;
+    let employee = add_employee(&mut ctx, salary).await;
+    let employee_token_account = employee.ata(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &ctx.mint,
+    );
+
+    let advance_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_advance(
+            &employee.pubkey(),
+            &employee_token_account,
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap(),
+    );
+    ctx.test
+        .process_transaction(advance_tx, false)
+        .await
+        .unwrap();
+
+    // After a raise, the salary left once the advance is repaid exceeds what the vault holds.
+    let new_salary = 300;
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(new_salary);
+    let update_salary_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::update_salary(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            encrypted_salary,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(update_salary_tx, false)
+        .await
+        .unwrap();
+
+    // The employee is paid what the vault holds, the advance is settled and the rest is recorded
+    // as a shortfall.
+    let claim_tx = employee_tx(
+        &ctx,
+        &employee,
+        payroll::instruction::claim_salary_repaying_advance(
+            &employee.pubkey(),
+            &employee_token_account,
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            2,
+            1,
+        )
+        .unwrap(),
+    );
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 120)
+        .await;
+    ctx.test
+        .assert_total_balance(&ctx.payroll_token_account, &ctx.mxe_pubkey, 0)
+        .await;
+
+    let employee_state = ctx.payroll().await.employees[0];
+    let mxe = ctx.test.get_mxe(&ctx.mxe_pubkey).unwrap();
+    assert!(employee_state.last_claim_succeeded);
+    assert!(!employee_state.advance_outstanding);
+    assert!(employee_state.shortfall_outstanding);
+    assert_eq!(
+        new_salary - salary - 20,
+        mxe.rescue_decrypt(employee_state.encrypted_shortfall)
+    );
+    assert_eq!(120, mxe.rescue_decrypt(employee_state.total_claimed));
+}
+
+#[tokio::test]
+async fn test_add_employee_at_capacity() {
+    let mut ctx = setup_payroll(1000).await;
+
+    let add_employee_tx = |ctx: &PayrollTest, employee: &Pubkey| {
+        let encrypted_salary = ctx
+            .test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_encrypt(100);
+        Transaction::new_signed_with_payer(
+            &[payroll::instruction::add_employee(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                employee,
+                encrypted_salary,
+                0,
+            )
+            .unwrap()],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        )
+    };
+
+    // Fill every employee slot.
+    for _ in 0..MAX_EMPLOYEES {
+        let tx = add_employee_tx(&ctx, &Pubkey::new_unique());
+        ctx.test.process_transaction(tx, false).await.unwrap();
+    }
+    assert_eq!(MAX_EMPLOYEES, ctx.payroll().await.employees.len());
+
+    // The next employee doesn't fit.
+    let tx = add_employee_tx(&ctx, &Pubkey::new_unique());
+    let err = ctx.test.process_transaction(tx, false).await.unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::EmployeeLimitReached as u32),
+    );
+    assert_eq!(MAX_EMPLOYEES, ctx.payroll().await.employees.len());
+}
+
+#[tokio::test]
+async fn test_add_employee_twice() {
+    let mut ctx = setup_payroll(1000).await;
+    let employee = add_employee(&mut ctx, 100).await;
+
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(200);
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::add_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            encrypted_salary,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::EmployeeAlreadyExists as u32),
+    );
+
+    // The employee keeps a single entry with their original salary.
+    let payroll = ctx.payroll().await;
+    assert_eq!(1, payroll.employees.len());
+    assert_eq!(
+        100,
+        ctx.test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_decrypt(payroll.employees[0].encrypted_salary)
+    );
+}
+
+#[tokio::test]
+async fn test_add_employee_uninitialized_salary() {
+    let mut ctx = setup_payroll(1000).await;
+
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::add_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &Pubkey::new_unique(),
+            RescueCiphertext::default(),
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    let err = ctx
+        .test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap_err();
+    assert_instruction_error(
+        err,
+        0,
+        InstructionError::Custom(PayrollError::SalaryUninitialized as u32),
+    );
+    assert!(ctx.payroll().await.employees.is_empty());
+}
+
+#[tokio::test]
+async fn test_add_employees_beyond_initial_capacity() {
+    let mut ctx = setup_payroll(1000).await;
+
+    // The payroll account grows with every employee added.
+    let mut employees = Vec::new();
+    for _ in 0..19 {
+        let employee = Pubkey::new_unique();
+        let encrypted_salary = ctx
+            .test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_encrypt(100);
+        let add_employee_tx = Transaction::new_signed_with_payer(
+            &[payroll::instruction::add_employee(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                &employee,
+                encrypted_salary,
+                0,
+            )
+            .unwrap()],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        );
+        ctx.test
+            .process_transaction(add_employee_tx, false)
+            .await
+            .unwrap();
+        employees.push(employee);
+    }
+    let employee = add_employee(&mut ctx, 100).await;
+    employees.push(employee.pubkey());
+
+    let payroll = ctx.payroll().await;
+    assert_eq!(20, payroll.employees.len());
+    for (i, employee) in employees.iter().enumerate() {
+        assert_eq!(employee.to_bytes(), payroll.employees[i].key);
+    }
+
+    // The last employee added can claim their salary.
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+}
+
+#[tokio::test]
+async fn test_remove_employee() {
+    let mut ctx = setup_payroll(1000).await;
+
+    let employer_tx = |ctx: &PayrollTest, instruction: Instruction| {
+        Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        )
+    };
+
+    // Fill every employee slot.
+    let employees = (0..MAX_EMPLOYEES)
+        .map(|_| Pubkey::new_unique())
+        .collect::<Vec<_>>();
+    for employee in &employees {
+        let encrypted_salary = ctx
+            .test
+            .get_mxe(&ctx.mxe_pubkey)
+            .unwrap()
+            .rescue_encrypt(100);
+        let tx = employer_tx(
+            &ctx,
+            payroll::instruction::add_employee(
+                &ctx.employer.pubkey(),
+                &ctx.payroll,
+                employee,
+                encrypted_salary,
+                0,
+            )
+            .unwrap(),
+        );
+        ctx.test.process_transaction(tx, false).await.unwrap();
+    }
+
+    // Removing the first employee moves the last one into its slot.
+    let tx = employer_tx(
+        &ctx,
+        payroll::instruction::remove_employee(&ctx.employer.pubkey(), &ctx.payroll, &employees[0])
+            .unwrap(),
+    );
+    ctx.test.process_transaction(tx, false).await.unwrap();
+    let payroll = ctx.payroll().await;
+    assert_eq!(MAX_EMPLOYEES - 1, payroll.employees.len());
+    assert_eq!(
+        employees[MAX_EMPLOYEES - 1].to_bytes(),
+        payroll.employees[0].key
+    );
+
+    // The removed employee can't be removed twice.
+    let tx = employer_tx(
+        &ctx,
+        payroll::instruction::remove_employee(&ctx.employer.pubkey(), &ctx.payroll, &employees[0])
+            .unwrap(),
+    );
+    let err = ctx.test.process_transaction(tx, false).await.unwrap_err();
+    assert_instruction_error(err, 0, InstructionError::InvalidAccountData);
+
+    // The freed slot can be used again.
+    let employee = add_employee(&mut ctx, 100).await;
+    let payroll = ctx.payroll().await;
+    assert_eq!(MAX_EMPLOYEES, payroll.employees.len());
+    assert_eq!(
+        employee.pubkey().to_bytes(),
+        payroll.employees[MAX_EMPLOYEES - 1].key
+    );
+    let claim_tx = claim_salary_tx(&ctx, &employee, 1, 0);
+    ctx.test.process_transaction(claim_tx, false).await.unwrap();
+    employee
+        .assert_pending_balance(&mut ctx.test, &ctx.mint, 100)
+        .await;
+}
+
+#[tokio::test]
+async fn test_setup_payroll_instructions() {
+    let mut ctx = setup_employer(1000).await;
+
+    let salaries = [100, 200, 300];
+    let employees = salaries
+        .iter()
+        .map(|salary| {
+            (
+                Pubkey::new_unique(),
+                ctx.test
+                    .get_mxe(&ctx.mxe_pubkey)
+                    .unwrap()
+                    .rescue_encrypt(*salary),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Run the full setup batch in order.
+    let instructions = payroll::instruction::setup_payroll_instructions(
+        &ctx.employer.pubkey(),
+        &ctx.mint,
+        1,
+        &employees,
+    )
+    .unwrap();
+    assert_eq!(1 + employees.len(), instructions.len());
+    for (i, instruction) in instructions.into_iter().enumerate() {
+        let tx = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&ctx.employer.pubkey()),
+            &[&ctx.employer.signer_keypair()],
+            ctx.test.get_recent_blockhash(),
+        );
+        ctx.test.process_transaction(tx, i == 0).await.unwrap();
+    }
+
+    // The payroll and all employees are initialized.
+    let payroll = ctx.payroll().await;
+    assert_eq!(ctx.employer.pubkey().to_bytes(), payroll.employer);
+    assert_eq!(ctx.mint.to_bytes(), payroll.mint);
+    assert_eq!(employees.len(), payroll.employees.len());
+    for (i, ((employee, _), salary)) in employees.iter().zip(salaries).enumerate() {
+        assert_eq!(employee.to_bytes(), payroll.employees[i].key);
+        assert_eq!(
+            salary,
+            ctx.test
+                .get_mxe(&ctx.mxe_pubkey)
+                .unwrap()
+                .rescue_decrypt(payroll.employees[i].encrypted_salary)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_query_runway() {
+    // Fund the payroll of 20 employees for two and a half runs.
+    let salaries = [10, 30].repeat(10);
+    let mut ctx = setup_payroll(1000).await;
+    for salary in salaries {
+        add_employee(&mut ctx, salary).await;
+    }
+
+    let query_runway_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::query_runway(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &ctx.mint,
+            1,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(query_runway_tx, false)
+        .await
+        .unwrap();
+
+    // Only full payroll runs count towards the runway.
+    assert_eq!(2, ctx.payroll().await.runway);
+}
+
+struct PayrollTest {
+    test: ConfidentialSPLTokenTest,
+    mxe_pubkey: Pubkey,
+    mint: Pubkey,
+    employer: Actor,
+    payroll: Pubkey,
+    payroll_token_account: Pubkey,
+}
+
+impl PayrollTest {
+    async fn payroll(&mut self) -> Payroll {
+        Payroll::try_from_slice(&self.test.get_account(&self.payroll).await.unwrap().data).unwrap()
+    }
+
+    /// Amount paid by the last claim of the employee at `employee_idx`, decrypted by `auditor`.
+    async fn audited_amount(&mut self, auditor: &Actor, employee_idx: usize) -> u64 {
+        let employee = self.payroll().await.employees[employee_idx];
+        let mxe = self.test.get_mxe(&self.mxe_pubkey).unwrap();
+        auditor.shared_decrypt(
+            &mxe,
+            employee.audit_amount_nonce,
+            employee.encrypted_audit_amount,
+        )
+    }
+}
+
+/// Sets up an employer holding `funding` available tokens, without initializing the payroll.
+async fn setup_employer(funding: u64) -> PayrollTest {
+    let mut test = ConfidentialSPLTokenTest::new(vec![CustomProgram {
+        program_name: "payroll",
+        program_id: payroll::ID,
+        processor: processor!(payroll::process_instruction),
+    }])
+    .await;
+
+    let mxe_pubkey = test
+        .enable_confidential_token_accounts_for_program(&payroll::ID)
+        .await;
+
+    let compiled_claim_circuit = payroll_encrypted_ixs::encrypted_computations::claim_salary();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        CLAIM_SALARY_COMP_DEF_OFFSET,
+        compiled_claim_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_cola_circuit =
+        payroll_encrypted_ixs::encrypted_computations::apply_cola_adjustment();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        APPLY_COLA_COMP_DEF_OFFSET,
+        compiled_cola_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_advance_circuit =
+        payroll_encrypted_ixs::encrypted_computations::claim_salary_repaying_advance();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        CLAIM_SALARY_REPAYING_ADVANCE_COMP_DEF_OFFSET,
+        compiled_advance_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_runway_circuit = payroll_encrypted_ixs::encrypted_computations::query_runway();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        QUERY_RUNWAY_COMP_DEF_OFFSET,
+        compiled_runway_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_streamed_circuit =
+        payroll_encrypted_ixs::encrypted_computations::claim_salary_streamed();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        CLAIM_SALARY_STREAMED_COMP_DEF_OFFSET,
+        compiled_streamed_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_withdraw_circuit =
+        payroll_encrypted_ixs::encrypted_computations::employer_withdraw();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        EMPLOYER_WITHDRAW_COMP_DEF_OFFSET,
+        compiled_withdraw_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_capped_circuit =
+        payroll_encrypted_ixs::encrypted_computations::claim_salary_capped();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        CLAIM_SALARY_CAPPED_COMP_DEF_OFFSET,
+        compiled_capped_circuit,
+    )
+    .await
+    .unwrap();
+    let compiled_pay_employee_circuit =
+        payroll_encrypted_ixs::encrypted_computations::pay_employee();
+    test.create_comp_def_for_test(
+        &payroll::ID,
+        PAY_EMPLOYEE_COMP_DEF_OFFSET,
+        compiled_pay_employee_circuit,
+    )
+    .await
+    .unwrap();
+
+    let mint_authority = Keypair::new();
+    let mint = test
+        .create_mint(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            9,
+            &mint_authority,
+        )
+        .await
+        .pubkey();
+
+    let employer = test.new_actor().await;
+    employer
+        .create_ata(
+            &mut test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &mint,
+        )
+        .await;
+    test.mint_to_account(
+        &confidential_spl_token::programs::confidential_spl_token::ID,
+        &mint,
+        &mint_authority,
+        funding,
+        &employer.ata(
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &mint,
+        ),
+    )
+    .await;
+    employer.deposit(&mut test, &mint, funding).await;
+    employer.apply_pending_balance(&mut test, &mint).await;
+
+    let (payroll, _) = addresses::payroll_pda(&employer.pubkey());
+    let payroll_token_account = addresses::payroll_token_account(&employer.pubkey(), &mint);
+
+    PayrollTest {
+        test,
+        mxe_pubkey,
+        mint,
+        employer,
+        payroll,
+        payroll_token_account,
+    }
+}
+
+/// Sets up an initialized payroll whose token account has been funded with `funding` tokens.
+async fn setup_payroll(funding: u64) -> PayrollTest {
+    setup_payroll_with_claim_cooldown(funding, 1).await
+}
+
+/// Same as `setup_payroll`, employees have to wait `claim_cooldown_slots` between claims.
+async fn setup_payroll_with_claim_cooldown(funding: u64, claim_cooldown_slots: u64) -> PayrollTest {
+    let mut ctx = setup_employer(funding).await;
+
+    let initialize_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::initialize(
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            claim_cooldown_slots,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(initialize_tx, true)
+        .await
+        .unwrap();
+
+    let payroll_token_account = ctx.payroll_token_account;
+    ctx.employer
+        .transfer(&mut ctx.test, &ctx.mint, funding, &payroll_token_account)
+        .await;
+
+    ctx
+}
+
+/// Creates a new employee with a token account and adds them to the payroll.
+async fn add_employee(ctx: &mut PayrollTest, salary: u64) -> Actor {
+    let employee = ctx.test.new_actor().await;
+    employee
+        .create_ata(
+            &mut ctx.test,
+            &confidential_spl_token::programs::confidential_spl_token::ID,
+            &ctx.mint,
+        )
+        .await;
+
+    let encrypted_salary = ctx
+        .test
+        .get_mxe(&ctx.mxe_pubkey)
+        .unwrap()
+        .rescue_encrypt(salary);
+    let add_employee_tx = Transaction::new_signed_with_payer(
+        &[payroll::instruction::add_employee(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            encrypted_salary,
+            0,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    );
+    ctx.test
+        .process_transaction(add_employee_tx, false)
+        .await
+        .unwrap();
+
+    employee
+}
+
+fn claim_salary_tx(
+    ctx: &PayrollTest,
+    employee: &Actor,
+    computation_offset: u32,
+    transfer_id: u32,
+) -> Transaction {
+    Transaction::new_signed_with_payer(
+        &[payroll::instruction::claim_salary(
+            &employee.pubkey(),
+            &employee.ata(
+                &confidential_spl_token::programs::confidential_spl_token::ID,
+                &ctx.mint,
+            ),
+            &ctx.employer.pubkey(),
+            &ctx.mint,
+            computation_offset,
+            transfer_id,
+        )
+        .unwrap()],
+        Some(&employee.pubkey()),
+        &[&employee.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    )
+}
+
+fn employee_tx(ctx: &PayrollTest, employee: &Actor, instruction: Instruction) -> Transaction {
+    Transaction::new_signed_with_payer(
+        &[instruction],
+        Some(&employee.pubkey()),
+        &[&employee.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    )
+}
+
+fn set_employee_frozen_tx(ctx: &PayrollTest, employee: &Actor, frozen: bool) -> Transaction {
+    Transaction::new_signed_with_payer(
+        &[payroll::instruction::set_employee_frozen(
+            &ctx.employer.pubkey(),
+            &ctx.payroll,
+            &employee.pubkey(),
+            frozen,
+        )
+        .unwrap()],
+        Some(&ctx.employer.pubkey()),
+        &[&ctx.employer.signer_keypair()],
+        ctx.test.get_recent_blockhash(),
+    )
 }